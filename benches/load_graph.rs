@@ -0,0 +1,86 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use osmtools::nearest_node::add_nearest_node_to_geo_df;
+use osmtools::pbfextractor::metrics::{Distance_, NodeMetric};
+use osmtools::pbfextractor::pbf::{Loader, Node};
+use osmtools::pbfextractor::units::Meters;
+use polars::df;
+use polars::frame::DataFrame;
+use proj4rs::Proj;
+use std::hint::black_box;
+
+const BRUEGGE_PBF: &str = "data/bruegge.osm.pbf";
+
+fn bench_load_graph(c: &mut Criterion) {
+    let loader = Loader::car(BRUEGGE_PBF).expect("complete loader configuration");
+    if let Err(error) = loader.validate_header() {
+        eprintln!("Skipping load_graph, {BRUEGGE_PBF} is not usable: {error}");
+        return;
+    }
+    let (_, edges) = loader.load_graph();
+    let mut group = c.benchmark_group("load_graph");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(edges.len() as u64));
+    group.bench_function("car/bruegge", |b| b.iter(|| loader.load_graph()));
+    group.finish();
+}
+
+/// `count` points on a regular grid around Bruges, about 10 m apart and
+/// shifted by `offset` cells.
+fn grid(count: usize, offset: f64) -> DataFrame {
+    let side = (count as f64).sqrt().ceil() as usize;
+    let (lat, long): (Vec<f64>, Vec<f64>) = (0..count)
+        .map(|i| {
+            let (row, col) = (i / side, i % side);
+            let (row, col) = (row as f64 + offset, col as f64 + offset);
+            (51.2 + row * 1e-4, 3.2 + col * 1.5e-4)
+        })
+        .unzip();
+    let osm_id: Vec<u64> = (0..count as u64).collect();
+    df!("osm_id" => osm_id, "lat" => lat, "long" => long).expect("columns have equal length")
+}
+
+fn bench_nearest_node(c: &mut Criterion) {
+    let nodes = grid(10_000, 0.0);
+    let mut group = c.benchmark_group("add_nearest_node_to_geo_df");
+    for size in [1_000, 10_000, 100_000] {
+        // Shifted by half a cell, so targets do not coincide with nodes.
+        let targets = grid(size, 0.5);
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &targets, |b, targets| {
+            b.iter(|| add_nearest_node_to_geo_df(black_box(targets.clone()), &nodes, 4839))
+        });
+    }
+    group.finish();
+}
+
+/// Edge lengths with the projections built once versus once per edge, as
+/// before the loader cached them.
+fn bench_distance(c: &mut Criterion) {
+    let source = Node::new(1, 51.2075825, 3.2284262);
+    let target = Node::new(2, 51.2076861, 3.2286302);
+    let distance = |from: &Proj, to: &Proj| -> Meters {
+        Distance_
+            .calc(black_box(&source), black_box(&target), from, to)
+            .expect("projectable coordinates")
+    };
+    let mut group = c.benchmark_group("distance");
+    group.bench_function("per_edge_projection", |b| {
+        b.iter(|| {
+            let from = Proj::from_epsg_code(4326).expect("known CRS");
+            let to = Proj::from_epsg_code(4839).expect("known CRS");
+            distance(&from, &to)
+        })
+    });
+    let from = Proj::from_epsg_code(4326).expect("known CRS");
+    let to = Proj::from_epsg_code(4839).expect("known CRS");
+    group.bench_function("cached_projection", |b| b.iter(|| distance(&from, &to)));
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_load_graph,
+    bench_nearest_node,
+    bench_distance
+);
+criterion_main!(benches);
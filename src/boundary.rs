@@ -1,7 +1,12 @@
 use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Display;
 use std::fs::File;
+use std::io;
+use std::path::Path;
 
-use geo::{Contains, LineString, Point, Polygon};
+use geo::{Contains, Geometry, LineString, MultiPolygon, Point, Polygon};
+use geojson::{FeatureCollection, GeoJson};
 use osmpbfreader::{NodeId, OsmId, OsmObj, OsmPbfReader, Relation};
 
 use crate::extractor::check_pbf_archives;
@@ -135,6 +140,75 @@ pub fn _load_osm_boundary(
     outer_rings.into_iter().zip(holes_by_outer).collect()
 }
 
+/// Named administrative area, e.g. a city or one of its suburbs.
+#[derive(Debug, Clone)]
+pub struct AdminArea {
+    pub name: String,
+    pub geometry: MultiPolygon,
+}
+
+#[derive(Debug)]
+pub enum AdminAreaError {
+    /// The input is not a valid GeoJSON `FeatureCollection`.
+    InvalidGeoJson(String),
+    Io(io::Error),
+}
+
+impl Error for AdminAreaError {}
+impl Display for AdminAreaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdminAreaError::InvalidGeoJson(reason) => write!(f, "Invalid GeoJSON: {reason}"),
+            AdminAreaError::Io(error) => write!(f, "Could not read admin areas: {error}"),
+        }
+    }
+}
+
+impl From<io::Error> for AdminAreaError {
+    fn from(error: io::Error) -> Self {
+        AdminAreaError::Io(error)
+    }
+}
+
+impl From<geojson::Error> for AdminAreaError {
+    fn from(error: geojson::Error) -> Self {
+        AdminAreaError::InvalidGeoJson(error.to_string())
+    }
+}
+
+/// Reads the admin areas of a GeoJSON `FeatureCollection`, e.g. an
+/// osm2geojson export of boundary relations, see [`parse_admin_areas`].
+pub fn load_admin_areas(
+    path: impl AsRef<Path>,
+    name_property: &str,
+) -> Result<Vec<AdminArea>, AdminAreaError> {
+    parse_admin_areas(&std::fs::read_to_string(path)?, name_property)
+}
+
+/// Parses the `Polygon` and `MultiPolygon` features of a GeoJSON
+/// `FeatureCollection`, named by their string property `name_property`.
+///
+/// Features without that property or with another geometry type are skipped.
+pub fn parse_admin_areas(
+    geojson: &str,
+    name_property: &str,
+) -> Result<Vec<AdminArea>, AdminAreaError> {
+    let collection = FeatureCollection::try_from(geojson.parse::<GeoJson>()?)?;
+    Ok(collection
+        .features
+        .into_iter()
+        .filter_map(|feature| {
+            let name = feature.property(name_property)?.as_str()?.to_owned();
+            let geometry = match Geometry::try_from(feature.geometry?).ok()? {
+                Geometry::Polygon(polygon) => MultiPolygon::new(vec![polygon]),
+                Geometry::MultiPolygon(multi_polygon) => multi_polygon,
+                _ => return None,
+            };
+            Some(AdminArea { name, geometry })
+        })
+        .collect())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -147,4 +221,42 @@ mod tests {
         assert!(shell.len() > 3);
         assert_eq!(shell.first(), shell.last());
     }
+
+    #[test]
+    fn test_parse_admin_areas() {
+        let geojson = r#"{
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "properties": {"name": "Brugge"},
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[[3.1, 51.1], [3.3, 51.1], [3.3, 51.3], [3.1, 51.1]]]
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "properties": {"admin_level": "9"},
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[[3.1, 51.1], [3.3, 51.1], [3.3, 51.3], [3.1, 51.1]]]
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "properties": {"name": "Markt"},
+                    "geometry": {"type": "Point", "coordinates": [3.22, 51.21]}
+                }
+            ]
+        }"#;
+        let areas = parse_admin_areas(geojson, "name").unwrap();
+        assert_eq!(areas.len(), 1);
+        assert_eq!(areas[0].name, "Brugge");
+        assert_eq!(areas[0].geometry.0[0].exterior().0.len(), 4);
+        assert!(matches!(
+            parse_admin_areas("{}", "name"),
+            Err(AdminAreaError::InvalidGeoJson(_))
+        ));
+    }
 }
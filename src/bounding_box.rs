@@ -0,0 +1,240 @@
+use crate::utils::sources::default_bbox;
+use geo::{LineString, Polygon};
+use std::error::Error;
+use std::fmt::Display;
+use wkt::TryFromWkt;
+
+/// Approximate length of one degree of latitude in meters.
+pub(crate) const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// Axis aligned box in WGS84 coordinates.
+///
+/// Prefer this over a raw `Vec<(f64, f64)>` ring: the field names make the
+/// (longitude, latitude) order explicit.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct BoundingBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+impl BoundingBox {
+    pub fn new(min_lon: f64, min_lat: f64, max_lon: f64, max_lat: f64) -> BoundingBox {
+        BoundingBox {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        }
+    }
+
+    /// Square box extending `radius_m` meters from the center in every
+    /// cardinal direction.
+    pub fn from_center_radius(lat: f64, lon: f64, radius_m: f64) -> BoundingBox {
+        let d_lat = radius_m / METERS_PER_DEGREE;
+        let d_lon = radius_m / (METERS_PER_DEGREE * lat.to_radians().cos());
+        BoundingBox::new(lon - d_lon, lat - d_lat, lon + d_lon, lat + d_lat)
+    }
+
+    /// Closed counter-clockwise ring with `x` = longitude and `y` = latitude.
+    pub fn to_polygon(&self) -> Polygon {
+        Polygon::new(
+            LineString::from(vec![
+                (self.min_lon, self.min_lat),
+                (self.max_lon, self.min_lat),
+                (self.max_lon, self.max_lat),
+                (self.min_lon, self.max_lat),
+                (self.min_lon, self.min_lat),
+            ]),
+            vec![],
+        )
+    }
+}
+
+impl From<BoundingBox> for Polygon {
+    fn from(bounding_box: BoundingBox) -> Polygon {
+        bounding_box.to_polygon()
+    }
+}
+
+/// Geometries accepted by the `_load_osm_*` functions to restrict the
+/// extracted area.
+pub trait FilterGeometry {
+    fn into_polygon(self) -> Polygon;
+
+    /// Filter polygon used when extracting `city`, `None` to keep the whole
+    /// pbf file. Only `None` and [`NoGeometryFilter`] differ from
+    /// [`FilterGeometry::into_polygon`]: `None` falls back to the city's
+    /// [`default_bbox`], or to no filter for cities outside of the catalog.
+    fn into_filter_polygon(self, _city: &str) -> Option<Polygon>
+    where
+        Self: Sized,
+    {
+        Some(self.into_polygon())
+    }
+}
+
+/// A closed ring of `(longitude, latitude)` pairs.
+impl FilterGeometry for Vec<(f64, f64)> {
+    fn into_polygon(self) -> Polygon {
+        Polygon::new(LineString::from(self), vec![])
+    }
+}
+
+/// A closed ring of `(longitude, latitude)` pairs.
+impl<const N: usize> FilterGeometry for [(f64, f64); N] {
+    fn into_polygon(self) -> Polygon {
+        Polygon::new(LineString::from(self.to_vec()), vec![])
+    }
+}
+
+impl FilterGeometry for BoundingBox {
+    fn into_polygon(self) -> Polygon {
+        self.to_polygon()
+    }
+}
+
+impl FilterGeometry for Polygon {
+    fn into_polygon(self) -> Polygon {
+        self
+    }
+}
+
+/// `None` on its own covers no area, so [`FilterGeometry::into_polygon`]
+/// returns an empty polygon for it.
+impl<T: FilterGeometry> FilterGeometry for Option<T> {
+    fn into_polygon(self) -> Polygon {
+        self.map_or_else(
+            || Polygon::new(LineString::new(vec![]), vec![]),
+            T::into_polygon,
+        )
+    }
+
+    fn into_filter_polygon(self, city: &str) -> Option<Polygon> {
+        match self {
+            Some(geometry) => Some(geometry.into_polygon()),
+            None => default_bbox(city).map(Polygon::from),
+        }
+    }
+}
+
+/// Keeps the whole pbf file, e.g. a regional extract that is already clipped
+/// to the area of interest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoGeometryFilter;
+
+impl FilterGeometry for NoGeometryFilter {
+    /// The whole world.
+    fn into_polygon(self) -> Polygon {
+        BoundingBox::new(-180.0, -90.0, 180.0, 90.0).to_polygon()
+    }
+
+    fn into_filter_polygon(self, _city: &str) -> Option<Polygon> {
+        None
+    }
+}
+
+#[derive(Debug)]
+pub enum GeometryError {
+    /// The input is not valid WKT.
+    InvalidWkt(String),
+    /// The input is valid WKT, but not a `POLYGON`.
+    NotAPolygon(String),
+}
+
+impl Error for GeometryError {}
+impl Display for GeometryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeometryError::InvalidWkt(reason) => write!(f, "Invalid WKT: {reason}"),
+            GeometryError::NotAPolygon(found) => write!(f, "Expected a POLYGON, found {found}"),
+        }
+    }
+}
+
+/// Parses a WKT `POLYGON((lon lat, ...))`, e.g. the output of PostGIS
+/// `ST_AsText`.
+pub fn polygon_from_wkt(value: &str) -> Result<Polygon, GeometryError> {
+    Polygon::try_from_wkt_str(value).map_err(|error| match error {
+        wkt::conversion::Error::MismatchedGeometry { found, .. } => {
+            GeometryError::NotAPolygon(found.into())
+        }
+        other => GeometryError::InvalidWkt(other.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{Contains, Point};
+
+    #[test]
+    fn test_polygon_uses_lon_lat_order() {
+        let bounding_box = BoundingBox::new(3.22183, 51.20391, 3.23663, 51.20887);
+        let polygon = bounding_box.to_polygon();
+        assert_eq!(polygon.exterior().0[0].x, 3.22183);
+        assert_eq!(polygon.exterior().0[0].y, 51.20391);
+        assert!(polygon.contains(&Point::new(3.23, 51.205)));
+        assert!(!polygon.contains(&Point::new(51.205, 3.23)));
+    }
+
+    #[test]
+    fn test_matches_coordinate_vec() {
+        let ring = vec![
+            (3.22183, 51.20391),
+            (3.23663, 51.20391),
+            (3.23663, 51.20887),
+            (3.22183, 51.20887),
+            (3.22183, 51.20391),
+        ];
+        let bounding_box = BoundingBox::new(3.22183, 51.20391, 3.23663, 51.20887);
+        assert_eq!(ring.into_polygon(), Polygon::from(bounding_box));
+    }
+
+    #[test]
+    fn test_polygon_from_wkt() {
+        let polygon = polygon_from_wkt(
+            "POLYGON((3.22183 51.20391, 3.23663 51.20391, 3.23663 51.20887, \
+             3.22183 51.20887, 3.22183 51.20391))",
+        )
+        .unwrap();
+        let bounding_box = BoundingBox::new(3.22183, 51.20391, 3.23663, 51.20887);
+        assert_eq!(polygon, Polygon::from(bounding_box));
+    }
+
+    #[test]
+    fn test_polygon_from_wkt_rejects_other_geometries() {
+        assert!(matches!(
+            polygon_from_wkt("LINESTRING(3.2 51.2, 3.3 51.3)"),
+            Err(GeometryError::NotAPolygon(_))
+        ));
+        assert!(matches!(
+            polygon_from_wkt("POLYGON((3.2 51.2, 3.3"),
+            Err(GeometryError::InvalidWkt(_))
+        ));
+    }
+
+    #[test]
+    fn test_coordinate_array() {
+        let ring = [
+            (3.22183, 51.20391),
+            (3.23663, 51.20391),
+            (3.23663, 51.20887),
+            (3.22183, 51.20887),
+            (3.22183, 51.20391),
+        ];
+        assert_eq!(ring.into_polygon(), ring.to_vec().into_polygon());
+    }
+
+    #[test]
+    fn test_from_center_radius() {
+        let bounding_box = BoundingBox::from_center_radius(51.2, 3.2, 1000.0);
+        assert!(bounding_box.min_lon < 3.2 && 3.2 < bounding_box.max_lon);
+        assert!(bounding_box.min_lat < 51.2 && 51.2 < bounding_box.max_lat);
+        assert!((bounding_box.max_lat - 51.2 - 1000.0 / METERS_PER_DEGREE).abs() < 1e-12);
+        // A degree of longitude is shorter than a degree of latitude away from
+        // the equator.
+        assert!(bounding_box.max_lon - 3.2 > bounding_box.max_lat - 51.2);
+    }
+}
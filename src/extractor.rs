@@ -1,15 +1,31 @@
+use crate::boundary::AdminArea;
+use crate::bounding_box::{BoundingBox, FilterGeometry};
+use crate::pbfextractor::header::pbf_source_timestamp;
 use crate::pbfextractor::metrics::{
-    BicycleEdgeFilter, CarEdgeFilter, EdgeFilter, WalkingEdgeFilter,
+    Ascent, BicycleEdgeFilter, CarEdgeFilter, CostMetric, Distance_, EdgeFilter, HorseEdgeFilter,
+    Metric, ToblerWalkingTime, TruckEdgeFilter, WalkingEdgeFilter,
 };
-use crate::pbfextractor::node_pbf::PoiLoaderBuilder;
-use crate::pbfextractor::pbf::{Loader, OsmLoaderBuilder};
+use crate::pbfextractor::node_pbf::{
+    assign_admin_areas, count_pois_per_polygon, Poi, PoiLoaderBuilder,
+};
+use crate::pbfextractor::pbf::{Loader, MetricIndices, OsmLoaderBuilder};
 use crate::struct_to_dataframe;
-use geo::{LineString, Polygon};
+use chrono::{DateTime, Utc};
+use geo::Polygon;
 use log::info;
 use polars::frame::DataFrame;
-use std::fs::File;
-use std::io::{self, BufWriter, Error, ErrorKind};
+use polars::prelude::{
+    ChunkAgg, ChunkCompareEq, Column, DataType, NamedFrom, PolarsError, PolarsResult, Series,
+};
+use polars_io::parquet::write::KeyValueMetadata;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
+use std::fs::{create_dir_all, File};
+use std::io::{BufWriter, Error, ErrorKind};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
 
 pub(crate) fn check_pbf_archives(
     city_name: &str,
@@ -18,8 +34,8 @@ pub(crate) fn check_pbf_archives(
 ) -> Result<PathBuf, Error> {
     let pbf_path;
     if download {
-        pbf_path = crate::download::download(&city_name.into(), &archive_path.into())
-            .expect("Error in Download");
+        pbf_path =
+            crate::download::download(&city_name.into(), archive_path).expect("Error in Download");
     } else {
         pbf_path =
             Path::new(archive_path).join(Path::new(&(city_name.to_lowercase() + ".osm.pbf")));
@@ -33,184 +49,960 @@ pub(crate) fn check_pbf_archives(
     return Ok(pbf_path);
 }
 
-fn get_edge_outpath(outpath: &str, city_name: &str, network_type: &str) -> String {
-    let mut outpath_edges = get_outpath(outpath, city_name, network_type);
-    outpath_edges.push_str("_edges.parquet");
-    outpath_edges
+/// Template of the output file paths below the output directory. The
+/// placeholders `{city}` (lowercase), `{mode}` (e.g. `walking`) and `{kind}`
+/// (e.g. `edges` or `nodes`) are replaced and `.parquet` is appended, so
+/// `{mode}/{city}/{kind}` writes `walking/bruegge/edges.parquet`. Missing
+/// directories are created. The default `{city}_{mode}_{kind}` writes
+/// `bruegge_walking_edges.parquet`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputNaming {
+    template: String,
 }
 
-fn get_node_outpath(outpath: &str, city_name: &str, network_type: &str) -> String {
-    let mut outpath_node = get_outpath(outpath, city_name, network_type);
-    outpath_node.push_str("_nodes.parquet");
-    outpath_node
+impl Default for OutputNaming {
+    fn default() -> Self {
+        OutputNaming {
+            template: "{city}_{mode}_{kind}".to_owned(),
+        }
+    }
 }
 
-fn get_outpath(outpath: &str, city_name: &str, network_type: &str) -> String {
-    let mut outpath = outpath.to_owned();
-    outpath.push_str("/");
-    outpath.push_str(&*city_name.to_lowercase());
-    outpath.push_str("_");
-    outpath.push_str(network_type);
-    outpath
+impl OutputNaming {
+    /// Fails if `template` lacks the `{kind}` placeholder, as the files of an
+    /// extraction would overwrite each other.
+    pub fn new<VALUE: Into<String>>(template: VALUE) -> Result<Self, OutputNamingError> {
+        let template = template.into();
+        if !template.contains("{kind}") {
+            return Err(OutputNamingError { template });
+        }
+        Ok(OutputNaming { template })
+    }
+
+    fn path(&self, outpath: &str, city_name: &str, mode: &str, kind: &str) -> PathBuf {
+        let name = self
+            .template
+            .replace("{city}", &city_name.to_lowercase())
+            .replace("{mode}", mode)
+            .replace("{kind}", kind);
+        Path::new(outpath).join(name + ".parquet")
+    }
 }
 
-pub fn _load_osm_pois(
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputNamingError {
+    template: String,
+}
+
+impl std::error::Error for OutputNamingError {}
+
+impl Display for OutputNamingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Output naming template {} lacks the {{kind}} placeholder",
+            self.template
+        )
+    }
+}
+
+fn get_edge_outpath(
+    outpath: &str,
+    naming: Option<&OutputNaming>,
+    city_name: &str,
+    network_type: &str,
+) -> PathBuf {
+    get_outpath(outpath, naming, city_name, network_type, "edges")
+}
+
+fn get_node_outpath(
+    outpath: &str,
+    naming: Option<&OutputNaming>,
+    city_name: &str,
+    network_type: &str,
+) -> PathBuf {
+    get_outpath(outpath, naming, city_name, network_type, "nodes")
+}
+
+fn get_outpath(
+    outpath: &str,
+    naming: Option<&OutputNaming>,
+    city_name: &str,
+    network_type: &str,
+    kind: &str,
+) -> PathBuf {
+    match naming {
+        Some(naming) => naming.path(outpath, city_name, network_type, kind),
+        None => OutputNaming::default().path(outpath, city_name, network_type, kind),
+    }
+}
+
+pub type ExtractorResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// EPSG code of the CRS distances are calculated in by the `_load_osm_*`
+/// functions.
+pub const TARGET_CRS: u16 = 4839;
+
+/// What to extract of a city, displayed and parsed by its lowercase name as
+/// used in output file names, e.g. `walking`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NetworkType {
+    Walking,
+    Cycling,
+    Driving,
+    Pois,
+}
+
+impl NetworkType {
+    pub const ALL: [NetworkType; 4] = [
+        NetworkType::Walking,
+        NetworkType::Cycling,
+        NetworkType::Driving,
+        NetworkType::Pois,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NetworkType::Walking => "walking",
+            NetworkType::Cycling => "cycling",
+            NetworkType::Driving => "driving",
+            NetworkType::Pois => "pois",
+        }
+    }
+}
+
+impl Display for NetworkType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for NetworkType {
+    type Err = ParseNetworkTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NetworkType::ALL
+            .into_iter()
+            .find(|network_type| network_type.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseNetworkTypeError { name: s.to_owned() })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseNetworkTypeError {
+    name: String,
+}
+
+impl std::error::Error for ParseNetworkTypeError {}
+
+impl Display for ParseNetworkTypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<&str> = NetworkType::ALL.iter().map(|t| t.as_str()).collect();
+        write!(
+            f,
+            "Unknown network type {}, expected one of {}",
+            self.name,
+            names.join(", ")
+        )
+    }
+}
+
+fn poi_loader_builder(
     city_name: &str,
-    geometry_vec: Vec<(f64, f64)>,
     archive_path: &str,
     nodes_to_match_path: Option<&str>,
     nodes_to_match_df: Option<&DataFrame>,
-    outpath: &str,
     download: bool,
-) -> DataFrame {
-    let bounding_box = Polygon::new(LineString::from(geometry_vec), vec![]);
-    let pbf_path = check_pbf_archives(city_name, archive_path, download)
-        .expect("Download failed or Path not existing");
+    target_crs: u16,
+) -> ExtractorResult<PoiLoaderBuilder> {
+    let pbf_path = check_pbf_archives(city_name, archive_path, download)?;
 
     // Then give kdtree to PoiLoader, or create it inside of PoiLoader from nodes from csv
     // Search nearest neighbor in loop in PoiLoader
     let mut osm_loader_builder = PoiLoaderBuilder::default();
 
+    osm_loader_builder.target_crs(target_crs).pbf_path(pbf_path);
+    if let Some(df) = nodes_to_match_df {
+        osm_loader_builder.nodes_to_match_polars(df.clone());
+    }
+    if let Some(path) = nodes_to_match_path {
+        osm_loader_builder.nodes_to_match_parquet(path);
+    }
+    Ok(osm_loader_builder)
+}
+
+/// Builder for a network loader of `city_name`, restricted to `geometry`.
+fn osm_loader_builder<Filter: EdgeFilter + Default>(
+    city_name: &str,
+    geometry: impl FilterGeometry,
+    archive_path: &str,
+    download: bool,
+    edge_filter: Filter,
+    target_crs: u16,
+) -> ExtractorResult<OsmLoaderBuilder<Filter>> {
+    let pbf_path = check_pbf_archives(city_name, archive_path, download)?;
+    let mut osm_loader_builder = OsmLoaderBuilder::default();
     osm_loader_builder
-        .target_crs(4839u16)
-        .filter_geometry(bounding_box)
+        .edge_filter(edge_filter)
+        .target_crs(target_crs)
         .pbf_path(pbf_path);
-    match nodes_to_match_df {
-        Some(df) => {
-            osm_loader_builder.nodes_to_match_polars(df.clone());
-        }
-        _ => (),
+    if let Some(polygon) = geometry.into_filter_polygon(city_name) {
+        osm_loader_builder.filter_geometry(polygon);
     }
-    match nodes_to_match_path {
-        Some(path) => {
-            osm_loader_builder.nodes_to_match_parquet(path);
-        }
-        None => (),
+    Ok(osm_loader_builder)
+}
+
+/// Extracts the POIs inside `geometry` without writing any output files,
+/// with distances to the matched nodes in the CRS of EPSG code
+/// `target_crs`, usually [`TARGET_CRS`]. With `admin_areas`, the
+/// `admin_area` column holds the smallest area containing each POI, see
+/// [`assign_admin_areas`]; otherwise it is null.
+#[allow(clippy::too_many_arguments)]
+pub fn load_osm_pois_df(
+    city_name: &str,
+    geometry: impl FilterGeometry,
+    archive_path: &str,
+    nodes_to_match_path: Option<&str>,
+    nodes_to_match_df: Option<&DataFrame>,
+    download: bool,
+    admin_areas: Option<&[AdminArea]>,
+    target_crs: u16,
+) -> ExtractorResult<DataFrame> {
+    let mut osm_loader_builder = poi_loader_builder(
+        city_name,
+        archive_path,
+        nodes_to_match_path,
+        nodes_to_match_df,
+        download,
+        target_crs,
+    )?;
+    if let Some(polygon) = geometry.into_filter_polygon(city_name) {
+        osm_loader_builder.filter_geometry(polygon);
     }
-    let osm_loader = osm_loader_builder.build().expect("Parameter missing");
-    let outpath_nodes = get_node_outpath(outpath, city_name, "pois");
+    let osm_loader = osm_loader_builder.build()?;
 
-    let nodes = osm_loader.load_graph();
-    let output_file_nodes = File::create(outpath_nodes).unwrap();
-    let node_writer = BufWriter::new(output_file_nodes);
+    let mut nodes = osm_loader.load_graph();
+    if let Some(areas) = admin_areas {
+        assign_admin_areas(&mut nodes, areas);
+    }
+    Ok(poi_dataframe(nodes)?)
+}
 
-    let parquet_writer = polars_io::parquet::write::ParquetWriter::new(node_writer);
-    let mut df = struct_to_dataframe!(
-        nodes,
+/// POI frame as returned by [`load_osm_pois_df`], e.g. for POIs of a
+/// [`PoiLoaderBuilder`] configured beyond the options of the former.
+pub fn poi_dataframe(pois: Vec<Poi>) -> PolarsResult<DataFrame> {
+    struct_to_dataframe!(
+        pois,
         [
             osm_id,
             lat,
             long,
             nearest_osm_node,
             dist_to_nearest,
-            poi_type
+            dist_to_nearest_haversine,
+            poi_type,
+            name,
+            admin_area,
+            raw_tag,
+            matched_key,
+            matched_value,
+            wheelchair,
+            opening_hours
         ]
     )
-    .unwrap();
-    parquet_writer.finish(&mut df).unwrap();
+}
+
+/// Extracts the POIs inside `geometry` and writes them to
+/// `<city>_pois_nodes.parquet`, labeled with `admin_areas` as in
+/// [`load_osm_pois_df`]. With `split_by_type`, every POI type is
+/// additionally written to its own `<city>_pois_<type>.parquet`, and with
+/// `sidecar` the [`ExtractionMetadata`] to `<city>_pois.json`.
+#[allow(clippy::too_many_arguments)]
+pub fn _load_osm_pois(
+    city_name: &str,
+    geometry: impl FilterGeometry,
+    archive_path: &str,
+    nodes_to_match_path: Option<&str>,
+    nodes_to_match_df: Option<&DataFrame>,
+    outpath: &str,
+    download: bool,
+    split_by_type: bool,
+    sidecar: bool,
+    naming: Option<&OutputNaming>,
+    admin_areas: Option<&[AdminArea]>,
+) -> DataFrame {
+    let mut df = load_osm_pois_df(
+        city_name,
+        geometry,
+        archive_path,
+        nodes_to_match_path,
+        nodes_to_match_df,
+        download,
+        admin_areas,
+        TARGET_CRS,
+    )
+    .expect("Error in loading");
+    let outpath_nodes = get_node_outpath(outpath, naming, city_name, "pois");
+    write_parquet(&mut df, &outpath_nodes, None).expect("Error in writing");
+    if split_by_type {
+        write_pois_by_type(&df, outpath, naming, city_name).expect("Error in writing");
+    }
+    if sidecar {
+        let metadata = ExtractionMetadata {
+            source_pbf: check_pbf_archives(city_name, archive_path, false).ok(),
+            osm_data_timestamp: source_timestamp(city_name, archive_path)
+                .map(|timestamp| timestamp.to_rfc3339()),
+            bbox: frame_extent(&df).expect("POIs have f64 coordinates"),
+            node_count: df.height(),
+            ..ExtractionMetadata::new(city_name, "pois")
+        };
+        let outdir = outpath_nodes.parent().unwrap_or(Path::new(""));
+        write_sidecar(&metadata, outdir).expect("Error in writing");
+    }
     df
 }
 
+/// Writes the rows of every `poi_type` of `df_pois` to
+/// `<city>_pois_<type>.parquet`.
+fn write_pois_by_type(
+    df_pois: &DataFrame,
+    outpath: &str,
+    naming: Option<&OutputNaming>,
+    city_name: &str,
+) -> PolarsResult<()> {
+    let poi_types = df_pois.column("poi_type")?.str()?;
+    let names: BTreeSet<&str> = poi_types.into_iter().flatten().collect();
+    for name in names {
+        let mut df = df_pois.filter(&poi_types.equal(name))?;
+        let path = get_outpath(outpath, naming, city_name, "pois", &name.to_lowercase());
+        write_parquet(&mut df, &path, None)?;
+    }
+    Ok(())
+}
+
+/// Extracts POIs for several disjoint study areas in one pass and returns the
+/// number of POIs per `(polygon_index, poi_type)` without writing any output
+/// files. Only POIs inside `geometry` are read, e.g. the bounding box of all
+/// `geometries`.
+pub fn load_osm_poi_counts_df(
+    city_name: &str,
+    geometry: impl FilterGeometry,
+    geometries: Vec<impl FilterGeometry>,
+    archive_path: &str,
+    nodes_to_match_path: Option<&str>,
+    nodes_to_match_df: Option<&DataFrame>,
+    download: bool,
+) -> ExtractorResult<DataFrame> {
+    let polygons: Vec<Polygon> = geometries
+        .into_iter()
+        .map(FilterGeometry::into_polygon)
+        .collect();
+    let mut osm_loader_builder = poi_loader_builder(
+        city_name,
+        archive_path,
+        nodes_to_match_path,
+        nodes_to_match_df,
+        download,
+        TARGET_CRS,
+    )?;
+    if let Some(polygon) = geometry.into_filter_polygon(city_name) {
+        osm_loader_builder.filter_geometry(polygon);
+    }
+    let osm_loader = osm_loader_builder.build()?;
+
+    let pois = osm_loader.load_graph();
+    let counts = count_pois_per_polygon(&pois, &polygons);
+    let df = struct_to_dataframe!(counts, [polygon_index, poi_type, count])?;
+    Ok(df)
+}
+
+/// Extracts POIs for several disjoint study areas in one pass and returns the
+/// number of POIs per `(polygon_index, poi_type)`.
+#[allow(clippy::too_many_arguments)]
+pub fn _load_osm_poi_counts(
+    city_name: &str,
+    geometry: impl FilterGeometry,
+    geometries: Vec<impl FilterGeometry>,
+    archive_path: &str,
+    nodes_to_match_path: Option<&str>,
+    nodes_to_match_df: Option<&DataFrame>,
+    outpath: &str,
+    download: bool,
+    naming: Option<&OutputNaming>,
+) -> DataFrame {
+    let mut df = load_osm_poi_counts_df(
+        city_name,
+        geometry,
+        geometries,
+        archive_path,
+        nodes_to_match_path,
+        nodes_to_match_df,
+        download,
+    )
+    .expect("Error in loading");
+    let outpath_counts = get_outpath(outpath, naming, city_name, "pois", "counts");
+    write_parquet(&mut df, &outpath_counts, None).expect("Error in writing");
+    df
+}
+
+/// Extracts the `network_type` network inside `geometry` without writing
+/// any output files, with lengths in the CRS of EPSG code `target_crs`,
+/// usually [`TARGET_CRS`]. `reverse_edges` only applies to cycling, walking
+/// edges are always reversed and driving edges follow the oneway tags.
+///
+/// Fails for [`NetworkType::Pois`], see [`load_osm_pois_df`] instead.
+pub fn load_osm_network_df(
+    city_name: &str,
+    network_type: NetworkType,
+    geometry: impl FilterGeometry,
+    archive_path: &str,
+    download: bool,
+    target_crs: u16,
+    reverse_edges: bool,
+) -> ExtractorResult<(DataFrame, DataFrame)> {
+    match network_type {
+        NetworkType::Walking => {
+            let osm_loader: Loader<WalkingEdgeFilter> = osm_loader_builder(
+                city_name,
+                geometry,
+                archive_path,
+                download,
+                WalkingEdgeFilter::default(),
+                target_crs,
+            )?
+            .reverse_edges(true)
+            .surface_quality(true)
+            .step_count(true)
+            .build()?;
+            let (df_nodes, mut df_edges) = osm_loader.load_graph_df()?;
+            let walk_time = walk_time_series(df_edges.column("length")?)?;
+            df_edges.with_column(walk_time)?;
+            Ok((df_nodes, df_edges))
+        }
+        NetworkType::Cycling => {
+            let osm_loader: Loader<BicycleEdgeFilter> = osm_loader_builder(
+                city_name,
+                geometry,
+                archive_path,
+                download,
+                BicycleEdgeFilter::default(),
+                target_crs,
+            )?
+            .reverse_edges(reverse_edges)
+            .surface_quality(true)
+            .cycle_infrastructure(true)
+            .build()?;
+            Ok(osm_loader.load_graph_df()?)
+        }
+        NetworkType::Driving => {
+            let osm_loader: Loader<CarEdgeFilter> = osm_loader_builder(
+                city_name,
+                geometry,
+                archive_path,
+                download,
+                CarEdgeFilter::default(),
+                target_crs,
+            )?
+            .ferry_time(true)
+            .build()?;
+            Ok(osm_loader.load_graph_df()?)
+        }
+        NetworkType::Pois => Err("POIs are no network, see load_osm_pois_df".into()),
+    }
+}
+
+/// Extracts the walking network inside `geometry` without writing any
+/// output files.
+pub fn load_osm_walking_df(
+    city_name: &str,
+    geometry: impl FilterGeometry,
+    archive_path: &str,
+    download: bool,
+) -> ExtractorResult<(DataFrame, DataFrame)> {
+    load_osm_network_df(
+        city_name,
+        NetworkType::Walking,
+        geometry,
+        archive_path,
+        download,
+        TARGET_CRS,
+        true,
+    )
+}
+
+/// `walk_time` column of the walking edges: walking time in seconds per
+/// edge after [`ToblerWalkingTime`]. The graph carries no elevation yet, so
+/// every edge is treated as flat.
+pub fn walk_time_series(lengths: &Column) -> PolarsResult<Series> {
+    let tobler = ToblerWalkingTime::new(Rc::new(Distance_), Rc::new(Ascent));
+    let map: MetricIndices = [(Distance_.name(), 0), (Ascent.name(), 1)].into();
+    let times = lengths
+        .f64()?
+        .into_no_null_iter()
+        .map(|length| CostMetric::<f64>::calc(&tobler, &[length, 0.0], &map))
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|e| PolarsError::ComputeError(format!("{e:?}").into()))?;
+    Ok(Series::new("walk_time".into(), times))
+}
+
 pub fn _load_osm_walking(
     city_name: &str,
-    geometry_vec: Vec<(f64, f64)>,
+    geometry: impl FilterGeometry,
     archive_path: &str,
     outpath: &str,
     download: bool,
+    naming: Option<&OutputNaming>,
 ) -> (DataFrame, DataFrame) {
-    let bounding_box = Polygon::new(LineString::from(geometry_vec), vec![]);
-    let pbf_path = check_pbf_archives(city_name, archive_path, download)
-        .expect("Download failed or Path not existing");
-    let osm_loader: Loader<WalkingEdgeFilter> = OsmLoaderBuilder::default()
-        .edge_filter(WalkingEdgeFilter)
-        .target_crs(4839u16)
-        .filter_geometry(bounding_box)
-        .pbf_path(pbf_path)
-        .reverse_edges(true)
-        .build()
-        .expect("Parameter missing");
-    let outpath_edges = get_edge_outpath(outpath, city_name, "walking");
-    let outpath_nodes = get_node_outpath(outpath, city_name, "walking");
+    let (mut df_nodes, mut df_edges) =
+        load_osm_walking_df(city_name, geometry, archive_path, download).expect("Error in loading");
+    let outpath_edges = get_edge_outpath(outpath, naming, city_name, "walking");
+    let outpath_nodes = get_node_outpath(outpath, naming, city_name, "walking");
 
     // let graph = flate2::write::GzEncoder::new(graph, flate2::Compression::best());
-    write_graph(&osm_loader, &outpath_edges, &outpath_nodes).expect("Error in writing")
+    write_graph(
+        &mut df_nodes,
+        &mut df_edges,
+        &outpath_edges,
+        &outpath_nodes,
+        source_timestamp(city_name, archive_path),
+        None,
+        None,
+    )
+    .expect("Error in writing");
+    (df_nodes, df_edges)
 }
+
+/// Extracts the cycling network inside `geometry` without writing any
+/// output files.
+pub fn load_osm_cycling_df(
+    city_name: &str,
+    geometry: impl FilterGeometry,
+    reverse_edges: &bool,
+    archive_path: &str,
+    download: bool,
+) -> ExtractorResult<(DataFrame, DataFrame)> {
+    load_osm_network_df(
+        city_name,
+        NetworkType::Cycling,
+        geometry,
+        archive_path,
+        download,
+        TARGET_CRS,
+        *reverse_edges,
+    )
+}
+
 pub fn _load_osm_cycling(
     city_name: &str,
-    geometry_vec: Vec<(f64, f64)>,
+    geometry: impl FilterGeometry,
     reverse_edges: &bool,
     archive_path: &str,
     outpath: &str,
     download: bool,
+    naming: Option<&OutputNaming>,
 ) -> (DataFrame, DataFrame) {
-    let bounding_box = Polygon::new(LineString::from(geometry_vec), vec![]);
-    let pbf_path = check_pbf_archives(city_name, archive_path, download)
-        .expect("Download failed or Path not existing");
-    let osm_loader: Loader<BicycleEdgeFilter> = OsmLoaderBuilder::default()
-        .edge_filter(BicycleEdgeFilter)
-        .target_crs(4839u16)
-        .filter_geometry(bounding_box)
-        .pbf_path(pbf_path)
-        .reverse_edges(*reverse_edges)
-        .build()
-        .expect("Parameter missing");
-    let outpath_edges = get_edge_outpath(outpath, city_name, "cycling");
-    let outpath_nodes = get_node_outpath(outpath, city_name, "cycling");
+    let (mut df_nodes, mut df_edges) =
+        load_osm_cycling_df(city_name, geometry, reverse_edges, archive_path, download)
+            .expect("Error in loading");
+    let outpath_edges = get_edge_outpath(outpath, naming, city_name, "cycling");
+    let outpath_nodes = get_node_outpath(outpath, naming, city_name, "cycling");
     // let graph = flate2::write::GzEncoder::new(graph, flate2::Compression::best());
-    write_graph(&osm_loader, &outpath_edges, &outpath_nodes).expect("Error in writing")
+    write_graph(
+        &mut df_nodes,
+        &mut df_edges,
+        &outpath_edges,
+        &outpath_nodes,
+        source_timestamp(city_name, archive_path),
+        None,
+        None,
+    )
+    .expect("Error in writing");
+    (df_nodes, df_edges)
 }
+
+/// Extracts the driving network inside `geometry`, with a `ferry_time`
+/// column for ferry edges, without writing any output files.
+pub fn load_osm_driving_df(
+    city_name: &str,
+    geometry: impl FilterGeometry,
+    archive_path: &str,
+    download: bool,
+) -> ExtractorResult<(DataFrame, DataFrame)> {
+    load_osm_network_df(
+        city_name,
+        NetworkType::Driving,
+        geometry,
+        archive_path,
+        download,
+        TARGET_CRS,
+        false,
+    )
+}
+
 pub fn _load_osm_driving(
     city_name: &str,
-    geometry_vec: Vec<(f64, f64)>,
+    geometry: impl FilterGeometry,
     archive_path: &str,
     outpath: &str,
     download: bool,
+    naming: Option<&OutputNaming>,
 ) -> (DataFrame, DataFrame) {
-    let bounding_box = Polygon::new(LineString::from(geometry_vec), vec![]);
-    let pbf_path = check_pbf_archives(city_name, archive_path, download)
-        .expect("Download failed or Path not existing");
-    let osm_loader: Loader<CarEdgeFilter> = OsmLoaderBuilder::default()
-        .edge_filter(CarEdgeFilter)
-        .target_crs(4839u16)
-        .filter_geometry(bounding_box)
-        .pbf_path(pbf_path)
-        .build()
-        .expect("Parameter missing");
-    let outpath_edges = get_edge_outpath(outpath, city_name, "driving");
-    let outpath_nodes = get_node_outpath(outpath, city_name, "driving");
+    let (mut df_nodes, mut df_edges) =
+        load_osm_driving_df(city_name, geometry, archive_path, download).expect("Error in loading");
+    let outpath_edges = get_edge_outpath(outpath, naming, city_name, "driving");
+    let outpath_nodes = get_node_outpath(outpath, naming, city_name, "driving");
     // let graph = flate2::write::GzEncoder::new(graph, flate2::Compression::best());
-    write_graph(&osm_loader, &outpath_edges, &outpath_nodes).expect("Error in writing")
+    write_graph(
+        &mut df_nodes,
+        &mut df_edges,
+        &outpath_edges,
+        &outpath_nodes,
+        source_timestamp(city_name, archive_path),
+        None,
+        None,
+    )
+    .expect("Error in writing");
+    (df_nodes, df_edges)
 }
 
-fn write_graph<T: EdgeFilter>(
-    l: &Loader<T>,
-    outpath_edges: &str,
-    outpath_nodes: &str,
-) -> Result<(DataFrame, DataFrame), io::Error> {
-    let output_file_edges = File::create(outpath_edges).unwrap();
-    let output_file_nodes = File::create(outpath_nodes).unwrap();
-    let edge_writer = BufWriter::new(output_file_edges);
-    let node_writer = BufWriter::new(output_file_nodes);
+/// Extracts the truck network inside `geometry`, i.e. the driving network
+/// with `maxweight`, `maxheight`, `maxwidth` and `hgv_no` edge columns,
+/// without writing any output files.
+pub fn load_osm_truck_df(
+    city_name: &str,
+    geometry: impl FilterGeometry,
+    archive_path: &str,
+    download: bool,
+) -> ExtractorResult<(DataFrame, DataFrame)> {
+    let osm_loader: Loader<TruckEdgeFilter> = osm_loader_builder(
+        city_name,
+        geometry,
+        archive_path,
+        download,
+        TruckEdgeFilter::default(),
+        TARGET_CRS,
+    )?
+    .vehicle_restrictions(true)
+    .build()?;
+    Ok(osm_loader.load_graph_df()?)
+}
 
-    let (nodes, edges) = l.load_graph();
+pub fn _load_osm_truck(
+    city_name: &str,
+    geometry: impl FilterGeometry,
+    archive_path: &str,
+    outpath: &str,
+    download: bool,
+    naming: Option<&OutputNaming>,
+) -> (DataFrame, DataFrame) {
+    let (mut df_nodes, mut df_edges) =
+        load_osm_truck_df(city_name, geometry, archive_path, download).expect("Error in loading");
+    let outpath_edges = get_edge_outpath(outpath, naming, city_name, "truck");
+    let outpath_nodes = get_node_outpath(outpath, naming, city_name, "truck");
+    write_graph(
+        &mut df_nodes,
+        &mut df_edges,
+        &outpath_edges,
+        &outpath_nodes,
+        source_timestamp(city_name, archive_path),
+        None,
+        None,
+    )
+    .expect("Error in writing");
+    (df_nodes, df_edges)
+}
 
-    info!("Writing edges to {}", outpath_edges);
+/// Extracts the bridleway network inside `geometry`, see
+/// [`HorseEdgeFilter`], without writing any output files.
+pub fn load_osm_horse_df(
+    city_name: &str,
+    geometry: impl FilterGeometry,
+    archive_path: &str,
+    download: bool,
+) -> ExtractorResult<(DataFrame, DataFrame)> {
+    let osm_loader: Loader<HorseEdgeFilter> = osm_loader_builder(
+        city_name,
+        geometry,
+        archive_path,
+        download,
+        HorseEdgeFilter,
+        TARGET_CRS,
+    )?
+    .build()?;
+    Ok(osm_loader.load_graph_df()?)
+}
 
-    let mut parquet_writer = polars_io::parquet::write::ParquetWriter::new(edge_writer);
-    let mut df_edges: polars::prelude::DataFrame =
-        struct_to_dataframe!(edges, [source_osm, dest_osm, length]).unwrap();
-    parquet_writer.finish(&mut df_edges).unwrap();
+pub fn _load_osm_horse(
+    city_name: &str,
+    geometry: impl FilterGeometry,
+    archive_path: &str,
+    outpath: &str,
+    download: bool,
+    naming: Option<&OutputNaming>,
+) -> (DataFrame, DataFrame) {
+    let (mut df_nodes, mut df_edges) =
+        load_osm_horse_df(city_name, geometry, archive_path, download).expect("Error in loading");
+    let outpath_edges = get_edge_outpath(outpath, naming, city_name, "horse");
+    let outpath_nodes = get_node_outpath(outpath, naming, city_name, "horse");
+    write_graph(
+        &mut df_nodes,
+        &mut df_edges,
+        &outpath_edges,
+        &outpath_nodes,
+        source_timestamp(city_name, archive_path),
+        None,
+        None,
+    )
+    .expect("Error in writing");
+    (df_nodes, df_edges)
+}
+
+/// Rows per parquet row group. Row groups are the unit Polars serializes in
+/// parallel, so large frames are split into several of them.
+const PARQUET_ROW_GROUP_SIZE: usize = 512 * 512;
+
+/// Parquet key-value metadata holding the replication time of the source
+/// pbf file in RFC 3339 format.
+pub const OSM_DATA_TIMESTAMP_KEY: &str = "osm_data_timestamp";
+
+/// Parquet key-value metadata holding the extent of the graph's nodes as
+/// `min_lon,min_lat,max_lon,max_lat`, see [`LoadStats`](crate::pbfextractor::pbf::LoadStats).
+pub const GRAPH_EXTENT_KEY: &str = "graph_extent";
 
-    info!("Writing nodes to {}", outpath_nodes);
-    parquet_writer = polars_io::parquet::write::ParquetWriter::new(node_writer);
-    let mut df_nodes = struct_to_dataframe!(nodes, [osm_id, lat, long]).unwrap();
-    parquet_writer.finish(&mut df_nodes).unwrap();
-    Ok((df_nodes, df_edges))
+/// Replication time of the local pbf file of `city_name`, see
+/// [`Loader::source_timestamp`].
+fn source_timestamp(city_name: &str, archive_path: &str) -> Option<DateTime<Utc>> {
+    let pbf_path = check_pbf_archives(city_name, archive_path, false).ok()?;
+    pbf_source_timestamp(&pbf_path).ok().flatten()
+}
+
+/// Description of an extraction, written as `<city>_<mode>.json` sidecar
+/// next to its output files for reproducibility.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExtractionMetadata {
+    pub city: String,
+    /// Extracted network or `pois`, e.g. `driving`.
+    pub mode: String,
+    /// EPSG code of the CRS distances were calculated in.
+    pub target_crs: u16,
+    /// Type of the [`EdgeFilter`] the edges passed, without module paths,
+    /// e.g. `CarEdgeFilter`. `None` for POIs.
+    pub edge_filter: Option<String>,
+    pub source_pbf: Option<PathBuf>,
+    /// Replication time of the source pbf file in RFC 3339 format.
+    pub osm_data_timestamp: Option<String>,
+    /// Extent of the extracted nodes.
+    pub bbox: Option<BoundingBox>,
+    pub node_count: usize,
+    /// `None` for POIs.
+    pub edge_count: Option<usize>,
+}
+
+impl ExtractionMetadata {
+    /// Metadata of an empty extraction of `city` in [`TARGET_CRS`].
+    pub fn new(city: &str, mode: &str) -> ExtractionMetadata {
+        ExtractionMetadata {
+            city: city.to_owned(),
+            mode: mode.to_owned(),
+            target_crs: TARGET_CRS,
+            edge_filter: None,
+            source_pbf: None,
+            osm_data_timestamp: None,
+            bbox: None,
+            node_count: 0,
+            edge_count: None,
+        }
+    }
+
+    /// Metadata of an empty extraction of `city` whose edges pass `Filter`.
+    pub fn with_edge_filter<Filter: EdgeFilter>(city: &str, mode: &str) -> ExtractionMetadata {
+        ExtractionMetadata {
+            edge_filter: Some(short_type_name::<Filter>()),
+            ..ExtractionMetadata::new(city, mode)
+        }
+    }
+}
+
+/// Name of `T` with the module paths of it and its type parameters
+/// stripped, e.g. `Or<BicycleEdgeFilter, WalkingEdgeFilter>`.
+fn short_type_name<T>() -> String {
+    let mut name = String::new();
+    let mut path = String::new();
+    for c in std::any::type_name::<T>().chars() {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            path.push(c);
+        } else {
+            name.push_str(path.rsplit("::").next().unwrap_or_default());
+            path.clear();
+            name.push(c);
+        }
+    }
+    name.push_str(path.rsplit("::").next().unwrap_or_default());
+    name
+}
+
+/// Writes `metadata` to `<city>_<mode>.json` in `outdir`.
+fn write_sidecar(metadata: &ExtractionMetadata, outdir: &Path) -> PolarsResult<()> {
+    let path = outdir.join(format!(
+        "{}_{}.json",
+        metadata.city.to_lowercase(),
+        metadata.mode
+    ));
+    create_dir_all(outdir)?;
+    info!("Writing extraction metadata to {}", path.display());
+    serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), metadata)
+        .map_err(|e| PolarsError::ComputeError(e.to_string().into()))
+}
+
+/// Writes the edges and nodes files concurrently, as they are independent.
+/// Both files carry `source_timestamp` as [`OSM_DATA_TIMESTAMP_KEY`]
+/// metadata if it is known, and the extent of the `lat` and `long` node
+/// columns as [`GRAPH_EXTENT_KEY`] if the nodes have coordinates.
+///
+/// `column_names` maps column names to the names written instead, e.g.
+/// `source_osm` to `from_node`. Columns missing from it keep their names,
+/// and the frames themselves are not renamed.
+///
+/// With `sidecar`, its city, mode, CRS, edge filter and source are written
+/// next to the nodes file together with the counts, extent and
+/// `source_timestamp` of the graph, see [`ExtractionMetadata`].
+pub fn write_graph(
+    df_nodes: &mut DataFrame,
+    df_edges: &mut DataFrame,
+    outpath_edges: &Path,
+    outpath_nodes: &Path,
+    source_timestamp: Option<DateTime<Utc>>,
+    column_names: Option<&HashMap<String, String>>,
+    sidecar: Option<&ExtractionMetadata>,
+) -> PolarsResult<()> {
+    let extent = frame_extent(df_nodes)?;
+    if let Some(sidecar) = sidecar {
+        let metadata = ExtractionMetadata {
+            osm_data_timestamp: source_timestamp.map(|timestamp| timestamp.to_rfc3339()),
+            bbox: extent,
+            node_count: df_nodes.height(),
+            edge_count: Some(df_edges.height()),
+            ..sidecar.clone()
+        };
+        write_sidecar(&metadata, outpath_nodes.parent().unwrap_or(Path::new("")))?;
+    }
+    let mut renamed_nodes;
+    let mut renamed_edges;
+    let (df_nodes, df_edges) = match column_names {
+        Some(names) => {
+            renamed_nodes = renamed_columns(df_nodes, names)?;
+            renamed_edges = renamed_columns(df_edges, names)?;
+            (&mut renamed_nodes, &mut renamed_edges)
+        }
+        None => (df_nodes, df_edges),
+    };
+    let mut entries = Vec::new();
+    if let Some(timestamp) = source_timestamp {
+        entries.push((OSM_DATA_TIMESTAMP_KEY.to_owned(), timestamp.to_rfc3339()));
+    }
+    if let Some(e) = extent {
+        entries.push((
+            GRAPH_EXTENT_KEY.to_owned(),
+            format!("{},{},{},{}", e.min_lon, e.min_lat, e.max_lon, e.max_lat),
+        ));
+    }
+    let metadata = (!entries.is_empty()).then(|| KeyValueMetadata::from_static(entries));
+    std::thread::scope(|scope| {
+        let edges = scope.spawn(|| {
+            info!("Writing edges to {}", outpath_edges.display());
+            write_parquet(df_edges, outpath_edges, metadata.clone())
+        });
+        info!("Writing nodes to {}", outpath_nodes.display());
+        let nodes = write_parquet(df_nodes, outpath_nodes, metadata.clone());
+        edges.join().expect("edge writer thread panicked")?;
+        nodes
+    })
+}
+
+/// Extent of the `lat` and `long` columns of `df_nodes`, `None` if either
+/// is missing or the frame is empty.
+fn frame_extent(df_nodes: &DataFrame) -> PolarsResult<Option<BoundingBox>> {
+    let (Ok(lat), Ok(long)) = (df_nodes.column("lat"), df_nodes.column("long")) else {
+        return Ok(None);
+    };
+    let (lat, long) = (
+        lat.cast(&DataType::Float64)?,
+        long.cast(&DataType::Float64)?,
+    );
+    let (lat, long) = (lat.f64()?, long.f64()?);
+    Ok(match (lat.min(), lat.max(), long.min(), long.max()) {
+        (Some(min_lat), Some(max_lat), Some(min_lon), Some(max_lon)) => {
+            Some(BoundingBox::new(min_lon, min_lat, max_lon, max_lat))
+        }
+        _ => None,
+    })
+}
+
+/// Copy of `df` with its columns renamed according to `names`.
+fn renamed_columns(df: &DataFrame, names: &HashMap<String, String>) -> PolarsResult<DataFrame> {
+    let columns: Vec<String> = df
+        .get_column_names()
+        .iter()
+        .map(|name| {
+            names
+                .get(name.as_str())
+                .cloned()
+                .unwrap_or_else(|| name.to_string())
+        })
+        .collect();
+    let mut renamed = df.clone();
+    renamed.set_column_names(columns)?;
+    Ok(renamed)
+}
+
+fn write_parquet(
+    df: &mut DataFrame,
+    outpath: &Path,
+    metadata: Option<KeyValueMetadata>,
+) -> PolarsResult<()> {
+    if let Some(parent) = outpath.parent() {
+        create_dir_all(parent)?;
+    }
+    let writer = BufWriter::new(File::create(outpath)?);
+    polars_io::parquet::write::ParquetWriter::new(writer)
+        .with_row_group_size(Some(PARQUET_ROW_GROUP_SIZE))
+        .with_key_value_metadata(metadata)
+        .set_parallel(true)
+        .finish(df)?;
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::pbfextractor::test_utils::{node, tags, way, write_pbf_fixture};
+
+    #[test]
+    fn test_network_type_names() {
+        for network_type in NetworkType::ALL {
+            let name = network_type.to_string();
+            assert_eq!(name.parse::<NetworkType>(), Ok(network_type));
+        }
+        assert_eq!(NetworkType::Pois.to_string(), "pois");
+        assert_eq!("Cycling".parse::<NetworkType>(), Ok(NetworkType::Cycling));
+        let error = "sailing".parse::<NetworkType>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown network type sailing, expected one of walking, cycling, driving, pois"
+        );
+    }
+
+    #[test]
+    fn test_walk_time_series() {
+        let lengths = Column::new("length".into(), [0.0, 100.0]);
+        let times = walk_time_series(&lengths).unwrap();
+        assert_eq!(times.name().as_str(), "walk_time");
+        let times: Vec<f64> = times.f64().unwrap().into_no_null_iter().collect();
+        assert_eq!(times[0], 0.0);
+        // Tobler's function walks 6 * e^(-0.175) km/h on flat ground.
+        let expected = 100.0 / (6.0 * (-0.175f64).exp() / 3.6);
+        assert!((times[1] - expected).abs() < 1e-9);
+    }
 
     #[test]
     fn integration_test_osm_walking() {
@@ -222,9 +1014,23 @@ mod tests {
             (3.22183, 51.20391),
         ];
         let (nodes, edges) =
-            _load_osm_walking("Bruegge", bounding_box.clone(), "data", "test", false);
+            _load_osm_walking("Bruegge", bounding_box.clone(), "data", "test", false, None);
         assert_eq!(nodes.shape(), (1813, 3));
-        assert_eq!(edges.shape(), (4032, 3));
+        assert_eq!(edges.height(), 4032);
+        assert_eq!(
+            edges.get_column_names_str(),
+            vec![
+                "source_osm",
+                "dest_osm",
+                "length",
+                "way_id",
+                "surface_quality",
+                "rough_paving",
+                "step_count",
+                "walk_time"
+            ]
+        );
+        assert_eq!(edges.column("walk_time").unwrap().null_count(), 0);
     }
 
     #[test]
@@ -243,9 +1049,13 @@ mod tests {
             "data",
             "test",
             false,
+            None,
         );
         assert_eq!(nodes.shape(), (1653, 3));
-        assert_eq!(edges.shape(), (3325, 3));
+        assert_eq!(edges.shape(), (3325, 8));
+        for column in ["surface_quality", "rough_paving", "infra_class", "dismount"] {
+            assert!(edges.column(column).is_ok(), "missing {column}");
+        }
     }
 
     #[test]
@@ -258,9 +1068,13 @@ mod tests {
             (3.22183, 51.20391),
         ];
         let (nodes, edges) =
-            _load_osm_driving("Bruegge", bounding_box.clone(), "data", "test", false);
+            _load_osm_driving("Bruegge", bounding_box.clone(), "data", "test", false, None);
         assert_eq!(nodes.shape(), (470, 3));
-        assert_eq!(edges.shape(), (659, 3));
+        assert_eq!(edges.height(), 659);
+        assert_eq!(
+            edges.get_column_names_str(),
+            vec!["source_osm", "dest_osm", "length", "way_id", "ferry_time"]
+        );
     }
 
     #[test]
@@ -280,7 +1094,791 @@ mod tests {
             None,
             "test",
             false,
+            false,
+            false,
+            None,
+            None,
+        );
+        assert_eq!(result.height(), 287);
+        assert_eq!(
+            result.get_column_names_str(),
+            vec![
+                "osm_id",
+                "lat",
+                "long",
+                "nearest_osm_node",
+                "dist_to_nearest",
+                "dist_to_nearest_haversine",
+                "poi_type",
+                "name",
+                "admin_area",
+                "raw_tag",
+                "matched_key",
+                "matched_value",
+                "wheelchair",
+                "opening_hours"
+            ]
+        );
+    }
+
+    #[test]
+    fn integration_test_osm_pois_split_by_type() {
+        use polars_io::SerReader;
+
+        let out = std::env::temp_dir().join("osmtools_pois_split_by_type");
+        std::fs::create_dir_all(&out).unwrap();
+        let result = _load_osm_pois(
+            "Bruegge",
+            crate::bounding_box::BoundingBox::new(3.22183, 51.20391, 3.23663, 51.20887),
+            "data",
+            None,
+            None,
+            out.to_str().unwrap(),
+            false,
+            true,
+            false,
+            None,
+            None,
+        );
+        let poi_types: BTreeSet<&str> = result
+            .column("poi_type")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect();
+        assert!(poi_types.len() > 1);
+        let mut total = 0;
+        for poi_type in poi_types {
+            let path = out.join(format!("bruegge_pois_{}.parquet", poi_type.to_lowercase()));
+            let file = File::open(&path).unwrap();
+            total += polars_io::parquet::read::ParquetReader::new(file)
+                .finish()
+                .unwrap()
+                .height();
+        }
+        assert_eq!(total, result.height());
+    }
+
+    #[test]
+    fn test_in_memory_api_writes_no_files() {
+        let archive_path = std::env::temp_dir().join("osmtools_in_memory_api");
+        std::fs::create_dir_all(&archive_path).unwrap();
+        std::fs::copy("data/bruegge.osm.pbf", archive_path.join("bruegge.osm.pbf")).unwrap();
+        let bounding_box = vec![
+            (3.22183, 51.20391),
+            (3.23663, 51.20391),
+            (3.23663, 51.20887),
+            (3.22183, 51.20887),
+            (3.22183, 51.20391),
+        ];
+        let (nodes, edges) = load_osm_walking_df(
+            "Bruegge",
+            bounding_box,
+            archive_path.to_str().unwrap(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(nodes.width(), 3);
+        assert_eq!(edges.width(), 8);
+        let files: Vec<_> = std::fs::read_dir(&archive_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, vec!["bruegge.osm.pbf"]);
+    }
+
+    /// A chain of `rows` nodes and edges.
+    fn chain_graph(rows: usize) -> (DataFrame, DataFrame) {
+        let nodes = polars::df!(
+            "osm_id" => (0..rows as u64).collect::<Vec<u64>>(),
+            "lat" => (0..rows).map(|i| 51.0 + i as f64 * 1e-7).collect::<Vec<f64>>(),
+            "long" => (0..rows).map(|i| 3.0 + i as f64 * 1e-7).collect::<Vec<f64>>(),
+        )
+        .unwrap();
+        let edges = polars::df!(
+            "source_osm" => (0..rows as u64).collect::<Vec<u64>>(),
+            "dest_osm" => (1..=rows as u64).collect::<Vec<u64>>(),
+            "length" => (0..rows).map(|i| i as f64 * 0.5).collect::<Vec<f64>>(),
+        )
+        .unwrap();
+        (nodes, edges)
+    }
+
+    #[test]
+    fn test_write_graph_roundtrip() {
+        use polars_io::SerReader;
+
+        // Several row groups, the last one partial.
+        let (mut nodes, mut edges) = chain_graph(2 * PARQUET_ROW_GROUP_SIZE + 1);
+        let (expected_nodes, expected_edges) = (nodes.clone(), edges.clone());
+        let dir = std::env::temp_dir().join("osmtools_write_graph_parquet");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (edge_path, node_path) = (dir.join("edges.parquet"), dir.join("nodes.parquet"));
+
+        write_graph(
+            &mut nodes, &mut edges, &edge_path, &node_path, None, None, None,
+        )
+        .unwrap();
+        assert!(nodes.equals(&expected_nodes));
+        assert!(edges.equals(&expected_edges));
+
+        let read = |path: &Path| {
+            polars_io::parquet::read::ParquetReader::new(File::open(path).unwrap())
+                .finish()
+                .unwrap()
+        };
+        assert!(read(&edge_path).equals(&expected_edges));
+        assert!(read(&node_path).equals(&expected_nodes));
+    }
+
+    #[test]
+    #[ignore = "writes 2M rows, run with --ignored to check the write time"]
+    fn test_write_graph_large_frame_time() {
+        let (mut nodes, mut edges) = chain_graph(2_000_000);
+        let dir = std::env::temp_dir().join("osmtools_write_graph_large");
+        std::fs::create_dir_all(&dir).unwrap();
+        let start = std::time::Instant::now();
+        write_graph(
+            &mut nodes,
+            &mut edges,
+            &dir.join("edges.parquet"),
+            &dir.join("nodes.parquet"),
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(start.elapsed() < std::time::Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_write_graph_source_timestamp() {
+        use polars_io::SerReader;
+
+        let mut nodes = polars::df!("osm_id" => [1u64, 2]).unwrap();
+        let mut edges = polars::df!("source_osm" => [1u64], "dest_osm" => [2u64]).unwrap();
+        let dir = std::env::temp_dir().join("osmtools_write_graph_timestamp");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (edge_path, node_path) = (dir.join("edges.parquet"), dir.join("nodes.parquet"));
+        let timestamp = DateTime::from_timestamp(1_700_000_000, 0);
+        write_graph(
+            &mut nodes, &mut edges, &edge_path, &node_path, timestamp, None, None,
+        )
+        .unwrap();
+
+        for path in [&edge_path, &node_path] {
+            let mut reader =
+                polars_io::parquet::read::ParquetReader::new(File::open(path).unwrap());
+            let metadata = reader.get_metadata().unwrap();
+            let value = metadata
+                .key_value_metadata()
+                .iter()
+                .flatten()
+                .find(|kv| kv.key == OSM_DATA_TIMESTAMP_KEY)
+                .and_then(|kv| kv.value.clone());
+            assert_eq!(value.as_deref(), Some("2023-11-14T22:13:20+00:00"));
+        }
+    }
+
+    #[test]
+    fn test_write_graph_extent() {
+        use polars_io::SerReader;
+
+        let mut nodes = polars::df!(
+            "osm_id" => [1u64, 2, 3],
+            "lat" => [51.2, 51.1, 51.3],
+            "long" => [3.3, 3.4, 3.2],
+        )
+        .unwrap();
+        let mut edges = polars::df!("source_osm" => [1u64], "dest_osm" => [2u64]).unwrap();
+        let dir = std::env::temp_dir().join("osmtools_write_graph_extent");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (edge_path, node_path) = (dir.join("edges.parquet"), dir.join("nodes.parquet"));
+        write_graph(
+            &mut nodes, &mut edges, &edge_path, &node_path, None, None, None,
+        )
+        .unwrap();
+
+        for path in [&edge_path, &node_path] {
+            let mut reader =
+                polars_io::parquet::read::ParquetReader::new(File::open(path).unwrap());
+            let metadata = reader.get_metadata().unwrap();
+            let value = metadata
+                .key_value_metadata()
+                .iter()
+                .flatten()
+                .find(|kv| kv.key == GRAPH_EXTENT_KEY)
+                .and_then(|kv| kv.value.clone());
+            assert_eq!(value.as_deref(), Some("3.2,51.1,3.4,51.3"));
+        }
+    }
+
+    #[test]
+    fn test_short_type_name() {
+        use crate::pbfextractor::metrics::Or;
+
+        assert_eq!(short_type_name::<CarEdgeFilter>(), "CarEdgeFilter");
+        assert_eq!(
+            short_type_name::<Or<BicycleEdgeFilter, WalkingEdgeFilter>>(),
+            "Or<BicycleEdgeFilter, WalkingEdgeFilter>"
+        );
+        assert_eq!(ExtractionMetadata::new("Bruegge", "pois").edge_filter, None);
+    }
+
+    #[test]
+    fn integration_test_driving_sidecar() {
+        let (mut nodes, mut edges) = load_osm_driving_df(
+            "Bruegge",
+            BoundingBox::new(3.22183, 51.20391, 3.23663, 51.20887),
+            "data",
+            false,
+        )
+        .unwrap();
+        let dir = std::env::temp_dir().join("osmtools_driving_sidecar");
+        std::fs::create_dir_all(&dir).unwrap();
+        let metadata = ExtractionMetadata {
+            source_pbf: Some(PathBuf::from("data/bruegge.osm.pbf")),
+            ..ExtractionMetadata::with_edge_filter::<CarEdgeFilter>("Bruegge", "driving")
+        };
+        write_graph(
+            &mut nodes,
+            &mut edges,
+            &dir.join("bruegge_driving_edges.parquet"),
+            &dir.join("bruegge_driving_nodes.parquet"),
+            source_timestamp("Bruegge", "data"),
+            None,
+            Some(&metadata),
+        )
+        .unwrap();
+
+        let file = File::open(dir.join("bruegge_driving.json")).unwrap();
+        let sidecar: serde_json::Value = serde_json::from_reader(file).unwrap();
+        assert_eq!(sidecar["target_crs"], 4839);
+        assert_eq!(sidecar["node_count"], nodes.height());
+        assert_eq!(sidecar["edge_count"], edges.height());
+        assert_eq!(sidecar["mode"], "driving");
+        assert_eq!(sidecar["edge_filter"], "CarEdgeFilter");
+        assert_eq!(sidecar["source_pbf"], "data/bruegge.osm.pbf");
+        assert!(sidecar["osm_data_timestamp"].is_string());
+        assert!(sidecar["bbox"]["min_lon"].as_f64().unwrap() >= 3.22183);
+    }
+
+    #[test]
+    fn test_write_graph_column_names() {
+        use polars_io::SerReader;
+
+        let mut nodes = polars::df!("osm_id" => [1u64, 2]).unwrap();
+        let mut edges = polars::df!(
+            "source_osm" => [1u64],
+            "dest_osm" => [2u64],
+            "length" => [3.5],
+        )
+        .unwrap();
+        let dir = std::env::temp_dir().join("osmtools_write_graph_column_names");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (edge_path, node_path) = (dir.join("edges.parquet"), dir.join("nodes.parquet"));
+        let names: HashMap<String, String> = [
+            ("source_osm", "from_node"),
+            ("dest_osm", "to_node"),
+            ("length", "weight"),
+        ]
+        .into_iter()
+        .map(|(old, new)| (old.to_owned(), new.to_owned()))
+        .collect();
+        write_graph(
+            &mut nodes,
+            &mut edges,
+            &edge_path,
+            &node_path,
+            None,
+            Some(&names),
+            None,
+        )
+        .unwrap();
+
+        let read = |path: &Path| {
+            polars_io::parquet::read::ParquetReader::new(File::open(path).unwrap())
+                .finish()
+                .unwrap()
+        };
+        let written = read(&edge_path);
+        assert_eq!(
+            written.get_column_names_str(),
+            vec!["from_node", "to_node", "weight"]
+        );
+        assert_eq!(
+            written.column("weight").unwrap().f64().unwrap().get(0),
+            Some(3.5)
+        );
+        assert_eq!(read(&node_path).get_column_names_str(), vec!["osm_id"]);
+        assert_eq!(
+            edges.get_column_names_str(),
+            vec!["source_osm", "dest_osm", "length"]
         );
-        assert_eq!(result.shape(), (287, 6));
+    }
+
+    #[test]
+    fn test_outpath_with_trailing_separator() {
+        let expected = Path::new("test").join("bruegge_walking_edges.parquet");
+        let path = get_edge_outpath("test/", None, "Bruegge", "walking");
+        assert_eq!(path, expected);
+        assert_eq!(path.to_str(), expected.to_str());
+        assert_eq!(
+            get_node_outpath("test", None, "Bruegge", "walking"),
+            Path::new("test").join("bruegge_walking_nodes.parquet")
+        );
+    }
+
+    #[test]
+    fn test_walking_walk_time() {
+        use crate::bounding_box::BoundingBox;
+
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2060, 3.2270),
+            way(10, &[("highway", "footway")], &[1, 2]),
+        ];
+        let pbf_path = write_pbf_fixture("osmtools_walk_time.osm.pbf", objects);
+        let archive = pbf_path.parent().unwrap().to_str().unwrap();
+        let (_, edges) = load_osm_walking_df(
+            "osmtools_walk_time",
+            BoundingBox::new(3.2, 51.2, 3.3, 51.21),
+            archive,
+            false,
+        )
+        .unwrap();
+        assert_eq!(edges.height(), 2);
+        let expected = walk_time_series(edges.column("length").unwrap()).unwrap();
+        assert!(edges
+            .column("walk_time")
+            .unwrap()
+            .as_materialized_series()
+            .equals(&expected));
+        let times = edges.column("walk_time").unwrap().f64().unwrap();
+        assert!(times.into_no_null_iter().all(|time| time > 0.0));
+    }
+
+    #[test]
+    fn test_empty_bounding_box() {
+        use crate::bounding_box::BoundingBox;
+
+        use polars::prelude::DataType;
+        use polars_io::SerReader;
+
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2060, 3.2270),
+            way(10, &[("highway", "residential")], &[1, 2]),
+        ];
+        let pbf_path = write_pbf_fixture("osmtools_empty_bbox.osm.pbf", objects);
+        let archive = pbf_path.parent().unwrap().to_str().unwrap();
+        let out = std::env::temp_dir().join("osmtools_empty_bbox_out");
+        std::fs::create_dir_all(&out).unwrap();
+        // A patch of the North Sea without any OSM data.
+        let ocean = BoundingBox::new(2.5, 51.6, 2.51, 51.61);
+
+        let (nodes, edges) = _load_osm_walking(
+            "osmtools_empty_bbox",
+            ocean,
+            archive,
+            out.to_str().unwrap(),
+            false,
+            None,
+        );
+        assert_eq!(nodes.height(), 0);
+        assert_eq!(edges.height(), 0);
+        assert_eq!(nodes.column("osm_id").unwrap().dtype(), &DataType::UInt64);
+        assert_eq!(nodes.column("lat").unwrap().dtype(), &DataType::Float64);
+        assert_eq!(edges.column("length").unwrap().dtype(), &DataType::Float64);
+        assert_eq!(
+            edges.column("surface_quality").unwrap().dtype(),
+            &DataType::Float64
+        );
+        assert_eq!(
+            edges.column("walk_time").unwrap().dtype(),
+            &DataType::Float64
+        );
+
+        let read = |suffix: &str| {
+            let path = out.join(format!("osmtools_empty_bbox_{suffix}"));
+            polars_io::parquet::read::ParquetReader::new(File::open(path).unwrap())
+                .finish()
+                .unwrap()
+        };
+        assert_eq!(read("walking_nodes.parquet").schema(), nodes.schema());
+        assert_eq!(read("walking_edges.parquet").schema(), edges.schema());
+
+        let pois = _load_osm_pois(
+            "osmtools_empty_bbox",
+            ocean,
+            archive,
+            None,
+            Some(&nodes),
+            out.to_str().unwrap(),
+            false,
+            false,
+            false,
+            None,
+            None,
+        );
+        assert_eq!(pois.height(), 0);
+        assert_eq!(pois.column("poi_type").unwrap().dtype(), &DataType::String);
+        assert_eq!(read("pois_nodes.parquet").schema(), pois.schema());
+    }
+
+    #[test]
+    fn test_osm_truck() {
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            node(3, 51.2060, 3.2270),
+            way(
+                10,
+                &[("highway", "residential"), ("maxweight", "7.5")],
+                &[1, 2],
+            ),
+            way(11, &[("highway", "residential"), ("hgv", "no")], &[2, 3]),
+        ];
+        let pbf_path = write_pbf_fixture("osmtools_truck.osm.pbf", objects);
+        let archive = pbf_path.parent().unwrap().to_str().unwrap();
+        let out = std::env::temp_dir().join("osmtools_truck_out");
+        std::fs::create_dir_all(&out).unwrap();
+        let bounding_box = vec![
+            (3.22, 51.20),
+            (3.23, 51.20),
+            (3.23, 51.21),
+            (3.22, 51.21),
+            (3.22, 51.20),
+        ];
+
+        let (nodes, edges) = _load_osm_truck(
+            "osmtools_truck",
+            bounding_box,
+            archive,
+            out.to_str().unwrap(),
+            false,
+            None,
+        );
+        assert_eq!(nodes.height(), 3);
+        assert_eq!(edges.height(), 4);
+        let sorted = edges
+            .sort(["way_id", "source_osm"], Default::default())
+            .unwrap();
+        let max_weights: Vec<Option<f64>> = sorted
+            .column("maxweight")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(max_weights, vec![Some(7.5), Some(7.5), None, None]);
+        let hgv_no: Vec<Option<bool>> = sorted
+            .column("hgv_no")
+            .unwrap()
+            .bool()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(
+            hgv_no,
+            vec![Some(false), Some(false), Some(true), Some(true)]
+        );
+        assert_eq!(sorted.column("maxheight").unwrap().null_count(), 4);
+        assert!(out.join("osmtools_truck_truck_edges.parquet").exists());
+    }
+
+    #[test]
+    fn test_osm_horse() {
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            node(3, 51.2060, 3.2270),
+            way(10, &[("highway", "bridleway")], &[1, 2]),
+            way(11, &[("highway", "footway")], &[2, 3]),
+        ];
+        let pbf_path = write_pbf_fixture("osmtools_horse.osm.pbf", objects);
+        let archive = pbf_path.parent().unwrap().to_str().unwrap();
+        let out = std::env::temp_dir().join("osmtools_horse_out");
+        std::fs::create_dir_all(&out).unwrap();
+
+        let (nodes, edges) = _load_osm_horse(
+            "osmtools_horse",
+            BoundingBox::new(3.22, 51.20, 3.23, 51.21),
+            archive,
+            out.to_str().unwrap(),
+            false,
+            None,
+        );
+        assert_eq!(nodes.height(), 2);
+        let way_ids: Vec<Option<u64>> = edges
+            .column("way_id")
+            .unwrap()
+            .u64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(way_ids, vec![Some(10), Some(10)]);
+        assert!(out.join("osmtools_horse_horse_edges.parquet").exists());
+    }
+
+    #[test]
+    fn test_poi_names() {
+        use crate::bounding_box::NoGeometryFilter;
+        use osmpbfreader::{Node, NodeId, OsmObj};
+
+        let poi = |id: i64, poi_tags: &[(&str, &str)]| {
+            OsmObj::Node(Node {
+                id: NodeId(id),
+                tags: tags(poi_tags),
+                decimicro_lat: 512_050_000,
+                decimicro_lon: 32_250_000,
+            })
+        };
+        let objects = vec![
+            poi(
+                1,
+                &[("amenity", "restaurant"), ("name", "De Vlaamsche Pot")],
+            ),
+            poi(2, &[("amenity", "cafe"), ("name:en", "The Old Cafe")]),
+            poi(3, &[("amenity", "restaurant")]),
+        ];
+        let pbf_path = write_pbf_fixture("osmtools_poi_names.osm.pbf", objects);
+        let archive = pbf_path.parent().unwrap().to_str().unwrap();
+
+        let pois = load_osm_pois_df(
+            "osmtools_poi_names",
+            NoGeometryFilter,
+            archive,
+            None,
+            None,
+            false,
+            None,
+            TARGET_CRS,
+        )
+        .unwrap()
+        .sort(["osm_id"], Default::default())
+        .unwrap();
+        let names: Vec<Option<&str>> = pois
+            .column("name")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(
+            names,
+            vec![Some("De Vlaamsche Pot"), Some("The Old Cafe"), None]
+        );
+    }
+
+    #[test]
+    fn test_poi_dataframe() {
+        let mut townhall = Poi::new(1, 51.2, 3.2, None, "Other".into());
+        townhall.raw_tag = Some("amenity=townhall".into());
+        let mut shop = Poi::new(2, 51.2, 3.2, None, "Shops".into());
+        shop.opening_hours = Some("Mo-Sa 09:00-18:00".into());
+        let df = poi_dataframe(vec![townhall, shop]).unwrap();
+        let strings = |name: &str| -> Vec<Option<String>> {
+            df.column(name)
+                .unwrap()
+                .str()
+                .unwrap()
+                .into_iter()
+                .map(|value| value.map(str::to_owned))
+                .collect()
+        };
+        assert_eq!(
+            strings("raw_tag"),
+            vec![Some("amenity=townhall".into()), None]
+        );
+        assert_eq!(
+            strings("opening_hours"),
+            vec![None, Some("Mo-Sa 09:00-18:00".into())]
+        );
+    }
+
+    #[test]
+    fn test_poi_counts_geometry() {
+        use osmpbfreader::{Node, NodeId, OsmObj};
+
+        let poi = |id: i64, long: f64| {
+            OsmObj::Node(Node {
+                id: NodeId(id),
+                tags: tags(&[("amenity", "restaurant")]),
+                decimicro_lat: 512_050_000,
+                decimicro_lon: (long * 1e7) as i32,
+            })
+        };
+        // Two POIs in the first area, one of them outside of the filter box,
+        // and one in the second.
+        let objects = vec![poi(1, 3.2205), poi(2, 3.2245), poi(3, 3.2305)];
+        let pbf_path = write_pbf_fixture("osmtools_poi_counts.osm.pbf", objects);
+        let archive = pbf_path.parent().unwrap().to_str().unwrap();
+
+        let counts = load_osm_poi_counts_df(
+            "osmtools_poi_counts",
+            BoundingBox::new(3.221, 51.2, 3.24, 51.21),
+            vec![
+                BoundingBox::new(3.22, 51.2, 3.225, 51.21),
+                BoundingBox::new(3.23, 51.2, 3.235, 51.21),
+            ],
+            archive,
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        let column = |name: &str| -> Vec<u32> {
+            counts
+                .column(name)
+                .unwrap()
+                .u32()
+                .unwrap()
+                .into_no_null_iter()
+                .collect()
+        };
+        assert_eq!(column("polygon_index"), vec![0, 1]);
+        assert_eq!(column("count"), vec![1, 1]);
+    }
+
+    #[test]
+    fn test_poi_admin_areas() {
+        use crate::bounding_box::NoGeometryFilter;
+        use geo::{polygon, MultiPolygon};
+        use osmpbfreader::{Node, NodeId, OsmObj};
+
+        let objects = vec![OsmObj::Node(Node {
+            id: NodeId(1),
+            tags: tags(&[("amenity", "restaurant")]),
+            decimicro_lat: 512_050_000,
+            decimicro_lon: 32_250_000,
+        })];
+        let pbf_path = write_pbf_fixture("osmtools_poi_admin_areas.osm.pbf", objects);
+        let archive = pbf_path.parent().unwrap().to_str().unwrap();
+        let areas = vec![AdminArea {
+            name: "Brugge".into(),
+            geometry: MultiPolygon::new(vec![polygon![
+                (x: 3.2, y: 51.2),
+                (x: 3.3, y: 51.2),
+                (x: 3.3, y: 51.3),
+                (x: 3.2, y: 51.3),
+            ]]),
+        }];
+        let admin_areas = |areas: Option<&[AdminArea]>| {
+            let pois = load_osm_pois_df(
+                "osmtools_poi_admin_areas",
+                NoGeometryFilter,
+                archive,
+                None,
+                None,
+                false,
+                areas,
+                TARGET_CRS,
+            )
+            .unwrap();
+            pois.column("admin_area")
+                .unwrap()
+                .str()
+                .unwrap()
+                .into_iter()
+                .map(|area| area.map(str::to_owned))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(admin_areas(Some(&areas)), vec![Some("Brugge".to_owned())]);
+        assert_eq!(admin_areas(None), vec![None]);
+    }
+
+    #[test]
+    fn test_default_bbox_without_geometry() {
+        use crate::bounding_box::BoundingBox;
+
+        let street = |id: i64, nodes: &[i64]| way(id, &[("highway", "residential")], nodes);
+        // A street in Bruges and one in Ghent, outside of the default box of
+        // Bruges.
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2060, 3.2270),
+            node(3, 51.0540, 3.7170),
+            node(4, 51.0550, 3.7190),
+            street(10, &[1, 2]),
+            street(11, &[3, 4]),
+        ];
+        let archive = std::env::temp_dir().join("osmtools_default_bbox");
+        std::fs::create_dir_all(&archive).unwrap();
+        let pbf_path = write_pbf_fixture("osmtools_default_bbox/bruegge.osm.pbf", objects);
+        std::fs::copy(&pbf_path, archive.join("hogwarts.osm.pbf")).unwrap();
+        let archive = archive.to_str().unwrap();
+
+        let (nodes, _) =
+            load_osm_driving_df("Bruegge", None::<BoundingBox>, archive, false).unwrap();
+        assert_eq!(nodes.height(), 2);
+        let (nodes, _) =
+            load_osm_driving_df("Hogwarts", None::<BoundingBox>, archive, false).unwrap();
+        assert_eq!(nodes.height(), 4);
+    }
+
+    #[test]
+    fn test_no_geometry_filter() {
+        use crate::bounding_box::NoGeometryFilter;
+
+        let street = |id: i64, nodes: &[i64]| way(id, &[("highway", "residential")], nodes);
+        // Streets in Bruges and Ghent, far apart.
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2060, 3.2270),
+            node(3, 51.0540, 3.7170),
+            node(4, 51.0550, 3.7190),
+            street(10, &[1, 2]),
+            street(11, &[3, 4]),
+        ];
+        let archive = std::env::temp_dir().join("osmtools_no_geometry_filter");
+        std::fs::create_dir_all(&archive).unwrap();
+        write_pbf_fixture("osmtools_no_geometry_filter/bruegge.osm.pbf", objects);
+        let out = archive.join("out");
+        std::fs::create_dir_all(&out).unwrap();
+
+        let (nodes, edges) = _load_osm_walking(
+            "Bruegge",
+            NoGeometryFilter,
+            archive.to_str().unwrap(),
+            out.to_str().unwrap(),
+            false,
+            None,
+        );
+        assert_eq!(nodes.height(), 4);
+        assert_eq!(edges.height(), 4);
+    }
+
+    #[test]
+    fn test_output_naming_template() {
+        use crate::bounding_box::NoGeometryFilter;
+
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2060, 3.2270),
+            way(10, &[("highway", "residential")], &[1, 2]),
+        ];
+        let archive = std::env::temp_dir().join("osmtools_output_naming");
+        std::fs::create_dir_all(&archive).unwrap();
+        write_pbf_fixture("osmtools_output_naming/bruegge.osm.pbf", objects);
+        let out = archive.join("out");
+        let _ = std::fs::remove_dir_all(&out);
+
+        let naming = OutputNaming::new("{mode}/{city}/{kind}").unwrap();
+        _load_osm_driving(
+            "Bruegge",
+            NoGeometryFilter,
+            archive.to_str().unwrap(),
+            out.to_str().unwrap(),
+            false,
+            Some(&naming),
+        );
+        assert!(out.join("driving/bruegge/edges.parquet").is_file());
+        assert!(out.join("driving/bruegge/nodes.parquet").is_file());
+        assert!(!out.join("bruegge_driving_edges.parquet").exists());
+
+        assert!(OutputNaming::new("{city}_{mode}").is_err());
     }
 }
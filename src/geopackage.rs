@@ -0,0 +1,353 @@
+//! GeoPackage output: the nodes and edges of a graph in a single SQLite file,
+//! with point and line geometries GIS tools like QGIS display directly.
+use polars::prelude::{AnyValue, Column, DataFrame, DataType, PolarsError};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
+use std::path::Path;
+
+/// Coordinates are written as WGS84 longitude / latitude.
+const SRS_ID: i32 = 4326;
+
+const WGS84_DEFINITION: &str = r#"GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AUTHORITY["EPSG","4326"]]"#;
+
+/// Metadata tables required by the GeoPackage 1.3 specification.
+const SCHEMA: &str = "
+    PRAGMA application_id = 1196444487;
+    PRAGMA user_version = 10300;
+    CREATE TABLE gpkg_spatial_ref_sys (
+        srs_name TEXT NOT NULL,
+        srs_id INTEGER PRIMARY KEY,
+        organization TEXT NOT NULL,
+        organization_coordsys_id INTEGER NOT NULL,
+        definition TEXT NOT NULL,
+        description TEXT
+    );
+    CREATE TABLE gpkg_contents (
+        table_name TEXT NOT NULL PRIMARY KEY,
+        data_type TEXT NOT NULL,
+        identifier TEXT UNIQUE,
+        description TEXT DEFAULT '',
+        last_change DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+        min_x DOUBLE,
+        min_y DOUBLE,
+        max_x DOUBLE,
+        max_y DOUBLE,
+        srs_id INTEGER REFERENCES gpkg_spatial_ref_sys (srs_id)
+    );
+    CREATE TABLE gpkg_geometry_columns (
+        table_name TEXT NOT NULL REFERENCES gpkg_contents (table_name),
+        column_name TEXT NOT NULL,
+        geometry_type_name TEXT NOT NULL,
+        srs_id INTEGER NOT NULL REFERENCES gpkg_spatial_ref_sys (srs_id),
+        z TINYINT NOT NULL,
+        m TINYINT NOT NULL,
+        PRIMARY KEY (table_name, column_name),
+        UNIQUE (table_name)
+    );
+    INSERT INTO gpkg_spatial_ref_sys VALUES
+        ('Undefined cartesian SRS', -1, 'NONE', -1, 'undefined', NULL),
+        ('Undefined geographic SRS', 0, 'NONE', 0, 'undefined', NULL);
+";
+
+#[derive(Debug)]
+pub enum GeoPackageError {
+    Sqlite(rusqlite::Error),
+    Polars(PolarsError),
+    Io(std::io::Error),
+}
+
+impl Error for GeoPackageError {}
+impl Display for GeoPackageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeoPackageError::Sqlite(error) => write!(f, "Could not write GeoPackage: {error}"),
+            GeoPackageError::Polars(error) => write!(f, "Invalid graph DataFrame: {error}"),
+            GeoPackageError::Io(error) => write!(f, "Could not write GeoPackage: {error}"),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for GeoPackageError {
+    fn from(error: rusqlite::Error) -> Self {
+        GeoPackageError::Sqlite(error)
+    }
+}
+
+impl From<std::io::Error> for GeoPackageError {
+    fn from(error: std::io::Error) -> Self {
+        GeoPackageError::Io(error)
+    }
+}
+
+impl From<PolarsError> for GeoPackageError {
+    fn from(error: PolarsError) -> Self {
+        GeoPackageError::Polars(error)
+    }
+}
+
+/// Writes the node and edge DataFrames of `load_graph_df` into a new
+/// GeoPackage at `path`, replacing an existing file.
+///
+/// The `nodes` table gets a `POINT` geometry from `lat` and `long`, the
+/// `edges` table a `LINESTRING` from its source to its destination node. All
+/// scalar columns are copied; nested columns such as `via` are skipped.
+pub fn write_graph_gpkg(
+    df_nodes: &DataFrame,
+    df_edges: &DataFrame,
+    path: &Path,
+) -> Result<(), GeoPackageError> {
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let ids = df_nodes.column("osm_id")?.u64()?;
+    let lats = df_nodes.column("lat")?.f64()?;
+    let longs = df_nodes.column("long")?.f64()?;
+    let points: Vec<Option<Vec<u8>>> = longs
+        .into_iter()
+        .zip(lats)
+        .map(|(long, lat)| Some(point_geometry(long?, lat?)))
+        .collect();
+    let coordinates: HashMap<u64, (f64, f64)> = ids
+        .into_iter()
+        .zip(longs.into_iter().zip(lats))
+        .filter_map(|(id, (long, lat))| Some((id?, (long?, lat?))))
+        .collect();
+    let sources = df_edges.column("source_osm")?.u64()?;
+    let dests = df_edges.column("dest_osm")?.u64()?;
+    let lines: Vec<Option<Vec<u8>>> = sources
+        .into_iter()
+        .zip(dests)
+        .map(|(source, dest)| {
+            let source = coordinates.get(&source?)?;
+            let dest = coordinates.get(&dest?)?;
+            Some(line_geometry(&[*source, *dest]))
+        })
+        .collect();
+    let bounds = bounds(coordinates.values());
+
+    let mut connection = Connection::open(path)?;
+    connection.execute_batch(SCHEMA)?;
+    let transaction = connection.transaction()?;
+    transaction.execute(
+        "INSERT INTO gpkg_spatial_ref_sys VALUES ('WGS 84 geodetic', ?1, 'EPSG', ?1, ?2, NULL)",
+        params![SRS_ID, WGS84_DEFINITION],
+    )?;
+    write_table(
+        &transaction,
+        "nodes",
+        "POINT",
+        df_nodes,
+        points.into_iter(),
+        bounds,
+    )?;
+    write_table(
+        &transaction,
+        "edges",
+        "LINESTRING",
+        df_edges,
+        lines.into_iter(),
+        bounds,
+    )?;
+    transaction.commit()?;
+    Ok(())
+}
+
+fn write_table(
+    transaction: &rusqlite::Transaction,
+    table: &str,
+    geometry_type: &str,
+    df: &DataFrame,
+    geometries: impl Iterator<Item = Option<Vec<u8>>>,
+    bounds: Option<[f64; 4]>,
+) -> Result<(), GeoPackageError> {
+    let columns: Vec<(&Column, &str)> = df
+        .get_columns()
+        .iter()
+        .filter_map(|column| sql_type(column.dtype()).map(|sql_type| (column, sql_type)))
+        .collect();
+    let definitions: Vec<String> = columns
+        .iter()
+        .map(|(column, sql_type)| format!("\"{}\" {sql_type}", column.name()))
+        .collect();
+    transaction.execute_batch(&format!(
+        "CREATE TABLE {table} (fid INTEGER PRIMARY KEY AUTOINCREMENT, geom {geometry_type}, {});",
+        definitions.join(", ")
+    ))?;
+    let [min_x, min_y, max_x, max_y] = bounds.map_or([None; 4], |b| b.map(Some));
+    transaction.execute(
+        "INSERT INTO gpkg_contents (table_name, data_type, identifier, min_x, min_y, max_x, max_y, srs_id)
+         VALUES (?1, 'features', ?1, ?2, ?3, ?4, ?5, ?6)",
+        params![table, min_x, min_y, max_x, max_y, SRS_ID],
+    )?;
+    transaction.execute(
+        "INSERT INTO gpkg_geometry_columns VALUES (?1, 'geom', ?2, ?3, 0, 0)",
+        params![table, geometry_type, SRS_ID],
+    )?;
+
+    let names: Vec<String> = columns
+        .iter()
+        .map(|(column, _)| format!("\"{}\"", column.name()))
+        .collect();
+    let placeholders: Vec<String> = (2..=columns.len() + 1).map(|i| format!("?{i}")).collect();
+    let mut insert = transaction.prepare(&format!(
+        "INSERT INTO {table} (geom, {}) VALUES (?1, {})",
+        names.join(", "),
+        placeholders.join(", ")
+    ))?;
+    for (row, geometry) in geometries.enumerate() {
+        let mut values = Vec::with_capacity(columns.len() + 1);
+        values.push(geometry.map_or(Value::Null, Value::Blob));
+        for (column, _) in &columns {
+            values.push(sql_value(column.get(row)?));
+        }
+        insert.execute(params_from_iter(values))?;
+    }
+    Ok(())
+}
+
+/// `[min_x, min_y, max_x, max_y]` of `coordinates`, `None` if empty.
+fn bounds<'a>(coordinates: impl Iterator<Item = &'a (f64, f64)>) -> Option<[f64; 4]> {
+    coordinates.fold(None, |bounds, &(x, y)| {
+        let [min_x, min_y, max_x, max_y] = bounds.unwrap_or([x, y, x, y]);
+        Some([min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)])
+    })
+}
+
+fn sql_type(dtype: &DataType) -> Option<&'static str> {
+    match dtype {
+        DataType::Boolean => Some("BOOLEAN"),
+        dtype if dtype.is_integer() => Some("INTEGER"),
+        dtype if dtype.is_float() => Some("DOUBLE"),
+        DataType::String => Some("TEXT"),
+        _ => None,
+    }
+}
+
+fn sql_value(value: AnyValue) -> Value {
+    match value {
+        AnyValue::Boolean(value) => Value::Integer(value.into()),
+        AnyValue::UInt8(value) => Value::Integer(value.into()),
+        AnyValue::UInt16(value) => Value::Integer(value.into()),
+        AnyValue::UInt32(value) => Value::Integer(value.into()),
+        AnyValue::UInt64(value) => Value::Integer(value as i64),
+        AnyValue::Int8(value) => Value::Integer(value.into()),
+        AnyValue::Int16(value) => Value::Integer(value.into()),
+        AnyValue::Int32(value) => Value::Integer(value.into()),
+        AnyValue::Int64(value) => Value::Integer(value),
+        AnyValue::Float32(value) => Value::Real(value.into()),
+        AnyValue::Float64(value) => Value::Real(value),
+        AnyValue::String(value) => Value::Text(value.to_owned()),
+        AnyValue::StringOwned(value) => Value::Text(value.to_string()),
+        _ => Value::Null,
+    }
+}
+
+/// GeoPackage binary header: magic, version 0, little endian without
+/// envelope, and the SRS id.
+fn geometry_header() -> Vec<u8> {
+    let mut blob = vec![b'G', b'P', 0, 0b0000_0001];
+    blob.extend_from_slice(&SRS_ID.to_le_bytes());
+    blob
+}
+
+fn point_geometry(x: f64, y: f64) -> Vec<u8> {
+    let mut blob = geometry_header();
+    blob.push(1);
+    blob.extend_from_slice(&1u32.to_le_bytes());
+    blob.extend_from_slice(&x.to_le_bytes());
+    blob.extend_from_slice(&y.to_le_bytes());
+    blob
+}
+
+fn line_geometry(points: &[(f64, f64)]) -> Vec<u8> {
+    let mut blob = geometry_header();
+    blob.push(1);
+    blob.extend_from_slice(&2u32.to_le_bytes());
+    blob.extend_from_slice(&(points.len() as u32).to_le_bytes());
+    for (x, y) in points {
+        blob.extend_from_slice(&x.to_le_bytes());
+        blob.extend_from_slice(&y.to_le_bytes());
+    }
+    blob
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::df;
+
+    fn count(connection: &Connection, table: &str) -> usize {
+        connection
+            .query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_write_graph_gpkg() {
+        let nodes = df!(
+            "osm_id" => [1u64, 2, 3],
+            "lat" => [51.20, 51.21, 51.22],
+            "long" => [3.22, 3.23, 3.24],
+        )
+        .unwrap();
+        let edges = df!(
+            "source_osm" => [1u64, 2],
+            "dest_osm" => [2u64, 3],
+            "length" => [1000.0, 1200.0],
+            "rough_paving" => [false, true],
+        )
+        .unwrap();
+        let path = std::env::temp_dir().join("osmtools_write_graph.gpkg");
+        write_graph_gpkg(&nodes, &edges, &path).unwrap();
+        // Overwriting an existing file works as well.
+        write_graph_gpkg(&nodes, &edges, &path).unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        assert_eq!(count(&connection, "nodes"), 3);
+        assert_eq!(count(&connection, "edges"), 2);
+        assert_eq!(count(&connection, "gpkg_geometry_columns"), 2);
+        let application_id: i64 = connection
+            .query_row("PRAGMA application_id", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(application_id, 0x4750_4B47);
+
+        let (geometry, rough_paving): (Vec<u8>, bool) = connection
+            .query_row(
+                "SELECT geom, rough_paving FROM edges WHERE source_osm = 2",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert!(rough_paving);
+        assert_eq!(geometry, line_geometry(&[(3.23, 51.21), (3.24, 51.22)]));
+        assert_eq!(&geometry[..2], b"GP");
+
+        let max_y: f64 = connection
+            .query_row(
+                "SELECT max_y FROM gpkg_contents WHERE table_name = 'nodes'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(max_y, 51.22);
+    }
+
+    #[test]
+    fn integration_test_driving_gpkg() {
+        let bounding_box =
+            crate::bounding_box::BoundingBox::new(3.22183, 51.20391, 3.23663, 51.20887);
+        let (nodes, edges) =
+            crate::extractor::load_osm_driving_df("Bruegge", bounding_box, "data", false).unwrap();
+        let path = std::env::temp_dir().join("osmtools_bruegge_driving.gpkg");
+        write_graph_gpkg(&nodes, &edges, &path).unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        assert_eq!(count(&connection, "nodes"), nodes.height());
+        assert_eq!(count(&connection, "edges"), edges.height());
+    }
+}
@@ -1,7 +1,11 @@
 pub mod boundary;
+pub mod bounding_box;
 pub mod extractor;
+pub mod geopackage;
 pub mod pbfextractor;
+pub mod sqlite;
 mod utils;
 
 pub use crate::utils::download;
 pub use crate::utils::nearest_node;
+pub use crate::utils::sources;
@@ -1,20 +1,184 @@
-use osmtools::extractor::_load_osm_walking;
-
-fn main() {
-    let bounding_box = vec![
-        (6.920048187831242, 50.95191352496238),
-        (6.914718454317494, 50.95139404663996),
-        (6.912244653478552, 50.94960750370038),
-        (6.9121243909494865, 50.947946386464025),
-        (6.913193727339149, 50.946630066581065),
-        (6.916816673975859, 50.94491473824968),
-        (6.91963482276293, 50.945722106445714),
-        (6.921678024030143, 50.94749420959562),
-        (6.926477367692996, 50.95170352804581),
-        (6.925129734228591, 50.95228632794152),
-        (6.920048187831242, 50.95191352496238),
-    ];
-    // _load_osm_pois("Koeln", bounding_box, "data", "data/koeln_walking_nodes.csv", "data", false);
-    _load_osm_walking("Koeln", bounding_box.clone(), "data", "data", false);
-    // load_osm_cycling("Koeln", bounding_box, "data", "data", false);
+use clap::{Parser, Subcommand, ValueEnum};
+use osmtools::boundary::load_admin_areas;
+use osmtools::bounding_box::{BoundingBox, FilterGeometry, NoGeometryFilter};
+use osmtools::extractor::{load_osm_network_df, load_osm_pois_df, NetworkType};
+use osmtools::geopackage::write_graph_gpkg;
+use osmtools::sources::default_bbox;
+use polars::frame::DataFrame;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(
+    name = "osmtools",
+    about = "Extract routable graphs and POIs from OSM extracts"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Extracts one network or the POIs of a city into parquet files.
+    Extract(ExtractArgs),
+}
+
+#[derive(Parser)]
+struct ExtractArgs {
+    /// City name, used to locate (or download) `<city>.osm.pbf` and to name
+    /// the output files.
+    #[arg(long)]
+    city: String,
+    /// One of `walking`, `cycling`, `driving` or `pois`.
+    #[arg(long)]
+    mode: NetworkType,
+    /// Filter box as `minlon,minlat,maxlon,maxlat`. Defaults to the box of
+    /// the city in the BBBike catalog.
+    #[arg(long, value_parser = parse_bbox, allow_hyphen_values = true)]
+    bbox: Option<BoundingBox>,
+    /// Keeps the whole pbf file instead of filtering it by a bounding box,
+    /// e.g. for an already clipped regional extract.
+    #[arg(long, conflicts_with = "bbox")]
+    no_geometry_filter: bool,
+    /// Directory the output files are written to.
+    #[arg(long, default_value = "data")]
+    out: PathBuf,
+    /// Directory containing (or receiving) the pbf archive.
+    #[arg(long, default_value = "data")]
+    archive: PathBuf,
+    /// Downloads the pbf archive from BBBike instead of reading it from
+    /// `--archive`.
+    #[arg(long)]
+    download: bool,
+    /// EPSG code of the projected CRS used for lengths and distances.
+    #[arg(long, default_value_t = 4839)]
+    crs: u16,
+    /// Parquet file of graph nodes the POIs are matched to. Without it, POIs
+    /// are extracted without a nearest node.
+    #[arg(long)]
+    match_nodes: Option<PathBuf>,
+    /// GeoJSON file of admin boundaries, e.g. suburbs, named by their `name`
+    /// property. Fills the `admin_area` column of the POIs with the smallest
+    /// boundary containing them.
+    #[arg(long)]
+    admin_areas: Option<PathBuf>,
+    /// Adds the reverse of every cycling edge.
+    #[arg(long)]
+    reverse_edges: bool,
+    /// Output format. A GeoPackage holds nodes and edges in a single
+    /// `<city>_<mode>.gpkg` file and is only available for networks.
+    #[arg(long, value_enum, default_value_t = Format::Parquet)]
+    format: Format,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Parquet,
+    Gpkg,
+}
+
+fn parse_bbox(value: &str) -> Result<BoundingBox, String> {
+    let coordinates = value
+        .split(',')
+        .map(|c| c.trim().parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|e| format!("invalid coordinate: {e}"))?;
+    match coordinates.as_slice() {
+        [min_lon, min_lat, max_lon, max_lat] => {
+            Ok(BoundingBox::new(*min_lon, *min_lat, *max_lon, *max_lat))
+        }
+        _ => Err("expected minlon,minlat,maxlon,maxlat".into()),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let Cli {
+        command: Command::Extract(args),
+    } = Cli::parse();
+    if matches!(args.mode, NetworkType::Pois) && args.format == Format::Gpkg {
+        return Err("GeoPackage output requires a network mode".into());
+    }
+    if args.no_geometry_filter {
+        return extract(&args, NoGeometryFilter);
+    }
+    let bbox = args.bbox.or_else(|| default_bbox(&args.city));
+    let bbox = bbox.ok_or_else(|| {
+        format!(
+            "no default bounding box for {}, pass --bbox or --no-geometry-filter",
+            args.city
+        )
+    })?;
+    extract(&args, bbox)
+}
+
+/// Runs the library extractor of `args.mode` and writes its output. Only the
+/// output handling is specific to the CLI, so the columns match
+/// [`load_osm_network_df`] and [`load_osm_pois_df`].
+fn extract(
+    args: &ExtractArgs,
+    geometry: impl FilterGeometry,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let archive = args.archive.to_string_lossy();
+    if matches!(args.mode, NetworkType::Pois) {
+        let admin_areas = match &args.admin_areas {
+            Some(path) => Some(load_admin_areas(path, "name")?),
+            None => None,
+        };
+        let match_nodes = args
+            .match_nodes
+            .as_ref()
+            .map(|path| path.to_string_lossy().into_owned());
+        let mut pois = load_osm_pois_df(
+            &args.city,
+            geometry,
+            &archive,
+            match_nodes.as_deref(),
+            None,
+            args.download,
+            admin_areas.as_deref(),
+            args.crs,
+        )?;
+        return write_parquet(&mut pois, &output_path(args, "_nodes.parquet"));
+    }
+    let (mut nodes, mut edges) = load_osm_network_df(
+        &args.city,
+        args.mode,
+        geometry,
+        &archive,
+        args.download,
+        args.crs,
+        args.reverse_edges,
+    )?;
+    match args.format {
+        Format::Parquet => {
+            write_parquet(&mut edges, &output_path(args, "_edges.parquet"))?;
+            write_parquet(&mut nodes, &output_path(args, "_nodes.parquet"))
+        }
+        Format::Gpkg => {
+            let path = output_path(args, ".gpkg");
+            std::fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))?;
+            Ok(write_graph_gpkg(&nodes, &edges, &path)?)
+        }
+    }
+}
+
+/// Same naming scheme as the library: `<city>_<mode><suffix>`, e.g.
+/// `bruegge_walking_edges.parquet`.
+fn output_path(args: &ExtractArgs, suffix: &str) -> PathBuf {
+    args.out.join(format!(
+        "{}_{}{}",
+        args.city.to_lowercase(),
+        args.mode,
+        suffix
+    ))
+}
+
+fn write_parquet(df: &mut DataFrame, path: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+    std::fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))?;
+    let writer = BufWriter::new(File::create(path)?);
+    polars_io::parquet::write::ParquetWriter::new(writer).finish(df)?;
+    Ok(())
 }
@@ -0,0 +1,103 @@
+//! GPX 1.1 export of extracted POIs, e.g. for loading them onto a handheld
+//! GPS for field verification.
+use super::node_pbf::Poi;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Writes `pois` as GPX 1.1 waypoints to `path`.
+///
+/// Each waypoint is named after its `poi_type`; the description carries the
+/// OSM id and, if the POIs were matched, the distance to the nearest graph
+/// node.
+pub fn write_pois_gpx(pois: &[Poi], path: &Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<gpx version="1.1" creator="osmtools" xmlns="http://www.topografix.com/GPX/1/1">"#
+    )?;
+    for poi in pois {
+        writeln!(writer, r#"  <wpt lat="{}" lon="{}">"#, poi.lat, poi.long)?;
+        writeln!(writer, "    <name>{}</name>", escape(&poi.poi_type))?;
+        match poi.dist_to_nearest {
+            Some(distance) => writeln!(
+                writer,
+                "    <desc>osm_id={} dist_to_nearest={}</desc>",
+                poi.osm_id, distance
+            )?,
+            None => writeln!(writer, "    <desc>osm_id={}</desc>", poi.osm_id)?,
+        }
+        writeln!(writer, "  </wpt>")?;
+    }
+    writeln!(writer, "</gpx>")?;
+    writer.flush()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pbfextractor::node_pbf::PoiLoaderBuilder;
+    use geo::{LineString, Polygon};
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("Food & <Drinks>"), "Food &amp; &lt;Drinks&gt;");
+    }
+
+    #[test]
+    fn integration_test_pois_gpx() {
+        let bounding_box = Polygon::new(
+            LineString::from(vec![
+                (3.22183, 51.20391),
+                (3.23663, 51.20391),
+                (3.23663, 51.20887),
+                (3.22183, 51.20887),
+                (3.22183, 51.20391),
+            ]),
+            vec![],
+        );
+        let pois = PoiLoaderBuilder::default()
+            .target_crs(4839u16)
+            .filter_geometry(bounding_box)
+            .pbf_path("data/bruegge.osm.pbf")
+            .nodes_to_match_parquet("test/bruegge_poitest_walking_nodes.parquet")
+            .build()
+            .unwrap()
+            .load_graph();
+        let path = Path::new("test/bruegge_pois.gpx");
+        write_pois_gpx(&pois, path).unwrap();
+
+        let mut reader = Reader::from_file(path).unwrap();
+        reader.config_mut().check_end_names = true;
+        let mut buf = Vec::new();
+        let (mut root, mut waypoints) = (None, 0);
+        loop {
+            match reader.read_event_into(&mut buf).unwrap() {
+                Event::Start(e) if root.is_none() => {
+                    let version = e.try_get_attribute("version").unwrap().unwrap();
+                    assert_eq!(version.value.as_ref(), b"1.1");
+                    root = Some(e.name().as_ref().to_vec());
+                }
+                Event::Start(e) if e.name().as_ref() == b"wpt" => {
+                    assert!(e.try_get_attribute("lat").unwrap().is_some());
+                    assert!(e.try_get_attribute("lon").unwrap().is_some());
+                    waypoints += 1;
+                }
+                Event::Eof => break,
+                _ => (),
+            }
+            buf.clear();
+        }
+        assert_eq!(root, Some(b"gpx".to_vec()));
+        assert_eq!(waypoints, pois.len());
+    }
+}
@@ -0,0 +1,301 @@
+//! Validation of the `OSMHeader` block every PBF file starts with.
+//!
+//! `osmpbfreader` skips the header block, so a file requiring features it
+//! cannot decode (e.g. `HistoricalInformation`) silently yields no or only
+//! some objects. Checking `required_features` up front turns that into an
+//! error naming the feature.
+//!
+//! [`IndexedBlobs`] reads the data blobs along with their headers, which
+//! `osmpbfreader` drops as well, to skip blobs outside of a bounding box.
+use chrono::{DateTime, Utc};
+use geo::{Intersects, Rect};
+use osmpbfreader::fileformat::{Blob, BlobHeader};
+use osmpbfreader::osmformat::{HeaderBBox, HeaderBlock};
+use protobuf::Message;
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+/// Required features `osmpbfreader` can decode.
+const SUPPORTED_FEATURES: &[&str] = &["OsmSchema-V0.6", "DenseNodes"];
+/// Upper bound for the size of a blob header, from the PBF specification.
+const MAX_BLOB_HEADER_SIZE: u32 = 64 * 1024;
+
+#[derive(Debug)]
+pub enum PbfHeaderError {
+    /// The file requires a feature the reader does not support.
+    UnsupportedPbfFeature(String),
+    /// The file does not start with a valid `OSMHeader` block.
+    InvalidHeader(String),
+    Io(io::Error),
+}
+
+impl Error for PbfHeaderError {}
+impl Display for PbfHeaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PbfHeaderError::UnsupportedPbfFeature(feature) => {
+                write!(f, "Unsupported required PBF feature {feature}")
+            }
+            PbfHeaderError::InvalidHeader(reason) => write!(f, "Invalid PBF header: {reason}"),
+            PbfHeaderError::Io(error) => write!(f, "Could not read PBF header: {error}"),
+        }
+    }
+}
+
+impl From<io::Error> for PbfHeaderError {
+    fn from(error: io::Error) -> Self {
+        PbfHeaderError::Io(error)
+    }
+}
+
+impl From<protobuf::Error> for PbfHeaderError {
+    fn from(error: protobuf::Error) -> Self {
+        PbfHeaderError::InvalidHeader(error.to_string())
+    }
+}
+
+/// Checks that every `required_features` entry of the PBF file at `path` is
+/// supported.
+pub fn validate_pbf_header(path: &Path) -> Result<(), PbfHeaderError> {
+    validate_pbf_header_from_reader(&mut BufReader::new(File::open(path)?))
+}
+
+/// Same as [`validate_pbf_header`] for a reader positioned at the start of
+/// the PBF data. Consumes the header block.
+pub fn validate_pbf_header_from_reader<R: Read>(reader: &mut R) -> Result<(), PbfHeaderError> {
+    let header = read_header_block(reader)?;
+    match header
+        .required_features
+        .iter()
+        .find(|feature| !SUPPORTED_FEATURES.contains(&feature.as_str()))
+    {
+        Some(feature) => Err(PbfHeaderError::UnsupportedPbfFeature(feature.clone())),
+        None => Ok(()),
+    }
+}
+
+/// Time the data of the PBF file at `path` was replicated at, from the
+/// `osmosis_replication_timestamp` header field. `None` if the header has
+/// no timestamp.
+pub fn pbf_source_timestamp(path: &Path) -> Result<Option<DateTime<Utc>>, PbfHeaderError> {
+    let header = read_header_block(&mut BufReader::new(File::open(path)?))?;
+    Ok(header
+        .has_osmosis_replication_timestamp()
+        .then(|| DateTime::from_timestamp(header.osmosis_replication_timestamp(), 0))
+        .flatten())
+}
+
+fn read_header_block<R: Read>(reader: &mut R) -> Result<HeaderBlock, PbfHeaderError> {
+    let mut size = [0; 4];
+    reader.read_exact(&mut size)?;
+    let size = u32::from_be_bytes(size);
+    if size > MAX_BLOB_HEADER_SIZE {
+        return Err(PbfHeaderError::InvalidHeader(format!(
+            "blob header of {size} bytes"
+        )));
+    }
+    let blob_header = BlobHeader::parse_from_bytes(&read_bytes(reader, size as usize)?)?;
+    if blob_header.type_() != "OSMHeader" {
+        return Err(PbfHeaderError::InvalidHeader(format!(
+            "first block is {:?}, not OSMHeader",
+            blob_header.type_()
+        )));
+    }
+    let blob = Blob::parse_from_bytes(&read_bytes(reader, blob_header.datasize() as usize)?)?;
+    if blob.has_raw() {
+        Ok(HeaderBlock::parse_from_bytes(blob.raw())?)
+    } else if blob.has_zlib_data() {
+        let mut decoder = flate2::read::ZlibDecoder::new(blob.zlib_data());
+        Ok(HeaderBlock::parse_from_reader(&mut decoder)?)
+    } else {
+        Err(PbfHeaderError::InvalidHeader(
+            "unsupported header compression".into(),
+        ))
+    }
+}
+
+/// Nanodegrees per degree, the unit of [`HeaderBBox`].
+const NANODEGREES: f64 = 1e9;
+
+/// Bounding box of a data blob, stored in the `indexdata` of its header as
+/// an encoded [`HeaderBBox`]. The PBF format leaves `indexdata` free-form,
+/// so other contents yield `None`.
+pub(crate) fn blob_bbox(header: &BlobHeader) -> Option<Rect> {
+    let bbox = HeaderBBox::parse_from_bytes(header.indexdata.as_ref()?).ok()?;
+    if !bbox.is_initialized() {
+        return None;
+    }
+    let degrees = |nano: i64| nano as f64 / NANODEGREES;
+    Some(Rect::new(
+        (degrees(bbox.left()), degrees(bbox.bottom())),
+        (degrees(bbox.right()), degrees(bbox.top())),
+    ))
+}
+
+/// `indexdata` of a blob whose nodes span `bbox`, see [`blob_bbox`].
+pub(crate) fn blob_indexdata(bbox: Rect) -> Vec<u8> {
+    let nanodegrees = |degrees: f64| (degrees * NANODEGREES).round() as i64;
+    let mut header_bbox = HeaderBBox::new();
+    header_bbox.set_left(nanodegrees(bbox.min().x));
+    header_bbox.set_right(nanodegrees(bbox.max().x));
+    header_bbox.set_bottom(nanodegrees(bbox.min().y));
+    header_bbox.set_top(nanodegrees(bbox.max().y));
+    header_bbox
+        .write_to_bytes()
+        .expect("All fields of the box are set")
+}
+
+/// Iterator over the `OSMData` blobs of a pbf file, like
+/// `OsmPbfReader::blobs`, that skips blobs whose [`blob_bbox`] lies outside
+/// of `filter` without decompressing them. Blobs without a box are always
+/// yielded, so files without blob boxes are read in full.
+pub(crate) struct IndexedBlobs<'a, R> {
+    reader: &'a mut R,
+    filter: Option<Rect>,
+    skipped: &'a Cell<usize>,
+    finished: bool,
+}
+
+impl<'a, R: Read> IndexedBlobs<'a, R> {
+    /// Reads from the current position of `reader` and adds the number of
+    /// skipped blobs to `skipped`.
+    pub(crate) fn new(reader: &'a mut R, filter: Option<Rect>, skipped: &'a Cell<usize>) -> Self {
+        IndexedBlobs {
+            reader,
+            filter,
+            skipped,
+            finished: false,
+        }
+    }
+
+    /// `None` at the end of the file.
+    fn next_blob(&mut self) -> osmpbfreader::Result<Option<Blob>> {
+        loop {
+            let mut size = [0; 4];
+            match self.reader.read_exact(&mut size) {
+                Ok(()) => {}
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(error) => return Err(error.into()),
+            }
+            let size = u32::from_be_bytes(size);
+            if size > MAX_BLOB_HEADER_SIZE {
+                return Err(osmpbfreader::Error::InvalidData);
+            }
+            let header = BlobHeader::parse_from_bytes(&read_bytes(self.reader, size as usize)?)?;
+            let outside = self.filter.is_some_and(|filter| {
+                blob_bbox(&header).is_some_and(|bbox| !bbox.intersects(&filter))
+            });
+            if header.type_() != "OSMData" || outside {
+                let data_size = header.datasize() as u64;
+                io::copy(&mut self.reader.by_ref().take(data_size), &mut io::sink())?;
+                if outside {
+                    self.skipped.set(self.skipped.get() + 1);
+                }
+                continue;
+            }
+            let data = read_bytes(self.reader, header.datasize() as usize)?;
+            return Ok(Some(Blob::parse_from_bytes(&data)?));
+        }
+    }
+}
+
+impl<R: Read> Iterator for IndexedBlobs<'_, R> {
+    type Item = osmpbfreader::Result<Blob>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let blob = self.next_blob().transpose();
+        self.finished = !matches!(blob, Some(Ok(_)));
+        blob
+    }
+}
+
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    /// Writes a PBF file consisting of only a header requiring `features`.
+    pub(crate) fn write_header_fixture(name: &str, features: &[&str]) -> PathBuf {
+        let mut header = HeaderBlock::new();
+        header.required_features = features.iter().map(|f| f.to_string()).collect();
+        write_header_block_fixture(name, &header)
+    }
+
+    fn write_header_block_fixture(name: &str, header: &HeaderBlock) -> PathBuf {
+        let mut blob = Blob::new();
+        blob.set_raw(header.write_to_bytes().unwrap());
+        let blob = blob.write_to_bytes().unwrap();
+        let mut blob_header = BlobHeader::new();
+        blob_header.set_type("OSMHeader".into());
+        blob_header.set_datasize(blob.len() as i32);
+        let blob_header = blob_header.write_to_bytes().unwrap();
+
+        let path = std::env::temp_dir().join(name);
+        let mut file = File::create(&path).unwrap();
+        file.write_all(&(blob_header.len() as u32).to_be_bytes())
+            .unwrap();
+        file.write_all(&blob_header).unwrap();
+        file.write_all(&blob).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_supported_features() {
+        let path = write_header_fixture(
+            "osmtools_header_supported.osm.pbf",
+            &["OsmSchema-V0.6", "DenseNodes"],
+        );
+        assert!(validate_pbf_header(&path).is_ok());
+    }
+
+    #[test]
+    fn test_unsupported_feature() {
+        let path = write_header_fixture(
+            "osmtools_header_historical.osm.pbf",
+            &["OsmSchema-V0.6", "HistoricalInformation"],
+        );
+        match validate_pbf_header(&path) {
+            Err(PbfHeaderError::UnsupportedPbfFeature(feature)) => {
+                assert_eq!(feature, "HistoricalInformation")
+            }
+            other => panic!("expected UnsupportedPbfFeature, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_not_a_pbf() {
+        let path = std::env::temp_dir().join("osmtools_header_invalid.osm.pbf");
+        std::fs::write(&path, b"version https://git-lfs.github.com/spec/v1\n").unwrap();
+        assert!(matches!(
+            validate_pbf_header(&path),
+            Err(PbfHeaderError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn test_source_timestamp() {
+        let mut header = HeaderBlock::new();
+        header.set_osmosis_replication_timestamp(1_700_000_000);
+        let path = write_header_block_fixture("osmtools_header_timestamp.osm.pbf", &header);
+        assert_eq!(
+            pbf_source_timestamp(&path).unwrap().unwrap().to_rfc3339(),
+            "2023-11-14T22:13:20+00:00"
+        );
+        let path = write_header_fixture("osmtools_header_no_timestamp.osm.pbf", &[]);
+        assert_eq!(pbf_source_timestamp(&path).unwrap(), None);
+    }
+}
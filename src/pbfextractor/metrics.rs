@@ -21,10 +21,14 @@ use super::units::*;
 use geo::{Distance, Euclidean};
 use geo_types::Point;
 use osmpbfreader::Tags;
-use proj4rs::proj;
+use proj4rs::Proj;
 use smartstring::{LazyCompact, SmartString};
 
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
 use std::rc::Rc;
+use std::str::FromStr;
 
 #[derive(Debug)]
 pub enum MetricError {
@@ -52,45 +56,292 @@ pub trait TagMetric<T>: Metric {
     fn calc(&self, tags: &Tags) -> MetricResult<T>;
 }
 
+/// Metric between two nodes, given in `from` and measured in `to`. The
+/// projections are built once per loader rather than per edge.
 pub trait NodeMetric<T>: Metric {
-    fn calc(&self, source: &Node, target: &Node, from_crs: u16, to_crs: u16) -> MetricResult<T>;
+    fn calc(&self, source: &Node, target: &Node, from: &Proj, to: &Proj) -> MetricResult<T>;
 }
 
 pub trait CostMetric<T>: Metric {
     fn calc(&self, costs: &[f64], map: &MetricIndices) -> MetricResult<T>;
 }
 
+/// Direction of travel along a way, relative to the order of its nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+/// Metric of a way that may differ per direction of travel, e.g. speed limits
+/// tagged with `maxspeed:forward` and `maxspeed:backward`.
+pub trait DirectedTagMetric<T>: Metric {
+    fn calc(&self, tags: &Tags, direction: Direction) -> MetricResult<T>;
+}
+
+const KILOMETERS_PER_MILE: f64 = 1.609344;
+
+fn mph(speed: f64) -> f64 {
+    speed * KILOMETERS_PER_MILE
+}
+
+/// Regional speed conventions of [`CarSpeed`]: the default speed of each
+/// highway class and the unit of `maxspeed` values without one.
+///
+/// Displayed and parsed by its country code, e.g. `us`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpeedProfile {
+    /// German defaults, with unlimited speed on motorways and trunks.
+    #[default]
+    De,
+    Us,
+    Uk,
+}
+
+impl SpeedProfile {
+    pub const ALL: [SpeedProfile; 3] = [SpeedProfile::De, SpeedProfile::Us, SpeedProfile::Uk];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SpeedProfile::De => "de",
+            SpeedProfile::Us => "us",
+            SpeedProfile::Uk => "uk",
+        }
+    }
+
+    /// Whether speed limits are signposted in mph, so `maxspeed` values
+    /// without a unit are read as mph.
+    pub fn uses_mph(self) -> bool {
+        matches!(self, SpeedProfile::Us | SpeedProfile::Uk)
+    }
+
+    /// Speed in km/h on a way of class `highway` without a usable `maxspeed`
+    /// tag, before bounding it by `driver_max`.
+    pub fn default_speed(self, highway: Option<&str>, driver_max: f64) -> f64 {
+        if highway == Some("living_street") {
+            return self.living_street_speed();
+        }
+        match self {
+            SpeedProfile::De => match highway {
+                Some("motorway") | Some("trunk") => driver_max,
+                Some("primary") => 100.0,
+                Some("secondary") | Some("trunk_link") => 80.0,
+                Some("motorway_link")
+                | Some("primary_link")
+                | Some("secondary_link")
+                | Some("tertiary")
+                | Some("tertiary_link") => 70.0,
+                Some("service") => 30.0,
+                _ => 50.0,
+            },
+            SpeedProfile::Us => mph(match highway {
+                Some("motorway") => 65.0,
+                Some("trunk") | Some("primary") => 55.0,
+                Some("secondary") | Some("motorway_link") | Some("trunk_link") => 45.0,
+                Some("primary_link")
+                | Some("secondary_link")
+                | Some("tertiary")
+                | Some("tertiary_link") => 35.0,
+                Some("service") => 15.0,
+                _ => 25.0,
+            }),
+            SpeedProfile::Uk => mph(match highway {
+                Some("motorway") | Some("trunk") => 70.0,
+                Some("primary") | Some("secondary") => 60.0,
+                Some("motorway_link") | Some("trunk_link") | Some("tertiary") => 50.0,
+                Some("primary_link") | Some("secondary_link") | Some("tertiary_link") => 40.0,
+                Some("service") => 15.0,
+                _ => 30.0,
+            }),
+        }
+    }
+
+    /// Speed in km/h on a living street, where cars share the road with
+    /// pedestrians, see [`CarSpeed::with_living_street_speed`].
+    pub fn living_street_speed(self) -> f64 {
+        match self {
+            SpeedProfile::De => 5.0,
+            SpeedProfile::Us => mph(5.0),
+            SpeedProfile::Uk => mph(10.0),
+        }
+    }
+
+    /// Parses a `maxspeed` value to km/h, e.g. `50`, `30 mph` or
+    /// `GB:nsl_single`. Values without a unit are read as mph if
+    /// [`SpeedProfile::uses_mph`].
+    pub fn parse_max_speed(self, value: &str, driver_max: f64) -> Option<f64> {
+        match value {
+            "none" | "DE:motorway" => Some(driver_max),
+            "walk" | "DE:walk" => Some(10.0),
+            "living_street" | "DE:living_street" => Some(10.0),
+            "DE:urban" => Some(50.0),
+            "DE:rural" => Some(100.0),
+            "GB:nsl_single" => Some(mph(60.0)),
+            "GB:nsl_dual" | "GB:motorway" => Some(mph(70.0)),
+            _ => {
+                if let Some(speed) = value.strip_suffix("mph") {
+                    speed.trim().parse().ok().map(mph)
+                } else if let Some(speed) = value.strip_suffix("km/h") {
+                    speed.trim().parse().ok()
+                } else if self.uses_mph() {
+                    value.parse().ok().map(mph)
+                } else {
+                    value.parse().ok()
+                }
+            }
+        }
+    }
+
+    /// Parses a `maxspeed:conditional` value to `(km/h, condition)` pairs,
+    /// e.g. `30 @ (22:00-06:00)` to `(30.0, "22:00-06:00")`. Several
+    /// restrictions are separated by `;` outside of parentheses. The
+    /// condition is kept as raw text, restrictions with an unparsable speed
+    /// are skipped.
+    pub fn parse_conditional_max_speed(self, value: &str, driver_max: f64) -> Vec<(f64, String)> {
+        let mut restrictions = Vec::new();
+        let mut depth = 0;
+        let mut start = 0;
+        for (index, c) in value.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ';' if depth == 0 => {
+                    restrictions.push(&value[start..index]);
+                    start = index + 1;
+                }
+                _ => {}
+            }
+        }
+        restrictions.push(&value[start..]);
+        restrictions
+            .into_iter()
+            .filter_map(|restriction| {
+                let (speed, condition) = restriction.split_once('@')?;
+                let speed = self.parse_max_speed(speed.trim(), driver_max)?;
+                let condition = condition.trim();
+                let condition = condition
+                    .strip_prefix('(')
+                    .and_then(|c| c.strip_suffix(')'))
+                    .unwrap_or(condition);
+                Some((speed, condition.trim().to_owned()))
+            })
+            .collect()
+    }
+}
+
+impl Display for SpeedProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SpeedProfile {
+    type Err = ParseSpeedProfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SpeedProfile::ALL
+            .into_iter()
+            .find(|profile| profile.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseSpeedProfileError { name: s.to_owned() })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseSpeedProfileError {
+    name: String,
+}
+
+impl Error for ParseSpeedProfileError {}
+
+impl Display for ParseSpeedProfileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<&str> = SpeedProfile::ALL.iter().map(|p| p.as_str()).collect();
+        write!(
+            f,
+            "Unknown speed profile {}, expected one of {}",
+            self.name,
+            names.join(", ")
+        )
+    }
+}
+
+/// Speed a car can travel on a way, bounded by the driver's maximum speed.
 #[allow(dead_code)]
-fn bounded_speed(tags: &Tags, driver_max: f64) -> MetricResult<KilometersPerHour> {
-    let street_type = tags.get("highway").map(smartstring::alias::String::as_ref);
-    let tag_speed = match street_type {
-        Some("motorway") | Some("trunk") => driver_max,
-        Some("primary") => 100.0,
-        Some("secondary") | Some("trunk_link") => 80.0,
-        Some("motorway_link")
-        | Some("primary_link")
-        | Some("secondary_link")
-        | Some("tertiary")
-        | Some("tertiary_link") => 70.0,
-        Some("service") => 30.0,
-        Some("living_street") => 5.0,
-        _ => 50.0,
-    };
+pub struct CarSpeed {
+    driver_max: f64,
+    profile: SpeedProfile,
+    /// Overrides [`SpeedProfile::living_street_speed`].
+    living_street: Option<f64>,
+}
+metric!(CarSpeed);
 
-    let max_speed_tag = tags.get("maxspeed");
-    let max_speed = match max_speed_tag.map(smartstring::alias::String::as_ref) {
-        Some("none") => Some(driver_max),
-        Some("walk") | Some("DE:walk") => Some(10.0),
-        Some("living_street") | Some("DE:living_street") => Some(10.0),
-        Some(s) => s.parse().ok(),
-        None => None,
-    };
+impl CarSpeed {
+    pub fn new(driver_max: KilometersPerHour) -> CarSpeed {
+        CarSpeed {
+            driver_max: driver_max.0,
+            profile: SpeedProfile::default(),
+            living_street: None,
+        }
+    }
 
-    let speed = match max_speed {
-        Some(s) if s > 0.0 && s <= driver_max => s,
-        _ => tag_speed.min(driver_max),
-    };
-    Ok(KilometersPerHour(speed))
+    /// Uses the highway defaults and `maxspeed` units of `profile` instead
+    /// of the German ones.
+    pub fn with_profile(mut self, profile: SpeedProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Speed on `highway=living_street` and for `maxspeed=living_street`
+    /// instead of the profile's default, e.g. 7 or 20 km/h for shared spaces
+    /// in some countries.
+    pub fn with_living_street_speed(mut self, speed: KilometersPerHour) -> Self {
+        self.living_street = Some(speed.0);
+        self
+    }
+
+    fn bounded_speed(&self, tags: &Tags, direction: Option<Direction>) -> KilometersPerHour {
+        let living_street = self
+            .living_street
+            .unwrap_or_else(|| self.profile.living_street_speed());
+        let street_type = tags.get("highway").map(smartstring::alias::String::as_ref);
+        let tag_speed = match street_type {
+            Some("living_street") => living_street,
+            _ => self.profile.default_speed(street_type, self.driver_max),
+        };
+
+        let directed_key = match direction {
+            Some(Direction::Forward) => Some("maxspeed:forward"),
+            Some(Direction::Backward) => Some("maxspeed:backward"),
+            None => None,
+        };
+        let max_speed_tag = directed_key
+            .and_then(|key| tags.get(key))
+            .or_else(|| tags.get("maxspeed"));
+        let max_speed = max_speed_tag.and_then(|s| match s.as_str() {
+            "living_street" | "DE:living_street" if self.living_street.is_some() => {
+                Some(living_street)
+            }
+            _ => self.profile.parse_max_speed(s, self.driver_max),
+        });
+
+        let speed = match max_speed {
+            Some(s) if s > 0.0 && s <= self.driver_max => s,
+            _ => tag_speed.min(self.driver_max),
+        };
+        KilometersPerHour(speed)
+    }
+}
+
+impl TagMetric<KilometersPerHour> for CarSpeed {
+    fn calc(&self, tags: &Tags) -> MetricResult<KilometersPerHour> {
+        Ok(self.bounded_speed(tags, None))
+    }
+}
+
+impl DirectedTagMetric<KilometersPerHour> for CarSpeed {
+    fn calc(&self, tags: &Tags, direction: Direction) -> MetricResult<KilometersPerHour> {
+        Ok(self.bounded_speed(tags, Some(direction)))
+    }
 }
 
 #[allow(dead_code)]
@@ -99,19 +350,11 @@ pub struct Distance_;
 metric!(Distance_);
 
 impl NodeMetric<Meters> for Distance_ {
-    fn calc(
-        &self,
-        source: &Node,
-        target: &Node,
-        from_crs: u16,
-        target_crs: u16,
-    ) -> MetricResult<Meters> {
-        let src_proj = proj::Proj::from_epsg_code(from_crs).unwrap();
-        let target_proj = proj::Proj::from_epsg_code(target_crs).unwrap();
+    fn calc(&self, source: &Node, target: &Node, from: &Proj, to: &Proj) -> MetricResult<Meters> {
         let mut source_point = Point::new(source.long, source.lat).to_radians();
         let mut target_point = Point::new(target.long, target.lat).to_radians();
-        proj4rs::transform::transform(&src_proj, &target_proj, &mut source_point).unwrap();
-        proj4rs::transform::transform(&src_proj, &target_proj, &mut target_point).unwrap();
+        proj4rs::transform::transform(from, to, &mut source_point).unwrap();
+        proj4rs::transform::transform(from, to, &mut target_point).unwrap();
         Ok(Meters(Euclidean.distance(source_point, target_point)))
     }
 }
@@ -171,6 +414,75 @@ where
     }
 }
 
+/// Height difference in meters from the source to the target node of an edge,
+/// negative downhill.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct Ascent;
+metric!(Ascent);
+
+/// Walking time according to Tobler's hiking function, which peaks at
+/// 6 km/h on a slight downhill slope of 5% and slows down on steeper ones.
+#[allow(dead_code)]
+pub struct ToblerWalkingTime<D: Metric, A: Metric> {
+    distance: Rc<D>,
+    ascent: Rc<A>,
+}
+
+impl<D, A> Metric for ToblerWalkingTime<D, A>
+where
+    D: Metric,
+    A: Metric,
+{
+    fn name(&self) -> String {
+        format!(
+            "ToblerWalkingTime: {} / {}",
+            self.distance.name(),
+            self.ascent.name()
+        )
+    }
+}
+
+impl<D, A> ToblerWalkingTime<D, A>
+where
+    D: Metric,
+    A: Metric,
+{
+    pub fn new(distance: Rc<D>, ascent: Rc<A>) -> ToblerWalkingTime<D, A> {
+        ToblerWalkingTime { distance, ascent }
+    }
+}
+
+impl<D, A> CostMetric<Seconds> for ToblerWalkingTime<D, A>
+where
+    D: Metric,
+    A: Metric,
+{
+    fn calc(&self, costs: &[f64], map: &MetricIndices) -> MetricResult<Seconds> {
+        let dist_index = *map
+            .get(&self.distance.name())
+            .ok_or(MetricError::UnknownMetric)?;
+        let ascent_index = *map
+            .get(&self.ascent.name())
+            .ok_or(MetricError::UnknownMetric)?;
+
+        let dist = Meters(costs[dist_index]);
+        let slope = if dist.0 > 0.0 {
+            costs[ascent_index] / dist.0
+        } else {
+            0.0
+        };
+        let speed = KilometersPerHour(6.0 * (-3.5 * (slope + 0.05).abs()).exp());
+        let time = dist / MetersPerSecond::from(speed);
+
+        if time.0.is_finite() {
+            Ok(time)
+        } else {
+            Err(MetricError::NonFiniteTime(dist.0, speed.0))
+        }
+    }
+}
+
 impl<T> CostMetric<f64> for T
 where
     T: CostMetric<Seconds>,
@@ -184,8 +496,8 @@ impl<T> NodeMetric<f64> for T
 where
     T: NodeMetric<Meters>,
 {
-    fn calc(&self, source: &Node, target: &Node, from_crs: u16, to_crs: u16) -> MetricResult<f64> {
-        NodeMetric::<Meters>::calc(self, source, target, from_crs, to_crs).map(|c| c.0)
+    fn calc(&self, source: &Node, target: &Node, from: &Proj, to: &Proj) -> MetricResult<f64> {
+        NodeMetric::<Meters>::calc(self, source, target, from, to).map(|c| c.0)
     }
 }
 
@@ -251,12 +563,69 @@ where
     }
 }
 
-#[allow(dead_code)]
-pub struct BicycleUnsuitability;
+/// How unsuitable a way is for cycling, from 0.5 (cycleway) upwards.
+///
+/// Ways with cycling infrastructure or a sidewalk get a fixed low penalty,
+/// all others are looked up by their `highway` type in the weight table.
+#[derive(Clone, Debug)]
+pub struct BicycleUnsuitability {
+    weights: HashMap<String, f64>,
+}
 metric!(BicycleUnsuitability);
 
+/// Penalty of highway types missing from the weight table.
+const UNKNOWN_HIGHWAY_UNSUITABILITY: f64 = 6.0;
+
+/// Penalty of ways where cyclists have to push their bike, see
+/// [`requires_dismount`].
+const DISMOUNT_UNSUITABILITY: f64 = 2.0;
+
+impl Default for BicycleUnsuitability {
+    fn default() -> Self {
+        let weights = [
+            ("primary", 5.0),
+            ("primary_link", 5.0),
+            ("secondary", 4.0),
+            ("secondary_link", 4.0),
+            ("tertiary", 3.0),
+            ("tertiary_link", 3.0),
+            ("road", 3.0),
+            ("bridleway", 3.0),
+            ("unclassified", 2.0),
+            ("residential", 2.0),
+            ("traffic_island", 2.0),
+            ("living_street", 1.0),
+            ("service", 1.0),
+            ("track", 1.0),
+            ("platform", 1.0),
+            ("pedestrian", 1.0),
+            ("path", 1.0),
+            ("footway", 1.0),
+            ("cycleway", 0.5),
+        ];
+        BicycleUnsuitability {
+            weights: weights
+                .into_iter()
+                .map(|(highway, weight)| (highway.to_owned(), weight))
+                .collect(),
+        }
+    }
+}
+
+impl BicycleUnsuitability {
+    /// Overrides the penalties of the given highway types, keeping the
+    /// defaults for all others.
+    pub fn with_weights(mut self, weights: HashMap<String, f64>) -> Self {
+        self.weights.extend(weights);
+        self
+    }
+}
+
 impl TagMetric<f64> for BicycleUnsuitability {
     fn calc(&self, tags: &Tags) -> MetricResult<f64> {
+        if requires_dismount(tags) {
+            return Ok(DISMOUNT_UNSUITABILITY);
+        }
         let bicycle_tag = tags.get("bicycle");
         if tags.get("cycleway").is_some()
             || bicycle_tag.is_some() && bicycle_tag != Some(&SmartString::<LazyCompact>::from("no"))
@@ -269,33 +638,345 @@ impl TagMetric<f64> for BicycleUnsuitability {
             return Ok(1.0);
         }
 
-        let street_type = tags.get("highway").map(smartstring::alias::String::as_ref);
-        let unsuitability = match street_type {
-            Some("primary") => 5.0,
-            Some("primary_link") => 5.0,
-            Some("secondary") => 4.0,
-            Some("secondary_link") => 4.0,
-            Some("tertiary") => 3.0,
-            Some("tertiary_link") => 3.0,
-            Some("road") => 3.0,
-            Some("bridleway") => 3.0,
-            Some("unclassified") => 2.0,
-            Some("residential") => 2.0,
-            Some("traffic_island") => 2.0,
-            Some("living_street") => 1.0,
-            Some("service") => 1.0,
-            Some("track") => 1.0,
-            Some("platform") => 1.0,
-            Some("pedestrian") => 1.0,
-            Some("path") => 1.0,
-            Some("footway") => 1.0,
-            Some("cycleway") => 0.5,
-            _ => 6.0,
-        };
+        let unsuitability = tags
+            .get("highway")
+            .and_then(|street_type| self.weights.get(street_type.as_str()))
+            .copied()
+            .unwrap_or(UNKNOWN_HIGHWAY_UNSUITABILITY);
         Ok(unsuitability)
     }
 }
 
+/// Whether cyclists have to push their bike along the way
+/// (`bicycle=dismount`). [`BicycleEdgeFilter`] keeps these ways.
+pub fn requires_dismount(tags: &Tags) -> bool {
+    tags.contains("bicycle", "dismount")
+}
+
+/// Cycling infrastructure along a way, ordered from the most to the least
+/// separated from motor traffic.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub enum CycleInfrastructure {
+    /// A cycleway or a physically separated track.
+    Track,
+    /// A painted lane on the carriageway.
+    Lane,
+    /// A lane shared with motor traffic or buses, or a bicycle road.
+    Shared,
+    None,
+}
+
+impl CycleInfrastructure {
+    /// Reads `highway=cycleway`, `bicycle_road`, `cyclestreet` and the
+    /// `cycleway`, `cycleway:left`, `cycleway:right` and `cycleway:both`
+    /// tags. The best infrastructure on either side wins.
+    pub fn from_tags(tags: &Tags) -> CycleInfrastructure {
+        if tags.contains("highway", "cycleway") {
+            return CycleInfrastructure::Track;
+        }
+        let bicycle_road =
+            tags.contains("bicycle_road", "yes") || tags.contains("cyclestreet", "yes");
+        [
+            "cycleway",
+            "cycleway:left",
+            "cycleway:right",
+            "cycleway:both",
+        ]
+        .into_iter()
+        .filter_map(|key| tags.get(key))
+        .map(|value| match value.as_str() {
+            "track" | "opposite_track" | "separate" => CycleInfrastructure::Track,
+            "lane" | "opposite_lane" => CycleInfrastructure::Lane,
+            "shared_lane" | "share_busway" | "opposite_share_busway" | "shared" => {
+                CycleInfrastructure::Shared
+            }
+            _ => CycleInfrastructure::None,
+        })
+        .chain(bicycle_road.then_some(CycleInfrastructure::Shared))
+        .min()
+        .unwrap_or(CycleInfrastructure::None)
+    }
+
+    /// Value of the `infra_class` column, e.g. `lane`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CycleInfrastructure::Track => "track",
+            CycleInfrastructure::Lane => "lane",
+            CycleInfrastructure::Shared => "shared",
+            CycleInfrastructure::None => "none",
+        }
+    }
+}
+
+/// Comfort of a way's surface between 0 (impassable) and 1 (smooth asphalt),
+/// from its `surface`, `smoothness` and `tracktype` tags.
+///
+/// If several of the tags are present, the worst of them wins. Untagged ways
+/// are assumed to be paved.
+#[allow(dead_code)]
+pub struct SurfaceQuality;
+metric!(SurfaceQuality);
+
+impl SurfaceQuality {
+    /// The comfort score, or `None` if the way carries none of the tags.
+    pub fn score(&self, tags: &Tags) -> Option<f64> {
+        let tag =
+            |key: &str| -> Option<&str> { tags.get(key).map(smartstring::alias::String::as_ref) };
+        let surface = tag("surface").and_then(|surface| match surface {
+            "asphalt" | "concrete" | "paved" | "concrete:plates" => Some(1.0),
+            "paving_stones" | "chipseal" | "concrete:lanes" => Some(0.8),
+            "compacted" | "fine_gravel" | "metal" | "wood" => Some(0.6),
+            "cobblestone:flattened" | "sett" => Some(0.4),
+            "gravel" | "pebblestone" | "unpaved" | "grass_paver" => Some(0.4),
+            "cobblestone" | "ground" | "dirt" | "earth" => Some(0.3),
+            "unhewn_cobblestone" | "grass" => Some(0.2),
+            "sand" | "mud" => Some(0.1),
+            _ => None,
+        });
+        let smoothness = tag("smoothness").and_then(|smoothness| match smoothness {
+            "excellent" => Some(1.0),
+            "good" => Some(0.9),
+            "intermediate" => Some(0.7),
+            "bad" => Some(0.5),
+            "very_bad" => Some(0.3),
+            "horrible" => Some(0.2),
+            "very_horrible" => Some(0.1),
+            "impassable" => Some(0.0),
+            _ => None,
+        });
+        let tracktype = tag("tracktype").and_then(|tracktype| match tracktype {
+            "grade1" => Some(0.9),
+            "grade2" => Some(0.6),
+            "grade3" => Some(0.4),
+            "grade4" => Some(0.3),
+            "grade5" => Some(0.2),
+            _ => None,
+        });
+        [surface, smoothness, tracktype]
+            .into_iter()
+            .flatten()
+            .reduce(f64::min)
+    }
+}
+
+impl TagMetric<f64> for SurfaceQuality {
+    fn calc(&self, tags: &Tags) -> MetricResult<f64> {
+        Ok(self.score(tags).unwrap_or(1.0))
+    }
+}
+
+/// Whether the way is paved with cobblestones or setts, which are hard to
+/// use with wheelchairs, strollers and road bikes.
+pub fn is_rough_paving(tags: &Tags) -> bool {
+    [
+        "cobblestone",
+        "cobblestone:flattened",
+        "sett",
+        "unhewn_cobblestone",
+    ]
+    .iter()
+    .any(|surface| tags.contains("surface", surface))
+}
+
+/// Vehicle-dimension restrictions of a way, for truck routing.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VehicleRestrictions {
+    /// `maxweight` in tonnes.
+    pub max_weight: Option<f64>,
+    /// `maxheight` in meters.
+    pub max_height: Option<f64>,
+    /// `maxwidth` in meters.
+    pub max_width: Option<f64>,
+    /// Whether heavy goods vehicles are banned (`hgv=no`).
+    pub hgv_no: bool,
+}
+
+impl VehicleRestrictions {
+    pub fn from_tags(tags: &Tags) -> VehicleRestrictions {
+        VehicleRestrictions {
+            max_weight: tags.get("maxweight").and_then(|w| parse_weight(w)),
+            max_height: tags.get("maxheight").and_then(|h| parse_length(h)),
+            max_width: tags.get("maxwidth").and_then(|w| parse_length(w)),
+            hgv_no: tags.contains("hgv", "no"),
+        }
+    }
+}
+
+/// Splits e.g. `"3.5 t"` into `(3.5, "t")`, accepting a decimal comma.
+fn split_unit(value: &str) -> Option<(f64, &str)> {
+    let value = value.trim();
+    let end = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+        .unwrap_or(value.len());
+    let number = value[..end].replace(',', ".").parse().ok()?;
+    Some((number, value[end..].trim()))
+}
+
+/// Parses a `maxweight` value into tonnes, e.g. `"7.5"`, `"3.5 t"` or
+/// `"7500 kg"`. Returns `None` for values such as `none` or unknown units.
+pub fn parse_weight(value: &str) -> Option<f64> {
+    let (number, unit) = split_unit(value)?;
+    match unit {
+        "" | "t" => Some(number),
+        "kg" => Some(number / 1000.0),
+        "st" => Some(number * 0.907_184_74),
+        "lbs" | "lb" => Some(number * 0.000_453_592_37),
+        _ => None,
+    }
+}
+
+/// Parses a `maxheight` or `maxwidth` value into meters, e.g. `"4"`,
+/// `"3.8 m"`, `"12 ft"` or `"12'6\""`.
+pub fn parse_length(value: &str) -> Option<f64> {
+    if let Some((feet, inches)) = value.split_once('\'') {
+        let feet: f64 = feet.trim().parse().ok()?;
+        let inches = inches.trim().trim_end_matches('"').trim();
+        let inches: f64 = if inches.is_empty() {
+            0.0
+        } else {
+            inches.parse().ok()?
+        };
+        return Some((feet * 12.0 + inches) * 0.0254);
+    }
+    let (number, unit) = split_unit(value)?;
+    match unit {
+        "" | "m" => Some(number),
+        "ft" => Some(number * 0.3048),
+        _ => None,
+    }
+}
+
+/// Number of lanes of a way. Of several `;`-separated values the first is
+/// used; without a `lanes` tag the number is derived from the highway class.
+/// Invalid values yield `None`.
+pub fn lanes(tags: &Tags) -> Option<u8> {
+    if let Some(lanes) = tags.get("lanes") {
+        return lanes.split(';').next()?.trim().parse().ok();
+    }
+    let one_way = matches!(
+        tags.get("oneway").map(smartstring::alias::String::as_ref),
+        Some("yes") | Some("true") | Some("1") | Some("-1")
+    );
+    match tags
+        .get("highway")
+        .map(smartstring::alias::String::as_ref)?
+    {
+        "motorway" | "trunk" => Some(2),
+        "primary" | "secondary" | "tertiary" | "unclassified" | "residential" if one_way => Some(1),
+        "primary" | "secondary" | "tertiary" | "unclassified" | "residential" => Some(2),
+        "motorway_link" | "trunk_link" | "primary_link" | "secondary_link" | "tertiary_link"
+        | "living_street" | "service" | "track" => Some(1),
+        _ => None,
+    }
+}
+
+/// Width of a way in meters from its `width` tag, see [`parse_length`].
+pub fn width(tags: &Tags) -> Option<f64> {
+    tags.get("width").and_then(|w| parse_length(w))
+}
+
+/// Number of steps of a way from its `step_count` tag. Invalid values yield
+/// `None`.
+pub fn step_count(tags: &Tags) -> Option<u32> {
+    tags.get("step_count")?.trim().parse().ok()
+}
+
+/// Vertical layer of a way from its `layer` tag, negative below ground.
+/// Untagged ways are on layer 0 by convention but yield `None`, as do
+/// invalid values.
+pub fn layer(tags: &Tags) -> Option<i8> {
+    tags.get("layer")?.trim().parse().ok()
+}
+
+/// Whether the way is a bridge, i.e. has a `bridge` tag other than `no`,
+/// e.g. `yes` or `viaduct`.
+pub fn is_bridge(tags: &Tags) -> bool {
+    tags.get("bridge").is_some_and(|bridge| bridge != "no")
+}
+
+/// Whether the way is a tunnel, i.e. has a `tunnel` tag other than `no`,
+/// e.g. `yes`, `building_passage` or `culvert`.
+pub fn is_tunnel(tags: &Tags) -> bool {
+    tags.get("tunnel").is_some_and(|tunnel| tunnel != "no")
+}
+
+/// Average ferry speed used when a ferry route carries no `duration` tag.
+const DEFAULT_FERRY_SPEED: KilometersPerHour = KilometersPerHour(20.0);
+
+/// Whether the way is a ferry connection (`route=ferry`). Ferries usually
+/// carry pedestrians, bicycles and cars alike, so every edge filter keeps
+/// them unless the respective mode is explicitly excluded.
+pub fn is_ferry(tags: &Tags) -> bool {
+    tags.contains("route", "ferry")
+}
+
+/// Parses an OSM `duration` value (`HH:MM`, `HH:MM:SS` or plain minutes).
+fn parse_duration(value: &str) -> Option<Seconds> {
+    let parts: Vec<f64> = value
+        .trim()
+        .split(':')
+        .map(|part| part.trim().parse::<f64>().ok())
+        .collect::<Option<Vec<f64>>>()?;
+    let seconds = match parts.as_slice() {
+        [minutes] => minutes * 60.0,
+        [hours, minutes] => hours * 3600.0 + minutes * 60.0,
+        [hours, minutes, seconds] => hours * 3600.0 + minutes * 60.0 + seconds,
+        _ => return None,
+    };
+    Some(Seconds(seconds))
+}
+
+/// Duration of a whole ferry route from its `duration` tag.
+pub fn route_duration(tags: &Tags) -> Option<Seconds> {
+    tags.get("duration").and_then(|d| parse_duration(d))
+}
+
+/// Travel time over a ferry connection, taken from the `duration` tag if
+/// present and otherwise derived from the distance and a default ferry speed.
+pub struct FerryTravelTime {
+    speed: KilometersPerHour,
+}
+metric!(FerryTravelTime);
+
+impl Default for FerryTravelTime {
+    fn default() -> Self {
+        FerryTravelTime::new(DEFAULT_FERRY_SPEED)
+    }
+}
+
+impl FerryTravelTime {
+    pub fn new(speed: KilometersPerHour) -> FerryTravelTime {
+        FerryTravelTime { speed }
+    }
+
+    /// Travel time over a segment of length `distance` of a ferry route of
+    /// length `route_distance`. A [`route_duration`] covers the whole route,
+    /// so it is split in proportion to the segment's share of the route.
+    pub fn calc(
+        &self,
+        route_duration: Option<Seconds>,
+        distance: Meters,
+        route_distance: Meters,
+    ) -> MetricResult<Seconds> {
+        if let Some(duration) = route_duration {
+            let share = if route_distance.0 > 0.0 {
+                distance.0 / route_distance.0
+            } else {
+                1.0
+            };
+            return Ok(Seconds(duration.0 * share));
+        }
+        let time = distance / MetersPerSecond::from(self.speed);
+        if time.0.is_finite() {
+            Ok(time)
+        } else {
+            Err(MetricError::NonFiniteTime(distance.0, self.speed.0))
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub struct EdgeCount;
 metric!(EdgeCount);
@@ -312,7 +993,20 @@ pub trait EdgeFilter: Clone {
 
 #[allow(dead_code)]
 #[derive(Clone, Default)]
-pub struct BicycleEdgeFilter;
+pub struct BicycleEdgeFilter {
+    allow_motor_vehicle_free_ways: bool,
+}
+
+impl BicycleEdgeFilter {
+    /// Whether ways closed to motor vehicles (`motor_vehicle=no`) are kept for
+    /// cycling even if their highway class would otherwise be excluded.
+    /// Disabled by default. Steps, motorways and trunk roads stay excluded,
+    /// and an explicit `bicycle=no` always wins.
+    pub fn allow_motor_vehicle_free_ways(mut self, value: bool) -> Self {
+        self.allow_motor_vehicle_free_ways = value;
+        self
+    }
+}
 
 impl EdgeFilter for BicycleEdgeFilter {
     fn is_invalid(&self, tags: &Tags) -> bool {
@@ -322,11 +1016,30 @@ impl EdgeFilter for BicycleEdgeFilter {
         }
         if tags.get("cycleway").is_some()
             || bicycle_tag.is_some() && bicycle_tag != Some(&SmartString::<LazyCompact>::from("no"))
+            || is_ferry(tags)
         {
             return false;
         }
 
         let street_type = tags.get("highway").map(smartstring::alias::String::as_ref);
+        if self.allow_motor_vehicle_free_ways
+            && tags.contains("motor_vehicle", "no")
+            && !matches!(
+                street_type,
+                Some("steps")
+                    | Some("motorway")
+                    | Some("motorway_link")
+                    | Some("trunk")
+                    | Some("trunk_link")
+                    | Some("proposed")
+                    | Some("construction")
+                    | Some("abandoned")
+                    | Some("raceway")
+                    | None
+            )
+        {
+            return false;
+        }
         let side_walk: Option<&str> = tags.get("sidewalk").map(smartstring::alias::String::as_ref);
         let has_side_walk: bool = match side_walk {
             Some(s) => s != "no",
@@ -360,7 +1073,48 @@ impl EdgeFilter for BicycleEdgeFilter {
 
 #[allow(dead_code)]
 #[derive(Clone, Default)]
-pub struct WalkingEdgeFilter;
+pub struct WalkingEdgeFilter {
+    exclude_indoor: bool,
+    exclude_tracks_without_foot: bool,
+    exclude_steps: bool,
+}
+
+impl WalkingEdgeFilter {
+    /// Rejects indoor ways (`indoor=yes/room/corridor/area`) and ways on
+    /// underground levels (negative `level`), which are no street-level
+    /// walking routes. Disabled by default.
+    pub fn exclude_indoor(mut self, value: bool) -> Self {
+        self.exclude_indoor = value;
+        self
+    }
+
+    /// Rejects `highway=track` unless `foot=yes/designated/permissive`
+    /// permits walking, which keeps farm and forest tracks out of rural
+    /// pedestrian graphs. Disabled by default.
+    pub fn exclude_tracks_without_foot(mut self, value: bool) -> Self {
+        self.exclude_tracks_without_foot = value;
+        self
+    }
+
+    /// Rejects stairs (`highway=steps`) for step-free routing. Disabled by
+    /// default.
+    pub fn exclude_steps(mut self, value: bool) -> Self {
+        self.exclude_steps = value;
+        self
+    }
+}
+
+fn is_indoor(tags: &Tags) -> bool {
+    let indoor: Option<&str> = tags.get("indoor").map(smartstring::alias::String::as_ref);
+    let is_underground = tags
+        .get("level")
+        .and_then(|level| level.split(';').next()?.trim().parse::<f64>().ok())
+        .is_some_and(|level| level < 0.0);
+    matches!(
+        indoor,
+        Some("yes") | Some("room") | Some("corridor") | Some("area")
+    ) || is_underground
+}
 
 impl EdgeFilter for WalkingEdgeFilter {
     fn is_invalid(&self, tags: &Tags) -> bool {
@@ -368,6 +1122,23 @@ impl EdgeFilter for WalkingEdgeFilter {
         if walking_tag == Some(&SmartString::<LazyCompact>::from("no")) {
             return true;
         }
+        if self.exclude_indoor && is_indoor(tags) {
+            return true;
+        }
+        if self.exclude_tracks_without_foot
+            && tags.contains("highway", "track")
+            && !["yes", "designated", "permissive"]
+                .iter()
+                .any(|value| tags.contains("foot", value))
+        {
+            return true;
+        }
+        if self.exclude_steps && tags.contains("highway", "steps") {
+            return true;
+        }
+        if is_ferry(tags) {
+            return tags.contains("foot", "no");
+        }
 
         let street_type = tags.get("highway").map(smartstring::alias::String::as_ref);
         let side_walk: Option<&str> = tags.get("sidewalk").map(smartstring::alias::String::as_ref);
@@ -394,12 +1165,109 @@ impl EdgeFilter for WalkingEdgeFilter {
         )
     }
 }
-#[allow(dead_code)]
+
+/// Accepts bridleways, tracks and ways explicitly open to horses
+/// (`horse=yes/designated/permissive`), plus ordinary roads. Motorways,
+/// trunk roads and ways for pedestrians or cyclists are rejected unless a
+/// `horse` tag permits riding, `horse=no` always wins.
+#[derive(Clone, Default)]
+pub struct HorseEdgeFilter;
+
+impl EdgeFilter for HorseEdgeFilter {
+    fn is_invalid(&self, tags: &Tags) -> bool {
+        let horse_tag = tags.get("horse").map(smartstring::alias::String::as_ref);
+        match horse_tag {
+            Some("no") => return true,
+            Some("yes") | Some("designated") | Some("permissive") => return false,
+            _ => {}
+        }
+        if is_ferry(tags) {
+            return false;
+        }
+        let street_type = tags.get("highway").map(smartstring::alias::String::as_ref);
+        matches!(
+            street_type,
+            Some("motorway")
+                | Some("motorway_link")
+                | Some("trunk")
+                | Some("trunk_link")
+                | Some("footway")
+                | Some("pedestrian")
+                | Some("cycleway")
+                | Some("steps")
+                | Some("corridor")
+                | Some("elevator")
+                | Some("escalator")
+                | Some("platform")
+                | Some("raceway")
+                | Some("rest_area")
+                | Some("proposed")
+                | Some("construction")
+                | Some("abandoned")
+                | None
+        )
+    }
+}
+
+/// Accepts the ways a car may use. Weight, height and width limits and
+/// `hgv=no` depend on the truck and are therefore not filtered but extracted
+/// as edge columns, see [`VehicleRestrictions`].
 #[derive(Clone, Default)]
-pub struct CarEdgeFilter;
+pub struct TruckEdgeFilter {
+    car: CarEdgeFilter,
+}
+
+impl TruckEdgeFilter {
+    pub fn new(car: CarEdgeFilter) -> Self {
+        TruckEdgeFilter { car }
+    }
+}
+
+impl EdgeFilter for TruckEdgeFilter {
+    fn is_invalid(&self, tags: &Tags) -> bool {
+        self.car.is_invalid(tags)
+    }
+}
+
+/// Surfaces a regular car cannot drive on, regardless of the highway class.
+const DEFAULT_CAR_SURFACE_BLOCKLIST: &[&str] = &["sand", "mud"];
+
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct CarEdgeFilter {
+    surface_blocklist: Vec<String>,
+}
+
+impl Default for CarEdgeFilter {
+    fn default() -> Self {
+        CarEdgeFilter::with_surface_blocklist(DEFAULT_CAR_SURFACE_BLOCKLIST.iter().copied())
+    }
+}
+
+impl CarEdgeFilter {
+    /// Creates a filter which additionally rejects every way whose `surface`
+    /// tag is contained in `surfaces`.
+    pub fn with_surface_blocklist<I, S>(surfaces: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        CarEdgeFilter {
+            surface_blocklist: surfaces.into_iter().map(Into::into).collect(),
+        }
+    }
+}
 
 impl EdgeFilter for CarEdgeFilter {
     fn is_invalid(&self, tags: &Tags) -> bool {
+        let surface: Option<&str> = tags.get("surface").map(smartstring::alias::String::as_ref);
+        if surface.is_some_and(|s| self.surface_blocklist.iter().any(|b| b == s)) {
+            return true;
+        }
+        if is_ferry(tags) {
+            return tags.contains("motor_vehicle", "no") || tags.contains("motorcar", "no");
+        }
+
         let street_type = tags.get("highway").map(smartstring::alias::String::as_ref);
         matches!(
             street_type,
@@ -425,46 +1293,517 @@ impl EdgeFilter for CarEdgeFilter {
     }
 }
 
+/// Accepts ways both filters accept.
+#[derive(Clone, Default)]
+pub struct And<A, B>(pub A, pub B);
+
+impl<A: EdgeFilter, B: EdgeFilter> EdgeFilter for And<A, B> {
+    fn is_invalid(&self, tags: &Tags) -> bool {
+        self.0.is_invalid(tags) || self.1.is_invalid(tags)
+    }
+}
+
+/// Accepts ways at least one of the filters accepts, e.g.
+/// `Or(WalkingEdgeFilter::default(), BicycleEdgeFilter::default())` for an
+/// active mobility network.
+#[derive(Clone, Default)]
+pub struct Or<A, B>(pub A, pub B);
+
+impl<A: EdgeFilter, B: EdgeFilter> EdgeFilter for Or<A, B> {
+    fn is_invalid(&self, tags: &Tags) -> bool {
+        self.0.is_invalid(tags) && self.1.is_invalid(tags)
+    }
+}
+
+/// Accepts exactly the ways the inner filter rejects.
+#[derive(Clone, Default)]
+pub struct Not<A>(pub A);
+
+impl<A: EdgeFilter> EdgeFilter for Not<A> {
+    fn is_invalid(&self, tags: &Tags) -> bool {
+        !self.0.is_invalid(tags)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::pbfextractor::metrics;
 
     use super::*;
 
+    #[test]
+    fn test_bicycle_unsuitability_weights() {
+        let primary = tags(&[("highway", "primary")]);
+        let residential = tags(&[("highway", "residential")]);
+        let unknown = tags(&[("highway", "busway")]);
+        let default = BicycleUnsuitability::default();
+        assert_eq!(TagMetric::calc(&default, &primary).unwrap(), 5.0);
+        assert_eq!(TagMetric::calc(&default, &unknown).unwrap(), 6.0);
+
+        let tuned = BicycleUnsuitability::default()
+            .with_weights(HashMap::from([("primary".to_owned(), 2.0)]));
+        assert_eq!(TagMetric::calc(&tuned, &primary).unwrap(), 2.0);
+        assert_eq!(TagMetric::calc(&tuned, &residential).unwrap(), 2.0);
+        assert_eq!(TagMetric::calc(&tuned, &unknown).unwrap(), 6.0);
+    }
+
+    #[test]
+    fn test_bicycle_unsuitability_dismount() {
+        let dismount = tags(&[("highway", "footway"), ("bicycle", "dismount")]);
+        let allowed = tags(&[("highway", "footway"), ("bicycle", "yes")]);
+        let unsuitability = BicycleUnsuitability::default();
+        assert_eq!(TagMetric::calc(&unsuitability, &dismount).unwrap(), 2.0);
+        assert_eq!(TagMetric::calc(&unsuitability, &allowed).unwrap(), 0.5);
+        assert!(requires_dismount(&dismount));
+        assert!(!requires_dismount(&allowed));
+        assert!(!BicycleEdgeFilter::default().is_invalid(&dismount));
+    }
+
     #[test]
     fn test_distance_with_crs() {
-        let source = Node {
-            osm_id: 1,
-            lat: 51.2075825,
-            long: 3.2284262,
-        };
-        let target = Node {
-            osm_id: 2,
-            lat: 51.2076861,
-            long: 3.2286302,
-        };
-        let from_crs = 4326;
-        let to_crs = 4839;
+        let from_crs = Proj::from_epsg_code(4326).unwrap();
+        let to_crs = Proj::from_epsg_code(4839).unwrap();
 
+        let source = Node::new(1, 51.2075825, 3.2284262);
+        let target = Node::new(2, 51.2076861, 3.2286302);
         let dist: Result<metrics::Meters, MetricError> =
-            Distance_.calc(&source, &target, from_crs, to_crs);
+            Distance_.calc(&source, &target, &from_crs, &to_crs);
         assert_eq!(dist.unwrap(), Meters(18.315216245523892));
 
-        let source = Node {
-            osm_id: 1,
-            lat: 51.207997,
-            long: 3.22208,
+        // Reusing the projections gives the same distances as fresh ones.
+        let source = Node::new(1, 51.207997, 3.22208);
+        let target = Node::new(2, 51.208031, 3.2220472);
+        let dist: Result<metrics::Meters, MetricError> =
+            Distance_.calc(&source, &target, &from_crs, &to_crs);
+        assert_eq!(dist.unwrap(), Meters(4.418689127008047));
+        let fresh: Result<metrics::Meters, MetricError> = Distance_.calc(
+            &source,
+            &target,
+            &Proj::from_epsg_code(4326).unwrap(),
+            &Proj::from_epsg_code(4839).unwrap(),
+        );
+        assert_eq!(fresh.unwrap(), Meters(4.418689127008047));
+    }
+
+    #[test]
+    fn test_tobler_walking_time() {
+        let tobler = ToblerWalkingTime::new(Rc::new(Distance_), Rc::new(Ascent));
+        let map: MetricIndices = [("Distance_".to_owned(), 0), ("Ascent".to_owned(), 1)].into();
+        let time = |ascent: f64| -> f64 {
+            CostMetric::<Seconds>::calc(&tobler, &[100.0, ascent], &map)
+                .unwrap()
+                .0
+        };
+        let (uphill, flat, downhill) = (time(10.0), time(0.0), time(-5.0));
+        assert!(uphill > flat);
+        assert!(flat > downhill);
+        // 100 m at the top speed of 6 km/h.
+        assert!((downhill - 60.0).abs() < 1e-9);
+        assert!(time(-30.0) > flat);
+        assert!(CostMetric::<Seconds>::calc(&tobler, &[100.0], &MetricIndices::new()).is_err());
+    }
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).into(), (*v).into()))
+            .collect()
+    }
+
+    #[test]
+    fn test_surface_quality() {
+        let quality = |pairs: &[(&str, &str)]| SurfaceQuality.score(&tags(pairs));
+        assert_eq!(quality(&[("surface", "asphalt")]), Some(1.0));
+        assert_eq!(
+            quality(&[("surface", "asphalt"), ("smoothness", "bad")]),
+            Some(0.5)
+        );
+        assert_eq!(
+            quality(&[("surface", "gravel"), ("smoothness", "excellent")]),
+            Some(0.4)
+        );
+        assert_eq!(
+            quality(&[("highway", "track"), ("tracktype", "grade2")]),
+            Some(0.6)
+        );
+        assert_eq!(quality(&[("smoothness", "impassable")]), Some(0.0));
+        assert_eq!(quality(&[("highway", "residential")]), None);
+        assert_eq!(
+            TagMetric::<f64>::calc(&SurfaceQuality, &tags(&[("highway", "residential")])).unwrap(),
+            1.0
+        );
+
+        assert!(quality(&[("surface", "cobblestone")]) < quality(&[("surface", "paving_stones")]));
+        assert!(is_rough_paving(&tags(&[("surface", "sett")])));
+        assert!(is_rough_paving(&tags(&[("surface", "cobblestone")])));
+        assert!(!is_rough_paving(&tags(&[("surface", "paving_stones")])));
+    }
+
+    #[test]
+    fn test_ferry_ways() {
+        let ferry = tags(&[("route", "ferry"), ("duration", "01:30")]);
+        assert!(!CarEdgeFilter::default().is_invalid(&ferry));
+        assert!(!BicycleEdgeFilter::default().is_invalid(&ferry));
+        assert!(!WalkingEdgeFilter::default().is_invalid(&ferry));
+        let foot_only = tags(&[("route", "ferry"), ("motor_vehicle", "no")]);
+        assert!(CarEdgeFilter::default().is_invalid(&foot_only));
+        assert!(!WalkingEdgeFilter::default().is_invalid(&foot_only));
+
+        let time = FerryTravelTime::default();
+        let duration = route_duration(&ferry);
+        assert_eq!(duration, Some(Seconds(5400.0)));
+        assert_eq!(
+            time.calc(duration, Meters(1000.0), Meters(1000.0)).unwrap(),
+            Seconds(5400.0)
+        );
+        assert_eq!(
+            time.calc(duration, Meters(250.0), Meters(1000.0)).unwrap(),
+            Seconds(1350.0)
+        );
+        let without_duration = tags(&[("route", "ferry")]);
+        assert_eq!(route_duration(&without_duration), None);
+        assert_eq!(
+            time.calc(None, Meters(2000.0), Meters(4000.0)).unwrap(),
+            Seconds(360.0)
+        );
+        assert_eq!(parse_duration("0:45:30"), Some(Seconds(2730.0)));
+        assert_eq!(parse_duration("25"), Some(Seconds(1500.0)));
+        assert_eq!(parse_duration("soon"), None);
+    }
+
+    #[test]
+    fn test_walking_filter_exclude_indoor() {
+        let indoor = tags(&[("highway", "footway"), ("indoor", "yes")]);
+        let underground = tags(&[("highway", "footway"), ("level", "-1")]);
+        let outdoor = tags(&[("highway", "footway"), ("level", "0")]);
+
+        let filter = WalkingEdgeFilter::default();
+        assert!(!filter.is_invalid(&indoor));
+        assert!(!filter.is_invalid(&underground));
+
+        let filter = WalkingEdgeFilter::default().exclude_indoor(true);
+        assert!(filter.is_invalid(&indoor));
+        assert!(filter.is_invalid(&underground));
+        assert!(!filter.is_invalid(&outdoor));
+    }
+
+    #[test]
+    fn test_walking_filter_exclude_tracks_without_foot() {
+        let track = tags(&[("highway", "track")]);
+        let footpath = tags(&[("highway", "track"), ("foot", "yes")]);
+
+        let filter = WalkingEdgeFilter::default();
+        assert!(!filter.is_invalid(&track));
+        assert!(!filter.is_invalid(&footpath));
+
+        let filter = WalkingEdgeFilter::default().exclude_tracks_without_foot(true);
+        assert!(filter.is_invalid(&track));
+        assert!(!filter.is_invalid(&footpath));
+        assert!(filter.is_invalid(&tags(&[("highway", "track"), ("foot", "no")])));
+    }
+
+    #[test]
+    fn test_horse_filter() {
+        let filter = HorseEdgeFilter;
+        assert!(!filter.is_invalid(&tags(&[("highway", "bridleway")])));
+        assert!(!filter.is_invalid(&tags(&[("highway", "track")])));
+        assert!(filter.is_invalid(&tags(&[("highway", "footway")])));
+        assert!(!filter.is_invalid(&tags(&[("highway", "footway"), ("horse", "yes")])));
+        assert!(filter.is_invalid(&tags(&[("highway", "bridleway"), ("horse", "no")])));
+        assert!(filter.is_invalid(&tags(&[("highway", "motorway")])));
+    }
+
+    #[test]
+    fn test_conditional_max_speed() {
+        let profile = SpeedProfile::De;
+        assert_eq!(
+            profile.parse_conditional_max_speed("30 @ (22:00-06:00)", 130.0),
+            vec![(30.0, "22:00-06:00".to_owned())]
+        );
+        assert_eq!(
+            profile.parse_conditional_max_speed(
+                "30 @ (Mo-Fr 07:00-17:00; Sa 08:00-12:00); 50 mph @ wet; fast @ snow",
+                130.0
+            ),
+            vec![
+                (30.0, "Mo-Fr 07:00-17:00; Sa 08:00-12:00".to_owned()),
+                (mph(50.0), "wet".to_owned())
+            ]
+        );
+        assert!(profile.parse_conditional_max_speed("30", 130.0).is_empty());
+    }
+
+    #[test]
+    fn test_walking_filter_exclude_steps() {
+        let steps = tags(&[("highway", "steps"), ("step_count", "12")]);
+
+        assert!(!WalkingEdgeFilter::default().is_invalid(&steps));
+        assert_eq!(step_count(&steps), Some(12));
+        assert_eq!(step_count(&tags(&[("highway", "steps")])), None);
+        assert_eq!(
+            step_count(&tags(&[("highway", "steps"), ("step_count", "many")])),
+            None
+        );
+
+        let filter = WalkingEdgeFilter::default().exclude_steps(true);
+        assert!(filter.is_invalid(&steps));
+        assert!(!filter.is_invalid(&tags(&[("highway", "footway")])));
+    }
+
+    #[test]
+    fn test_directional_max_speed() {
+        let speed = CarSpeed::new(KilometersPerHour(130.0));
+        let way = tags(&[
+            ("highway", "primary"),
+            ("maxspeed", "70"),
+            ("maxspeed:forward", "50"),
+            ("maxspeed:backward", "30"),
+        ]);
+        let forward: MetricResult<KilometersPerHour> =
+            DirectedTagMetric::calc(&speed, &way, Direction::Forward);
+        let backward: MetricResult<KilometersPerHour> =
+            DirectedTagMetric::calc(&speed, &way, Direction::Backward);
+        let undirected: MetricResult<KilometersPerHour> = TagMetric::calc(&speed, &way);
+        assert_eq!(forward.unwrap(), KilometersPerHour(50.0));
+        assert_eq!(backward.unwrap(), KilometersPerHour(30.0));
+        assert_eq!(undirected.unwrap(), KilometersPerHour(70.0));
+
+        // Falls back to `maxspeed`, then to the highway default.
+        let way = tags(&[("highway", "primary"), ("maxspeed:forward", "50")]);
+        let backward: MetricResult<KilometersPerHour> =
+            DirectedTagMetric::calc(&speed, &way, Direction::Backward);
+        assert_eq!(backward.unwrap(), KilometersPerHour(100.0));
+        let way = tags(&[("highway", "primary"), ("maxspeed", "70")]);
+        let backward: MetricResult<KilometersPerHour> =
+            DirectedTagMetric::calc(&speed, &way, Direction::Backward);
+        assert_eq!(backward.unwrap(), KilometersPerHour(70.0));
+    }
+
+    #[test]
+    fn test_speed_profiles() {
+        let primary = tags(&[("highway", "primary")]);
+        let speed = |profile: &str, way: &Tags| {
+            let metric =
+                CarSpeed::new(KilometersPerHour(130.0)).with_profile(profile.parse().unwrap());
+            TagMetric::<KilometersPerHour>::calc(&metric, way)
+                .unwrap()
+                .0
         };
-        let target = Node {
-            osm_id: 2,
-            lat: 51.208031,
-            long: 3.2220472,
+        assert_eq!(speed("de", &primary), 100.0);
+        assert!((speed("us", &primary) - 88.5).abs() < 0.1);
+        assert!((speed("uk", &primary) - 96.6).abs() < 0.1);
+
+        // Explicit units apply everywhere, bare values are mph in the US.
+        let signposted = tags(&[("highway", "primary"), ("maxspeed", "30 mph")]);
+        assert!((speed("de", &signposted) - 48.3).abs() < 0.1);
+        let bare = tags(&[("highway", "primary"), ("maxspeed", "30")]);
+        assert_eq!(speed("de", &bare), 30.0);
+        assert!((speed("us", &bare) - 48.3).abs() < 0.1);
+
+        // Implicit German zones resolve to their numeric defaults.
+        let urban = tags(&[("highway", "primary"), ("maxspeed", "DE:urban")]);
+        assert_eq!(speed("de", &urban), 50.0);
+        let rural = tags(&[("highway", "tertiary"), ("maxspeed", "DE:rural")]);
+        assert_eq!(speed("de", &rural), 100.0);
+        let motorway = tags(&[("highway", "motorway"), ("maxspeed", "DE:motorway")]);
+        assert_eq!(speed("de", &motorway), 130.0);
+
+        assert_eq!("UK".parse::<SpeedProfile>(), Ok(SpeedProfile::Uk));
+        assert_eq!(
+            "fr".parse::<SpeedProfile>().unwrap_err().to_string(),
+            "Unknown speed profile fr, expected one of de, us, uk"
+        );
+    }
+
+    #[test]
+    fn test_living_street_speed() {
+        let speed = |metric: &CarSpeed, way: &[(&str, &str)]| {
+            TagMetric::<KilometersPerHour>::calc(metric, &tags(way))
+                .unwrap()
+                .0
         };
-        let from_crs = 4326;
-        let to_crs = 4839;
+        let living_street = [("highway", "living_street")];
+        let de = CarSpeed::new(KilometersPerHour(130.0));
+        assert_eq!(speed(&de, &living_street), 5.0);
+        let uk = CarSpeed::new(KilometersPerHour(130.0)).with_profile(SpeedProfile::Uk);
+        assert!((speed(&uk, &living_street) - 16.1).abs() < 0.1);
 
-        let dist: Result<metrics::Meters, MetricError> =
-            Distance_.calc(&source, &target, from_crs, to_crs);
-        assert_eq!(dist.unwrap(), Meters(4.418689127008047));
+        let shared_space = CarSpeed::new(KilometersPerHour(130.0))
+            .with_living_street_speed(KilometersPerHour(20.0));
+        assert_eq!(speed(&shared_space, &living_street), 20.0);
+        let tagged = [("highway", "residential"), ("maxspeed", "living_street")];
+        assert_eq!(speed(&shared_space, &tagged), 20.0);
+        assert_eq!(speed(&de, &tagged), 10.0);
+        // Explicit limits still win over the living street speed.
+        let signposted = [("highway", "living_street"), ("maxspeed", "7")];
+        assert_eq!(speed(&shared_space, &signposted), 7.0);
+    }
+
+    #[test]
+    fn test_cycle_infrastructure() {
+        let class = |way_tags: &[(&str, &str)]| CycleInfrastructure::from_tags(&tags(way_tags));
+        assert_eq!(
+            class(&[("highway", "primary"), ("cycleway", "track")]),
+            CycleInfrastructure::Track
+        );
+        assert_eq!(
+            class(&[("highway", "secondary"), ("cycleway:right", "lane")]),
+            CycleInfrastructure::Lane
+        );
+        assert_eq!(
+            class(&[("highway", "residential"), ("bicycle_road", "yes")]),
+            CycleInfrastructure::Shared
+        );
+        assert_eq!(
+            class(&[
+                ("highway", "secondary"),
+                ("cycleway:left", "shared_lane"),
+                ("cycleway:right", "lane")
+            ]),
+            CycleInfrastructure::Lane
+        );
+        assert_eq!(
+            class(&[("highway", "cycleway")]),
+            CycleInfrastructure::Track
+        );
+        assert_eq!(
+            class(&[("highway", "primary"), ("cycleway", "no")]),
+            CycleInfrastructure::None
+        );
+        assert_eq!(class(&[("highway", "primary")]), CycleInfrastructure::None);
+    }
+
+    #[test]
+    fn test_bicycle_filter_motor_vehicle_free_ways() {
+        let filter = BicycleEdgeFilter::default();
+        assert!(filter.is_invalid(&tags(&[("highway", "service"), ("motor_vehicle", "no")])));
+        assert!(filter.is_invalid(&tags(&[("highway", "steps"), ("motor_vehicle", "no")])));
+
+        let filter = BicycleEdgeFilter::default().allow_motor_vehicle_free_ways(true);
+        assert!(filter.is_invalid(&tags(&[("highway", "service")])));
+        assert!(!filter.is_invalid(&tags(&[
+            ("highway", "service"),
+            ("motor_vehicle", "no"),
+            ("bicycle", "yes")
+        ])));
+        assert!(!filter.is_invalid(&tags(&[("highway", "service"), ("motor_vehicle", "no")])));
+        assert!(filter.is_invalid(&tags(&[
+            ("highway", "service"),
+            ("motor_vehicle", "no"),
+            ("bicycle", "no")
+        ])));
+        for hard_exclude in ["steps", "motorway", "motorway_link", "trunk", "trunk_link"] {
+            assert!(filter.is_invalid(&tags(&[("highway", hard_exclude), ("motor_vehicle", "no")])));
+        }
+    }
+
+    #[test]
+    fn test_car_filter_surface_blocklist() {
+        let filter = CarEdgeFilter::default();
+        assert!(!filter.is_invalid(&tags(&[("highway", "unclassified")])));
+        assert!(filter.is_invalid(&tags(&[("highway", "unclassified"), ("surface", "mud")])));
+        assert!(filter.is_invalid(&tags(&[("highway", "track"), ("surface", "mud")])));
+
+        let filter = CarEdgeFilter::with_surface_blocklist(["gravel"]);
+        assert!(!filter.is_invalid(&tags(&[("highway", "unclassified"), ("surface", "mud")])));
+        assert!(filter.is_invalid(&tags(&[("highway", "unclassified"), ("surface", "gravel")])));
+    }
+
+    #[test]
+    fn test_vehicle_restrictions() {
+        assert_eq!(parse_weight("7.5"), Some(7.5));
+        assert_eq!(parse_weight("3.5 t"), Some(3.5));
+        assert_eq!(parse_weight("3,5t"), Some(3.5));
+        assert_eq!(parse_weight("7500 kg"), Some(7.5));
+        assert_eq!(parse_weight("none"), None);
+        assert_eq!(parse_length("4"), Some(4.0));
+        assert_eq!(parse_length("3.8 m"), Some(3.8));
+        assert!((parse_length("12'6\"").unwrap() - 3.81).abs() < 1e-9);
+        assert!((parse_length("12'").unwrap() - 3.6576).abs() < 1e-9);
+        assert!((parse_length("10 ft").unwrap() - 3.048).abs() < 1e-9);
+        assert_eq!(parse_length("default"), None);
+
+        let restrictions = VehicleRestrictions::from_tags(&tags(&[
+            ("highway", "residential"),
+            ("maxweight", "7.5"),
+            ("maxheight", "12'6\""),
+            ("hgv", "no"),
+        ]));
+        assert_eq!(restrictions.max_weight, Some(7.5));
+        assert!(restrictions.max_height.is_some());
+        assert_eq!(restrictions.max_width, None);
+        assert!(restrictions.hgv_no);
+
+        let filter = TruckEdgeFilter::default();
+        assert!(!filter.is_invalid(&tags(&[("highway", "residential"), ("hgv", "no")])));
+        assert!(filter.is_invalid(&tags(&[("highway", "footway")])));
+    }
+
+    #[test]
+    fn test_lanes_and_width() {
+        let street = |pairs: &[(&str, &str)]| {
+            let mut street = tags(&[("highway", "residential")]);
+            for (k, v) in pairs {
+                street.insert((*k).into(), (*v).into());
+            }
+            street
+        };
+        assert_eq!(lanes(&street(&[("lanes", "2")])), Some(2));
+        assert_eq!(lanes(&street(&[("lanes", "1;2")])), Some(1));
+        assert_eq!(lanes(&street(&[("lanes", "two")])), None);
+        assert_eq!(lanes(&street(&[])), Some(2));
+        assert_eq!(lanes(&street(&[("oneway", "yes")])), Some(1));
+        assert_eq!(lanes(&tags(&[("highway", "footway")])), None);
+
+        assert_eq!(width(&street(&[("width", "3.5")])), Some(3.5));
+        assert_eq!(width(&street(&[("width", "3 m")])), Some(3.0));
+        assert_eq!(width(&street(&[("width", "wide")])), None);
+        assert_eq!(width(&street(&[])), None);
+    }
+
+    #[test]
+    fn test_layer_bridge_and_tunnel() {
+        let bridge = tags(&[("highway", "primary"), ("bridge", "yes"), ("layer", "1")]);
+        assert_eq!(layer(&bridge), Some(1));
+        assert!(is_bridge(&bridge));
+        assert!(!is_tunnel(&bridge));
+
+        let tunnel = tags(&[
+            ("highway", "primary"),
+            ("tunnel", "building_passage"),
+            ("layer", "-2"),
+        ]);
+        assert_eq!(layer(&tunnel), Some(-2));
+        assert!(is_tunnel(&tunnel));
+        assert!(!is_bridge(&tunnel));
+
+        let street = tags(&[("highway", "primary"), ("bridge", "no"), ("layer", "top")]);
+        assert_eq!(layer(&street), None);
+        assert!(!is_bridge(&street));
+        assert_eq!(layer(&tags(&[("highway", "primary")])), None);
+    }
+
+    #[test]
+    fn test_filter_combinators() {
+        let steps = tags(&[("highway", "steps")]);
+        let trunk_with_cycleway = tags(&[("highway", "trunk"), ("cycleway", "track")]);
+        let residential = tags(&[("highway", "residential")]);
+        let motorway = tags(&[("highway", "motorway")]);
+
+        let both = And(WalkingEdgeFilter::default(), BicycleEdgeFilter::default());
+        assert!(both.is_invalid(&steps));
+        assert!(both.is_invalid(&trunk_with_cycleway));
+        assert!(!both.is_invalid(&residential));
+
+        let either = Or(WalkingEdgeFilter::default(), BicycleEdgeFilter::default());
+        assert!(!either.is_invalid(&steps));
+        assert!(!either.is_invalid(&trunk_with_cycleway));
+        assert!(either.is_invalid(&motorway));
+
+        let footway = tags(&[("highway", "footway")]);
+
+        let car_free = And(WalkingEdgeFilter::default(), Not(CarEdgeFilter::default()));
+        assert!(!car_free.is_invalid(&footway));
+        assert!(car_free.is_invalid(&residential));
     }
 }
@@ -1,4 +1,11 @@
+pub mod gpx;
+pub mod header;
 pub mod metrics;
 pub mod node_pbf;
+pub mod osc;
 pub mod pbf;
+pub mod poi_rtree;
+#[cfg(test)]
+pub(crate) mod test_utils;
+mod tiling;
 pub mod units;
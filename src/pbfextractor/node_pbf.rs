@@ -1,66 +1,453 @@
-use super::pbf::{Latitude, LoaderBuildError, Longitude, OsmNodeId};
-use geo::Point;
-use geo::{Contains, Polygon};
+use super::pbf::{
+    coords, osm_node_id, projection, Latitude, LoaderBuildError, Longitude, OsmNodeId,
+};
+use super::tiling::GeometryFilter;
+use crate::boundary::AdminArea;
+use crate::bounding_box::{polygon_from_wkt, GeometryError};
+use crate::sources::{default_bbox, SourceNotFoundError};
+use chrono::NaiveDateTime;
+use geo::{Area, BoundingRect, Contains, Polygon};
+use geo::{Coord, Distance, Haversine, Point};
 use kiddo::ImmutableKdTree;
 use kiddo::SquaredEuclidean;
 use log::debug;
+use log::info;
 use log::warn;
-use osmpbfreader::{Node, OsmObj, OsmPbfReader};
-use polars::prelude::DataFrame;
+use opening_hours::{OpeningHours, RuleKind};
+use osmpbfreader::{Node, OsmObj, OsmPbfReader, Tags};
+use polars::prelude::{col, Column, DataFrame, DataType, LazyFrame, PolarsError, PolarsResult};
 use polars_io::SerReader;
 use proj4rs::Proj;
+use rstar::primitives::{GeomWithData, Rectangle};
+use rstar::RTree;
 use serde::Serialize;
 use smartstring::{LazyCompact, SmartString};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt::Display;
 use std::fs::File;
 use std::io::BufReader;
 use std::iter::zip;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
 
 pub struct PoiLoader {
     pbf_path: PathBuf,
-    filter_geometry: Option<Polygon>,
+    filter_geometry: Option<GeometryFilter>,
     pub proj_from: Proj,
     pub proj_to: Proj,
-    kdtree: ImmutableKdTree<f64, 2>,
-    nodes_to_match: Vec<super::pbf::Node>,
+    /// `None` if no nodes to match were supplied.
+    matcher: Option<NodeMatcher>,
+    /// Additional node sets, e.g. of the walking and the driving network,
+    /// see [`PoiLoaderBuilder::labeled_nodes_to_match`].
+    labeled_matchers: Vec<(String, Option<NodeMatcher>)>,
+    include_other: bool,
+    skip_lifecycle: bool,
+    only_types: Option<HashSet<PoiType>>,
+    classifier: Option<Arc<Classifier>>,
 }
 
+/// Custom POI classification, see [`PoiLoaderBuilder::classifier`].
+pub type Classifier = dyn Fn(&Tags) -> Option<String> + Send + Sync;
+
+/// Constructed by the loader or through [`PoiBuilder`], so fields can be
+/// added without breaking callers.
 #[derive(Debug, Serialize)]
+#[non_exhaustive]
 pub struct Poi {
     pub osm_id: OsmNodeId,
     pub lat: Latitude,
     pub long: Longitude,
-    pub nearest_osm_node: OsmNodeId,
-    pub dist_to_nearest: f64,
+    /// `None` if the loader was built without nodes to match.
+    pub nearest_osm_node: Option<OsmNodeId>,
+    /// Euclidean distance to the nearest node in the target CRS.
+    pub dist_to_nearest: Option<f64>,
+    /// Great-circle distance to the nearest node in meters, unaffected by the
+    /// distortion of the target CRS.
+    pub dist_to_nearest_haversine: Option<f64>,
     pub poi_type: String,
+    /// Value of the `name` tag, or of `name:en` if the former is absent.
+    pub name: Option<String>,
+    /// The `key=value` tag that made an `"Other"` POI qualify, `None` for all
+    /// specific categories.
+    pub raw_tag: Option<String>,
+    /// Key of the tag that classified the POI, e.g. `shop`.
+    pub matched_key: Option<String>,
+    /// Value of the tag that classified the POI, e.g. `supermarket`.
+    pub matched_value: Option<String>,
+    /// Value of the `wheelchair` tag, e.g. `yes`, `no` or `limited`.
+    pub wheelchair: Option<String>,
+    /// Raw value of the `opening_hours` tag, see [`is_open_at`].
+    pub opening_hours: Option<String>,
+    /// Name of the smallest admin area containing the POI, see
+    /// [`assign_admin_areas`].
+    pub admin_area: Option<String>,
+    /// Nearest node and distance to it per labeled node set, see
+    /// [`PoiLoader::labeled_nearest_columns`].
+    pub labeled_nearest: BTreeMap<String, (OsmNodeId, f64)>,
 }
 
 impl Poi {
-    fn new(
+    pub(crate) fn new(
         osm_id: OsmNodeId,
         lat: Latitude,
         long: Longitude,
-        nearest_osm_node: OsmNodeId,
-        dist_to_nearest: f64,
+        nearest: Option<(OsmNodeId, f64)>,
         poi_type: String,
     ) -> Poi {
         Poi {
             osm_id,
             lat,
             long,
-            nearest_osm_node,
-            dist_to_nearest,
+            nearest_osm_node: nearest.map(|(node, _)| node),
+            dist_to_nearest: nearest.map(|(_, distance)| distance),
+            dist_to_nearest_haversine: None,
             poi_type,
+            name: None,
+            raw_tag: None,
+            matched_key: None,
+            matched_value: None,
+            wheelchair: None,
+            opening_hours: None,
+            admin_area: None,
+            labeled_nearest: BTreeMap::new(),
+        }
+    }
+
+    /// Position as `x = long`, `y = lat`.
+    pub fn coord(&self) -> Coord {
+        Coord {
+            x: self.long,
+            y: self.lat,
         }
     }
 }
 
+impl From<&Poi> for Point {
+    fn from(poi: &Poi) -> Point {
+        poi.coord().into()
+    }
+}
+
+/// Builds a [`Poi`] outside of the loader, e.g. for tests or POIs from other
+/// sources. `osm_id`, `lat`, `long` and `poi_type` are required, all other
+/// fields default to `None`.
+#[derive(Default)]
+pub struct PoiBuilder {
+    osm_id: Option<OsmNodeId>,
+    lat: Option<Latitude>,
+    long: Option<Longitude>,
+    poi_type: Option<String>,
+    nearest_osm_node: Option<OsmNodeId>,
+    dist_to_nearest: Option<f64>,
+    dist_to_nearest_haversine: Option<f64>,
+    name: Option<String>,
+    raw_tag: Option<String>,
+    matched_key: Option<String>,
+    matched_value: Option<String>,
+    wheelchair: Option<String>,
+    opening_hours: Option<String>,
+    admin_area: Option<String>,
+}
+
+impl PoiBuilder {
+    pub fn osm_id(&mut self, value: OsmNodeId) -> &mut Self {
+        self.osm_id = Some(value);
+        self
+    }
+    pub fn lat(&mut self, value: Latitude) -> &mut Self {
+        self.lat = Some(value);
+        self
+    }
+    pub fn long(&mut self, value: Longitude) -> &mut Self {
+        self.long = Some(value);
+        self
+    }
+    pub fn poi_type<VALUE: Into<String>>(&mut self, value: VALUE) -> &mut Self {
+        self.poi_type = Some(value.into());
+        self
+    }
+    /// Sets the nearest node and the distances to it, see [`Poi`].
+    pub fn nearest(&mut self, node: OsmNodeId, distance: f64, haversine: f64) -> &mut Self {
+        self.nearest_osm_node = Some(node);
+        self.dist_to_nearest = Some(distance);
+        self.dist_to_nearest_haversine = Some(haversine);
+        self
+    }
+    pub fn name<VALUE: Into<String>>(&mut self, value: VALUE) -> &mut Self {
+        self.name = Some(value.into());
+        self
+    }
+    pub fn raw_tag<VALUE: Into<String>>(&mut self, value: VALUE) -> &mut Self {
+        self.raw_tag = Some(value.into());
+        self
+    }
+    /// Sets the `key=value` tag that classified the POI.
+    pub fn matched_tag<KEY: Into<String>, VALUE: Into<String>>(
+        &mut self,
+        key: KEY,
+        value: VALUE,
+    ) -> &mut Self {
+        self.matched_key = Some(key.into());
+        self.matched_value = Some(value.into());
+        self
+    }
+    pub fn wheelchair<VALUE: Into<String>>(&mut self, value: VALUE) -> &mut Self {
+        self.wheelchair = Some(value.into());
+        self
+    }
+    pub fn opening_hours<VALUE: Into<String>>(&mut self, value: VALUE) -> &mut Self {
+        self.opening_hours = Some(value.into());
+        self
+    }
+    pub fn admin_area<VALUE: Into<String>>(&mut self, value: VALUE) -> &mut Self {
+        self.admin_area = Some(value.into());
+        self
+    }
+    pub fn build(&self) -> Result<Poi, LoaderBuildError> {
+        let required = |field: &str| LoaderBuildError::new(field.into());
+        Ok(Poi {
+            nearest_osm_node: self.nearest_osm_node,
+            dist_to_nearest: self.dist_to_nearest,
+            dist_to_nearest_haversine: self.dist_to_nearest_haversine,
+            name: self.name.clone(),
+            raw_tag: self.raw_tag.clone(),
+            matched_key: self.matched_key.clone(),
+            matched_value: self.matched_value.clone(),
+            wheelchair: self.wheelchair.clone(),
+            opening_hours: self.opening_hours.clone(),
+            admin_area: self.admin_area.clone(),
+            ..Poi::new(
+                self.osm_id.ok_or_else(|| required("osm_id"))?,
+                self.lat.ok_or_else(|| required("lat"))?,
+                self.long.ok_or_else(|| required("long"))?,
+                None,
+                self.poi_type.clone().ok_or_else(|| required("poi_type"))?,
+            )
+        })
+    }
+}
+
+/// Whether `poi` is open at the local time `datetime` according to its
+/// `opening_hours` tag.
+///
+/// `None` if the tag is absent, cannot be parsed or leaves the state unknown
+/// (e.g. `Mo-Fr 09:00-17:00 unknown`). Public holidays are not known to the
+/// evaluation.
+pub fn is_open_at(poi: &Poi, datetime: NaiveDateTime) -> Option<bool> {
+    let opening_hours: OpeningHours = poi.opening_hours.as_deref()?.parse().ok()?;
+    match opening_hours.state(datetime).0 {
+        RuleKind::Open => Some(true),
+        RuleKind::Closed => Some(false),
+        RuleKind::Unknown => None,
+    }
+}
+
+/// Number of POIs of one `poi_type` inside the sub-polygon at `polygon_index`.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct PolygonPoiCount {
+    pub polygon_index: u32,
+    pub poi_type: String,
+    pub count: u32,
+}
+
+/// Aggregates `pois` per category for each of the disjoint `polygons`.
+///
+/// A POI is attributed to the first polygon containing it; POIs outside of
+/// every polygon are not counted. The result is ordered by polygon index and
+/// POI type.
+pub fn count_pois_per_polygon(pois: &[Poi], polygons: &[Polygon]) -> Vec<PolygonPoiCount> {
+    let mut counts: BTreeMap<(usize, &str), u32> = BTreeMap::new();
+    for poi in pois {
+        let point = Point::new(poi.long, poi.lat);
+        if let Some(index) = polygons.iter().position(|p| p.contains(&point)) {
+            *counts.entry((index, poi.poi_type.as_str())).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .map(|((index, poi_type), count)| PolygonPoiCount {
+            polygon_index: index as u32,
+            poi_type: poi_type.to_owned(),
+            count,
+        })
+        .collect()
+}
+
+/// Sets [`Poi::admin_area`] to the name of the smallest of `areas` containing
+/// each POI, e.g. its suburb rather than its city. POIs outside of every area
+/// keep `None`.
+///
+/// Only the areas whose bounding box contains a POI are tested, found through
+/// an R-tree of the boxes.
+pub fn assign_admin_areas(pois: &mut [Poi], areas: &[AdminArea]) {
+    let envelopes = areas
+        .iter()
+        .enumerate()
+        .filter_map(|(index, area)| {
+            let rect = area.geometry.bounding_rect()?;
+            let envelope =
+                Rectangle::from_corners(rect.min().x_y().into(), rect.max().x_y().into());
+            Some(GeomWithData::new(envelope, index))
+        })
+        .collect();
+    let rtree: RTree<GeomWithData<Rectangle<[f64; 2]>, usize>> = RTree::bulk_load(envelopes);
+    let sizes: Vec<f64> = areas
+        .iter()
+        .map(|area| area.geometry.unsigned_area())
+        .collect();
+    for poi in pois {
+        let point = Point::from(&*poi);
+        poi.admin_area = rtree
+            .locate_all_at_point(&[poi.long, poi.lat])
+            .map(|envelope| envelope.data)
+            .filter(|&index| areas[index].geometry.contains(&point))
+            .min_by(|&a, &b| sizes[a].total_cmp(&sizes[b]))
+            .map(|index| areas[index].name.clone());
+    }
+}
+
+/// Category of a POI, stored as [`PoiType::as_str`] in [`Poi::poi_type`].
+///
+/// Displayed and parsed by its lowercase name, e.g. `grocery`. Parsing
+/// ignores case, so the values of the `poi_type` column parse as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PoiType {
+    Parks,
+    Banks,
+    Health,
+    Education,
+    Sustenance,
+    Grocery,
+    Charging,
+    Shops,
+    Other,
+}
+
+impl PoiType {
+    pub const ALL: [PoiType; 9] = [
+        PoiType::Parks,
+        PoiType::Banks,
+        PoiType::Health,
+        PoiType::Education,
+        PoiType::Sustenance,
+        PoiType::Grocery,
+        PoiType::Charging,
+        PoiType::Shops,
+        PoiType::Other,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PoiType::Parks => "Parks",
+            PoiType::Banks => "Banks",
+            PoiType::Health => "Health",
+            PoiType::Education => "Education",
+            PoiType::Sustenance => "Sustenance",
+            PoiType::Grocery => "Grocery",
+            PoiType::Charging => "Charging",
+            PoiType::Shops => "Shops",
+            PoiType::Other => "Other",
+        }
+    }
+}
+
+impl Display for PoiType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.as_str().to_ascii_lowercase())
+    }
+}
+
+impl FromStr for PoiType {
+    type Err = ParsePoiTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PoiType::ALL
+            .into_iter()
+            .find(|poi_type| poi_type.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParsePoiTypeError { name: s.to_owned() })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsePoiTypeError {
+    name: String,
+}
+
+impl Error for ParsePoiTypeError {}
+
+impl Display for ParsePoiTypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<String> = PoiType::ALL.iter().map(ToString::to_string).collect();
+        write!(
+            f,
+            "Unknown POI type {}, expected one of {}",
+            self.name,
+            names.join(", ")
+        )
+    }
+}
+
+/// Number of `pois` per [`PoiType`]. A [`PoiLoaderBuilder::classifier`] may
+/// assign types beyond [`PoiType::ALL`], these are counted as
+/// [`PoiType::Other`].
+pub fn count_pois_per_type(pois: &[Poi]) -> HashMap<PoiType, usize> {
+    let mut counts = HashMap::new();
+    for poi in pois {
+        *counts.entry(counted_type(poi)).or_default() += 1;
+    }
+    counts
+}
+
+/// [`PoiType`] `poi` is counted as, [`PoiType::Other`] for types of a
+/// [`PoiLoaderBuilder::classifier`] beyond [`PoiType::ALL`].
+fn counted_type(poi: &Poi) -> PoiType {
+    PoiType::from_str(&poi.poi_type).unwrap_or(PoiType::Other)
+}
+
+/// Aggregates `pois` per `nearest_osm_node`, with one `<type>_count` column
+/// per entry of [`PoiType::ALL`] (e.g. `grocery_count`). Types beyond these
+/// are counted as [`PoiType::Other`], like in [`count_pois_per_type`].
+///
+/// POIs without a nearest node are not counted. Rows are ordered by node id.
+pub fn count_pois_per_node(pois: &[Poi]) -> DataFrame {
+    let mut counts: BTreeMap<OsmNodeId, [u32; PoiType::ALL.len()]> = BTreeMap::new();
+    for poi in pois {
+        let Some(node) = poi.nearest_osm_node else {
+            continue;
+        };
+        let poi_type = counted_type(poi);
+        let index = PoiType::ALL
+            .iter()
+            .position(|t| *t == poi_type)
+            .expect("PoiType::ALL lists every type");
+        counts.entry(node).or_default()[index] += 1;
+    }
+    let mut columns = vec![Column::new(
+        "nearest_osm_node".into(),
+        counts.keys().copied().collect::<Vec<_>>(),
+    )];
+    columns.extend(PoiType::ALL.iter().enumerate().map(|(index, poi_type)| {
+        Column::new(
+            format!("{poi_type}_count").into(),
+            counts.values().map(|c| c[index]).collect::<Vec<_>>(),
+        )
+    }));
+    DataFrame::new(columns).expect("columns have equal length")
+}
+
 #[derive(Default)]
 pub struct PoiLoaderBuilder {
     pbf_path: Option<PathBuf>,
     filter_geometry: Option<Polygon>,
     target_crs: Option<u16>,
     nodes_to_match: Option<Vec<super::pbf::Node>>,
+    labeled_nodes_to_match: BTreeMap<String, Vec<super::pbf::Node>>,
+    include_other: bool,
+    skip_lifecycle: Option<bool>,
+    only_types: Option<HashSet<PoiType>>,
+    classifier: Option<Arc<Classifier>>,
 }
 
 #[allow(dead_code)]
@@ -80,11 +467,61 @@ impl PoiLoaderBuilder {
         new.filter_geometry = Some(value.into());
         new
     }
+    /// Parses `value` as a WKT `POLYGON` and uses it as filter geometry.
+    pub fn filter_geometry_wkt(&mut self, value: &str) -> Result<&mut Self, GeometryError> {
+        let new = self;
+        new.filter_geometry = Some(polygon_from_wkt(value)?);
+        Ok(new)
+    }
+    /// Uses the default bounding box of `city` in the catalog as filter
+    /// geometry, see [`default_bbox`]. Errors for cities outside of the
+    /// catalog.
+    pub fn filter_geometry_from_city(
+        &mut self,
+        city: &str,
+    ) -> Result<&mut Self, SourceNotFoundError> {
+        let new = self;
+        let bbox = default_bbox(city).ok_or_else(|| SourceNotFoundError::new(city.into()))?;
+        new.filter_geometry = Some(bbox.into());
+        Ok(new)
+    }
     pub fn target_crs<VALUE: Into<u16>>(&mut self, value: VALUE) -> &mut Self {
         let new = self;
         new.target_crs = Some(value.into());
         new
     }
+    /// Keeps nodes tagged with `shop`, `amenity`, `leisure` or `tourism` that
+    /// match no specific category as POIs of type `"Other"`. Off by default.
+    pub fn include_other(&mut self, value: bool) -> &mut Self {
+        let new = self;
+        new.include_other = value;
+        new
+    }
+    /// Drops nodes whose classifying key also carries a `disused:` or
+    /// `abandoned:` lifecycle prefix, e.g. `shop=bakery` next to
+    /// `disused:shop=bakery`. On by default.
+    pub fn skip_lifecycle(&mut self, value: bool) -> &mut Self {
+        let new = self;
+        new.skip_lifecycle = Some(value);
+        new
+    }
+    /// Only classifies and emits POIs of the given types. `Other` still
+    /// requires [`PoiLoaderBuilder::include_other`].
+    pub fn only_types<VALUE: Into<HashSet<PoiType>>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.only_types = Some(value.into());
+        new
+    }
+    /// Classifies nodes by `classifier` instead of the built-in tag tables:
+    /// nodes it returns a category for become POIs of that `poi_type`, all
+    /// others are skipped. Park ways, `include_other`, `only_types` and
+    /// `skip_lifecycle` are then left to the classifier, and POIs carry no
+    /// matched tag.
+    pub fn classifier(&mut self, value: Box<Classifier>) -> &mut Self {
+        let new = self;
+        new.classifier = Some(value.into());
+        new
+    }
     pub fn nodes_to_match<VALUE: Into<Vec<super::pbf::Node>>>(
         &mut self,
         value: VALUE,
@@ -112,33 +549,41 @@ impl PoiLoaderBuilder {
     }
     pub fn nodes_to_match_polars(&mut self, df: DataFrame) -> &mut Self {
         let new = self;
-        new.nodes_to_match = Some(
-            zip(
-                df.column("osm_id")
-                    .unwrap()
-                    .u64()
-                    .expect("wrong dtype on osm id")
-                    .into_iter(),
-                zip(
-                    df.column("lat")
-                        .unwrap()
-                        .f64()
-                        .expect("Lat has wrong dtype")
-                        .into_iter(),
-                    df.column("long")
-                        .unwrap()
-                        .f64()
-                        .expect("Long has wrong dtype")
-                        .into_iter(),
-                ),
-            )
-            .map(|(osm_id, (lat, long))| {
-                super::pbf::Node::new(osm_id.unwrap(), lat.unwrap(), long.unwrap())
-            })
-            .collect(),
-        );
+        new.nodes_to_match = Some(nodes_from_polars(&df));
+        new
+    }
+    /// Additionally snaps each POI to the nearest node of every labeled set,
+    /// e.g. `walking` and `driving`, see [`PoiLoader::labeled_nearest_columns`].
+    /// Sets with a label that was already added replace the earlier one.
+    pub fn labeled_nodes_to_match<LABEL: Into<String>>(
+        &mut self,
+        label: LABEL,
+        nodes: Vec<super::pbf::Node>,
+    ) -> &mut Self {
+        let new = self;
+        new.labeled_nodes_to_match.insert(label.into(), nodes);
+        new
+    }
+    /// Like [`PoiLoaderBuilder::labeled_nodes_to_match`] for node DataFrames
+    /// with `osm_id`, `lat` and `long` columns, keyed by label.
+    pub fn labeled_nodes_to_match_polars<LABEL: Into<String>>(
+        &mut self,
+        sets: impl IntoIterator<Item = (LABEL, DataFrame)>,
+    ) -> &mut Self {
+        let new = self;
+        for (label, df) in sets {
+            new.labeled_nodes_to_match(label, nodes_from_polars(&df));
+        }
         new
     }
+    /// Collects only the `osm_id`, `lat` and `long` columns of `lf`, so
+    /// filters and projections on it run before the nodes are matched.
+    pub fn nodes_to_match_lazy(&mut self, lf: LazyFrame) -> PolarsResult<&mut Self> {
+        let df = lf
+            .select([col("osm_id"), col("lat"), col("long")])
+            .collect()?;
+        Ok(self.nodes_to_match_polars(df))
+    }
     pub fn build(&self) -> Result<PoiLoader, LoaderBuildError> {
         let target_crs = self
             .target_crs
@@ -146,33 +591,39 @@ impl PoiLoaderBuilder {
             .expect("Requires CRS to be set for any calculation");
         let source_crs = 4326;
 
-        let nodes_to_match = match &self.nodes_to_match {
-            Some(value) => value,
-            None => panic!("Nodes are necessary for matching"),
-        };
-        let mut nodes_projected: Vec<Point> = nodes_to_match
+        let proj_from = projection(source_crs)?;
+        let proj_to = projection(*target_crs)?;
+        // Without nodes to match, POIs are only classified and not snapped.
+        let matcher = self
+            .nodes_to_match
+            .as_ref()
+            .and_then(|nodes| NodeMatcher::new(nodes.clone(), &proj_from, &proj_to));
+        let labeled_matchers = self
+            .labeled_nodes_to_match
             .iter()
-            .map(|n| Point::new(n.long, n.lat).to_radians())
+            .map(|(label, nodes)| {
+                let matcher = NodeMatcher::new(nodes.clone(), &proj_from, &proj_to);
+                (label.clone(), matcher)
+            })
             .collect();
-        let proj_from = proj4rs::Proj::from_epsg_code(source_crs).unwrap();
-        let proj_to = proj4rs::Proj::from_epsg_code(*target_crs).unwrap();
-        nodes_projected
-            .iter_mut()
-            .for_each(|x| proj4rs::transform::transform(&proj_from, &proj_to, x).unwrap());
-        let nodes_projected_arr: Vec<[f64; 2]> =
-            nodes_projected.iter().map(|p| [p.x(), p.y()]).collect();
-        let kdtree = ImmutableKdTree::new_from_slice(&nodes_projected_arr);
 
         Ok(PoiLoader {
             pbf_path: match self.pbf_path {
                 Some(ref value) => Clone::clone(value),
                 None => return Err(LoaderBuildError::new("pbf_path".into())),
             },
-            filter_geometry: Clone::clone(&self.filter_geometry),
+            filter_geometry: self
+                .filter_geometry
+                .as_ref()
+                .map(|polygon| GeometryFilter::new(polygon, false)),
             proj_from,
             proj_to,
-            nodes_to_match: nodes_to_match.to_owned(),
-            kdtree,
+            matcher,
+            labeled_matchers,
+            include_other: self.include_other,
+            skip_lifecycle: self.skip_lifecycle.unwrap_or(true),
+            only_types: self.only_types.clone(),
+            classifier: self.classifier.clone(),
         })
     }
 }
@@ -190,20 +641,17 @@ impl PoiLoader {
         let mut reader = OsmPbfReader::new(fs);
 
         let mut skipped_nodes = 0;
+        let mut negative_ids = 0;
 
         let mut nodes: Vec<Poi> = reader
             .par_iter()
             .filter_map(|obj| {
                 if let Ok(OsmObj::Node(n)) = obj {
-                    let result = process_potential_poi(
-                        &n,
-                        &self.filter_geometry,
-                        &self.proj_from,
-                        &self.proj_to,
-                        &self.kdtree,
-                        &self.nodes_to_match,
-                        None,
-                    );
+                    if osm_node_id(n.id).is_none() {
+                        negative_ids += 1;
+                        return None;
+                    }
+                    let result = self.process_potential_poi(&n, None);
                     match result {
                         Some(poi) => Some(poi),
                         None => {
@@ -216,10 +664,30 @@ impl PoiLoader {
                 }
             })
             .collect();
+        if negative_ids > 0 {
+            warn!("Skipped {negative_ids} nodes with negative ids");
+        }
+
+        if self.classifier.is_none() && self.allows(PoiType::Parks) {
+            reader.rewind().expect("Can't rewind pbf file!");
+            nodes.extend(self.park_way_pois(&mut reader));
+        }
+
+        debug!("Collected {} nodes", nodes.len());
+        let counts = count_pois_per_type(&nodes);
+        let summary: Vec<String> = PoiType::ALL
+            .iter()
+            .filter_map(|poi_type| Some(format!("{poi_type} {}", counts.get(poi_type)?)))
+            .collect();
+        info!("POIs per type: {}", summary.join(", "));
+        debug!("Calculating Metrics");
 
-        reader.rewind().expect("Can't rewind pbf file!");
+        nodes
+    }
 
-        let way_nodes: Vec<Poi> = reader
+    /// POIs for the nodes of ways tagged as parks.
+    fn park_way_pois(&self, reader: &mut OsmPbfReader<File>) -> Vec<Poi> {
+        let park_objs = reader
             .get_objs_and_deps(|obj| {
                 obj.is_way()
                     && PARKS_ATTRIBUTES.iter().any(|(k, v)| {
@@ -232,83 +700,223 @@ impl PoiLoader {
                         }
                     })
             })
-            .unwrap()
-            .iter()
-            .filter_map(|(_, obj)| {
-                if let OsmObj::Node(node) = obj {
-                    process_potential_poi(
-                        node,
-                        &self.filter_geometry,
-                        &self.proj_from,
-                        &self.proj_to,
-                        &self.kdtree,
-                        &self.nodes_to_match,
-                        Some("Parks".into()),
+            .unwrap();
+        let mut park_tags = HashMap::new();
+        for way in park_objs.values().filter_map(OsmObj::way) {
+            if let Some(&tag) = PARKS_ATTRIBUTES
+                .iter()
+                .find(|(k, v)| way.tags.contains(k, v))
+            {
+                park_tags.extend(way.nodes.iter().map(|node| (*node, tag)));
+            }
+        }
+        park_objs
+            .values()
+            .filter_map(|obj| {
+                let node = obj.node()?;
+                let tag = park_tags.get(&node.id)?;
+                self.process_potential_poi(node, Some((PoiType::Parks.as_str().into(), *tag)))
+            })
+            .collect()
+    }
+
+    fn allows(&self, poi_type: PoiType) -> bool {
+        self.only_types
+            .as_ref()
+            .is_none_or(|only_types| only_types.contains(&poi_type))
+    }
+
+    /// Loads the POIs and counts them per nearest node and category, see
+    /// [`count_pois_per_node`]. Requires nodes to match.
+    ///
+    /// Fails with [`PolarsError::NoData`] if no POI was matched to a node,
+    /// e.g. because the nodes to match are in a different CRS or area.
+    pub fn load_poi_counts(&self) -> PolarsResult<DataFrame> {
+        let counts = count_pois_per_node(&self.load_graph());
+        if counts.height() == 0 {
+            return Err(PolarsError::NoData(
+                format!(
+                    "No POI in {} was matched to a node, check the nodes' CRS and area",
+                    self.pbf_path.display()
+                )
+                .into(),
+            ));
+        }
+        Ok(counts)
+    }
+
+    /// `matched` is the category and tag the node was already classified by,
+    /// e.g. for nodes of park ways.
+    fn process_potential_poi(
+        &self,
+        n: &osmpbfreader::Node,
+        matched: Option<(String, (&str, &str))>,
+    ) -> Option<Poi> {
+        let (lat, lng) = coords(n);
+        let point_original = geo::Point::new(lng, lat);
+        if self
+            .filter_geometry
+            .as_ref()
+            .is_some_and(|f| !f.contains(&point_original))
+        {
+            return None;
+        }
+        let (poi_type, matched_tag, raw_tag) = if let Some(classifier) = &self.classifier {
+            (classifier(&n.tags)?, None, None)
+        } else {
+            match matched.or_else(|| identify_type(n, self.only_types.as_ref())) {
+                Some((poi_type, (key, value))) => (poi_type, Some((key, value.to_owned())), None),
+                // Nodes of a specific type left out by `only_types` are not
+                // `Other` either.
+                None if self.include_other
+                    && self.allows(PoiType::Other)
+                    && (self.only_types.is_none() || identify_type(n, None).is_none()) =>
+                {
+                    let (key, value) = identify_other(n)?;
+                    let raw_tag = format!("{key}={value}");
+                    (
+                        PoiType::Other.as_str().into(),
+                        Some((key, value)),
+                        Some(raw_tag),
                     )
-                } else {
-                    None
                 }
+                None => return None,
+            }
+        };
+        if self.skip_lifecycle
+            && matched_tag
+                .as_ref()
+                .is_some_and(|(key, _)| has_lifecycle_prefix(n, key))
+        {
+            return None;
+        }
+        let (matched_key, matched_value) = matched_tag
+            .map(|(key, value)| (key.to_owned(), value))
+            .unzip();
+        let mut point = geo::Point::new(lng, lat).to_radians();
+        proj4rs::transform::transform(&self.proj_from, &self.proj_to, &mut point).unwrap();
+        let nearest = self.matcher.as_ref().map(|matcher| matcher.nearest(point));
+        let labeled_nearest = self
+            .labeled_matchers
+            .iter()
+            .filter_map(|(label, matcher)| {
+                let (node, distance) = matcher.as_ref()?.nearest(point);
+                Some((label.clone(), (node.osm_id, distance)))
             })
             .collect();
+        let dist_to_nearest_haversine = nearest
+            .map(|(node, _)| Haversine.distance(point_original, Point::new(node.long, node.lat)));
+        let nearest = nearest.map(|(node, distance)| (node.osm_id, distance));
+        Some(Poi {
+            dist_to_nearest_haversine,
+            labeled_nearest,
+            raw_tag,
+            matched_key,
+            matched_value,
+            name: n
+                .tags
+                .get("name")
+                .or_else(|| n.tags.get("name:en"))
+                .map(ToString::to_string),
+            wheelchair: n.tags.get("wheelchair").map(ToString::to_string),
+            opening_hours: n.tags.get("opening_hours").map(ToString::to_string),
+            ..Poi::new(osm_node_id(n.id)?, lat, lng, nearest, poi_type)
+        })
+    }
 
-        nodes.extend(way_nodes);
+    /// `nearest_<label>_node` and `dist_to_nearest_<label>` columns of `pois`
+    /// for every labeled node set, in label order. Both are null for POIs
+    /// without a node of the set, e.g. if the set is empty.
+    pub fn labeled_nearest_columns(&self, pois: &[Poi]) -> Vec<Column> {
+        self.labeled_matchers
+            .iter()
+            .flat_map(|(label, _)| {
+                let (nodes, distances): (Vec<Option<OsmNodeId>>, Vec<Option<f64>>) = pois
+                    .iter()
+                    .map(|poi| poi.labeled_nearest.get(label).copied().unzip())
+                    .unzip();
+                [
+                    Column::new(format!("nearest_{label}_node").into(), nodes),
+                    Column::new(format!("dist_to_nearest_{label}").into(), distances),
+                ]
+            })
+            .collect()
+    }
+}
 
-        debug!("Collected {} nodes", nodes.len());
-        debug!("Calculating Metrics");
+/// Nodes to snap POIs to, indexed by their position in the target CRS.
+struct NodeMatcher {
+    kdtree: ImmutableKdTree<f64, 2>,
+    nodes: Vec<super::pbf::Node>,
+}
 
-        nodes
+impl NodeMatcher {
+    /// `None` for an empty network, e.g. of a bounding box at sea, which has
+    /// no node to snap to.
+    fn new(nodes: Vec<super::pbf::Node>, proj_from: &Proj, proj_to: &Proj) -> Option<Self> {
+        if nodes.is_empty() {
+            return None;
+        }
+        let mut nodes_projected: Vec<Point> = nodes
+            .iter()
+            .map(|n| Point::new(n.long, n.lat).to_radians())
+            .collect();
+        nodes_projected
+            .iter_mut()
+            .for_each(|x| proj4rs::transform::transform(proj_from, proj_to, x).unwrap());
+        let nodes_projected_arr: Vec<[f64; 2]> =
+            nodes_projected.iter().map(|p| [p.x(), p.y()]).collect();
+        Some(NodeMatcher {
+            kdtree: ImmutableKdTree::new_from_slice(&nodes_projected_arr),
+            nodes,
+        })
     }
-}
 
-fn process_potential_poi(
-    n: &osmpbfreader::Node,
-    filter_geometry: &Option<Polygon>,
-    proj_from: &proj4rs::Proj,
-    proj_to: &proj4rs::Proj,
-    kdtree: &ImmutableKdTree<f64, 2>,
-    nodes_to_match: &Vec<super::pbf::Node>,
-    poi_type: Option<String>,
-) -> Option<Poi> {
-    let lat = f64::from(n.decimicro_lat) / 10_000_000.0;
-    let lng = f64::from(n.decimicro_lon) / 10_000_000.0;
-    let point_original = geo::Point::new(lng, lat);
-    if filter_geometry
-        .as_ref()
-        .is_some_and(|f| !f.contains(&point_original))
-    {
-        None
-    } else {
-        let mut point = geo::Point::new(lng, lat).to_radians();
-        proj4rs::transform::transform(proj_from, proj_to, &mut point).unwrap();
-        let nearest_node = kdtree.nearest_one::<SquaredEuclidean>(&[point.x(), point.y()]);
-        let osm_nearest_node: &super::pbf::Node = nodes_to_match
-            .get::<usize>(nearest_node.item as usize)
+    /// Nearest node to `point`, given in the target CRS, and the Euclidean
+    /// distance to it.
+    fn nearest(&self, point: Point) -> (&super::pbf::Node, f64) {
+        let nearest_node = self
+            .kdtree
+            .nearest_one::<SquaredEuclidean>(&[point.x(), point.y()]);
+        let osm_nearest_node = self
+            .nodes
+            .get(nearest_node.item as usize)
             .expect("Impossible, all nodes have to exist");
-        if let Some(poi_type_) = poi_type {
-            Some(Poi::new(
-                n.id.0.try_into().unwrap(),
-                lat,
-                lng,
-                osm_nearest_node.osm_id,
-                nearest_node.distance.sqrt(),
-                poi_type_,
-            ))
-        } else {
-            match identify_type(&n) {
-                Some(v) => Some(Poi::new(
-                    n.id.0.try_into().unwrap(),
-                    lat,
-                    lng,
-                    osm_nearest_node.osm_id,
-                    nearest_node.distance.sqrt(),
-                    v,
-                )),
-                None => None,
-            }
-        }
+        (osm_nearest_node, nearest_node.distance.sqrt())
     }
 }
 
+/// Nodes of a DataFrame with `osm_id`, `lat` and `long` columns. Other
+/// numeric dtypes, e.g. `i64` ids or `f32` coordinates, are cast to `u64`
+/// and `f64`.
+fn nodes_from_polars(df: &DataFrame) -> Vec<super::pbf::Node> {
+    let column = |name: &str, dtype: DataType| {
+        df.column(name)
+            .and_then(|c| c.cast(&dtype))
+            .unwrap_or_else(|error| panic!("Can't read {name} of the nodes to match: {error}"))
+    };
+    let (osm_ids, lats, longs) = (
+        column("osm_id", DataType::UInt64),
+        column("lat", DataType::Float64),
+        column("long", DataType::Float64),
+    );
+    zip(
+        osm_ids.u64().expect("Cast to u64"),
+        zip(
+            lats.f64().expect("Cast to f64"),
+            longs.f64().expect("Cast to f64"),
+        ),
+    )
+    .map(|(osm_id, (lat, long))| {
+        super::pbf::Node::new(
+            osm_id.expect("Node without a non-negative osm id"),
+            lat.expect("Node without lat"),
+            long.expect("Node without long"),
+        )
+    })
+    .collect()
+}
+
 const PARKS_ATTRIBUTES: &[(&str, &str)] = &[("leisure", "park"), ("leisure", "dog park")];
 const GROCERY_ATTRIBUTES: &[(&str, &str)] = &[
     ("shop", "alcohol"),
@@ -374,6 +982,7 @@ const SUSTENANCE_ATTRIBUTES: &[(&str, &str)] = &[
     ("amenity", "ice-cream"),
     ("amenity", "biergarten"),
 ];
+const CHARGING_ATTRIBUTES: &[(&str, &str)] = &[("amenity", "charging_station")];
 const SHOPS_QUERY: &[(&str, &str)] = &[
     ("shop", "department store"),
     ("shop", "general"),
@@ -513,40 +1122,776 @@ const SHOPS_QUERY: &[(&str, &str)] = &[
     ("shop", "outpost"),
 ];
 
-fn identify_type(n: &Node) -> Option<String> {
-    let is_park = PARKS_ATTRIBUTES.iter().any(|(k, v)| n.tags.contains(k, v));
-    if is_park {
-        return Some("Parks".into());
+/// Categories in order of precedence, with the tags that select them.
+const CATEGORY_ATTRIBUTES: &[(PoiType, &[(&str, &str)])] = &[
+    (PoiType::Parks, PARKS_ATTRIBUTES),
+    (PoiType::Banks, BANKS_ATTRIBUTES),
+    (PoiType::Health, HEALTH_ATTRIBUTES),
+    (PoiType::Education, EDUCATION_ATTRIBUTES),
+    (PoiType::Sustenance, SUSTENANCE_ATTRIBUTES),
+    (PoiType::Grocery, GROCERY_ATTRIBUTES),
+    (PoiType::Charging, CHARGING_ATTRIBUTES),
+    (PoiType::Shops, SHOPS_QUERY),
+];
+
+/// Returns the category of `n` and the `(key, value)` tag that selected it.
+/// Only the tables of `only_types` are checked, if given.
+fn identify_type(
+    n: &Node,
+    only_types: Option<&HashSet<PoiType>>,
+) -> Option<(String, (&'static str, &'static str))> {
+    CATEGORY_ATTRIBUTES
+        .iter()
+        .filter(|(poi_type, _)| only_types.is_none_or(|only_types| only_types.contains(poi_type)))
+        .find_map(|(poi_type, attributes)| {
+            attributes
+                .iter()
+                .find(|(k, v)| n.tags.contains(k, v))
+                .map(|&tag| (poi_type.as_str().into(), tag))
+        })
+}
+
+/// Prefixes marking a feature that no longer serves its purpose.
+const LIFECYCLE_PREFIXES: &[&str] = &["disused", "abandoned"];
+
+/// Whether `n` carries `key` with a lifecycle prefix, e.g. `disused:shop`.
+fn has_lifecycle_prefix(n: &Node, key: &str) -> bool {
+    LIFECYCLE_PREFIXES
+        .iter()
+        .any(|prefix| n.tags.contains_key(format!("{prefix}:{key}").as_str()))
+}
+
+/// Keys whose presence marks a node as a POI even if it fits no category.
+const OTHER_KEYS: &[&str] = &["shop", "amenity", "leisure", "tourism"];
+
+/// Returns the first tag of `n` with a key from [`OTHER_KEYS`].
+fn identify_other(n: &Node) -> Option<(&'static str, String)> {
+    OTHER_KEYS
+        .iter()
+        .find_map(|&k| n.tags.get(k).map(|v| (k, v.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{LineString, Polygon};
+    use osmpbfreader::{NodeId, Tags};
+
+    fn node(tags: &[(&str, &str)]) -> Node {
+        Node {
+            id: NodeId(1),
+            tags: tags
+                .iter()
+                .map(|(k, v)| ((*k).into(), (*v).into()))
+                .collect::<Tags>(),
+            decimicro_lat: 512_000_000,
+            decimicro_lon: 32_000_000,
+        }
     }
-    let is_bank = BANKS_ATTRIBUTES.iter().any(|(k, v)| n.tags.contains(k, v));
-    if is_bank {
-        return Some("Banks".into());
+
+    #[test]
+    fn test_poi_into_point() {
+        let poi = Poi::new(1, 51.2, 3.2, None, "Banks".into());
+        let point = Point::from(&poi);
+        assert_eq!((point.x(), point.y()), (3.2, 51.2));
+        assert_eq!(point.0, poi.coord());
     }
-    let is_health = HEALTH_ATTRIBUTES.iter().any(|(k, v)| n.tags.contains(k, v));
-    if is_health {
-        return Some("Health".into());
+
+    #[test]
+    fn test_identify_charging_station() {
+        let charging = node(&[("amenity", "charging_station"), ("capacity", "4")]);
+        assert_eq!(
+            identify_type(&charging, None),
+            Some(("Charging".into(), ("amenity", "charging_station")))
+        );
+        assert_eq!(identify_type(&node(&[("amenity", "parking")]), None), None);
     }
-    let is_education = EDUCATION_ATTRIBUTES
-        .iter()
-        .any(|(k, v)| n.tags.contains(k, v));
-    if is_education {
-        return Some("Education".into());
+
+    #[test]
+    fn test_matched_tag() {
+        let bakery = node(&[("shop", "bakery"), ("name", "Bakkerij")]);
+        let poi = PoiLoaderBuilder::default()
+            .pbf_path("unused.osm.pbf")
+            .target_crs(4839u16)
+            .build()
+            .unwrap()
+            .process_potential_poi(&bakery, None)
+            .unwrap();
+        assert_eq!(poi.poi_type, "Grocery");
+        assert_eq!(poi.matched_key, Some("shop".into()));
+        assert_eq!(poi.matched_value, Some("bakery".into()));
     }
-    let is_sustenance = SUSTENANCE_ATTRIBUTES
-        .iter()
-        .any(|(k, v)| n.tags.contains(k, v));
-    if is_sustenance {
-        return Some("Sustenance".into());
+
+    #[test]
+    fn test_negative_node_ids() {
+        use crate::pbfextractor::test_utils::write_pbf_fixture;
+
+        let bakery = |id: i64| {
+            let mut bakery = node(&[("shop", "bakery")]);
+            bakery.id = NodeId(id);
+            OsmObj::Node(bakery)
+        };
+        let path = write_pbf_fixture(
+            "osmtools_negative_poi_ids.osm.pbf",
+            vec![bakery(-1), bakery(1)],
+        );
+        let pois = PoiLoaderBuilder::default()
+            .pbf_path(path)
+            .target_crs(4839u16)
+            .build()
+            .unwrap()
+            .load_graph();
+        assert_eq!(pois.len(), 1);
+        assert_eq!(pois[0].osm_id, 1);
     }
-    let is_grocery = GROCERY_ATTRIBUTES
-        .iter()
-        .any(|(k, v)| n.tags.contains(k, v));
-    if is_grocery {
-        return Some("Grocery".into());
+
+    #[test]
+    fn test_poi_type_names() {
+        for poi_type in PoiType::ALL {
+            let name = poi_type.to_string();
+            assert_eq!(name, name.to_lowercase());
+            assert_eq!(name.parse::<PoiType>(), Ok(poi_type));
+            assert_eq!(poi_type.as_str().parse::<PoiType>(), Ok(poi_type));
+        }
+        assert_eq!(PoiType::Grocery.to_string(), "grocery");
+        assert_eq!("grocery".parse::<PoiType>(), Ok(PoiType::Grocery));
+        let error = "bakery".parse::<PoiType>().unwrap_err();
+        assert!(error.to_string().starts_with("Unknown POI type bakery"));
     }
-    let is_shop = SHOPS_QUERY.iter().any(|(k, v)| n.tags.contains(k, v));
-    if is_shop {
-        return Some("Shops".into());
+
+    #[test]
+    fn test_only_types() {
+        use crate::pbfextractor::test_utils::{way, write_pbf_fixture};
+
+        let poi = |id: i64, poi_tags: &[(&str, &str)]| {
+            let mut poi = node(poi_tags);
+            poi.id = NodeId(id);
+            OsmObj::Node(poi)
+        };
+        let objects = vec![
+            poi(1, &[("shop", "bakery")]),
+            poi(2, &[("amenity", "pharmacy")]),
+            poi(3, &[("amenity", "school")]),
+            poi(4, &[("amenity", "townhall")]),
+            poi(5, &[]),
+            way(10, &[("leisure", "park")], &[5, 1]),
+        ];
+        let path = write_pbf_fixture("osmtools_only_types.osm.pbf", objects);
+        let load = |only_types: &[PoiType]| {
+            let mut pois = PoiLoaderBuilder::default()
+                .pbf_path(path.clone())
+                .target_crs(4839u16)
+                .include_other(true)
+                .only_types(only_types.iter().copied().collect::<HashSet<_>>())
+                .build()
+                .unwrap()
+                .load_graph();
+            pois.sort_by_key(|poi| poi.osm_id);
+            pois.into_iter()
+                .map(|poi| (poi.osm_id, poi.poi_type))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(load(&[PoiType::Grocery]), vec![(1, "Grocery".into())]);
+        assert_eq!(
+            load(&[PoiType::Health, PoiType::Other]),
+            vec![(2, "Health".into()), (4, "Other".into())]
+        );
+        assert_eq!(
+            load(&[PoiType::Parks]),
+            vec![(1, "Parks".into()), (5, "Parks".into())]
+        );
+    }
+
+    #[test]
+    fn test_park_way_matched_tag() {
+        use crate::pbfextractor::test_utils::{node, way, write_pbf_fixture};
+
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            node(3, 51.2060, 3.2270),
+            way(10, &[("leisure", "dog park")], &[1, 2]),
+        ];
+        let path = write_pbf_fixture("osmtools_park_way.osm.pbf", objects);
+        let pois = PoiLoaderBuilder::default()
+            .pbf_path(path)
+            .target_crs(4839u16)
+            .build()
+            .unwrap()
+            .load_graph();
+        assert_eq!(pois.len(), 2);
+        for poi in pois {
+            assert_eq!(poi.poi_type, "Parks");
+            assert_eq!(poi.matched_key, Some("leisure".into()));
+            assert_eq!(poi.matched_value, Some("dog park".into()));
+        }
+    }
+
+    #[test]
+    fn test_skip_lifecycle() {
+        let loader = |skip_lifecycle| {
+            PoiLoaderBuilder::default()
+                .pbf_path("unused.osm.pbf")
+                .target_crs(4839u16)
+                .skip_lifecycle(skip_lifecycle)
+                .build()
+                .unwrap()
+        };
+        let disused = node(&[("disused:shop", "bakery")]);
+        assert!(loader(true).process_potential_poi(&disused, None).is_none());
+        let historic = node(&[("shop", "bakery"), ("disused:shop", "bakery")]);
+        assert!(loader(true)
+            .process_potential_poi(&historic, None)
+            .is_none());
+        assert!(loader(false)
+            .process_potential_poi(&historic, None)
+            .is_some());
+        let abandoned = node(&[("amenity", "bank"), ("abandoned:amenity", "bank")]);
+        assert!(loader(true)
+            .process_potential_poi(&abandoned, None)
+            .is_none());
+        let other_key = node(&[("shop", "bakery"), ("disused:amenity", "cafe")]);
+        assert!(loader(true)
+            .process_potential_poi(&other_key, None)
+            .is_some());
+    }
+
+    #[test]
+    fn test_identify_other() {
+        let townhall = node(&[("amenity", "townhall"), ("name", "Stadhuis")]);
+        assert_eq!(identify_type(&townhall, None), None);
+        assert_eq!(
+            identify_other(&townhall),
+            Some(("amenity", "townhall".into()))
+        );
+        assert_eq!(identify_other(&node(&[("highway", "bus_stop")])), None);
+
+        let loader = |include_other| {
+            PoiLoaderBuilder::default()
+                .pbf_path("unused.osm.pbf")
+                .target_crs(4839u16)
+                .nodes_to_match(vec![super::super::pbf::Node::new(7, 51.2, 3.2)])
+                .include_other(include_other)
+                .build()
+                .unwrap()
+        };
+        assert!(loader(false)
+            .process_potential_poi(&townhall, None)
+            .is_none());
+        let poi = loader(true).process_potential_poi(&townhall, None).unwrap();
+        assert_eq!(poi.poi_type, "Other");
+        assert_eq!(poi.raw_tag, Some("amenity=townhall".into()));
+        assert_eq!(poi.matched_key, Some("amenity".into()));
+        assert_eq!(poi.matched_value, Some("townhall".into()));
+        assert_eq!(poi.nearest_osm_node, Some(7));
+    }
+
+    #[test]
+    fn test_poi_distance_matches_edge_metric() {
+        use crate::pbfextractor::metrics::{Distance_, NodeMetric};
+        use crate::pbfextractor::units::Meters;
+        use geo::{Distance, Haversine};
+
+        let target = super::super::pbf::Node::new(7, 51.2093, 3.2247);
+        let loader = PoiLoaderBuilder::default()
+            .pbf_path("unused.osm.pbf")
+            .target_crs(4839u16)
+            .nodes_to_match(vec![target.clone()])
+            .build()
+            .unwrap();
+        let mut shop = node(&[("shop", "bakery")]);
+        shop.decimicro_lat = 512_080_000;
+        shop.decimicro_lon = 32_270_000;
+        let poi = loader.process_potential_poi(&shop, None).unwrap();
+
+        let source = super::super::pbf::Node::new(1, poi.lat, poi.long);
+        let expected: Meters = Distance_
+            .calc(&source, &target, &loader.proj_from, &loader.proj_to)
+            .unwrap();
+        let dist_to_nearest = poi.dist_to_nearest.unwrap();
+        assert!((dist_to_nearest - expected.0).abs() < 1e-6);
+        let great_circle = Haversine.distance(
+            Point::new(poi.long, poi.lat),
+            Point::new(target.long, target.lat),
+        );
+        assert!((dist_to_nearest - great_circle).abs() / great_circle < 0.01);
+        assert!((poi.dist_to_nearest_haversine.unwrap() - great_circle).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_haversine_distance_in_distorted_crs() {
+        // Web Mercator stretches distances by 1 / cos(lat), about 2.9 at 70°N.
+        let target = super::super::pbf::Node::new(7, 70.0, 25.0);
+        let loader = PoiLoaderBuilder::default()
+            .pbf_path("unused.osm.pbf")
+            .target_crs(3857u16)
+            .nodes_to_match(vec![target.clone()])
+            .build()
+            .unwrap();
+        let mut shop = node(&[("shop", "bakery")]);
+        shop.decimicro_lat = 700_010_000;
+        shop.decimicro_lon = 250_000_000;
+        let poi = loader.process_potential_poi(&shop, None).unwrap();
+
+        let projected = poi.dist_to_nearest.unwrap();
+        let haversine = poi.dist_to_nearest_haversine.unwrap();
+        assert!((haversine - 111.2).abs() < 0.5);
+        let stretch = projected / haversine;
+        assert!((stretch - 1.0 / 70f64.to_radians().cos()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_filter_geometry_from_city() {
+        let mut builder = PoiLoaderBuilder::default();
+        builder.filter_geometry_from_city("koeln").unwrap();
+        // Cologne Cathedral.
+        let center = Point::new(6.9583, 50.9413);
+        assert!(builder.filter_geometry.unwrap().contains(&center));
+        assert!(PoiLoaderBuilder::default()
+            .filter_geometry_from_city("Hogwarts")
+            .is_err());
+    }
+
+    #[test]
+    fn test_poi_builder() {
+        let poi = PoiBuilder::default()
+            .osm_id(7)
+            .lat(51.2)
+            .long(3.2)
+            .poi_type("Grocery")
+            .build()
+            .unwrap();
+        assert_eq!((poi.osm_id, poi.lat, poi.long), (7, 51.2, 3.2));
+        assert_eq!(poi.poi_type, "Grocery");
+        assert_eq!((poi.nearest_osm_node, poi.dist_to_nearest), (None, None));
+        assert_eq!((poi.name, poi.admin_area), (None, None));
+
+        let missing = PoiBuilder::default().osm_id(7).lat(51.2).long(3.2).build();
+        assert_eq!(
+            missing.unwrap_err().to_string(),
+            "Missing required field poi_type"
+        );
+    }
+
+    #[test]
+    fn test_without_nodes_to_match() {
+        use crate::struct_to_dataframe;
+
+        let loader = PoiLoaderBuilder::default()
+            .pbf_path("unused.osm.pbf")
+            .target_crs(4839u16)
+            .build()
+            .unwrap();
+        let bakery = loader
+            .process_potential_poi(&node(&[("shop", "bakery")]), None)
+            .unwrap();
+        assert_eq!(bakery.poi_type, "Grocery");
+        assert_eq!(
+            (bakery.nearest_osm_node, bakery.dist_to_nearest),
+            (None, None)
+        );
+
+        let pois = vec![bakery];
+        let df = struct_to_dataframe!(pois, [osm_id, nearest_osm_node, dist_to_nearest, poi_type])
+            .unwrap();
+        assert_eq!(df.column("nearest_osm_node").unwrap().null_count(), 1);
+        assert_eq!(df.column("dist_to_nearest").unwrap().null_count(), 1);
+    }
+
+    #[test]
+    fn test_nodes_to_match_lazy() {
+        use polars::prelude::{df, lit, IntoLazy};
+
+        let df = df!(
+            "osm_id" => [1u64, 2, 3],
+            "lat" => [51.19, 51.21, 51.22],
+            "long" => [3.22, 3.23, 3.24],
+            "highway" => ["primary", "residential", "service"],
+        )
+        .unwrap();
+        let filter = col("lat").gt(lit(51.2));
+        let mut lazy = PoiLoaderBuilder::default();
+        lazy.nodes_to_match_lazy(df.clone().lazy().filter(filter.clone()))
+            .unwrap();
+        let mut eager = PoiLoaderBuilder::default();
+        eager.nodes_to_match_polars(df.lazy().filter(filter).collect().unwrap());
+        let nodes = |builder: &PoiLoaderBuilder| -> Vec<(u64, f64, f64)> {
+            builder
+                .nodes_to_match
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|n| (n.osm_id, n.lat, n.long))
+                .collect()
+        };
+        assert_eq!(nodes(&lazy), vec![(2, 51.21, 3.23), (3, 51.22, 3.24)]);
+        assert_eq!(nodes(&lazy), nodes(&eager));
+    }
+
+    #[test]
+    fn test_nodes_to_match_parquet_coerces_dtypes() {
+        use polars::prelude::df;
+
+        let mut df = df!(
+            "osm_id" => [1i64, 2],
+            "lat" => [51.25f32, 51.5],
+            "long" => [3.25f32, 3.5],
+        )
+        .unwrap();
+        let path = std::env::temp_dir().join("osmtools_nodes_to_match_dtypes.parquet");
+        polars_io::parquet::write::ParquetWriter::new(File::create(&path).unwrap())
+            .finish(&mut df)
+            .unwrap();
+        let mut builder = PoiLoaderBuilder::default();
+        builder.nodes_to_match_parquet(path.to_str().unwrap());
+        let nodes: Vec<(u64, f64, f64)> = builder
+            .nodes_to_match
+            .unwrap()
+            .iter()
+            .map(|n| (n.osm_id, n.lat, n.long))
+            .collect();
+        assert_eq!(nodes, vec![(1, 51.25, 3.25), (2, 51.5, 3.5)]);
+    }
+
+    #[test]
+    fn test_labeled_nodes_to_match() {
+        use polars::prelude::df;
+
+        let walking = df!(
+            "osm_id" => [1u64, 2],
+            "lat" => [51.2000, 51.2100],
+            "long" => [3.2000, 3.2100],
+        )
+        .unwrap();
+        let driving = df!(
+            "osm_id" => [10u64, 20],
+            "lat" => [51.2050, 51.3000],
+            "long" => [3.2050, 3.3000],
+        )
+        .unwrap();
+        let loader = PoiLoaderBuilder::default()
+            .pbf_path("unused.osm.pbf")
+            .target_crs(4839u16)
+            .labeled_nodes_to_match_polars([("walking", walking), ("driving", driving)])
+            .build()
+            .unwrap();
+        let mut shop = node(&[("shop", "bakery")]);
+        shop.decimicro_lat = 512_040_000;
+        shop.decimicro_lon = 32_040_000;
+        let poi = loader.process_potential_poi(&shop, None).unwrap();
+        assert_eq!(poi.nearest_osm_node, None);
+
+        let columns = loader.labeled_nearest_columns(&[poi]);
+        let names: Vec<&str> = columns.iter().map(|c| c.name().as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "nearest_driving_node",
+                "dist_to_nearest_driving",
+                "nearest_walking_node",
+                "dist_to_nearest_walking"
+            ]
+        );
+        assert_eq!(columns[0].u64().unwrap().get(0), Some(10));
+        assert_eq!(columns[2].u64().unwrap().get(0), Some(1));
+        assert!(columns.iter().all(|c| c.null_count() == 0));
+    }
+
+    #[test]
+    fn test_classifier() {
+        let loader = PoiLoaderBuilder::default()
+            .pbf_path("unused.osm.pbf")
+            .target_crs(4839u16)
+            .classifier(Box::new(|tags| {
+                tags.contains("atm", "yes").then(|| "Atm".to_owned())
+            }))
+            .build()
+            .unwrap();
+        let bank = loader
+            .process_potential_poi(&node(&[("amenity", "bank"), ("atm", "yes")]), None)
+            .unwrap();
+        assert_eq!(bank.poi_type, "Atm");
+        assert_eq!(bank.matched_key, None);
+        assert!(loader
+            .process_potential_poi(&node(&[("amenity", "bank")]), None)
+            .is_none());
+        assert!(loader
+            .process_potential_poi(&node(&[("shop", "bakery")]), None)
+            .is_none());
+    }
+
+    #[test]
+    fn test_empty_nodes_to_match() {
+        let loader = PoiLoaderBuilder::default()
+            .pbf_path("unused.osm.pbf")
+            .target_crs(4839u16)
+            .nodes_to_match(vec![])
+            .build()
+            .unwrap();
+        let bakery = loader
+            .process_potential_poi(&node(&[("shop", "bakery")]), None)
+            .unwrap();
+        assert_eq!(
+            (bakery.nearest_osm_node, bakery.dist_to_nearest),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn test_wheelchair() {
+        use crate::struct_to_dataframe;
+
+        let loader = PoiLoaderBuilder::default()
+            .pbf_path("unused.osm.pbf")
+            .target_crs(4839u16)
+            .build()
+            .unwrap();
+        let pharmacy = loader
+            .process_potential_poi(
+                &node(&[("amenity", "pharmacy"), ("wheelchair", "yes")]),
+                None,
+            )
+            .unwrap();
+        assert_eq!(pharmacy.wheelchair.as_deref(), Some("yes"));
+        let untagged = loader
+            .process_potential_poi(&node(&[("amenity", "pharmacy")]), None)
+            .unwrap();
+        assert_eq!(untagged.wheelchair, None);
+
+        let pois = vec![pharmacy, untagged];
+        let df = struct_to_dataframe!(pois, [osm_id, wheelchair]).unwrap();
+        let wheelchair = df.column("wheelchair").unwrap().str().unwrap();
+        assert_eq!(wheelchair.get(0), Some("yes"));
+        assert_eq!(wheelchair.null_count(), 1);
+    }
+
+    #[test]
+    fn test_is_open_at() {
+        let loader = PoiLoaderBuilder::default()
+            .pbf_path("unused.osm.pbf")
+            .target_crs(4839u16)
+            .build()
+            .unwrap();
+        let pharmacy = |opening_hours: Option<&str>| {
+            let mut tags = vec![("amenity", "pharmacy")];
+            tags.extend(opening_hours.map(|value| ("opening_hours", value)));
+            loader.process_potential_poi(&node(&tags), None).unwrap()
+        };
+        let at =
+            |datetime: &str| NaiveDateTime::parse_from_str(datetime, "%Y-%m-%d %H:%M").unwrap();
+        let tuesday_noon = at("2024-06-04 12:00");
+        let sunday_noon = at("2024-06-09 12:00");
+
+        let office_hours = pharmacy(Some("Mo-Fr 09:00-17:00"));
+        assert_eq!(
+            office_hours.opening_hours.as_deref(),
+            Some("Mo-Fr 09:00-17:00")
+        );
+        assert_eq!(is_open_at(&office_hours, tuesday_noon), Some(true));
+        assert_eq!(is_open_at(&office_hours, sunday_noon), Some(false));
+        assert_eq!(
+            is_open_at(&office_hours, at("2024-06-04 17:30")),
+            Some(false)
+        );
+
+        assert_eq!(is_open_at(&pharmacy(None), tuesday_noon), None);
+        assert_eq!(is_open_at(&pharmacy(Some("whenever")), tuesday_noon), None);
+    }
+
+    fn square(min_x: f64, min_y: f64, size: f64) -> Polygon {
+        Polygon::new(
+            LineString::from(vec![
+                (min_x, min_y),
+                (min_x + size, min_y),
+                (min_x + size, min_y + size),
+                (min_x, min_y + size),
+                (min_x, min_y),
+            ]),
+            vec![],
+        )
+    }
+
+    #[test]
+    fn test_assign_admin_areas() {
+        let areas = vec![
+            AdminArea {
+                name: "Brugge".into(),
+                geometry: square(0.0, 0.0, 4.0).into(),
+            },
+            AdminArea {
+                name: "Sint-Kruis".into(),
+                geometry: square(1.0, 1.0, 1.0).into(),
+            },
+        ];
+        let mut pois = vec![
+            Poi::new(1, 1.5, 1.5, None, "Shops".into()),
+            Poi::new(2, 3.0, 3.0, None, "Shops".into()),
+            Poi::new(3, 5.0, 5.0, None, "Shops".into()),
+        ];
+        assign_admin_areas(&mut pois, &areas);
+        assert_eq!(pois[0].admin_area.as_deref(), Some("Sint-Kruis"));
+        assert_eq!(pois[1].admin_area.as_deref(), Some("Brugge"));
+        assert_eq!(pois[2].admin_area, None);
+    }
+
+    #[test]
+    fn test_count_pois_per_polygon() {
+        let polygons = vec![square(0.0, 0.0, 1.0), square(2.0, 2.0, 1.0)];
+        let pois = vec![
+            Poi::new(1, 0.5, 0.5, Some((10, 0.0)), "Shops".into()),
+            Poi::new(2, 0.6, 0.4, Some((10, 0.0)), "Shops".into()),
+            Poi::new(3, 0.2, 0.2, Some((10, 0.0)), "Banks".into()),
+            Poi::new(4, 2.5, 2.5, Some((11, 0.0)), "Shops".into()),
+            Poi::new(5, 5.0, 5.0, Some((12, 0.0)), "Shops".into()),
+        ];
+        let counts = count_pois_per_polygon(&pois, &polygons);
+        assert_eq!(
+            counts,
+            vec![
+                PolygonPoiCount {
+                    polygon_index: 0,
+                    poi_type: "Banks".into(),
+                    count: 1
+                },
+                PolygonPoiCount {
+                    polygon_index: 0,
+                    poi_type: "Shops".into(),
+                    count: 2
+                },
+                PolygonPoiCount {
+                    polygon_index: 1,
+                    poi_type: "Shops".into(),
+                    count: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_count_pois_per_node() {
+        let pois = vec![
+            Poi::new(1, 0.5, 0.5, Some((10, 0.0)), "Grocery".into()),
+            Poi::new(2, 0.6, 0.4, Some((10, 0.0)), "Grocery".into()),
+            Poi::new(3, 0.2, 0.2, Some((10, 0.0)), "Health".into()),
+            Poi::new(4, 2.5, 2.5, Some((11, 0.0)), "Shops".into()),
+            Poi::new(5, 5.0, 5.0, None, "Shops".into()),
+            Poi::new(6, 2.4, 2.4, Some((11, 0.0)), "bench".into()),
+        ];
+        let df = count_pois_per_node(&pois);
+        assert_eq!(df.shape(), (2, PoiType::ALL.len() + 1));
+        let counts = |name: &str| -> Vec<u32> {
+            df.column(name)
+                .unwrap()
+                .u32()
+                .unwrap()
+                .into_no_null_iter()
+                .collect()
+        };
+        assert_eq!(
+            df.column("nearest_osm_node")
+                .unwrap()
+                .u64()
+                .unwrap()
+                .into_no_null_iter()
+                .collect::<Vec<_>>(),
+            vec![10, 11]
+        );
+        assert_eq!(counts("grocery_count"), vec![2, 0]);
+        assert_eq!(counts("health_count"), vec![1, 0]);
+        assert_eq!(counts("shops_count"), vec![0, 1]);
+        assert_eq!(counts("other_count"), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_load_poi_counts_without_matches() {
+        use crate::pbfextractor::test_utils::write_pbf_fixture;
+
+        let mut bakery = node(&[("shop", "bakery")]);
+        bakery.id = NodeId(1);
+        let path = write_pbf_fixture(
+            "osmtools_unmatched_poi_counts.osm.pbf",
+            vec![OsmObj::Node(bakery)],
+        );
+        let counts = PoiLoaderBuilder::default()
+            .pbf_path(path)
+            .target_crs(4839u16)
+            .build()
+            .unwrap()
+            .load_poi_counts();
+        assert!(matches!(counts, Err(PolarsError::NoData(_))));
+    }
+
+    #[test]
+    fn test_count_pois_per_type() {
+        let pois = vec![
+            Poi::new(1, 0.5, 0.5, None, "Grocery".into()),
+            Poi::new(2, 0.6, 0.4, None, "Grocery".into()),
+            Poi::new(3, 0.2, 0.2, None, "Health".into()),
+            Poi::new(4, 2.5, 2.5, None, "bench".into()),
+        ];
+        assert_eq!(
+            count_pois_per_type(&pois),
+            HashMap::from([
+                (PoiType::Grocery, 2),
+                (PoiType::Health, 1),
+                (PoiType::Other, 1)
+            ])
+        );
+    }
+
+    #[test]
+    fn integration_test_count_pois_per_type() {
+        let pois = PoiLoaderBuilder::default()
+            .target_crs(4839u16)
+            .filter_geometry(square(3.22183, 51.20391, 0.01))
+            .pbf_path("data/bruegge.osm.pbf")
+            .build()
+            .unwrap()
+            .load_graph();
+        let counts = count_pois_per_type(&pois);
+        assert!(counts.len() > 1);
+        assert_eq!(counts.values().sum::<usize>(), pois.len());
+        // Every category on its own yields as many POIs as counted for it.
+        for poi_type in PoiType::ALL {
+            let only = PoiLoaderBuilder::default()
+                .target_crs(4839u16)
+                .filter_geometry(square(3.22183, 51.20391, 0.01))
+                .pbf_path("data/bruegge.osm.pbf")
+                .only_types([poi_type])
+                .build()
+                .unwrap()
+                .load_graph();
+            assert_eq!(
+                only.len(),
+                counts.get(&poi_type).copied().unwrap_or(0),
+                "{poi_type}"
+            );
+        }
+    }
+
+    #[test]
+    fn integration_test_load_poi_counts() {
+        let loader = PoiLoaderBuilder::default()
+            .target_crs(4839u16)
+            .filter_geometry(square(3.22183, 51.20391, 0.01))
+            .pbf_path("data/bruegge.osm.pbf")
+            .nodes_to_match_parquet("test/bruegge_poitest_walking_nodes.parquet")
+            .build()
+            .unwrap();
+        let total = loader.load_graph().len() as u32;
+        let df = loader.load_poi_counts().unwrap();
+        let counted: u32 = PoiType::ALL
+            .iter()
+            .map(|t| {
+                df.column(&format!("{t}_count"))
+                    .unwrap()
+                    .u32()
+                    .unwrap()
+                    .into_no_null_iter()
+                    .sum::<u32>()
+            })
+            .sum();
+        assert!(total > 0);
+        assert_eq!(counted, total);
     }
-    None
 }
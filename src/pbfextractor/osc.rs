@@ -0,0 +1,642 @@
+//! Applying OsmChange (`.osc`) diffs to a PBF extract, so a regularly
+//! refreshed extract does not have to be downloaded in full again.
+use super::header::{blob_indexdata, validate_pbf_header, PbfHeaderError};
+use super::pbf::{coords, DECIMICRO};
+use flate2::read::GzDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use geo::{BoundingRect, MultiPoint, Point};
+use log::debug;
+use osmpbfreader::fileformat::{Blob, BlobHeader};
+use osmpbfreader::osmformat::{self, relation::MemberType, HeaderBlock, PrimitiveBlock};
+use osmpbfreader::{
+    Node, NodeId, OsmId, OsmObj, OsmPbfReader, Ref, Relation, RelationId, Tags, Way, WayId,
+};
+use protobuf::{EnumOrUnknown, Message};
+use quick_xml::events::attributes::AttrError;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::mem::discriminant;
+use std::path::Path;
+
+/// Objects per `OSMData` block, as recommended by the PBF specification.
+const BLOCK_SIZE: usize = 8000;
+
+#[derive(Debug)]
+pub enum OscError {
+    /// The change file is not valid OsmChange XML.
+    InvalidOsc(String),
+    /// The base file is not a PBF file `osmpbfreader` can decode.
+    InvalidPbf(String),
+    Io(io::Error),
+}
+
+impl Error for OscError {}
+impl Display for OscError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OscError::InvalidOsc(reason) => write!(f, "Invalid OsmChange file: {reason}"),
+            OscError::InvalidPbf(reason) => write!(f, "Invalid PBF file: {reason}"),
+            OscError::Io(error) => write!(f, "Could not apply OsmChange file: {error}"),
+        }
+    }
+}
+
+impl From<io::Error> for OscError {
+    fn from(error: io::Error) -> Self {
+        OscError::Io(error)
+    }
+}
+
+impl From<quick_xml::Error> for OscError {
+    fn from(error: quick_xml::Error) -> Self {
+        OscError::InvalidOsc(error.to_string())
+    }
+}
+
+impl From<AttrError> for OscError {
+    fn from(error: AttrError) -> Self {
+        OscError::InvalidOsc(error.to_string())
+    }
+}
+
+impl From<osmpbfreader::Error> for OscError {
+    fn from(error: osmpbfreader::Error) -> Self {
+        OscError::InvalidPbf(error.to_string())
+    }
+}
+
+impl From<PbfHeaderError> for OscError {
+    fn from(error: PbfHeaderError) -> Self {
+        match error {
+            PbfHeaderError::Io(error) => OscError::Io(error),
+            other => OscError::InvalidPbf(other.to_string()),
+        }
+    }
+}
+
+impl From<protobuf::Error> for OscError {
+    fn from(error: protobuf::Error) -> Self {
+        OscError::InvalidPbf(error.to_string())
+    }
+}
+
+/// Applies the OsmChange file `osc` to `base_pbf` and writes the result to
+/// `out`.
+///
+/// Created and modified nodes, ways and relations replace the base object of
+/// the same id, deleted ones are dropped. `osc` may be gzip compressed if its
+/// name ends in `.gz`.
+///
+/// The changes are merged into the base objects in order, so `base_pbf` has
+/// to be sorted by type and id, as extracts from Geofabrik or osmium are. The
+/// output is then sorted as well; an unsorted base fails with
+/// [`OscError::InvalidPbf`].
+pub fn apply_osc(base_pbf: &Path, osc: &Path, out: &Path) -> Result<(), OscError> {
+    validate_pbf_header(base_pbf)?;
+    let mut changes = read_osc(osc)?;
+    debug!(
+        "Applying {} changes to {}",
+        changes.len(),
+        base_pbf.display()
+    );
+
+    let mut reader = OsmPbfReader::new(BufReader::new(File::open(base_pbf)?));
+    let mut writer = PbfWriter::new(BufWriter::new(File::create(out)?))?;
+    let mut previous: Option<OsmId> = None;
+    for obj in reader.iter() {
+        let obj = obj?;
+        let id = obj.id();
+        if let Some(previous) = previous.filter(|previous| *previous >= id) {
+            return Err(OscError::InvalidPbf(format!(
+                "objects are not sorted by type and id, {id:?} follows {previous:?}"
+            )));
+        }
+        previous = Some(id);
+        // Changes of ids before `obj` create objects missing from the base.
+        while changes
+            .first_key_value()
+            .is_some_and(|(changed, _)| *changed < id)
+        {
+            if let Some((_, Some(created))) = changes.pop_first() {
+                writer.push(created)?;
+            }
+        }
+        match changes.remove(&id) {
+            Some(Some(changed)) => writer.push(changed)?,
+            Some(None) => {}
+            None => writer.push(obj)?,
+        }
+    }
+    for created in changes.into_values().flatten() {
+        writer.push(created)?;
+    }
+    writer.finish()
+}
+
+/// Reads the changes of an OsmChange file; deleted objects map to `None`.
+fn read_osc(path: &Path) -> Result<BTreeMap<OsmId, Option<OsmObj>>, OscError> {
+    let file = File::open(path)?;
+    let input: Box<dyn Read> = if path.extension().is_some_and(|e| e == "gz") {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    parse_osc(BufReader::new(input))
+}
+
+fn parse_osc<R: BufRead>(input: R) -> Result<BTreeMap<OsmId, Option<OsmObj>>, OscError> {
+    let mut reader = Reader::from_reader(input);
+    let mut buf = Vec::new();
+    let mut changes = BTreeMap::new();
+    let mut deleting = None;
+    let mut current: Option<OsmObj> = None;
+    loop {
+        let (element, is_empty) = match reader.read_event_into(&mut buf)? {
+            Event::Start(element) => (element, false),
+            Event::Empty(element) => (element, true),
+            Event::End(element) => {
+                match element.name().as_ref() {
+                    b"create" | b"modify" | b"delete" => deleting = None,
+                    b"node" | b"way" | b"relation" => {
+                        finish_object(&mut changes, current.take(), deleting)?
+                    }
+                    _ => {}
+                }
+                buf.clear();
+                continue;
+            }
+            Event::Eof => break,
+            _ => {
+                buf.clear();
+                continue;
+            }
+        };
+        match element.name().as_ref() {
+            b"create" | b"modify" => deleting = Some(false),
+            b"delete" => deleting = Some(true),
+            b"node" | b"way" | b"relation" => {
+                let obj = start_object(&element, deleting == Some(true))?;
+                if is_empty {
+                    finish_object(&mut changes, Some(obj), deleting)?;
+                } else {
+                    current = Some(obj);
+                }
+            }
+            b"tag" => {
+                if let Some(obj) = current.as_mut() {
+                    let tags = match obj {
+                        OsmObj::Node(node) => &mut node.tags,
+                        OsmObj::Way(way) => &mut way.tags,
+                        OsmObj::Relation(relation) => &mut relation.tags,
+                    };
+                    tags.insert(
+                        attribute(&element, "k")?.into(),
+                        attribute(&element, "v")?.into(),
+                    );
+                }
+            }
+            b"nd" => {
+                if let Some(OsmObj::Way(way)) = current.as_mut() {
+                    way.nodes.push(NodeId(parse(&element, "ref")?));
+                }
+            }
+            b"member" => {
+                if let Some(OsmObj::Relation(relation)) = current.as_mut() {
+                    let id = parse(&element, "ref")?;
+                    let member = match attribute(&element, "type")?.as_str() {
+                        "node" => NodeId(id).into(),
+                        "way" => WayId(id).into(),
+                        "relation" => RelationId(id).into(),
+                        other => {
+                            return Err(OscError::InvalidOsc(format!(
+                                "unknown member type {other}"
+                            )))
+                        }
+                    };
+                    relation.refs.push(Ref {
+                        member,
+                        role: attribute(&element, "role")?.into(),
+                    });
+                }
+            }
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(changes)
+}
+
+/// Object of an OsmChange element without its children. Deleted nodes need
+/// no coordinates.
+fn start_object(element: &BytesStart, deleted: bool) -> Result<OsmObj, OscError> {
+    let id = parse(element, "id")?;
+    Ok(match element.name().as_ref() {
+        b"node" => {
+            let (lat, lon): (f64, f64) = if deleted {
+                (0.0, 0.0)
+            } else {
+                (parse(element, "lat")?, parse(element, "lon")?)
+            };
+            OsmObj::Node(Node {
+                id: NodeId(id),
+                tags: Tags::new(),
+                decimicro_lat: (lat * DECIMICRO).round() as i32,
+                decimicro_lon: (lon * DECIMICRO).round() as i32,
+            })
+        }
+        b"way" => OsmObj::Way(Way {
+            id: WayId(id),
+            tags: Tags::new(),
+            nodes: Vec::new(),
+        }),
+        _ => OsmObj::Relation(Relation {
+            id: RelationId(id),
+            tags: Tags::new(),
+            refs: Vec::new(),
+        }),
+    })
+}
+
+fn finish_object(
+    changes: &mut BTreeMap<OsmId, Option<OsmObj>>,
+    obj: Option<OsmObj>,
+    deleting: Option<bool>,
+) -> Result<(), OscError> {
+    let Some(obj) = obj else {
+        return Ok(());
+    };
+    match deleting {
+        Some(true) => changes.insert(obj.id(), None),
+        Some(false) => changes.insert(obj.id(), Some(obj)),
+        None => {
+            return Err(OscError::InvalidOsc(format!(
+                "{:?} outside of create, modify or delete",
+                obj.id()
+            )))
+        }
+    };
+    Ok(())
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<String, OscError> {
+    match element.try_get_attribute(name)? {
+        Some(value) => Ok(value.unescape_value()?.into_owned()),
+        None => Err(OscError::InvalidOsc(format!(
+            "missing attribute {name} on {}",
+            String::from_utf8_lossy(element.name().as_ref())
+        ))),
+    }
+}
+
+fn parse<T: std::str::FromStr>(element: &BytesStart, name: &str) -> Result<T, OscError> {
+    let value = attribute(element, name)?;
+    value
+        .parse()
+        .map_err(|_| OscError::InvalidOsc(format!("invalid {name} {value:?}")))
+}
+
+/// Writes objects as zlib compressed blocks of a single object type.
+pub(super) struct PbfWriter<W: Write> {
+    writer: W,
+    block: Vec<OsmObj>,
+}
+
+impl<W: Write> PbfWriter<W> {
+    pub(super) fn new(mut writer: W) -> Result<Self, OscError> {
+        let mut header = HeaderBlock::new();
+        header.required_features = vec!["OsmSchema-V0.6".into()];
+        header.set_writingprogram("osmtools".into());
+        write_blob(&mut writer, "OSMHeader", &header.write_to_bytes()?, None)?;
+        Ok(PbfWriter {
+            writer,
+            block: Vec::with_capacity(BLOCK_SIZE),
+        })
+    }
+
+    pub(super) fn push(&mut self, obj: OsmObj) -> Result<(), OscError> {
+        if self.block.len() == BLOCK_SIZE
+            || self
+                .block
+                .first()
+                .is_some_and(|first| discriminant(first) != discriminant(&obj))
+        {
+            self.flush_block()?;
+        }
+        self.block.push(obj);
+        Ok(())
+    }
+
+    fn flush_block(&mut self) -> Result<(), OscError> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let block = encode_block(&self.block);
+        let points: MultiPoint = self
+            .block
+            .iter()
+            .filter_map(OsmObj::node)
+            .map(|node| {
+                let (lat, lon) = coords(node);
+                Point::new(lon, lat)
+            })
+            .collect();
+        // Node blocks carry their extent, so readers can skip them.
+        let indexdata = points.bounding_rect().map(blob_indexdata);
+        write_blob(
+            &mut self.writer,
+            "OSMData",
+            &block.write_to_bytes()?,
+            indexdata,
+        )?;
+        self.block.clear();
+        Ok(())
+    }
+
+    pub(super) fn finish(mut self) -> Result<(), OscError> {
+        self.flush_block()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn write_blob<W: Write>(
+    writer: &mut W,
+    blob_type: &str,
+    data: &[u8],
+    indexdata: Option<Vec<u8>>,
+) -> Result<(), OscError> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    let mut blob = Blob::new();
+    blob.set_raw_size(data.len() as i32);
+    blob.set_zlib_data(encoder.finish()?);
+    let blob = blob.write_to_bytes()?;
+    let mut header = BlobHeader::new();
+    header.set_type(blob_type.into());
+    header.set_datasize(blob.len() as i32);
+    header.indexdata = indexdata;
+    let header = header.write_to_bytes()?;
+    writer.write_all(&(header.len() as u32).to_be_bytes())?;
+    writer.write_all(&header)?;
+    writer.write_all(&blob)?;
+    Ok(())
+}
+
+/// Encodes `objects` into a block with the default granularity of 100
+/// nanodegrees, so coordinates are stored in decimicro degrees as they are.
+fn encode_block(objects: &[OsmObj]) -> PrimitiveBlock {
+    let mut strings = StringTable::default();
+    let mut group = osmformat::PrimitiveGroup::new();
+    for obj in objects {
+        match obj {
+            OsmObj::Node(node) => {
+                let mut encoded = osmformat::Node::new();
+                encoded.set_id(node.id.0);
+                encoded.set_lat(node.decimicro_lat.into());
+                encoded.set_lon(node.decimicro_lon.into());
+                (encoded.keys, encoded.vals) = strings.tags(&node.tags);
+                group.nodes.push(encoded);
+            }
+            OsmObj::Way(way) => {
+                let mut encoded = osmformat::Way::new();
+                encoded.set_id(way.id.0);
+                (encoded.keys, encoded.vals) = strings.tags(&way.tags);
+                encoded.refs = delta_encode(way.nodes.iter().map(|n| n.0));
+                group.ways.push(encoded);
+            }
+            OsmObj::Relation(relation) => {
+                let mut encoded = osmformat::Relation::new();
+                encoded.set_id(relation.id.0);
+                (encoded.keys, encoded.vals) = strings.tags(&relation.tags);
+                encoded.memids = delta_encode(relation.refs.iter().map(|r| r.member.inner_id()));
+                encoded.roles_sid = relation
+                    .refs
+                    .iter()
+                    .map(|r| strings.index(&r.role) as i32)
+                    .collect();
+                encoded.types = relation
+                    .refs
+                    .iter()
+                    .map(|r| {
+                        EnumOrUnknown::new(match r.member {
+                            OsmId::Node(_) => MemberType::NODE,
+                            OsmId::Way(_) => MemberType::WAY,
+                            OsmId::Relation(_) => MemberType::RELATION,
+                        })
+                    })
+                    .collect();
+                group.relations.push(encoded);
+            }
+        }
+    }
+    let mut block = PrimitiveBlock::new();
+    block.stringtable.mut_or_insert_default().s = strings.table;
+    block.primitivegroup.push(group);
+    block
+}
+
+fn delta_encode(ids: impl Iterator<Item = i64>) -> Vec<i64> {
+    let mut last = 0;
+    ids.map(|id| {
+        let delta = id - last;
+        last = id;
+        delta
+    })
+    .collect()
+}
+
+/// String table of a block. Index 0 is the empty string, which the PBF format
+/// reserves as delimiter.
+struct StringTable {
+    table: Vec<Vec<u8>>,
+    indices: HashMap<String, u32>,
+}
+
+impl Default for StringTable {
+    fn default() -> Self {
+        StringTable {
+            table: vec![Vec::new()],
+            indices: HashMap::from([(String::new(), 0)]),
+        }
+    }
+}
+
+impl StringTable {
+    fn index(&mut self, value: &str) -> u32 {
+        if let Some(&index) = self.indices.get(value) {
+            return index;
+        }
+        let index = self.table.len() as u32;
+        self.table.push(value.as_bytes().to_vec());
+        self.indices.insert(value.to_owned(), index);
+        index
+    }
+
+    fn tags(&mut self, tags: &Tags) -> (Vec<u32>, Vec<u32>) {
+        tags.iter()
+            .map(|(key, value)| (self.index(key), self.index(value)))
+            .unzip()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pbfextractor::test_utils::{car_loader_builder, node, tags, write_pbf_fixture};
+    use flate2::write::GzEncoder;
+    use std::path::PathBuf;
+
+    fn street(id: i64, nodes: &[i64]) -> OsmObj {
+        OsmObj::Way(Way {
+            id: WayId(id),
+            tags: tags(&[("highway", "residential"), ("name", "Langestraat")]),
+            nodes: nodes.iter().map(|&n| NodeId(n)).collect(),
+        })
+    }
+
+    /// Four nodes in Bruges, a street 1-2-3 (way 10), a street 3-4 (way 11)
+    /// and a relation of both.
+    fn write_base(name: &str) -> PathBuf {
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            node(3, 51.2060, 3.2270),
+            node(4, 51.2065, 3.2280),
+            street(10, &[1, 2, 3]),
+            street(11, &[3, 4]),
+            OsmObj::Relation(Relation {
+                id: RelationId(20),
+                tags: tags(&[("type", "route")]),
+                refs: vec![
+                    Ref {
+                        member: WayId(10).into(),
+                        role: "".into(),
+                    },
+                    Ref {
+                        member: WayId(11).into(),
+                        role: "forward".into(),
+                    },
+                ],
+            }),
+        ];
+        write_pbf_fixture(name, objects)
+    }
+
+    /// Writes the OSC `content` gzip-compressed to `name` in the temp dir.
+    fn write_osc_gz(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(content.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        path
+    }
+
+    fn read_objects(path: &Path) -> BTreeMap<OsmId, OsmObj> {
+        OsmPbfReader::new(File::open(path).unwrap())
+            .iter()
+            .map(|obj| {
+                let obj = obj.unwrap();
+                (obj.id(), obj)
+            })
+            .collect()
+    }
+
+    fn way_ids(path: PathBuf) -> Vec<i64> {
+        let (_, edges) = car_loader_builder(path).build().unwrap().load_graph();
+        let mut way_ids: Vec<i64> = edges.iter().map(|e| e.way_id as i64).collect();
+        way_ids.sort();
+        way_ids.dedup();
+        way_ids
+    }
+
+    #[test]
+    fn test_pbf_roundtrip() {
+        let path = write_base("osmtools_osc_roundtrip.osm.pbf");
+        let objects = read_objects(&path);
+        assert_eq!(objects.len(), 7);
+        assert_eq!(objects[&OsmId::Node(NodeId(2))], node(2, 51.2055, 3.2260));
+        assert_eq!(objects[&OsmId::Way(WayId(10))], street(10, &[1, 2, 3]));
+        let relation = objects[&OsmId::Relation(RelationId(20))]
+            .relation()
+            .unwrap();
+        assert_eq!(relation.refs[1].member, WayId(11).into());
+        assert_eq!(relation.refs[1].role, "forward");
+    }
+
+    #[test]
+    fn test_apply_osc_deletes_way() {
+        let base = write_base("osmtools_osc_base.osm.pbf");
+        let osc = write_osc_gz(
+            "osmtools_osc_change.osc.gz",
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<osmChange version="0.6" generator="test">
+  <modify>
+    <node id="2" lat="51.2056" lon="3.2261">
+      <tag k="highway" v="traffic_signals"/>
+    </node>
+  </modify>
+  <create>
+    <node id="5" lat="51.2070" lon="3.2290"/>
+  </create>
+  <delete>
+    <way id="11"/>
+  </delete>
+</osmChange>"#,
+        );
+        let out = std::env::temp_dir().join("osmtools_osc_applied.osm.pbf");
+        apply_osc(&base, &osc, &out).unwrap();
+
+        let objects = read_objects(&out);
+        assert!(!objects.contains_key(&OsmId::Way(WayId(11))));
+        assert!(objects.contains_key(&OsmId::Node(NodeId(5))));
+        let modified = objects[&OsmId::Node(NodeId(2))].node().unwrap();
+        assert_eq!(modified.decimicro_lat, 512_056_000);
+        assert!(modified.tags.contains("highway", "traffic_signals"));
+        // The output stays sorted by type and id.
+        let ids: Vec<OsmId> = OsmPbfReader::new(File::open(&out).unwrap())
+            .iter()
+            .map(|obj| obj.unwrap().id())
+            .collect();
+        assert!(ids.is_sorted());
+
+        assert_eq!(way_ids(base), vec![10, 11]);
+        assert_eq!(way_ids(out), vec![10]);
+    }
+
+    #[test]
+    fn test_apply_osc_unsorted_base() {
+        let base = write_pbf_fixture(
+            "osmtools_osc_unsorted_base.osm.pbf",
+            vec![node(2, 51.2055, 3.2260), node(1, 51.2050, 3.2250)],
+        );
+        let osc = write_osc_gz(
+            "osmtools_osc_unsorted_change.osc.gz",
+            r#"<osmChange><delete><node id="1"/></delete></osmChange>"#,
+        );
+        let out = std::env::temp_dir().join("osmtools_osc_unsorted_applied.osm.pbf");
+        assert!(matches!(
+            apply_osc(&base, &osc, &out),
+            Err(OscError::InvalidPbf(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_osc() {
+        let missing_id = "<osmChange><delete><way/></delete></osmChange>";
+        assert!(matches!(
+            parse_osc(missing_id.as_bytes()),
+            Err(OscError::InvalidOsc(_))
+        ));
+        let outside_action = r#"<osmChange><way id="1"/></osmChange>"#;
+        assert!(matches!(
+            parse_osc(outside_action.as_bytes()),
+            Err(OscError::InvalidOsc(_))
+        ));
+    }
+}
@@ -1,4 +1,7 @@
-use geo::{Contains, Polygon};
+use geo::line_intersection::{line_intersection, LineIntersection};
+use geo::{
+    BoundingRect, Contains, Coord, Intersects, Line, LineString, Point, Polygon, SimplifyIdx,
+};
 /*
 Pbfextractor creates graph files for the cycle-routing projects from pbf and srtm data
 Copyright (C) 2018  Florian Barth
@@ -16,37 +19,176 @@ GNU General Public License for more details.
 You should have received a copy of the GNU General Public License
 along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
+use crate::bounding_box::{polygon_from_wkt, BoundingBox, GeometryError};
+use crate::sources::{default_bbox, SourceNotFoundError};
+use crate::struct_to_dataframe;
+use chrono::{DateTime, Utc};
+use memmap2::Mmap;
 use osmpbfreader::{OsmObj, OsmPbfReader, Way};
+use polars::prelude::{
+    DataFrame, DataType, Field, IntoSeries, NamedFrom, PolarsError, PolarsResult, Series,
+    StructChunked,
+};
 use proj4rs::transform::{Transform, TransformClosure};
+use proj4rs::Proj;
 
-use super::metrics::{Distance_, EdgeFilter, NodeMetric};
-use log::debug;
-use std::cmp::Ordering;
+use super::header::{
+    pbf_source_timestamp, validate_pbf_header, validate_pbf_header_from_reader, IndexedBlobs,
+    PbfHeaderError,
+};
+use super::metrics::{
+    is_bridge, is_ferry, is_rough_paving, is_tunnel, lanes, layer, requires_dismount,
+    route_duration, step_count, width, CarEdgeFilter, CycleInfrastructure, Distance_, EdgeFilter,
+    FerryTravelTime, NodeMetric, SpeedProfile, SurfaceQuality, VehicleRestrictions,
+};
+use super::tiling::GeometryFilter;
+use super::units::{Meters, Seconds};
+use par_map::ParMap;
+use std::cell::Cell;
 use std::collections::hash_map::HashMap;
 use std::collections::{BTreeMap, HashSet};
 use std::error::Error;
 use std::fmt::Display;
 use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Receiver, Sender};
-use std::thread::spawn;
+use std::thread::{available_parallelism, spawn};
+use tracing::{debug, field, info_span, warn};
 
 pub type MetricIndices = BTreeMap<String, usize>;
 #[derive(Debug)]
 pub struct LoaderBuildError {
     source: String,
+    kind: LoaderBuildErrorKind,
 }
+
+#[derive(Debug)]
+enum LoaderBuildErrorKind {
+    MissingField,
+    UnknownCrs,
+}
+
 impl LoaderBuildError {
     pub fn new(source: String) -> LoaderBuildError {
-        LoaderBuildError { source }
+        LoaderBuildError {
+            source,
+            kind: LoaderBuildErrorKind::MissingField,
+        }
+    }
+
+    /// The EPSG `code` has no projection definition.
+    pub fn unknown_crs(code: u16) -> LoaderBuildError {
+        LoaderBuildError {
+            source: code.to_string(),
+            kind: LoaderBuildErrorKind::UnknownCrs,
+        }
     }
 }
 
 impl Error for LoaderBuildError {}
 impl Display for LoaderBuildError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Missing required field {}", self.source)
+        match self.kind {
+            LoaderBuildErrorKind::MissingField => {
+                write!(f, "Missing required field {}", self.source)
+            }
+            LoaderBuildErrorKind::UnknownCrs => write!(f, "Unknown CRS EPSG:{}", self.source),
+        }
+    }
+}
+
+/// The pbf file, either read through its handle or memory-mapped, see
+/// [`OsmLoaderBuilder::mmap`].
+enum PbfSource {
+    File(File),
+    Mapped(Cursor<Mmap>),
+}
+
+impl Read for PbfSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            PbfSource::File(file) => file.read(buf),
+            PbfSource::Mapped(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Seek for PbfSource {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            PbfSource::File(file) => file.seek(pos),
+            PbfSource::Mapped(cursor) => cursor.seek(pos),
+        }
+    }
+}
+
+/// Projection of the EPSG `code`, checked once when a loader is built
+/// instead of failing for every edge.
+pub(crate) fn projection(code: u16) -> Result<Proj, LoaderBuildError> {
+    Proj::from_epsg_code(code).map_err(|_| LoaderBuildError::unknown_crs(code))
+}
+
+#[derive(Debug)]
+pub enum LoadGraphError {
+    /// The pbf header requires an unsupported feature or cannot be read.
+    Header(PbfHeaderError),
+    /// The thread collecting the referenced node ids stopped without
+    /// returning them.
+    NodeIdCollection,
+}
+
+impl Error for LoadGraphError {}
+impl Display for LoadGraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadGraphError::Header(error) => write!(f, "{error}"),
+            LoadGraphError::NodeIdCollection => {
+                write!(f, "Node id collection stopped before returning the ids")
+            }
+        }
+    }
+}
+
+impl From<PbfHeaderError> for LoadGraphError {
+    fn from(error: PbfHeaderError) -> Self {
+        LoadGraphError::Header(error)
+    }
+}
+
+/// Summary of a graph load, see [`Loader::try_load_graph_with_stats`].
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct LoadStats {
+    /// Extent of the kept nodes, which may be smaller than the filter
+    /// geometry if parts of it hold no ways. `None` for an empty graph.
+    pub extent: Option<BoundingBox>,
+    /// Ways skipped for having fewer than two nodes, which malformed
+    /// extracts contain.
+    pub short_ways: usize,
+    /// Blobs skipped unread, summed over the way and the node pass, for
+    /// lying outside of the box of the filter geometry. Only blobs whose
+    /// header records their box, as in files written by
+    /// [`apply_osc`](super::osc::apply_osc), are skipped, and only with
+    /// [`BoundaryMode::Contains`].
+    pub skipped_blobs: usize,
+    /// Nodes merged into another node at the same coordinates, see
+    /// [`OsmLoaderBuilder::merge_coincident_nodes`].
+    pub merged_nodes: usize,
+}
+
+/// Smallest box containing all `nodes`, `None` if there are none.
+pub fn graph_extent(nodes: &[Node]) -> Option<BoundingBox> {
+    let first = nodes.first()?;
+    let mut extent = BoundingBox::new(first.long, first.lat, first.long, first.lat);
+    for node in &nodes[1..] {
+        extent.min_lon = extent.min_lon.min(node.long);
+        extent.min_lat = extent.min_lat.min(node.lat);
+        extent.max_lon = extent.max_lon.max(node.long);
+        extent.max_lat = extent.max_lat.max(node.lat);
     }
+    Some(extent)
 }
 
 pub struct Loader<Filter: EdgeFilter> {
@@ -55,7 +197,32 @@ pub struct Loader<Filter: EdgeFilter> {
     filter_geometry: Option<Polygon>,
     pub source_crs: u16,
     pub target_crs: u16,
+    proj_from: Proj,
+    proj_to: Proj,
     reverse_edges: bool,
+    reverse_roundabouts: bool,
+    auto_tile: bool,
+    boundary: BoundaryMode,
+    content_hash: bool,
+    node_degree: bool,
+    surface_quality: bool,
+    cycle_infrastructure: bool,
+    vehicle_restrictions: bool,
+    lanes_and_width: bool,
+    step_count: bool,
+    layers: bool,
+    ferry_time: bool,
+    conditional_max_speed: Option<SpeedProfile>,
+    inline_edge_coords: bool,
+    contract: bool,
+    intersections_only: bool,
+    keep_contracted_geometry: bool,
+    simplify_tolerance_m: Option<f64>,
+    num_threads: usize,
+    coordinate_decimals: Option<u8>,
+    compact_ids: bool,
+    mmap: bool,
+    merge_coincident_nodes: bool,
 }
 
 #[derive(Default)]
@@ -65,6 +232,29 @@ pub struct OsmLoaderBuilder<Filter: EdgeFilter> {
     filter_geometry: Option<Polygon>,
     target_crs: Option<u16>,
     reverse_edges: Option<bool>,
+    reverse_roundabouts: Option<bool>,
+    auto_tile: Option<bool>,
+    boundary: Option<BoundaryMode>,
+    content_hash: Option<bool>,
+    node_degree: Option<bool>,
+    surface_quality: Option<bool>,
+    cycle_infrastructure: Option<bool>,
+    vehicle_restrictions: Option<bool>,
+    lanes_and_width: Option<bool>,
+    step_count: Option<bool>,
+    layers: Option<bool>,
+    ferry_time: Option<bool>,
+    conditional_max_speed: Option<SpeedProfile>,
+    inline_edge_coords: Option<bool>,
+    contract: Option<bool>,
+    intersections_only: Option<bool>,
+    keep_contracted_geometry: Option<bool>,
+    simplify_tolerance_m: Option<f64>,
+    num_threads: Option<usize>,
+    coordinate_decimals: Option<u8>,
+    compact_ids: Option<bool>,
+    mmap: Option<bool>,
+    merge_coincident_nodes: Option<bool>,
 }
 
 #[allow(dead_code)]
@@ -89,6 +279,24 @@ impl<Filter: EdgeFilter> OsmLoaderBuilder<Filter> {
         new.filter_geometry = Some(value.into());
         new
     }
+    /// Parses `value` as a WKT `POLYGON` and uses it as filter geometry.
+    pub fn filter_geometry_wkt(&mut self, value: &str) -> Result<&mut Self, GeometryError> {
+        let new = self;
+        new.filter_geometry = Some(polygon_from_wkt(value)?);
+        Ok(new)
+    }
+    /// Uses the default bounding box of `city` in the catalog as filter
+    /// geometry, see [`default_bbox`]. Errors for cities outside of the
+    /// catalog.
+    pub fn filter_geometry_from_city(
+        &mut self,
+        city: &str,
+    ) -> Result<&mut Self, SourceNotFoundError> {
+        let new = self;
+        let bbox = default_bbox(city).ok_or_else(|| SourceNotFoundError::new(city.into()))?;
+        new.filter_geometry = Some(bbox.into());
+        Ok(new)
+    }
     pub fn target_crs<VALUE: Into<u16>>(&mut self, value: VALUE) -> &mut Self {
         let new = self;
         new.target_crs = Some(value.into());
@@ -99,6 +307,188 @@ impl<Filter: EdgeFilter> OsmLoaderBuilder<Filter> {
         new.reverse_edges = Some(value.into());
         new
     }
+    /// Reverses the direction of roundabouts (`junction=roundabout` or
+    /// `circular`). OSM draws roundabouts in their direction of travel in
+    /// both left- and right-hand traffic areas, so this is only a repair
+    /// switch for extracts whose roundabouts were drawn the wrong way round.
+    pub fn reverse_roundabouts<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.reverse_roundabouts = Some(value.into());
+        new
+    }
+    /// Classifies a grid of tiles over large filter geometries up front, so
+    /// most nodes skip the full point-in-polygon check. This only speeds up
+    /// the geometry filter: the file is still read in one pass and memory use
+    /// is unchanged. The extracted graph, including edges crossing tile
+    /// seams, is the same either way.
+    pub fn auto_tile<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.auto_tile = Some(value.into());
+        new
+    }
+    /// How edges crossing the boundary of the filter geometry are treated,
+    /// see [`BoundaryMode`].
+    pub fn boundary<VALUE: Into<BoundaryMode>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.boundary = Some(value.into());
+        new
+    }
+    /// Adds a `hash` column to the node and edge DataFrames, see
+    /// [`Node::content_hash`] and [`Edge::content_hash`].
+    pub fn content_hash<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.content_hash = Some(value.into());
+        new
+    }
+    /// Adds `in_degree` and `out_degree` columns to the node DataFrame.
+    pub fn node_degree<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.node_degree = Some(value.into());
+        new
+    }
+    /// Adds `surface_quality` (see [`SurfaceQuality`]) and `rough_paving`
+    /// columns to the edge DataFrame.
+    pub fn surface_quality<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.surface_quality = Some(value.into());
+        new
+    }
+    /// Adds an `infra_class` column (see [`CycleInfrastructure`]) and a
+    /// `dismount` column (see [`requires_dismount`]) to the edge DataFrame.
+    pub fn cycle_infrastructure<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.cycle_infrastructure = Some(value.into());
+        new
+    }
+    /// Adds `maxweight` (tonnes), `maxheight`, `maxwidth` (meters) and
+    /// `hgv_no` columns to the edge DataFrame, see [`VehicleRestrictions`].
+    pub fn vehicle_restrictions<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.vehicle_restrictions = Some(value.into());
+        new
+    }
+    /// Adds `lanes` (see [`lanes`]) and `width_m` columns to the edge
+    /// DataFrame.
+    pub fn lanes_and_width<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.lanes_and_width = Some(value.into());
+        new
+    }
+    /// Adds a `step_count` column (see [`step_count`]) to the edge
+    /// DataFrame, null for edges without the tag.
+    pub fn step_count<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.step_count = Some(value.into());
+        new
+    }
+    /// Adds `bridge` and `tunnel` (see [`is_bridge`] and [`is_tunnel`]) and
+    /// `layer` (see [`layer`]) columns to the edge DataFrame, so crossings of
+    /// ways at different levels can be told apart.
+    pub fn layers<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.layers = Some(value.into());
+        new
+    }
+    /// Adds a `ferry_time` column with the travel time in seconds over ferry
+    /// edges, see [`FerryTravelTime`], null for all other edges.
+    pub fn ferry_time<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.ferry_time = Some(value.into());
+        new
+    }
+    /// Adds a `maxspeed_conditional` column holding the restrictions of the
+    /// `maxspeed:conditional` tag as a JSON list of `{speed, condition}`
+    /// objects, see [`SpeedProfile::parse_conditional_max_speed`]. Speeds
+    /// are in km/h and `null` for `none`. The column is null for edges
+    /// without the tag. `None` omits the column.
+    pub fn conditional_max_speed(&mut self, value: Option<SpeedProfile>) -> &mut Self {
+        let new = self;
+        new.conditional_max_speed = value;
+        new
+    }
+    /// Adds the coordinates of both endpoints as `source_lat`,
+    /// `source_long`, `dest_lat` and `dest_long` columns to the edge
+    /// DataFrame, so edges can be drawn without joining the nodes.
+    pub fn inline_edge_coords<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.inline_edge_coords = Some(value.into());
+        new
+    }
+    /// Contracts chains of degree-two nodes into single edges, see
+    /// [`contract_degree_two`].
+    pub fn contract<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.contract = Some(value.into());
+        new
+    }
+    /// Keeps only way endpoints and nodes shared by several ways, contracting
+    /// every other node like [`contract_degree_two`]. Unlike
+    /// [`OsmLoaderBuilder::contract`], nodes tagged e.g. as traffic signals
+    /// are contracted as well.
+    pub fn intersections_only<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.intersections_only = Some(value.into());
+        new
+    }
+    /// Keeps the coordinates of contracted nodes as a `via` column of
+    /// `{lat, long}` structs on the edges.
+    pub fn keep_contracted_geometry<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.keep_contracted_geometry = Some(value.into());
+        new
+    }
+    /// Simplifies the emitted way polylines, i.e. the `via` column and the
+    /// geometries of [`Loader::load_ways_with_geometry`], with Douglas-Peucker
+    /// at the given tolerance. Distances are measured in the target CRS, so
+    /// the tolerance is in meters for a projected one. Endpoints and nodes
+    /// shared by several ways are kept. `None` keeps every point.
+    pub fn simplify_tolerance_m(&mut self, value: Option<f64>) -> &mut Self {
+        let new = self;
+        new.simplify_tolerance_m = value;
+        new
+    }
+    /// Caps the number of threads decoding the pbf file. Defaults to all
+    /// cores; `0` is treated as `1`.
+    pub fn num_threads<VALUE: Into<usize>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.num_threads = Some(value.into());
+        new
+    }
+    /// Rounds the emitted node coordinates to the given number of decimal
+    /// places, e.g. 7 for about 1 cm. Edge lengths are computed from the full
+    /// precision coordinates. `None` keeps full precision.
+    pub fn coordinate_decimals(&mut self, value: Option<u8>) -> &mut Self {
+        let new = self;
+        new.coordinate_decimals = value;
+        new
+    }
+    /// Numbers the nodes `0..N` in order of their OSM ids, see
+    /// [`Node::internal_id`], [`Edge::source`] and [`Edge::dest`]. Adds an
+    /// `internal_id` column to the node DataFrame and `source` and `dest`
+    /// columns to the edge DataFrame.
+    pub fn compact_ids<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.compact_ids = Some(value.into());
+        new
+    }
+    /// Memory-maps the pbf file instead of reading it through the file
+    /// handle, so repeated extractions of the same file are served from the
+    /// page cache without read calls. The file must not be modified while
+    /// it is loaded.
+    pub fn mmap<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.mmap = Some(value.into());
+        new
+    }
+    /// Merges nodes at the same coordinates, as some imports create, into
+    /// the one with the smallest OSM id and drops the edges between them.
+    /// Coordinates are compared after rounding to
+    /// [`OsmLoaderBuilder::coordinate_decimals`] if set, exactly otherwise.
+    pub fn merge_coincident_nodes<VALUE: Into<bool>>(&mut self, value: VALUE) -> &mut Self {
+        let new = self;
+        new.merge_coincident_nodes = Some(value.into());
+        new
+    }
     pub fn build(&self) -> Result<Loader<Filter>, LoaderBuildError> {
         let target_crs = self
             .target_crs
@@ -108,81 +498,268 @@ impl<Filter: EdgeFilter> OsmLoaderBuilder<Filter> {
         Ok(Loader {
             pbf_path: match self.pbf_path {
                 Some(ref value) => Clone::clone(value),
-                None => {
-                    return Err(LoaderBuildError {
-                        source: "pbf_path".into(),
-                    })
-                }
+                None => return Err(LoaderBuildError::new("pbf_path".into())),
             },
             edge_filter: match self.edge_filter {
                 Some(ref value) => Clone::clone(value),
-                None => {
-                    return Err(LoaderBuildError {
-                        source: "edge_filter".into(),
-                    })
-                }
+                None => return Err(LoaderBuildError::new("edge_filter".into())),
             },
             filter_geometry: Clone::clone(&self.filter_geometry),
             source_crs,
             target_crs: target_crs.clone(),
+            proj_from: projection(source_crs)?,
+            proj_to: projection(*target_crs)?,
             reverse_edges: match self.reverse_edges {
                 Some(ref value) => Clone::clone(value),
                 None => false,
             },
+            reverse_roundabouts: self.reverse_roundabouts.unwrap_or(false),
+            auto_tile: self.auto_tile.unwrap_or(false),
+            boundary: self.boundary.unwrap_or_default(),
+            content_hash: self.content_hash.unwrap_or(false),
+            node_degree: self.node_degree.unwrap_or(false),
+            surface_quality: self.surface_quality.unwrap_or(false),
+            cycle_infrastructure: self.cycle_infrastructure.unwrap_or(false),
+            vehicle_restrictions: self.vehicle_restrictions.unwrap_or(false),
+            lanes_and_width: self.lanes_and_width.unwrap_or(false),
+            step_count: self.step_count.unwrap_or(false),
+            layers: self.layers.unwrap_or(false),
+            ferry_time: self.ferry_time.unwrap_or(false),
+            conditional_max_speed: self.conditional_max_speed,
+            inline_edge_coords: self.inline_edge_coords.unwrap_or(false),
+            contract: self.contract.unwrap_or(false),
+            intersections_only: self.intersections_only.unwrap_or(false),
+            keep_contracted_geometry: self.keep_contracted_geometry.unwrap_or(false),
+            simplify_tolerance_m: self.simplify_tolerance_m,
+            num_threads: self
+                .num_threads
+                .unwrap_or_else(|| available_parallelism().map_or(1, NonZeroUsize::get))
+                .max(1),
+            coordinate_decimals: self.coordinate_decimals,
+            compact_ids: self.compact_ids.unwrap_or(false),
+            mmap: self.mmap.unwrap_or(false),
+            merge_coincident_nodes: self.merge_coincident_nodes.unwrap_or(false),
         })
     }
 }
 
+impl Loader<CarEdgeFilter> {
+    /// Loader of the car network of `pbf_path` in EPSG:4839 without a filter
+    /// geometry, the configuration measured by `benches/load_graph.rs`.
+    pub fn car<VALUE: Into<PathBuf>>(pbf_path: VALUE) -> Result<Self, LoaderBuildError> {
+        OsmLoaderBuilder::default()
+            .edge_filter(CarEdgeFilter::default())
+            .target_crs(4839u16)
+            .pbf_path(pbf_path)
+            .build()
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 impl<Filter: EdgeFilter> Loader<Filter> {
-    /// Loads the graph from a pbf file.
+    /// Checks that the pbf file only requires features the reader supports.
+    ///
+    /// The loading methods run this check first. [`Loader::try_load_graph`]
+    /// returns its error, all others panic with it, including
+    /// [`Loader::load_graph_from_reader`] for the header of its reader.
+    pub fn validate_header(&self) -> Result<(), PbfHeaderError> {
+        validate_pbf_header(&self.pbf_path)
+    }
+
+    /// Time the source data was replicated at, see [`pbf_source_timestamp`].
+    /// `None` if the header has no timestamp or cannot be read.
+    pub fn source_timestamp(&self) -> Option<DateTime<Utc>> {
+        pbf_source_timestamp(&self.pbf_path).ok().flatten()
+    }
+
+    /// Loads the graph from a pbf file. Nodes are ordered by `osm_id` and
+    /// edges by `(source_osm, dest_osm, way_id, length)`, so repeated runs
+    /// yield the same output.
+    ///
+    /// The phases run in the `tracing` spans `ways`, `nodes`, `metrics` and
+    /// `dedup`, which carry the resulting counts. Without a `tracing`
+    /// subscriber they are reported as `log` records.
+    ///
+    /// Panics on the errors of [`Loader::try_load_graph`].
     pub fn load_graph(&self) -> (Vec<Node>, Vec<Edge>) {
+        self.try_load_graph()
+            .unwrap_or_else(|error| panic!("{}: {error}", self.pbf_path.display()))
+    }
+
+    /// Like [`Loader::load_graph`], but returns an unsupported or unreadable
+    /// header and failures of the node id collection as errors.
+    pub fn try_load_graph(&self) -> Result<(Vec<Node>, Vec<Edge>), LoadGraphError> {
+        let (nodes, edges, _) = self.try_load_graph_with_stats()?;
+        Ok((nodes, edges))
+    }
+
+    /// Like [`Loader::try_load_graph`], but also returns the [`LoadStats`]
+    /// of the graph.
+    pub fn try_load_graph_with_stats(
+        &self,
+    ) -> Result<(Vec<Node>, Vec<Edge>, LoadStats), LoadGraphError> {
+        self.load_graph_unchecked(self.try_open_pbf()?, None)
+    }
+
+    /// Ids of the nodes referenced by the ways that pass the edge filter, i.e.
+    /// the nodes [`Loader::load_graph`] reads coordinates for.
+    ///
+    /// Blobs are skipped like in the way pass of [`Loader::load_graph`]. A set
+    /// collected without a filter geometry only depends on the pbf file and
+    /// the edge filter, so loaders that differ in e.g. their filter geometry
+    /// can share it through [`Loader::load_graph_with_referenced_nodes`].
+    /// Panics on an invalid header, see [`Loader::validate_header`].
+    pub fn collect_referenced_nodes(&self) -> HashSet<osmpbfreader::NodeId> {
+        let mut reader = self.open_pbf();
+        let skipped_blobs = Cell::new(0);
+        self.par_indexed_objects(&mut reader, &skipped_blobs)
+            .filter_map(|obj| match obj {
+                Ok(OsmObj::Way(w)) if self.accepts_way(&w) => Some(w.nodes),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Like [`Loader::load_graph`], but reads the coordinates of the
+    /// precomputed `node_ids` from [`Loader::collect_referenced_nodes`]
+    /// instead of collecting them during the way pass. Panics on an invalid
+    /// header like the former.
+    pub fn load_graph_with_referenced_nodes(
+        &self,
+        node_ids: &HashSet<osmpbfreader::NodeId>,
+    ) -> (Vec<Node>, Vec<Edge>) {
+        let (nodes, edges, _) = self
+            .load_graph_unchecked(self.open_pbf(), Some(node_ids))
+            .expect("Given node ids need no collection");
+        (nodes, edges)
+    }
+
+    fn open_pbf(&self) -> PbfSource {
+        self.try_open_pbf()
+            .unwrap_or_else(|error| panic!("{}: {error}", self.pbf_path.display()))
+    }
+
+    fn try_open_pbf(&self) -> Result<PbfSource, PbfHeaderError> {
         debug!(
             "Extracting data out of: {}",
             self.pbf_path
                 .to_str()
                 .expect("Path could not be converted to string")
         );
-        let fs = File::open(self.pbf_path.as_path()).unwrap();
-        let mut reader = OsmPbfReader::new(fs);
+        self.validate_header()?;
+        let file = File::open(self.pbf_path.as_path())?;
+        if !self.mmap {
+            return Ok(PbfSource::File(file));
+        }
+        // SAFETY: The mapping is only read, modifying the file while it is
+        // loaded is documented as unsupported on the builder.
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(PbfSource::Mapped(Cursor::new(map)))
+    }
 
-        let (id_sender, id_receiver) = channel();
-        let set_receiver = self.collect_node_ids(id_receiver);
+    /// Loads the graph from pbf data in `reader`, e.g. an in-memory buffer,
+    /// instead of the pbf path. The data has to start at the beginning of
+    /// `reader`, as it is rewound between passes. Panics on an invalid
+    /// header, see [`Loader::validate_header`].
+    pub fn load_graph_from_reader<R: Read + Seek>(&self, mut reader: R) -> (Vec<Node>, Vec<Edge>) {
+        reader.rewind().expect("Can't rewind pbf data!");
+        if let Err(error) = validate_pbf_header_from_reader(&mut reader) {
+            panic!("{error}");
+        }
+        reader.rewind().expect("Can't rewind pbf data!");
+        let (nodes, edges, _) = self
+            .load_graph_unchecked(reader, None)
+            .unwrap_or_else(|error| panic!("{error}"));
+        (nodes, edges)
+    }
 
-        let mut edges: Vec<Edge> = reader
-            .par_iter()
-            .flat_map(|obj| {
-                if let Ok(OsmObj::Way(w)) = obj {
-                    self.process_way(&w, &id_sender)
-                } else {
+    /// Collects the referenced node ids during the way pass unless `node_ids`
+    /// are given.
+    fn load_graph_unchecked<R: Read + Seek>(
+        &self,
+        mut reader: R,
+        node_ids: Option<&HashSet<osmpbfreader::NodeId>>,
+    ) -> Result<(Vec<Node>, Vec<Edge>, LoadStats), LoadGraphError> {
+        let skipped_blobs = Cell::new(0);
+
+        let (id_sender, set_receiver) = if node_ids.is_none() {
+            let (id_sender, id_receiver) = channel();
+            (Some(id_sender), Some(self.collect_node_ids(id_receiver)))
+        } else {
+            (None, None)
+        };
+
+        let ways = info_span!("ways", edges = field::Empty, short_ways = field::Empty).entered();
+        let mut short_ways = 0;
+        let mut edges: Vec<Edge> = self
+            .par_indexed_objects(&mut reader, &skipped_blobs)
+            .flat_map(|obj| match obj {
+                Ok(OsmObj::Way(w)) if w.nodes.len() < 2 => {
+                    short_ways += 1;
                     Vec::new()
                 }
+                Ok(OsmObj::Way(w)) => self.process_way(&w, id_sender.as_ref()),
+                _ => Vec::new(),
             })
             .collect();
         debug!("Collected {} edges", edges.len());
+        if short_ways > 0 {
+            warn!("Skipped {short_ways} ways with fewer than two nodes");
+        }
+        ways.record("edges", edges.len());
+        ways.record("short_ways", short_ways);
+        ways.exit();
         reader.rewind().expect("Can't rewind pbf file!");
         drop(id_sender);
 
-        let id_set = set_receiver.recv().expect("Did not get node ids");
+        let nodes_span =
+            info_span!("nodes", nodes = field::Empty, skipped = field::Empty).entered();
+
+        let collected_ids = set_receiver.map(receive_node_ids).transpose()?;
+        let id_set = collected_ids
+            .as_ref()
+            .or(node_ids)
+            .expect("Node ids are either given or collected");
         let mut skipped_nodes = 0;
+        let mut negative_ids = 0;
+        // Nodes outside of the filter geometry that may still end an edge
+        // crossing it.
+        let mut outside_nodes = HashSet::new();
+        let mut protected_nodes = HashSet::new();
+        let geometry_filter = self
+            .filter_geometry
+            .as_ref()
+            .map(|geometry| GeometryFilter::new(geometry, self.auto_tile));
 
-        let mut nodes: Vec<Node> = reader
-            .par_iter()
+        let mut nodes: Vec<Node> = self
+            .par_indexed_objects(&mut reader, &skipped_blobs)
             .filter_map(|obj| {
                 if let Ok(OsmObj::Node(n)) = obj {
                     if id_set.contains(&n.id) {
-                        let lat = f64::from(n.decimicro_lat) / 10_000_000.0;
-                        let lng = f64::from(n.decimicro_lon) / 10_000_000.0;
-                        let point = geo::Point::new(lng, lat);
-                        if self
-                            .filter_geometry
+                        let Some(osm_id) = osm_node_id(n.id) else {
+                            negative_ids += 1;
+                            return None;
+                        };
+                        let (lat, lng) = coords(&n);
+                        let point = Point::new(lng, lat);
+                        let outside = geometry_filter
                             .as_ref()
-                            .is_some_and(|f| !f.contains(&point))
-                        {
+                            .is_some_and(|f| !f.contains(&point));
+                        if outside && self.boundary == BoundaryMode::Contains {
                             skipped_nodes += 1;
                             None
                         } else {
-                            Some(Node::new(n.id.0.try_into().unwrap(), lat, lng))
+                            if outside {
+                                outside_nodes.insert(osm_id);
+                            }
+                            if self.contract
+                                && !self.intersections_only
+                                && is_routing_relevant_node(&n.tags)
+                            {
+                                protected_nodes.insert(osm_id);
+                            }
+                            Some(Node::new(osm_id, lat, lng))
                         }
                     } else {
                         None
@@ -194,26 +771,271 @@ impl<Filter: EdgeFilter> Loader<Filter> {
             .collect();
 
         debug!("Collected {} nodes", nodes.len());
-        if self.filter_geometry.is_some() {
+        if negative_ids > 0 {
+            warn!("Skipped {negative_ids} nodes with negative ids and their edges");
+        }
+        nodes_span.record("nodes", nodes.len());
+        nodes_span.record("skipped", skipped_nodes);
+        nodes_span.exit();
+        if let Some(polygon) = &self.filter_geometry {
             debug!("Filtering nodes and edges based on geometry");
-            let map: HashMap<OsmNodeId, (usize, &Node)> =
-                nodes.iter().enumerate().map(|n| (n.1.osm_id, n)).collect();
-            let mut edges_replace: Vec<Edge> = vec![];
-            for edge in edges {
-                if map.contains_key(&edge.source_osm) & map.contains_key(&edge.dest_osm) {
-                    edges_replace.push(edge);
+            match self.boundary {
+                BoundaryMode::Contains => {
+                    let map: HashMap<OsmNodeId, (usize, &Node)> =
+                        nodes.iter().enumerate().map(|n| (n.1.osm_id, n)).collect();
+                    let mut edges_replace: Vec<Edge> = vec![];
+                    for edge in edges {
+                        if map.contains_key(&edge.source_osm) & map.contains_key(&edge.dest_osm) {
+                            edges_replace.push(edge);
+                        }
+                    }
+                    edges = edges_replace;
+                }
+                BoundaryMode::Intersects => {
+                    (nodes, edges) = keep_intersecting_edges(nodes, edges, &outside_nodes, polygon);
+                }
+                BoundaryMode::Clip => {
+                    (nodes, edges) = clip_edges(nodes, edges, &outside_nodes, polygon);
                 }
             }
-            edges = edges_replace;
         }
 
-        self.rename_node_ids_and_calculate_node_metrics(&mut nodes, &mut edges);
+        let dangling_edges = drop_dangling_edges(&nodes, &mut edges);
+        if dangling_edges > 0 {
+            warn!("Skipped {dangling_edges} edges referencing nodes missing from the pbf file");
+        }
+        let merged_nodes = if self.merge_coincident_nodes {
+            merge_coincident_nodes(
+                &mut nodes,
+                &mut edges,
+                &mut protected_nodes,
+                self.coordinate_decimals,
+            )
+        } else {
+            0
+        };
+        if merged_nodes > 0 {
+            debug!("Merged {merged_nodes} coincident nodes");
+        }
+
+        info_span!("metrics", edges = edges.len()).in_scope(|| {
+            self.calculate_edge_lengths(&nodes, &mut edges);
+            if self.ferry_time {
+                calculate_ferry_times(&mut edges);
+            }
+        });
 
-        debug!("Deleting duplicate and dominated edges");
+        debug!("Deleting duplicate edges");
 
+        let dedup = info_span!("dedup", before = edges.len(), after = field::Empty).entered();
         self.delete_duplicate_edges(&mut edges);
-        edges = self.delete_dominated_edges(edges);
-        (nodes, edges)
+        dedup.record("after", edges.len());
+        dedup.exit();
+        if self.contract || self.intersections_only {
+            debug!("Contracting degree-two nodes");
+            (nodes, edges) = contract_degree_two(
+                nodes,
+                edges,
+                &protected_nodes,
+                self.keep_contracted_geometry,
+            );
+            if let Some(simplifier) = self.simplifier() {
+                simplify_via(&simplifier, &nodes, &mut edges);
+            }
+        }
+        calculate_node_degrees(&mut nodes, &edges);
+        // Blobs are decoded in parallel, so the collection order varies
+        // between runs. Parallel edges of different ways or lengths are
+        // ordered by these as well, which leaves only identical edges tied.
+        nodes.sort_unstable_by_key(|n| n.osm_id);
+        edges.sort_by(|e1, e2| {
+            (e1.source_osm, e1.dest_osm, e1.way_id)
+                .cmp(&(e2.source_osm, e2.dest_osm, e2.way_id))
+                .then(e1.length.total_cmp(&e2.length))
+        });
+        if let Some(decimals) = self.coordinate_decimals {
+            round_coordinates(&mut nodes, &mut edges, decimals);
+        }
+        if self.inline_edge_coords {
+            inline_edge_coordinates(&nodes, &mut edges);
+        }
+        if self.compact_ids {
+            compact_node_ids(&mut nodes, &mut edges);
+        }
+        let stats = LoadStats {
+            extent: graph_extent(&nodes),
+            short_ways,
+            skipped_blobs: skipped_blobs.get(),
+            merged_nodes,
+        };
+        Ok((nodes, edges, stats))
+    }
+
+    /// Loads the graph as node and edge DataFrames.
+    pub fn load_graph_df(&self) -> PolarsResult<(DataFrame, DataFrame)> {
+        let (nodes, edges) = self
+            .try_load_graph()
+            .map_err(|e| PolarsError::ComputeError(e.to_string().into()))?;
+        let node_hashes: Vec<u64> = nodes.iter().map(Node::content_hash).collect();
+        let edge_hashes: Vec<u64> = edges.iter().map(Edge::content_hash).collect();
+        let (in_degrees, out_degrees): (Vec<u32>, Vec<u32>) =
+            nodes.iter().map(|n| (n.in_degree, n.out_degree)).unzip();
+        let via = if self.keep_contracted_geometry {
+            Some(via_series(&edges)?)
+        } else {
+            None
+        };
+        let (surface_qualities, rough_paving): (Vec<Option<f64>>, Vec<bool>) = edges
+            .iter()
+            .map(|e| (e.surface_quality, e.rough_paving))
+            .unzip();
+        let infra_classes: Vec<Option<&str>> = edges
+            .iter()
+            .map(|e| e.infra_class.map(CycleInfrastructure::as_str))
+            .collect();
+        let dismount: Vec<bool> = edges.iter().map(|e| e.dismount).collect();
+        let max_weights: Vec<Option<f64>> = edges.iter().map(|e| e.max_weight).collect();
+        let max_heights: Vec<Option<f64>> = edges.iter().map(|e| e.max_height).collect();
+        let max_widths: Vec<Option<f64>> = edges.iter().map(|e| e.max_width).collect();
+        let hgv_no: Vec<bool> = edges.iter().map(|e| e.hgv_no).collect();
+        let (lane_counts, widths): (Vec<Option<u8>>, Vec<Option<f64>>) =
+            edges.iter().map(|e| (e.lanes, e.width)).unzip();
+        let step_counts: Vec<Option<u32>> = edges.iter().map(|e| e.step_count).collect();
+        let bridges: Vec<bool> = edges.iter().map(|e| e.bridge).collect();
+        let tunnels: Vec<bool> = edges.iter().map(|e| e.tunnel).collect();
+        let layers: Vec<Option<i8>> = edges.iter().map(|e| e.layer).collect();
+        let ferry_times: Vec<Option<f64>> = edges.iter().map(|e| e.ferry_time).collect();
+        let max_speed_conditionals: Vec<Option<String>> = edges
+            .iter()
+            .map(|e| e.max_speed_conditional.clone())
+            .collect();
+        let (source_lats, source_longs): (Vec<Option<f64>>, Vec<Option<f64>>) =
+            edges.iter().map(|e| (e.source_lat, e.source_long)).unzip();
+        let (dest_lats, dest_longs): (Vec<Option<f64>>, Vec<Option<f64>>) =
+            edges.iter().map(|e| (e.dest_lat, e.dest_long)).unzip();
+        let internal_ids: Vec<Option<u64>> = nodes.iter().map(|n| n.internal_id).collect();
+        let (sources, dests): (Vec<Option<u64>>, Vec<Option<u64>>) =
+            edges.iter().map(|e| (e.source, e.dest)).unzip();
+        let mut df_edges = struct_to_dataframe!(edges, [source_osm, dest_osm, length, way_id])?;
+        let mut df_nodes = struct_to_dataframe!(nodes, [osm_id, lat, long])?;
+        if self.content_hash {
+            df_nodes.with_column(Series::new("hash".into(), node_hashes))?;
+            df_edges.with_column(Series::new("hash".into(), edge_hashes))?;
+        }
+        if self.node_degree {
+            df_nodes.with_column(Series::new("in_degree".into(), in_degrees))?;
+            df_nodes.with_column(Series::new("out_degree".into(), out_degrees))?;
+        }
+        if self.surface_quality {
+            df_edges.with_column(Series::new("surface_quality".into(), surface_qualities))?;
+            df_edges.with_column(Series::new("rough_paving".into(), rough_paving))?;
+        }
+        if self.cycle_infrastructure {
+            df_edges.with_column(Series::new("infra_class".into(), infra_classes))?;
+            df_edges.with_column(Series::new("dismount".into(), dismount))?;
+        }
+        if self.vehicle_restrictions {
+            df_edges.with_column(Series::new("maxweight".into(), max_weights))?;
+            df_edges.with_column(Series::new("maxheight".into(), max_heights))?;
+            df_edges.with_column(Series::new("maxwidth".into(), max_widths))?;
+            df_edges.with_column(Series::new("hgv_no".into(), hgv_no))?;
+        }
+        if self.lanes_and_width {
+            df_edges.with_column(Series::new("lanes".into(), lane_counts))?;
+            df_edges.with_column(Series::new("width_m".into(), widths))?;
+        }
+        if self.step_count {
+            df_edges.with_column(Series::new("step_count".into(), step_counts))?;
+        }
+        if self.layers {
+            df_edges.with_column(Series::new("bridge".into(), bridges))?;
+            df_edges.with_column(Series::new("tunnel".into(), tunnels))?;
+            df_edges.with_column(Series::new("layer".into(), layers))?;
+        }
+        if self.ferry_time {
+            df_edges.with_column(Series::new("ferry_time".into(), ferry_times))?;
+        }
+        if self.conditional_max_speed.is_some() {
+            df_edges.with_column(Series::new(
+                "maxspeed_conditional".into(),
+                max_speed_conditionals,
+            ))?;
+        }
+        if self.inline_edge_coords {
+            df_edges.with_column(Series::new("source_lat".into(), source_lats))?;
+            df_edges.with_column(Series::new("source_long".into(), source_longs))?;
+            df_edges.with_column(Series::new("dest_lat".into(), dest_lats))?;
+            df_edges.with_column(Series::new("dest_long".into(), dest_longs))?;
+        }
+        if let Some(via) = via {
+            df_edges.with_column(via)?;
+        }
+        if self.compact_ids {
+            df_nodes.with_column(Series::new("internal_id".into(), internal_ids))?;
+            df_edges.with_column(Series::new("source".into(), sources))?;
+            df_edges.with_column(Series::new("dest".into(), dests))?;
+        }
+        Ok((df_nodes, df_edges))
+    }
+
+    /// Loads every way accepted by the edge filter together with its full
+    /// geometry, e.g. for map matching.
+    ///
+    /// Returns one row per way with its `way_id` and a `geometry` list of
+    /// `{lat, long}` structs in node order. With a filter geometry, a way is
+    /// kept whole as long as at least one of its nodes lies inside.
+    ///
+    /// An unreadable file or unsupported header is returned as error, I/O
+    /// errors as [`PolarsError::IO`].
+    pub fn load_ways_with_geometry(&self) -> PolarsResult<DataFrame> {
+        let source = self.try_open_pbf().map_err(|error| match error {
+            PbfHeaderError::Io(error) => PolarsError::from(error),
+            error => PolarsError::ComputeError(error.to_string().into()),
+        })?;
+        let mut reader = OsmPbfReader::new(source);
+
+        let ways: Vec<(OsmWayId, Vec<osmpbfreader::NodeId>)> = self
+            .par_objects(&mut reader)
+            .filter_map(|obj| match obj {
+                Ok(OsmObj::Way(w)) if !self.edge_filter.is_invalid(&w.tags) => {
+                    Some((w.id.0 as OsmWayId, w.nodes))
+                }
+                _ => None,
+            })
+            .collect();
+        debug!("Collected {} ways", ways.len());
+        let id_set: HashSet<osmpbfreader::NodeId> = ways
+            .iter()
+            .flat_map(|(_, nodes)| nodes.iter().copied())
+            .collect();
+        reader
+            .rewind()
+            .map_err(|error| PolarsError::ComputeError(error.to_string().into()))?;
+
+        let geometry_filter = self
+            .filter_geometry
+            .as_ref()
+            .map(|geometry| GeometryFilter::new(geometry, self.auto_tile));
+        let coordinates: HashMap<osmpbfreader::NodeId, WayNode> = self
+            .par_objects(&mut reader)
+            .filter_map(|obj| match obj {
+                Ok(OsmObj::Node(n)) if id_set.contains(&n.id) => {
+                    let (lat, long) = coords(&n);
+                    let inside = geometry_filter
+                        .as_ref()
+                        .is_none_or(|f| f.contains(&Point::new(long, lat)));
+                    Some((n.id, WayNode { lat, long, inside }))
+                }
+                _ => None,
+            })
+            .collect();
+
+        way_geometries_to_dataframe(ways, &coordinates, self.simplifier().as_ref())
+    }
+
+    fn simplifier(&self) -> Option<Simplifier<'_>> {
+        self.simplify_tolerance_m
+            .map(|tolerance| Simplifier::new(tolerance, &self.proj_from, &self.proj_to))
     }
 
     fn collect_node_ids(
@@ -227,138 +1049,1013 @@ impl<Filter: EdgeFilter> Loader<Filter> {
             for id in ids {
                 set.insert(id);
             }
-            send.send(set)
-                .expect("Cannot send node ids back to main thread");
+            // The receiver is only gone if loading was abandoned.
+            let _ = send.send(set);
         });
         recv
     }
 
-    fn process_way(&self, w: &Way, id_sender: &Sender<osmpbfreader::NodeId>) -> Vec<Edge> {
+    /// Whether `w` contributes edges to the graph: it passes the edge filter
+    /// and has at least two nodes.
+    fn accepts_way(&self, w: &Way) -> bool {
+        w.nodes.len() >= 2 && !self.edge_filter.is_invalid(&w.tags)
+    }
+
+    fn process_way(&self, w: &Way, id_sender: Option<&Sender<osmpbfreader::NodeId>>) -> Vec<Edge> {
         let mut edges = Vec::new();
-        if self.edge_filter.is_invalid(&w.tags) {
+        if !self.accepts_way(w) {
             return edges;
         }
-        let is_one_way: bool;
-        if self.reverse_edges {
-            is_one_way = false;
+        let one_way = if self.reverse_edges {
+            OneWay::Both
         } else {
-            is_one_way = self.is_one_way(w);
-        }
+            self.is_one_way(w)
+        };
+        let (surface_quality, rough_paving) = if self.surface_quality {
+            (SurfaceQuality.score(&w.tags), is_rough_paving(&w.tags))
+        } else {
+            (None, false)
+        };
+        let infra_class = self
+            .cycle_infrastructure
+            .then(|| CycleInfrastructure::from_tags(&w.tags));
+        let dismount = self.cycle_infrastructure && requires_dismount(&w.tags);
+        let restrictions = if self.vehicle_restrictions {
+            VehicleRestrictions::from_tags(&w.tags)
+        } else {
+            VehicleRestrictions::default()
+        };
+        let (lanes, width) = if self.lanes_and_width {
+            (lanes(&w.tags), width(&w.tags))
+        } else {
+            (None, None)
+        };
+        let step_count = if self.step_count {
+            step_count(&w.tags)
+        } else {
+            None
+        };
+        let ferry = self.ferry_time && is_ferry(&w.tags);
+        let route_duration = if ferry {
+            route_duration(&w.tags).map(|duration| duration.0)
+        } else {
+            None
+        };
+        let (bridge, tunnel, layer) = if self.layers {
+            (is_bridge(&w.tags), is_tunnel(&w.tags), layer(&w.tags))
+        } else {
+            (false, false, None)
+        };
+        let max_speed_conditional = self.conditional_max_speed.and_then(|profile| {
+            let value = w.tags.get("maxspeed:conditional")?;
+            let restrictions: Vec<_> = profile
+                .parse_conditional_max_speed(value, f64::INFINITY)
+                .into_iter()
+                .map(|(speed, condition)| serde_json::json!({"speed": speed, "condition": condition}))
+                .collect();
+            Some(serde_json::Value::from(restrictions).to_string())
+        });
+        let way_edge = |source: OsmNodeId, dest: OsmNodeId| Edge {
+            way_id: w.id.0 as OsmWayId,
+            surface_quality,
+            rough_paving,
+            infra_class,
+            dismount,
+            max_weight: restrictions.max_weight,
+            max_height: restrictions.max_height,
+            max_width: restrictions.max_width,
+            hgv_no: restrictions.hgv_no,
+            lanes,
+            width,
+            step_count,
+            bridge,
+            tunnel,
+            layer,
+            ferry,
+            route_duration,
+            max_speed_conditional: max_speed_conditional.clone(),
+            ..Edge::new(source, dest)
+        };
         for (index, node) in w.nodes[0..(w.nodes.len() - 1)].iter().enumerate() {
-            id_sender.send(*node).expect("could not send id to id set");
-            let edge = Edge::new(node.0 as OsmNodeId, w.nodes[index + 1].0 as OsmNodeId);
-            edges.push(edge);
-            if !is_one_way {
-                let edge = Edge::new(w.nodes[index + 1].0 as OsmNodeId, node.0 as OsmNodeId);
-                edges.push(edge);
+            // A stopped collector surfaces when the set is received.
+            if let Some(id_sender) = id_sender {
+                let _ = id_sender.send(*node);
+            }
+            // Segments touching a node with a negative id are dropped, the
+            // node pass counts these nodes.
+            let (Some(source), Some(dest)) = (osm_node_id(*node), osm_node_id(w.nodes[index + 1]))
+            else {
+                continue;
+            };
+            if one_way != OneWay::Backward {
+                edges.push(way_edge(source, dest));
+            }
+            if one_way != OneWay::Forward {
+                edges.push(way_edge(dest, source));
             }
         }
 
-        id_sender
-            .send(*w.nodes.last().unwrap())
-            .expect("could not send id to id set");
+        if let Some(id_sender) = id_sender {
+            let _ = id_sender.send(*w.nodes.last().unwrap());
+        }
         edges
     }
-    fn is_one_way(&self, way: &Way) -> bool {
+    fn is_one_way(&self, way: &Way) -> OneWay {
         let one_way = way.tags.get("oneway");
         let highway = way.tags.get("highway");
-        let junction = way.tags.get("junction");
-        match one_way.map(smartstring::SmartString::as_ref) {
-            Some("yes") | Some("true") => true,
-            Some("no") | Some("false") => false,
+        let roundabout = way
+            .tags
+            .get("junction")
+            .map(|j| j == "roundabout" || j == "circular")
+            .unwrap_or(false);
+        let direction = match one_way.map(smartstring::SmartString::as_ref) {
+            Some("yes") | Some("true") | Some("1") => OneWay::Forward,
+            Some("-1") | Some("reverse") => OneWay::Backward,
+            Some("no") | Some("false") | Some("0") => OneWay::Both,
             _ => {
-                highway.map(|h| h == "motorway").unwrap_or(false)
-                    || junction
-                        .map(|j| j == "roundabout" || j == "circular")
-                        .unwrap_or(false)
+                if highway.map(|h| h == "motorway").unwrap_or(false) || roundabout {
+                    OneWay::Forward
+                } else {
+                    OneWay::Both
+                }
             }
+        };
+        match direction {
+            OneWay::Forward if roundabout && self.reverse_roundabouts => OneWay::Backward,
+            OneWay::Backward if roundabout && self.reverse_roundabouts => OneWay::Forward,
+            direction => direction,
         }
     }
 
-    fn rename_node_ids_and_calculate_node_metrics(&self, nodes: &mut [Node], edges: &mut [Edge]) {
+    fn calculate_edge_lengths(&self, nodes: &[Node], edges: &mut [Edge]) {
         let map: HashMap<OsmNodeId, &Node> = nodes.iter().map(|n| (n.osm_id, n)).collect();
         for e in edges.iter_mut() {
             let source = map[&e.source_osm];
             let dest = map[&e.dest_osm];
 
             e.length = Distance_
-                .calc(source, dest, self.source_crs, self.target_crs)
+                .calc(source, dest, &self.proj_from, &self.proj_to)
                 .expect("Cannot calculate distance");
         }
     }
 
+    /// Keeps a single edge per `(source_osm, dest_osm)`: the shortest one,
+    /// and among equally short ones that of the smallest way. Comparing
+    /// endpoints only keeps edges whose computed lengths differ by float noise
+    /// from surviving as duplicates.
     fn delete_duplicate_edges(&self, edges: &mut Vec<Edge>) {
         edges.sort_by(|e1, e2| {
-            let mut result = e1.source_osm.cmp(&e2.source_osm);
-            if result == Ordering::Equal {
-                result = e1.dest_osm.cmp(&e2.dest_osm);
-            }
-            if result == Ordering::Equal {
-                result = e1
-                    .length
-                    .partial_cmp(&e2.length)
-                    .expect("Failure in comparing values");
-            }
-            result
+            e1.source_osm
+                .cmp(&e2.source_osm)
+                .then(e1.dest_osm.cmp(&e2.dest_osm))
+                .then(e1.length.total_cmp(&e2.length))
+                .then(e1.way_id.cmp(&e2.way_id))
         });
-        edges.dedup();
+        edges.dedup_by_key(|e| (e.source_osm, e.dest_osm));
     }
 
-    fn delete_dominated_edges(&self, edges: Vec<Edge>) -> Vec<Edge> {
-        let mut indices = ::std::collections::BTreeSet::new();
-        for i in 1..edges.len() {
-            let first = &edges[i - 1];
-            let second = &edges[i];
-            if !(first.source_osm == second.source_osm && first.dest_osm == second.dest_osm) {
-                continue;
-            }
-            if first.length <= second.length {
-                indices.insert(i);
-            }
-        }
-        edges
-            .into_iter()
-            .enumerate()
-            .filter(|(i, _)| !indices.contains(i))
-            .map(|(_, e)| e)
-            .collect()
+    /// Iterates the objects of `reader`, decoding its blobs on
+    /// `num_threads` threads.
+    fn par_objects<'a, R: Read>(
+        &self,
+        reader: &'a mut OsmPbfReader<R>,
+    ) -> impl Iterator<Item = osmpbfreader::Result<OsmObj>> + 'a {
+        reader
+            .blobs()
+            .with_nb_threads(self.num_threads)
+            .par_flat_map(osmpbfreader::blobs::result_blob_into_iter)
     }
-}
 
-pub type OsmNodeId = u64;
-pub type Latitude = f64;
-pub type Longitude = f64;
+    /// Like [`Loader::par_objects`], but skips blobs outside of the bounding
+    /// box of the filter geometry if their header carries a box, see
+    /// [`IndexedBlobs`], and counts them in `skipped`. Only with
+    /// [`BoundaryMode::Contains`] no object outside of the box can affect
+    /// the graph.
+    fn par_indexed_objects<'a, R: Read>(
+        &self,
+        reader: &'a mut R,
+        skipped: &'a Cell<usize>,
+    ) -> impl Iterator<Item = osmpbfreader::Result<OsmObj>> + 'a {
+        let filter = self
+            .filter_geometry
+            .as_ref()
+            .filter(|_| self.boundary == BoundaryMode::Contains)
+            .and_then(Polygon::bounding_rect);
+        IndexedBlobs::new(reader, filter, skipped)
+            .with_nb_threads(self.num_threads)
+            .par_flat_map(osmpbfreader::blobs::result_blob_into_iter)
+    }
+}
 
-#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
-pub struct Node {
-    pub osm_id: OsmNodeId,
-    pub lat: Latitude,
-    pub long: Longitude,
+/// Waits for the node ids collected by [`Loader::collect_node_ids`].
+fn receive_node_ids(
+    receiver: Receiver<HashSet<osmpbfreader::NodeId>>,
+) -> Result<HashSet<osmpbfreader::NodeId>, LoadGraphError> {
+    receiver
+        .recv()
+        .map_err(|_| LoadGraphError::NodeIdCollection)
 }
 
-impl Transform for Node {
-    fn transform_coordinates<F: TransformClosure>(
-        &mut self,
-        f: &mut F,
-    ) -> proj4rs::errors::Result<()> {
-        f(self.long, self.lat, 0.0).map(|(x, y, _)| {
-            self.long = x;
-            self.lat = y;
-        })
-    }
+/// Whether a node carries a tag routing depends on, e.g. a barrier or traffic
+/// signals, so it must stay a node of the graph.
+fn is_routing_relevant_node(tags: &osmpbfreader::Tags) -> bool {
+    tags.contains_key("barrier")
+        || tags.contains("railway", "level_crossing")
+        || [
+            "traffic_signals",
+            "stop",
+            "give_way",
+            "crossing",
+            "mini_roundabout",
+        ]
+        .iter()
+        .any(|value| tags.contains("highway", value))
+}
+
+/// Collapses chains of degree-two nodes into single edges.
+///
+/// A node is contracted if it is not in `protected`, all its edges belong to
+/// the same way and it connects exactly two other nodes, either by one
+/// incoming and one outgoing edge (oneway) or by edges in both directions to
+/// both of them. The contracted edge keeps the endpoint ids, the way id and
+/// surface attributes of the chain, and its length is the sum of the chain's
+/// lengths. With `keep_geometry`, the coordinates of the contracted nodes are
+/// stored in [`Edge::via`].
+pub fn contract_degree_two(
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    protected: &HashSet<OsmNodeId>,
+    keep_geometry: bool,
+) -> (Vec<Node>, Vec<Edge>) {
+    let mut outgoing: HashMap<OsmNodeId, Vec<usize>> = HashMap::new();
+    let mut incoming: HashMap<OsmNodeId, Vec<usize>> = HashMap::new();
+    for (i, edge) in edges.iter().enumerate() {
+        outgoing.entry(edge.source_osm).or_default().push(i);
+        incoming.entry(edge.dest_osm).or_default().push(i);
+    }
+    let no_edges = Vec::new();
+    let mut contractible: HashSet<OsmNodeId> = nodes
+        .iter()
+        .map(|n| n.osm_id)
+        .filter(|id| !protected.contains(id))
+        .filter(|id| {
+            let out = outgoing.get(id).unwrap_or(&no_edges);
+            let inc = incoming.get(id).unwrap_or(&no_edges);
+            let mut neighbours: Vec<OsmNodeId> = out
+                .iter()
+                .map(|&e| edges[e].dest_osm)
+                .chain(inc.iter().map(|&e| edges[e].source_osm))
+                .collect();
+            neighbours.sort_unstable();
+            neighbours.dedup();
+            let way_id = out.first().map(|&e| edges[e].way_id);
+            let single_way = out
+                .iter()
+                .chain(inc)
+                .all(|&e| Some(edges[e].way_id) == way_id);
+            let oneway = out.len() == 1 && inc.len() == 1;
+            let twoway = out.len() == 2 && inc.len() == 2;
+            neighbours.len() == 2 && !neighbours.contains(id) && single_way && (oneway || twoway)
+        })
+        .collect();
+    let coordinates: HashMap<OsmNodeId, (Latitude, Longitude)> =
+        nodes.iter().map(|n| (n.osm_id, (n.lat, n.long))).collect();
+
+    let mut visited = vec![false; edges.len()];
+    let mut contracted = Vec::new();
+    let mut walk = |start: usize, contractible: &HashSet<OsmNodeId>, visited: &mut Vec<bool>| {
+        visited[start] = true;
+        let first = &edges[start];
+        let mut edge = Edge {
+            length: first.length,
+            way_id: first.way_id,
+            surface_quality: first.surface_quality,
+            rough_paving: first.rough_paving,
+            infra_class: first.infra_class,
+            dismount: first.dismount,
+            max_weight: first.max_weight,
+            max_height: first.max_height,
+            max_width: first.max_width,
+            hgv_no: first.hgv_no,
+            lanes: first.lanes,
+            width: first.width,
+            step_count: first.step_count,
+            bridge: first.bridge,
+            tunnel: first.tunnel,
+            layer: first.layer,
+            ferry: first.ferry,
+            route_duration: first.route_duration,
+            ferry_time: first.ferry_time,
+            max_speed_conditional: first.max_speed_conditional.clone(),
+            ..Edge::new(first.source_osm, first.dest_osm)
+        };
+        let mut previous = first.source_osm;
+        while contractible.contains(&edge.dest_osm) {
+            let current = edge.dest_osm;
+            let next = outgoing[&current]
+                .iter()
+                .copied()
+                .find(|&e| edges[e].dest_osm != previous)
+                .expect("contractible nodes have an outgoing edge onwards");
+            visited[next] = true;
+            if keep_geometry {
+                edge.via.push(coordinates[&current]);
+            }
+            edge.length += edges[next].length;
+            edge.ferry_time = match (edge.ferry_time, edges[next].ferry_time) {
+                (None, None) => None,
+                (time, next_time) => Some(time.unwrap_or(0.0) + next_time.unwrap_or(0.0)),
+            };
+            edge.dest_osm = edges[next].dest_osm;
+            previous = current;
+        }
+        contracted.push(edge);
+    };
+    for (start, edge) in edges.iter().enumerate() {
+        if !contractible.contains(&edge.source_osm) {
+            walk(start, &contractible, &mut visited);
+        }
+    }
+    // Cycles made up only of contractible nodes have no start yet; keep one
+    // node of each of them.
+    while let Some(start) = (0..edges.len()).find(|&e| !visited[e]) {
+        let anchor = edges[start].source_osm;
+        contractible.remove(&anchor);
+        for &e in &outgoing[&anchor] {
+            walk(e, &contractible, &mut visited);
+        }
+    }
+
+    let nodes = nodes
+        .into_iter()
+        .filter(|n| !contractible.contains(&n.osm_id))
+        .collect();
+    (nodes, contracted)
+}
+
+/// `via` column of `{lat, long}` struct lists, see [`Edge::via`].
+fn via_series(edges: &[Edge]) -> PolarsResult<Series> {
+    let mut vias = Vec::with_capacity(edges.len());
+    for edge in edges {
+        let lat = Series::new(
+            "lat".into(),
+            edge.via.iter().map(|(lat, _)| *lat).collect::<Vec<_>>(),
+        );
+        let long = Series::new(
+            "long".into(),
+            edge.via.iter().map(|(_, long)| *long).collect::<Vec<_>>(),
+        );
+        let via = StructChunked::from_series("via".into(), edge.via.len(), [lat, long].iter())?;
+        vias.push(via.into_series());
+    }
+    Series::new("via".into(), vias).cast(&coordinate_list_dtype())
+}
+
+/// Douglas-Peucker simplification of polylines, see
+/// [`OsmLoaderBuilder::simplify_tolerance_m`].
+struct Simplifier<'a> {
+    tolerance: f64,
+    proj_from: &'a Proj,
+    proj_to: &'a Proj,
+}
+
+impl<'a> Simplifier<'a> {
+    fn new(tolerance: f64, proj_from: &'a Proj, proj_to: &'a Proj) -> Self {
+        Simplifier {
+            tolerance,
+            proj_from,
+            proj_to,
+        }
+    }
+
+    /// Indices of the points of `line` the simplification keeps, in order.
+    /// The endpoints and the points for which `pinned` holds are always
+    /// kept; the pieces between them are simplified independently.
+    fn keep(&self, line: &[(Latitude, Longitude)], pinned: impl Fn(usize) -> bool) -> Vec<usize> {
+        if line.len() < 3 {
+            return (0..line.len()).collect();
+        }
+        let projected: Vec<Coord> = line
+            .iter()
+            .map(|&(lat, long)| {
+                let mut point = Point::new(long, lat).to_radians();
+                proj4rs::transform::transform(self.proj_from, self.proj_to, &mut point).unwrap();
+                point.0
+            })
+            .collect();
+        let mut kept = vec![0];
+        let mut start = 0;
+        for end in 1..line.len() {
+            if end == line.len() - 1 || pinned(end) {
+                let piece = LineString::from(projected[start..=end].to_vec());
+                let indices = piece.simplify_idx(self.tolerance);
+                kept.extend(indices.into_iter().skip(1).map(|i| start + i));
+                start = end;
+            }
+        }
+        kept
+    }
+}
+
+/// Simplifies the polyline of each edge from its source through
+/// [`Edge::via`] to its destination. Contracted nodes belong to a single
+/// way, so only the endpoints need to be kept.
+fn simplify_via(simplifier: &Simplifier, nodes: &[Node], edges: &mut [Edge]) {
+    let map: HashMap<OsmNodeId, &Node> = nodes.iter().map(|n| (n.osm_id, n)).collect();
+    for edge in edges.iter_mut().filter(|e| !e.via.is_empty()) {
+        let (source, dest) = (map[&edge.source_osm], map[&edge.dest_osm]);
+        let line: Vec<(Latitude, Longitude)> = std::iter::once((source.lat, source.long))
+            .chain(edge.via.iter().copied())
+            .chain(std::iter::once((dest.lat, dest.long)))
+            .collect();
+        let kept = simplifier.keep(&line, |_| false);
+        edge.via = kept[1..kept.len() - 1].iter().map(|&i| line[i]).collect();
+    }
+}
+
+/// `list[struct{lat, long}]`, spelled out so that the column keeps its type
+/// even without any rows to infer it from.
+fn coordinate_list_dtype() -> DataType {
+    DataType::List(Box::new(DataType::Struct(vec![
+        Field::new("lat".into(), DataType::Float64),
+        Field::new("long".into(), DataType::Float64),
+    ])))
+}
+
+/// Rounds the coordinates of `nodes` and of the contracted geometry of
+/// `edges` to `decimals` decimal places.
+fn round_coordinates(nodes: &mut [Node], edges: &mut [Edge], decimals: u8) {
+    let factor = 10f64.powi(decimals.into());
+    let round = |value: f64| (value * factor).round() / factor;
+    for node in nodes {
+        node.lat = round(node.lat);
+        node.long = round(node.long);
+    }
+    for (lat, long) in edges.iter_mut().flat_map(|e| e.via.iter_mut()) {
+        *lat = round(*lat);
+        *long = round(*long);
+    }
+}
+
+/// Drops the edges with an endpoint missing from `nodes`, e.g. a node a
+/// clipped extract does not contain, and returns their number.
+fn drop_dangling_edges(nodes: &[Node], edges: &mut Vec<Edge>) -> usize {
+    let ids: HashSet<OsmNodeId> = nodes.iter().map(|n| n.osm_id).collect();
+    let before = edges.len();
+    edges.retain(|e| ids.contains(&e.source_osm) && ids.contains(&e.dest_osm));
+    before - edges.len()
+}
+
+/// Replaces nodes sharing their coordinates, optionally rounded to
+/// `decimals`, with the one of the smallest OSM id. Edges are redirected to
+/// it and the resulting self-loops dropped, a merged protected node
+/// protects the kept one. Returns the number of removed nodes.
+fn merge_coincident_nodes(
+    nodes: &mut Vec<Node>,
+    edges: &mut Vec<Edge>,
+    protected_nodes: &mut HashSet<OsmNodeId>,
+    decimals: Option<u8>,
+) -> usize {
+    let factor = decimals.map(|decimals| 10f64.powi(decimals.into()));
+    let key = |value: f64| {
+        factor
+            .map_or(value, |factor| (value * factor).round() / factor)
+            .to_bits()
+    };
+    let mut canonical: HashMap<(u64, u64), OsmNodeId> = HashMap::new();
+    for node in nodes.iter() {
+        canonical
+            .entry((key(node.lat), key(node.long)))
+            .and_modify(|id| *id = (*id).min(node.osm_id))
+            .or_insert(node.osm_id);
+    }
+    if canonical.len() == nodes.len() {
+        return 0;
+    }
+    let replacements: HashMap<OsmNodeId, OsmNodeId> = nodes
+        .iter()
+        .filter_map(|node| {
+            let id = canonical[&(key(node.lat), key(node.long))];
+            (id != node.osm_id).then_some((node.osm_id, id))
+        })
+        .collect();
+    for (merged, kept) in &replacements {
+        if protected_nodes.remove(merged) {
+            protected_nodes.insert(*kept);
+        }
+    }
+    nodes.retain(|n| !replacements.contains_key(&n.osm_id));
+    for edge in edges.iter_mut() {
+        edge.source_osm = *replacements
+            .get(&edge.source_osm)
+            .unwrap_or(&edge.source_osm);
+        edge.dest_osm = *replacements.get(&edge.dest_osm).unwrap_or(&edge.dest_osm);
+    }
+    edges.retain(|e| e.source_osm != e.dest_osm);
+    replacements.len()
+}
+
+/// Splits the time of every ferry route over its edges in proportion to
+/// their length, see [`FerryTravelTime`]. The route length only covers the
+/// part of the route inside the extract, and counts every segment once
+/// regardless of the directions it is traversable in.
+fn calculate_ferry_times(edges: &mut [Edge]) {
+    let mut segments = HashSet::new();
+    let mut route_lengths: HashMap<OsmWayId, f64> = HashMap::new();
+    for e in edges.iter().filter(|e| e.ferry) {
+        let segment = (
+            e.way_id,
+            e.source_osm.min(e.dest_osm),
+            e.source_osm.max(e.dest_osm),
+        );
+        if segments.insert(segment) {
+            *route_lengths.entry(e.way_id).or_default() += e.length;
+        }
+    }
+    let metric = FerryTravelTime::default();
+    for e in edges.iter_mut().filter(|e| e.ferry) {
+        let time = metric
+            .calc(
+                e.route_duration.map(Seconds),
+                Meters(e.length),
+                Meters(route_lengths[&e.way_id]),
+            )
+            .expect("Cannot calculate ferry time");
+        e.ferry_time = Some(time.0);
+    }
+}
+
+/// OSM id of `node`, `None` for the negative ids editors assign to objects
+/// that were not uploaded yet.
+pub(crate) fn osm_node_id(node: osmpbfreader::NodeId) -> Option<OsmNodeId> {
+    node.0.try_into().ok()
+}
+
+/// Decimicro degrees per degree, the unit of coordinates in pbf files.
+pub(crate) const DECIMICRO: f64 = 10_000_000.0;
+
+/// `(lat, long)` of `n` in degrees.
+pub(crate) fn coords(n: &osmpbfreader::Node) -> (Latitude, Longitude) {
+    (
+        f64::from(n.decimicro_lat) / DECIMICRO,
+        f64::from(n.decimicro_lon) / DECIMICRO,
+    )
+}
+
+/// Copies the coordinates of the endpoints of `edges` from `nodes`.
+fn inline_edge_coordinates(nodes: &[Node], edges: &mut [Edge]) {
+    let map: HashMap<OsmNodeId, &Node> = nodes.iter().map(|n| (n.osm_id, n)).collect();
+    for edge in edges {
+        let source = map[&edge.source_osm];
+        let dest = map[&edge.dest_osm];
+        edge.source_lat = Some(source.lat);
+        edge.source_long = Some(source.long);
+        edge.dest_lat = Some(dest.lat);
+        edge.dest_long = Some(dest.long);
+    }
+}
+
+/// Sorts `nodes` by OSM id and numbers them `0..N` in that order, so the
+/// numbering only depends on the extracted nodes. Sets the endpoints of
+/// `edges` accordingly.
+fn compact_node_ids(nodes: &mut [Node], edges: &mut [Edge]) {
+    nodes.sort_unstable_by_key(|n| n.osm_id);
+    let mut internal_ids = HashMap::with_capacity(nodes.len());
+    for (internal_id, node) in (0..).zip(nodes.iter_mut()) {
+        node.internal_id = Some(internal_id);
+        internal_ids.insert(node.osm_id, internal_id);
+    }
+    for edge in edges {
+        edge.source = Some(internal_ids[&edge.source_osm]);
+        edge.dest = Some(internal_ids[&edge.dest_osm]);
+    }
+}
+
+/// Sets the in- and out-degree of every node from the directed `edges`.
+fn calculate_node_degrees(nodes: &mut [Node], edges: &[Edge]) {
+    let index: HashMap<OsmNodeId, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.osm_id, i))
+        .collect();
+    for edge in edges {
+        if let Some(&i) = index.get(&edge.source_osm) {
+            nodes[i].out_degree += 1;
+        }
+        if let Some(&i) = index.get(&edge.dest_osm) {
+            nodes[i].in_degree += 1;
+        }
+    }
+}
+
+/// Keeps the edges with an endpoint inside `polygon` or crossing it, and the
+/// nodes inside or ending a kept edge.
+fn keep_intersecting_edges(
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    outside: &HashSet<OsmNodeId>,
+    polygon: &Polygon,
+) -> (Vec<Node>, Vec<Edge>) {
+    let coords: HashMap<OsmNodeId, Coord> = nodes.iter().map(|n| (n.osm_id, n.coord())).collect();
+    let edges: Vec<Edge> = edges
+        .into_iter()
+        .filter(|e| {
+            let (Some(&source), Some(&dest)) = (coords.get(&e.source_osm), coords.get(&e.dest_osm))
+            else {
+                return false;
+            };
+            !outside.contains(&e.source_osm)
+                || !outside.contains(&e.dest_osm)
+                || Line::new(source, dest).intersects(polygon)
+        })
+        .collect();
+    let used: HashSet<OsmNodeId> = edges
+        .iter()
+        .flat_map(|e| [e.source_osm, e.dest_osm])
+        .collect();
+    let nodes = nodes
+        .into_iter()
+        .filter(|n| !outside.contains(&n.osm_id) || used.contains(&n.osm_id))
+        .collect();
+    (nodes, edges)
+}
+
+/// Cuts edges crossing the boundary of `polygon` where they cross it and
+/// keeps the parts inside, ending in synthetic nodes on the boundary.
+///
+/// Synthetic nodes are numbered upwards from [`SYNTHETIC_NODE_ID_BASE`], in
+/// order of the crossed segments, so the numbering only depends on the
+/// extract and never collides with an OSM node id.
+fn clip_edges(
+    mut nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    outside: &HashSet<OsmNodeId>,
+    polygon: &Polygon,
+) -> (Vec<Node>, Vec<Edge>) {
+    let coords: HashMap<OsmNodeId, Coord> = nodes.iter().map(|n| (n.osm_id, n.coord())).collect();
+    let mut kept = Vec::with_capacity(edges.len());
+    // Edges with an endpoint outside, by their endpoints in ascending order,
+    // so both directions of a segment share the synthetic nodes.
+    let mut crossing: BTreeMap<(OsmNodeId, OsmNodeId), Vec<Edge>> = BTreeMap::new();
+    for edge in edges {
+        if !coords.contains_key(&edge.source_osm) || !coords.contains_key(&edge.dest_osm) {
+            continue;
+        }
+        if outside.contains(&edge.source_osm) || outside.contains(&edge.dest_osm) {
+            let key = if edge.source_osm < edge.dest_osm {
+                (edge.source_osm, edge.dest_osm)
+            } else {
+                (edge.dest_osm, edge.source_osm)
+            };
+            crossing.entry(key).or_default().push(edge);
+        } else {
+            kept.push(edge);
+        }
+    }
+
+    let boundary: Vec<Line> = polygon
+        .exterior()
+        .lines()
+        .chain(polygon.interiors().iter().flat_map(|ring| ring.lines()))
+        .collect();
+    let mut next_id = SYNTHETIC_NODE_ID_BASE;
+    let mut synthetic = Vec::new();
+    for ((first, second), segment_edges) in crossing {
+        let (a, b) = (coords[&first], coords[&second]);
+        let segment = Line::new(a, b);
+        let squared_length = segment.dx().powi(2) + segment.dy().powi(2);
+        let mut crossings: Vec<f64> = boundary
+            .iter()
+            .filter_map(|line| match line_intersection(segment, *line)? {
+                LineIntersection::SinglePoint { intersection, .. } => Some(
+                    ((intersection.x - a.x) * segment.dx() + (intersection.y - a.y) * segment.dy())
+                        / squared_length,
+                ),
+                // Running along the boundary does not cross it.
+                LineIntersection::Collinear { .. } => None,
+            })
+            .filter(|t| *t > 0.0 && *t < 1.0)
+            .collect();
+        crossings.sort_by(f64::total_cmp);
+        crossings.dedup();
+
+        let mut stops = vec![(0.0, first)];
+        for t in crossings {
+            let position = a + (b - a) * t;
+            synthetic.push(Node::new(next_id, position.y, position.x));
+            stops.push((t, next_id));
+            next_id += 1;
+        }
+        stops.push((1.0, second));
+        let pieces: Vec<(OsmNodeId, OsmNodeId)> = stops
+            .windows(2)
+            .filter(|pair| {
+                let middle = a + (b - a) * ((pair[0].0 + pair[1].0) / 2.0);
+                polygon.contains(&Point::from(middle))
+            })
+            .map(|pair| (pair[0].1, pair[1].1))
+            .collect();
+        for edge in segment_edges {
+            for &(source, dest) in &pieces {
+                let (source_osm, dest_osm) = if edge.source_osm == first {
+                    (source, dest)
+                } else {
+                    (dest, source)
+                };
+                kept.push(Edge {
+                    source_osm,
+                    dest_osm,
+                    ..edge.clone()
+                });
+            }
+        }
+    }
+    // Touching the boundary leaves synthetic nodes without edges.
+    let used: HashSet<OsmNodeId> = kept
+        .iter()
+        .flat_map(|e| [e.source_osm, e.dest_osm])
+        .collect();
+    nodes.retain(|n| !outside.contains(&n.osm_id));
+    nodes.extend(synthetic.into_iter().filter(|n| used.contains(&n.osm_id)));
+    (nodes, kept)
+}
+
+/// How a filter geometry treats edges crossing its boundary.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BoundaryMode {
+    /// Keeps edges with both endpoints inside.
+    #[default]
+    Contains,
+    /// Keeps edges with an endpoint inside or crossing the geometry, with
+    /// their endpoints outside of it.
+    Intersects,
+    /// Cuts edges at the boundary, keeping their parts inside. The cut
+    /// edges end in new nodes on the boundary with ids from
+    /// [`SYNTHETIC_NODE_ID_BASE`] upwards.
+    Clip,
+}
+
+/// First id of the nodes [`BoundaryMode::Clip`] adds on the boundary. OSM
+/// node ids stay far below it, so `osm_id >= SYNTHETIC_NODE_ID_BASE` tells
+/// synthetic nodes apart, and the ids still fit into signed 64 bit columns.
+pub const SYNTHETIC_NODE_ID_BASE: OsmNodeId = 1 << 62;
+
+/// Directions in which a way may be traversed, relative to the order of its
+/// nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OneWay {
+    /// Only from the first to the last node (`oneway=yes`).
+    Forward,
+    /// Only from the last to the first node (`oneway=-1`).
+    Backward,
+    Both,
+}
+
+pub type OsmNodeId = u64;
+pub type OsmWayId = u64;
+pub type Latitude = f64;
+pub type Longitude = f64;
+
+/// Constructed by the loader, through [`Node::new`] or through
+/// [`NodeBuilder`], so fields can be added without breaking callers.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
+#[non_exhaustive]
+pub struct Node {
+    pub osm_id: OsmNodeId,
+    pub lat: Latitude,
+    pub long: Longitude,
+    /// Number of edges ending at this node.
+    pub in_degree: u32,
+    /// Number of edges starting at this node.
+    pub out_degree: u32,
+    /// Position of the node in `0..N` when ordered by OSM id. Only set if
+    /// the loader compacts ids.
+    pub internal_id: Option<u64>,
+}
+
+impl Transform for Node {
+    fn transform_coordinates<F: TransformClosure>(
+        &mut self,
+        f: &mut F,
+    ) -> proj4rs::errors::Result<()> {
+        f(self.long, self.lat, 0.0).map(|(x, y, _)| {
+            self.long = x;
+            self.lat = y;
+        })
+    }
 }
 
 impl Node {
     pub fn new(osm_id: OsmNodeId, lat: Latitude, long: Longitude) -> Node {
-        Node { osm_id, lat, long }
+        Node {
+            osm_id,
+            lat,
+            long,
+            in_degree: 0,
+            out_degree: 0,
+            internal_id: None,
+        }
+    }
+
+    /// Hash of the id and coordinates, stable across runs and platforms, to
+    /// find nodes that changed between two extracts of the same area.
+    pub fn content_hash(&self) -> u64 {
+        fnv1a(&[self.osm_id, self.lat.to_bits(), self.long.to_bits()])
+    }
+
+    /// Position as `x = long`, `y = lat`.
+    pub fn coord(&self) -> Coord {
+        Coord {
+            x: self.long,
+            y: self.lat,
+        }
+    }
+}
+
+impl From<&Node> for Point {
+    fn from(node: &Node) -> Point {
+        node.coord().into()
+    }
+}
+
+/// Builds a [`Node`] outside of the loader, e.g. for tests. `osm_id`, `lat`
+/// and `long` are required, all other fields default to those of
+/// [`Node::new`].
+#[derive(Default)]
+pub struct NodeBuilder {
+    osm_id: Option<OsmNodeId>,
+    lat: Option<Latitude>,
+    long: Option<Longitude>,
+    in_degree: Option<u32>,
+    out_degree: Option<u32>,
+    internal_id: Option<u64>,
+}
+
+impl NodeBuilder {
+    pub fn osm_id(&mut self, value: OsmNodeId) -> &mut Self {
+        self.osm_id = Some(value);
+        self
+    }
+    pub fn lat(&mut self, value: Latitude) -> &mut Self {
+        self.lat = Some(value);
+        self
+    }
+    pub fn long(&mut self, value: Longitude) -> &mut Self {
+        self.long = Some(value);
+        self
+    }
+    pub fn in_degree(&mut self, value: u32) -> &mut Self {
+        self.in_degree = Some(value);
+        self
+    }
+    pub fn out_degree(&mut self, value: u32) -> &mut Self {
+        self.out_degree = Some(value);
+        self
+    }
+    pub fn internal_id(&mut self, value: u64) -> &mut Self {
+        self.internal_id = Some(value);
+        self
+    }
+    pub fn build(&self) -> Result<Node, LoaderBuildError> {
+        let required = |field: &str| LoaderBuildError::new(field.into());
+        let node = Node::new(
+            self.osm_id.ok_or_else(|| required("osm_id"))?,
+            self.lat.ok_or_else(|| required("lat"))?,
+            self.long.ok_or_else(|| required("long"))?,
+        );
+        Ok(Node {
+            in_degree: self.in_degree.unwrap_or(node.in_degree),
+            out_degree: self.out_degree.unwrap_or(node.out_degree),
+            internal_id: self.internal_id,
+            ..node
+        })
+    }
+}
+
+struct WayNode {
+    lat: Latitude,
+    long: Longitude,
+    inside: bool,
+}
+
+fn way_geometries_to_dataframe(
+    ways: Vec<(OsmWayId, Vec<osmpbfreader::NodeId>)>,
+    coordinates: &HashMap<osmpbfreader::NodeId, WayNode>,
+    simplifier: Option<&Simplifier>,
+) -> PolarsResult<DataFrame> {
+    let mut occurrences: HashMap<osmpbfreader::NodeId, usize> = HashMap::new();
+    if simplifier.is_some() {
+        for node in ways.iter().flat_map(|(_, nodes)| nodes) {
+            *occurrences.entry(*node).or_default() += 1;
+        }
+    }
+    let mut way_ids = Vec::with_capacity(ways.len());
+    let mut geometries = Vec::with_capacity(ways.len());
+    for (way_id, nodes) in ways {
+        let (node_ids, mut way_nodes): (Vec<_>, Vec<&WayNode>) = nodes
+            .iter()
+            .filter_map(|n| Some((n, coordinates.get(n)?)))
+            .unzip();
+        if way_nodes.len() < 2 || !way_nodes.iter().any(|n| n.inside) {
+            continue;
+        }
+        if let Some(simplifier) = simplifier {
+            let line: Vec<_> = way_nodes.iter().map(|n| (n.lat, n.long)).collect();
+            let kept = simplifier.keep(&line, |i| occurrences[node_ids[i]] > 1);
+            way_nodes = kept.into_iter().map(|i| way_nodes[i]).collect();
+        }
+        let lat = Series::new(
+            "lat".into(),
+            way_nodes.iter().map(|n| n.lat).collect::<Vec<_>>(),
+        );
+        let long = Series::new(
+            "long".into(),
+            way_nodes.iter().map(|n| n.long).collect::<Vec<_>>(),
+        );
+        let geometry =
+            StructChunked::from_series("geometry".into(), way_nodes.len(), [lat, long].iter())?;
+        way_ids.push(way_id);
+        geometries.push(geometry.into_series());
     }
+    DataFrame::new(vec![
+        Series::new("way_id".into(), way_ids).into(),
+        Series::new("geometry".into(), geometries)
+            .cast(&coordinate_list_dtype())?
+            .into(),
+    ])
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+/// Constructed by the loader, through [`Edge::new`] or through
+/// [`EdgeBuilder`], so fields can be added without breaking callers.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[non_exhaustive]
 pub struct Edge {
     pub source_osm: OsmNodeId,
     pub dest_osm: OsmNodeId,
     pub length: f64,
+    /// The OSM way the edge was extracted from, 0 if unknown.
+    pub way_id: OsmWayId,
+    /// Surface comfort of the way, see [`SurfaceQuality`]. Only set if the
+    /// loader computes surface quality and the way carries surface tags.
+    pub surface_quality: Option<f64>,
+    /// Whether the way is paved with cobblestones or setts.
+    pub rough_paving: bool,
+    /// Cycling infrastructure of the way. Only set if the loader extracts
+    /// cycling infrastructure.
+    pub infra_class: Option<CycleInfrastructure>,
+    /// Whether cyclists have to push their bike, see [`requires_dismount`].
+    /// Only set if the loader extracts cycling infrastructure.
+    pub dismount: bool,
+    /// Weight limit of the way in tonnes. Only set if the loader extracts
+    /// vehicle restrictions, as are the height and width limits and `hgv_no`.
+    pub max_weight: Option<f64>,
+    /// Height limit of the way in meters.
+    pub max_height: Option<f64>,
+    /// Width limit of the way in meters.
+    pub max_width: Option<f64>,
+    /// Whether the way is closed to heavy goods vehicles.
+    pub hgv_no: bool,
+    /// Number of lanes, see [`lanes`]. Only set if the loader extracts lanes
+    /// and width.
+    pub lanes: Option<u8>,
+    /// Width of the way in meters.
+    pub width: Option<f64>,
+    /// Number of steps of the way, see [`step_count`]. Only set if the
+    /// loader extracts step counts.
+    pub step_count: Option<u32>,
+    /// Whether the way is a bridge, see [`is_bridge`]. Only set if the
+    /// loader extracts layers, as are `tunnel` and `layer`.
+    pub bridge: bool,
+    /// Whether the way is a tunnel, see [`is_tunnel`].
+    pub tunnel: bool,
+    /// Vertical layer of the way, see [`layer`].
+    pub layer: Option<i8>,
+    /// Whether the way is a ferry route, see [`is_ferry`]. Only set if the
+    /// loader computes ferry times, as are `route_duration` and `ferry_time`.
+    pub ferry: bool,
+    /// Tagged duration of the whole ferry route in seconds, see
+    /// [`route_duration`].
+    pub route_duration: Option<f64>,
+    /// Travel time over the edge in seconds, see [`FerryTravelTime`].
+    pub ferry_time: Option<f64>,
+    /// `maxspeed:conditional` restrictions of the way as a JSON list of
+    /// `{speed, condition}` objects. Only set if the loader extracts
+    /// conditional speed limits and the way carries the tag.
+    pub max_speed_conditional: Option<String>,
+    /// Latitude of the source node. Only set if the loader inlines edge
+    /// coordinates, as are the other endpoint coordinates.
+    pub source_lat: Option<Latitude>,
+    pub source_long: Option<Longitude>,
+    pub dest_lat: Option<Latitude>,
+    pub dest_long: Option<Longitude>,
+    /// `(lat, long)` of the nodes contracted into this edge, in travel order.
+    /// Empty unless the loader keeps contracted geometry.
+    pub via: Vec<(Latitude, Longitude)>,
+    /// [`Node::internal_id`] of the source node, if the loader compacts ids.
+    pub source: Option<u64>,
+    /// [`Node::internal_id`] of the destination node, if the loader compacts
+    /// ids.
+    pub dest: Option<u64>,
 }
 
 impl Edge {
@@ -368,8 +2065,230 @@ impl Edge {
             source_osm,
             dest_osm,
             length: dist,
+            way_id: 0,
+            surface_quality: None,
+            rough_paving: false,
+            infra_class: None,
+            dismount: false,
+            max_weight: None,
+            max_height: None,
+            max_width: None,
+            hgv_no: false,
+            lanes: None,
+            width: None,
+            step_count: None,
+            bridge: false,
+            tunnel: false,
+            layer: None,
+            ferry: false,
+            route_duration: None,
+            ferry_time: None,
+            max_speed_conditional: None,
+            source_lat: None,
+            source_long: None,
+            dest_lat: None,
+            dest_long: None,
+            via: Vec::new(),
+            source: None,
+            dest: None,
         }
     }
+
+    /// Hash of the endpoints and length, stable across runs and platforms, to
+    /// find edges that changed between two extracts of the same area.
+    pub fn content_hash(&self) -> u64 {
+        fnv1a(&[self.source_osm, self.dest_osm, self.length.to_bits()])
+    }
+}
+
+/// Builds an [`Edge`] outside of the loader, e.g. for tests. `source_osm`
+/// and `dest_osm` are required, all other fields default to those of
+/// [`Edge::new`].
+#[derive(Default)]
+pub struct EdgeBuilder {
+    source_osm: Option<OsmNodeId>,
+    dest_osm: Option<OsmNodeId>,
+    length: Option<f64>,
+    way_id: Option<OsmWayId>,
+    surface_quality: Option<f64>,
+    rough_paving: bool,
+    infra_class: Option<CycleInfrastructure>,
+    dismount: bool,
+    max_weight: Option<f64>,
+    max_height: Option<f64>,
+    max_width: Option<f64>,
+    hgv_no: bool,
+    lanes: Option<u8>,
+    width: Option<f64>,
+    step_count: Option<u32>,
+    bridge: bool,
+    tunnel: bool,
+    layer: Option<i8>,
+    ferry: bool,
+    route_duration: Option<f64>,
+    ferry_time: Option<f64>,
+    max_speed_conditional: Option<String>,
+    coordinates: Option<[f64; 4]>,
+    via: Vec<(Latitude, Longitude)>,
+    internal_ids: Option<(u64, u64)>,
+}
+
+impl EdgeBuilder {
+    pub fn source_osm(&mut self, value: OsmNodeId) -> &mut Self {
+        self.source_osm = Some(value);
+        self
+    }
+    pub fn dest_osm(&mut self, value: OsmNodeId) -> &mut Self {
+        self.dest_osm = Some(value);
+        self
+    }
+    pub fn length(&mut self, value: f64) -> &mut Self {
+        self.length = Some(value);
+        self
+    }
+    pub fn way_id(&mut self, value: OsmWayId) -> &mut Self {
+        self.way_id = Some(value);
+        self
+    }
+    pub fn surface_quality(&mut self, value: f64) -> &mut Self {
+        self.surface_quality = Some(value);
+        self
+    }
+    pub fn rough_paving(&mut self, value: bool) -> &mut Self {
+        self.rough_paving = value;
+        self
+    }
+    pub fn infra_class(&mut self, value: CycleInfrastructure) -> &mut Self {
+        self.infra_class = Some(value);
+        self
+    }
+    pub fn dismount(&mut self, value: bool) -> &mut Self {
+        self.dismount = value;
+        self
+    }
+    pub fn max_weight(&mut self, value: f64) -> &mut Self {
+        self.max_weight = Some(value);
+        self
+    }
+    pub fn max_height(&mut self, value: f64) -> &mut Self {
+        self.max_height = Some(value);
+        self
+    }
+    pub fn max_width(&mut self, value: f64) -> &mut Self {
+        self.max_width = Some(value);
+        self
+    }
+    pub fn hgv_no(&mut self, value: bool) -> &mut Self {
+        self.hgv_no = value;
+        self
+    }
+    pub fn lanes(&mut self, value: u8) -> &mut Self {
+        self.lanes = Some(value);
+        self
+    }
+    pub fn width(&mut self, value: f64) -> &mut Self {
+        self.width = Some(value);
+        self
+    }
+    pub fn step_count(&mut self, value: u32) -> &mut Self {
+        self.step_count = Some(value);
+        self
+    }
+    pub fn bridge(&mut self, value: bool) -> &mut Self {
+        self.bridge = value;
+        self
+    }
+    pub fn tunnel(&mut self, value: bool) -> &mut Self {
+        self.tunnel = value;
+        self
+    }
+    pub fn layer(&mut self, value: i8) -> &mut Self {
+        self.layer = Some(value);
+        self
+    }
+    pub fn ferry(&mut self, value: bool) -> &mut Self {
+        self.ferry = value;
+        self
+    }
+    pub fn route_duration(&mut self, value: f64) -> &mut Self {
+        self.route_duration = Some(value);
+        self
+    }
+    pub fn ferry_time(&mut self, value: f64) -> &mut Self {
+        self.ferry_time = Some(value);
+        self
+    }
+    pub fn max_speed_conditional<VALUE: Into<String>>(&mut self, value: VALUE) -> &mut Self {
+        self.max_speed_conditional = Some(value.into());
+        self
+    }
+    /// Sets the endpoint coordinates as if the loader inlined them.
+    pub fn coordinates(&mut self, source: &Node, dest: &Node) -> &mut Self {
+        self.coordinates = Some([source.lat, source.long, dest.lat, dest.long]);
+        self
+    }
+    pub fn via(&mut self, value: Vec<(Latitude, Longitude)>) -> &mut Self {
+        self.via = value;
+        self
+    }
+    /// Sets [`Node::internal_id`] of the source and destination node, as if
+    /// the loader compacted ids.
+    pub fn internal_ids(&mut self, source: u64, dest: u64) -> &mut Self {
+        self.internal_ids = Some((source, dest));
+        self
+    }
+    pub fn build(&self) -> Result<Edge, LoaderBuildError> {
+        let required = |field: &str| LoaderBuildError::new(field.into());
+        let edge = Edge::new(
+            self.source_osm.ok_or_else(|| required("source_osm"))?,
+            self.dest_osm.ok_or_else(|| required("dest_osm"))?,
+        );
+        let coordinates = self
+            .coordinates
+            .map(|[a, b, c, d]| [Some(a), Some(b), Some(c), Some(d)]);
+        let [source_lat, source_long, dest_lat, dest_long] = coordinates.unwrap_or_default();
+        Ok(Edge {
+            length: self.length.unwrap_or(edge.length),
+            way_id: self.way_id.unwrap_or(edge.way_id),
+            surface_quality: self.surface_quality,
+            rough_paving: self.rough_paving,
+            infra_class: self.infra_class,
+            dismount: self.dismount,
+            max_weight: self.max_weight,
+            max_height: self.max_height,
+            max_width: self.max_width,
+            hgv_no: self.hgv_no,
+            lanes: self.lanes,
+            width: self.width,
+            step_count: self.step_count,
+            bridge: self.bridge,
+            tunnel: self.tunnel,
+            layer: self.layer,
+            ferry: self.ferry,
+            route_duration: self.route_duration,
+            ferry_time: self.ferry_time,
+            max_speed_conditional: self.max_speed_conditional.clone(),
+            source_lat,
+            source_long,
+            dest_lat,
+            dest_long,
+            via: self.via.clone(),
+            source: self.internal_ids.map(|(source, _)| source),
+            dest: self.internal_ids.map(|(_, dest)| dest),
+            ..edge
+        })
+    }
+}
+
+/// 64 bit FNV-1a. Unlike `DefaultHasher` its output is guaranteed not to
+/// change between Rust versions.
+fn fnv1a(words: &[u64]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in words.iter().flat_map(|word| word.to_le_bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
 }
 
 impl PartialEq for Edge {
@@ -379,3 +2298,1814 @@ impl PartialEq for Edge {
             && self.length == rhs.length
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pbfextractor::metrics::CarEdgeFilter;
+    use crate::pbfextractor::test_utils::{car_loader_builder, node, tags, way, write_pbf_fixture};
+    use osmpbfreader::{NodeId, Tags, WayId};
+
+    fn loader() -> Loader<CarEdgeFilter> {
+        OsmLoaderBuilder::default()
+            .edge_filter(CarEdgeFilter::default())
+            .target_crs(4839u16)
+            .pbf_path("data/bruegge.osm.pbf")
+            .build()
+            .unwrap()
+    }
+
+    fn tagged_way(tags: &[(&str, &str)]) -> Way {
+        Way {
+            id: WayId(1),
+            tags: tags
+                .iter()
+                .map(|(k, v)| ((*k).into(), (*v).into()))
+                .collect::<Tags>(),
+            nodes: vec![NodeId(1), NodeId(2), NodeId(3)],
+        }
+    }
+
+    fn edge_pairs(loader: &Loader<CarEdgeFilter>, way: &Way) -> Vec<(OsmNodeId, OsmNodeId)> {
+        let (id_sender, _id_receiver) = channel();
+        loader
+            .process_way(way, Some(&id_sender))
+            .iter()
+            .map(|e| (e.source_osm, e.dest_osm))
+            .collect()
+    }
+
+    #[test]
+    fn test_closed_node_id_channel() {
+        let (id_sender, id_receiver) = channel();
+        let set_receiver = loader().collect_node_ids(id_receiver);
+        id_sender.send(NodeId(1)).unwrap();
+        drop(id_sender);
+        assert_eq!(
+            receive_node_ids(set_receiver).unwrap(),
+            HashSet::from([NodeId(1)])
+        );
+
+        // The collector stopped without sending the set.
+        let (set_sender, set_receiver) = channel::<HashSet<osmpbfreader::NodeId>>();
+        drop(set_sender);
+        assert!(matches!(
+            receive_node_ids(set_receiver),
+            Err(LoadGraphError::NodeIdCollection)
+        ));
+
+        // Ids sent after the collector stopped do not panic.
+        let (id_sender, id_receiver) = channel();
+        drop(id_receiver);
+        let street = tagged_way(&[("highway", "residential")]);
+        assert_eq!(loader().process_way(&street, Some(&id_sender)).len(), 4);
+    }
+
+    #[test]
+    fn test_reversed_one_way() {
+        let loader = loader();
+        let reversed = tagged_way(&[("highway", "residential"), ("oneway", "-1")]);
+        assert_eq!(loader.is_one_way(&reversed), OneWay::Backward);
+        assert_eq!(edge_pairs(&loader, &reversed), vec![(2, 1), (3, 2)]);
+    }
+
+    #[test]
+    fn test_reverse_roundabouts() {
+        let roundabout = tagged_way(&[("highway", "primary"), ("junction", "roundabout")]);
+        let street = tagged_way(&[("highway", "primary"), ("oneway", "yes")]);
+        let drawn = loader();
+        assert_eq!(edge_pairs(&drawn, &roundabout), vec![(1, 2), (2, 3)]);
+
+        let reversed = OsmLoaderBuilder::default()
+            .edge_filter(CarEdgeFilter::default())
+            .target_crs(4839u16)
+            .pbf_path("data/bruegge.osm.pbf")
+            .reverse_roundabouts(true)
+            .build()
+            .unwrap();
+        assert_eq!(edge_pairs(&reversed, &roundabout), vec![(2, 1), (3, 2)]);
+        assert_eq!(edge_pairs(&reversed, &street), vec![(1, 2), (2, 3)]);
+    }
+
+    #[test]
+    fn test_or_filter_is_superset() {
+        use crate::pbfextractor::metrics::{BicycleEdgeFilter, Or, WalkingEdgeFilter};
+        use osmpbfreader::OsmObj;
+
+        let mut objects: Vec<OsmObj> = (1..=5)
+            .map(|id| node(id, 51.205 + id as f64 * 0.0005, 3.225))
+            .collect();
+        objects.extend([
+            way(10, &[("highway", "steps")], &[1, 2]),
+            way(11, &[("highway", "trunk"), ("cycleway", "track")], &[2, 3]),
+            way(12, &[("highway", "residential")], &[3, 4]),
+            way(13, &[("highway", "motorway")], &[4, 5]),
+        ]);
+        let path = write_pbf_fixture("osmtools_or_filter.osm.pbf", objects);
+
+        fn edges<Filter: EdgeFilter + Default>(filter: Filter, path: &Path) -> HashSet<(u64, u64)> {
+            let (_, edges) = OsmLoaderBuilder::<Filter>::default()
+                .edge_filter(filter)
+                .target_crs(4839u16)
+                .pbf_path(path)
+                .build()
+                .unwrap()
+                .load_graph();
+            edges.iter().map(|e| (e.source_osm, e.dest_osm)).collect()
+        }
+        let walking = edges(WalkingEdgeFilter::default(), &path);
+        let cycling = edges(BicycleEdgeFilter::default(), &path);
+        let either = edges(
+            Or(WalkingEdgeFilter::default(), BicycleEdgeFilter::default()),
+            &path,
+        );
+        assert!(!walking.is_empty() && !cycling.is_empty());
+        assert!(!walking.is_subset(&cycling) && !cycling.is_subset(&walking));
+        assert_eq!(either, &walking | &cycling);
+    }
+
+    #[test]
+    fn integration_test_auto_tile() {
+        let bounding_box = Polygon::new(
+            geo::LineString::from(vec![
+                (3.15, 51.15),
+                (3.30, 51.15),
+                (3.30, 51.25),
+                (3.15, 51.25),
+                (3.15, 51.15),
+            ]),
+            vec![],
+        );
+        let load = |auto_tile: bool| {
+            OsmLoaderBuilder::<CarEdgeFilter>::default()
+                .edge_filter(CarEdgeFilter::default())
+                .target_crs(4839u16)
+                .filter_geometry(bounding_box.clone())
+                .pbf_path("data/bruegge.osm.pbf")
+                .auto_tile(auto_tile)
+                .build()
+                .unwrap()
+                .load_graph()
+        };
+        let (nodes, edges) = load(false);
+        let (tiled_nodes, tiled_edges) = load(true);
+        let ids = |nodes: &[Node]| nodes.iter().map(|n| n.osm_id).collect::<Vec<_>>();
+        assert_eq!(ids(&nodes), ids(&tiled_nodes));
+        assert!(edges == tiled_edges);
+    }
+
+    #[test]
+    fn test_auto_tile() {
+        // A 10x10 grid of nodes joined by east-west streets, cut diagonally by
+        // a triangle large enough to be tiled.
+        let id = |row: i64, col: i64| 10 * row + col + 1;
+        let mut objects = Vec::new();
+        for row in 0..10 {
+            for col in 0..10 {
+                objects.push(node(
+                    id(row, col),
+                    51.0 + row as f64 * 0.05,
+                    3.0 + col as f64 * 0.05,
+                ));
+            }
+        }
+        for row in 0..10 {
+            let nodes = (0..10).map(|col| id(row, col)).collect::<Vec<_>>();
+            objects.push(way(row + 1, &[("highway", "residential")], &nodes));
+        }
+        let path = write_pbf_fixture("osmtools_auto_tile.osm.pbf", objects);
+        let triangle = Polygon::new(
+            geo::LineString::from(vec![(3.0, 51.0), (3.5, 51.0), (3.0, 51.5), (3.0, 51.0)]),
+            vec![],
+        );
+        let load = |auto_tile: bool| {
+            car_loader_builder(&path)
+                .filter_geometry(triangle.clone())
+                .auto_tile(auto_tile)
+                .build()
+                .unwrap()
+                .load_graph()
+        };
+        let (nodes, edges) = load(false);
+        let (tiled_nodes, tiled_edges) = load(true);
+        let ids = |nodes: &[Node]| nodes.iter().map(|n| n.osm_id).collect::<Vec<_>>();
+        assert!(!edges.is_empty());
+        assert_eq!(ids(&nodes), ids(&tiled_nodes));
+        assert!(edges == tiled_edges);
+    }
+
+    #[test]
+    fn test_way_geometries_to_dataframe() {
+        let coordinates: HashMap<NodeId, WayNode> = [
+            (1, 51.0, 3.0, true),
+            (2, 51.1, 3.1, true),
+            (3, 51.2, 3.2, false),
+            (4, 51.3, 3.3, false),
+            (5, 51.4, 3.4, false),
+        ]
+        .into_iter()
+        .map(|(id, lat, long, inside)| (NodeId(id), WayNode { lat, long, inside }))
+        .collect();
+        let ways = vec![
+            (10, vec![NodeId(1), NodeId(2), NodeId(3)]),
+            // Entirely outside of the filter geometry.
+            (11, vec![NodeId(3), NodeId(4), NodeId(5)]),
+        ];
+        let df = way_geometries_to_dataframe(ways, &coordinates, None).unwrap();
+        assert_eq!(df.shape(), (1, 2));
+        assert_eq!(df.column("way_id").unwrap().u64().unwrap().get(0), Some(10));
+        let geometry = df
+            .column("geometry")
+            .unwrap()
+            .list()
+            .unwrap()
+            .get_as_series(0)
+            .unwrap();
+        assert_eq!(geometry.len(), 3);
+        let lats = geometry.struct_().unwrap().field_by_name("lat").unwrap();
+        assert_eq!(lats.f64().unwrap().get(2), Some(51.2));
+    }
+
+    #[test]
+    fn test_load_ways_with_geometry() {
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            node(3, 51.2060, 3.2270),
+            node(4, 51.2065, 3.2280),
+            way(10, &[("highway", "residential")], &[1, 2, 3, 4]),
+            way(11, &[("highway", "footway")], &[3, 4]),
+        ];
+        let path = write_pbf_fixture("osmtools_way_geometry.osm.pbf", objects);
+        let ways = car_loader_builder(path)
+            .build()
+            .unwrap()
+            .load_ways_with_geometry()
+            .unwrap();
+        assert_eq!(
+            ways.column("way_id").unwrap().u64().unwrap().get(0),
+            Some(10)
+        );
+        let geometry = ways
+            .column("geometry")
+            .unwrap()
+            .list()
+            .unwrap()
+            .get_as_series(0)
+            .unwrap();
+        let longs = geometry.struct_().unwrap().field_by_name("long").unwrap();
+        let longs: Vec<f64> = longs.f64().unwrap().into_no_null_iter().collect();
+        assert_eq!(longs, vec![3.2250, 3.2260, 3.2270, 3.2280]);
+        assert_eq!(ways.height(), 1);
+
+        let missing = car_loader_builder("osmtools_missing.osm.pbf")
+            .build()
+            .unwrap()
+            .load_ways_with_geometry();
+        assert!(matches!(missing, Err(PolarsError::IO { .. })));
+
+        let broken = std::env::temp_dir().join("osmtools_broken_way_geometry.osm.pbf");
+        std::fs::write(&broken, b"not a pbf file").unwrap();
+        let broken = car_loader_builder(broken)
+            .build()
+            .unwrap()
+            .load_ways_with_geometry();
+        assert!(broken.is_err());
+    }
+
+    #[test]
+    fn integration_test_load_ways_with_geometry() {
+        let path = "data/bruegge.osm.pbf";
+        let ways = car_loader_builder(path)
+            .build()
+            .unwrap()
+            .load_ways_with_geometry()
+            .unwrap();
+        let node_counts: HashMap<OsmWayId, usize> = OsmPbfReader::new(File::open(path).unwrap())
+            .iter()
+            .filter_map(|obj| match obj.unwrap() {
+                OsmObj::Way(w) => Some((w.id.0 as OsmWayId, w.nodes.len())),
+                _ => None,
+            })
+            .collect();
+        let ids = ways.column("way_id").unwrap().u64().unwrap();
+        let geometries = ways.column("geometry").unwrap().list().unwrap();
+        assert!(ways.height() > 0);
+        for (id, geometry) in ids.into_no_null_iter().zip(geometries.into_iter()) {
+            assert_eq!(geometry.unwrap().len(), node_counts[&id], "way {id}");
+        }
+    }
+
+    #[test]
+    fn test_simplify_collinear_points() {
+        let (wgs84, etrs89) = (projection(4326).unwrap(), projection(4839).unwrap());
+        let simplifier = Simplifier::new(1.0, &wgs84, &etrs89);
+        let line: Vec<(f64, f64)> = (0..5).map(|i| (51.2, 3.2 + i as f64 * 0.001)).collect();
+        assert_eq!(simplifier.keep(&line, |_| false), vec![0, 4]);
+        assert_eq!(simplifier.keep(&line, |i| i == 2), vec![0, 2, 4]);
+
+        // A detour of about 11 m survives an 8 m tolerance, but not a 20 m
+        // one.
+        let mut bent = line.clone();
+        bent[2].0 += 0.0001;
+        assert_eq!(
+            Simplifier::new(8.0, &wgs84, &etrs89).keep(&bent, |_| false),
+            vec![0, 2, 4]
+        );
+        assert_eq!(
+            Simplifier::new(20.0, &wgs84, &etrs89).keep(&bent, |_| false),
+            vec![0, 4]
+        );
+
+        // The node shared with way 11 is kept.
+        let coordinates: HashMap<NodeId, WayNode> = line
+            .iter()
+            .enumerate()
+            .map(|(i, &(lat, long))| {
+                let node = WayNode {
+                    lat,
+                    long,
+                    inside: true,
+                };
+                (NodeId(i as i64), node)
+            })
+            .collect();
+        let ways = vec![
+            (10, (0..5).map(NodeId).collect()),
+            (11, vec![NodeId(3), NodeId(3)]),
+        ];
+        let df = way_geometries_to_dataframe(ways, &coordinates, Some(&simplifier)).unwrap();
+        let geometry = df
+            .column("geometry")
+            .unwrap()
+            .list()
+            .unwrap()
+            .get_as_series(0)
+            .unwrap();
+        let longs = geometry.struct_().unwrap().field_by_name("long").unwrap();
+        let longs: Vec<f64> = longs.f64().unwrap().into_no_null_iter().collect();
+        assert_eq!(longs, vec![line[0].1, line[3].1, line[4].1]);
+    }
+
+    #[test]
+    fn test_ferry_way_yields_edges() {
+        let loader = loader();
+        let ferry = tagged_way(&[("route", "ferry"), ("duration", "00:20")]);
+        assert_eq!(
+            edge_pairs(&loader, &ferry),
+            vec![(1, 2), (2, 1), (2, 3), (3, 2)]
+        );
+    }
+
+    #[test]
+    fn test_ferry_time_column() {
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2050, 3.2300),
+            node(3, 51.2050, 3.2400),
+            node(4, 51.2055, 3.2400),
+            way(10, &[("route", "ferry"), ("duration", "00:30")], &[1, 2, 3]),
+            way(11, &[("highway", "residential")], &[3, 4]),
+        ];
+        let path = write_pbf_fixture("osmtools_ferry_time.osm.pbf", objects);
+        let (_, edges) = car_loader_builder(path)
+            .ferry_time(true)
+            .build()
+            .unwrap()
+            .load_graph();
+        let time = |source: OsmNodeId, dest: OsmNodeId| {
+            edges
+                .iter()
+                .find(|e| e.source_osm == source && e.dest_osm == dest)
+                .unwrap()
+                .ferry_time
+        };
+        // The second segment is twice as long as the first.
+        let (first, second) = (time(1, 2).unwrap(), time(2, 3).unwrap());
+        assert!((first + second - 1800.0).abs() < 1e-6);
+        assert!((second / first - 2.0).abs() < 0.01);
+        assert_eq!(time(3, 2), time(2, 3));
+        assert_eq!(time(3, 4), None);
+    }
+
+    #[test]
+    fn test_filter_geometry_wkt() {
+        let mut builder = OsmLoaderBuilder::<CarEdgeFilter>::default();
+        builder
+            .filter_geometry_wkt("POLYGON((0 0, 1 0, 1 1, 0 1, 0 0))")
+            .unwrap();
+        assert_eq!(builder.filter_geometry.unwrap().exterior().0.len(), 5);
+        assert!(OsmLoaderBuilder::<CarEdgeFilter>::default()
+            .filter_geometry_wkt("LINESTRING(0 0, 1 1)")
+            .is_err());
+    }
+
+    #[test]
+    fn test_unknown_target_crs() {
+        // EPSG codes are `u16` here, so 99999 cannot even be passed.
+        let error = OsmLoaderBuilder::<CarEdgeFilter>::default()
+            .pbf_path("unused.osm.pbf")
+            .edge_filter(CarEdgeFilter::default())
+            .target_crs(9999u16)
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "Unknown CRS EPSG:9999");
+        assert!(crate::pbfextractor::node_pbf::PoiLoaderBuilder::default()
+            .pbf_path("unused.osm.pbf")
+            .target_crs(9999u16)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_filter_geometry_from_city() {
+        let mut builder = OsmLoaderBuilder::<CarEdgeFilter>::default();
+        builder.filter_geometry_from_city("Koeln").unwrap();
+        // Cologne Cathedral.
+        let center = Point::new(6.9583, 50.9413);
+        assert!(builder.filter_geometry.unwrap().contains(&center));
+        assert_eq!(
+            OsmLoaderBuilder::<CarEdgeFilter>::default()
+                .filter_geometry_from_city("Hogwarts")
+                .err(),
+            Some(SourceNotFoundError::new("Hogwarts".into()))
+        );
+    }
+
+    #[test]
+    fn test_edges_carry_way_id() {
+        let loader = loader();
+        let mut fixture = tagged_way(&[("highway", "residential")]);
+        fixture.id = WayId(4242);
+        let (id_sender, _id_receiver) = channel();
+        let edges = loader.process_way(&fixture, Some(&id_sender));
+        assert_eq!(edges.len(), 4);
+        assert!(edges.iter().all(|e| e.way_id == 4242));
+    }
+
+    #[test]
+    fn test_duplicate_edges_keep_smallest_way_id() {
+        let loader = loader();
+        let edge = |way_id| Edge {
+            length: 10.0,
+            way_id,
+            ..Edge::new(1, 2)
+        };
+        let mut edges = vec![edge(7), edge(3), edge(5)];
+        loader.delete_duplicate_edges(&mut edges);
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].way_id, 3);
+    }
+
+    #[test]
+    fn test_duplicate_edges_ignore_float_noise() {
+        let loader = loader();
+        let edge = |length, way_id| Edge {
+            length,
+            way_id,
+            ..Edge::new(1, 2)
+        };
+        let mut edges = vec![edge(10.0 + 1e-9, 3), edge(10.0, 5), Edge::new(2, 1)];
+        loader.delete_duplicate_edges(&mut edges);
+        assert_eq!(edges.len(), 2);
+        assert_eq!((edges[0].length, edges[0].way_id), (10.0, 5));
+        assert_eq!(edges[1].source_osm, 2);
+    }
+
+    #[test]
+    fn test_node_degrees() {
+        let mut nodes = vec![
+            Node::new(1, 51.2, 3.2),
+            Node::new(2, 51.3, 3.2),
+            Node::new(3, 51.2, 3.3),
+        ];
+        // A oneway 1 -> 2 -> 3 and a two-way street between 1 and 3.
+        let edges = vec![
+            Edge::new(1, 2),
+            Edge::new(2, 3),
+            Edge::new(1, 3),
+            Edge::new(3, 1),
+        ];
+        calculate_node_degrees(&mut nodes, &edges);
+        let degrees: Vec<(u32, u32)> = nodes.iter().map(|n| (n.in_degree, n.out_degree)).collect();
+        assert_eq!(degrees, vec![(1, 2), (1, 1), (2, 1)]);
+        let total_in: u32 = nodes.iter().map(|n| n.in_degree).sum();
+        let total_out: u32 = nodes.iter().map(|n| n.out_degree).sum();
+        assert_eq!((total_in, total_out), (4, 4));
+    }
+
+    #[test]
+    fn integration_test_deterministic_order() {
+        let load = || {
+            car_loader_builder("data/bruegge.osm.pbf")
+                .build()
+                .unwrap()
+                .load_graph_df()
+                .unwrap()
+        };
+        let (nodes, edges) = load();
+        let (nodes_again, edges_again) = load();
+        assert!(edges.height() > 0);
+        assert!(nodes.equals(&nodes_again));
+        assert!(edges.equals(&edges_again));
+    }
+
+    #[test]
+    fn test_output_order() {
+        use osmpbfreader::OsmObj;
+
+        // Ids in descending order across several blobs.
+        let mut objects: Vec<OsmObj> = (1..=20_000)
+            .rev()
+            .map(|id| node(id, 51.2 + id as f64 * 1e-6, 3.2))
+            .collect();
+        objects.extend((1..20_000).rev().map(|id| {
+            way(
+                id,
+                &[("highway", "residential"), ("oneway", "yes")],
+                &[id + 1, id],
+            )
+        }));
+        let path = write_pbf_fixture("osmtools_output_order.osm.pbf", objects);
+        let (nodes, edges) = car_loader_builder(path).build().unwrap().load_graph();
+        assert_eq!(nodes.len(), 20_000);
+        assert!(nodes.windows(2).all(|w| w[0].osm_id < w[1].osm_id));
+        assert_eq!(edges.len(), 19_999);
+        assert!(edges
+            .windows(2)
+            .all(|w| (w[0].source_osm, w[0].dest_osm) < (w[1].source_osm, w[1].dest_osm)));
+    }
+
+    #[test]
+    fn test_referenced_nodes() {
+        use osmpbfreader::OsmObj;
+
+        let mut objects: Vec<OsmObj> = (1..=6)
+            .map(|id| node(id, 51.2 + id as f64 * 1e-3, 3.2))
+            .collect();
+        objects.push(way(1, &[("highway", "residential")], &[1, 2, 3, 4]));
+        objects.push(way(2, &[("highway", "footway")], &[4, 5, 6]));
+        let path = write_pbf_fixture("osmtools_referenced_nodes.osm.pbf", objects);
+        let loader = |filter_geometry: Option<Polygon>| {
+            let mut builder = OsmLoaderBuilder::<CarEdgeFilter>::default();
+            builder
+                .edge_filter(CarEdgeFilter::default())
+                .target_crs(4839u16)
+                .pbf_path(path.clone());
+            if let Some(polygon) = filter_geometry {
+                builder.filter_geometry(polygon);
+            }
+            builder.build().unwrap()
+        };
+
+        let node_ids = loader(None).collect_referenced_nodes();
+        assert_eq!(node_ids, (1..=4).map(NodeId).collect());
+        let clipped = crate::bounding_box::BoundingBox::new(3.1, 51.2, 3.3, 51.2025).to_polygon();
+        for loader in [loader(None), loader(Some(clipped))] {
+            let (nodes, edges) = loader.load_graph();
+            let (injected_nodes, injected_edges) =
+                loader.load_graph_with_referenced_nodes(&node_ids);
+            let node_keys = |nodes: &[Node]| -> Vec<(OsmNodeId, f64, f64)> {
+                nodes.iter().map(|n| (n.osm_id, n.lat, n.long)).collect()
+            };
+            assert_eq!(node_keys(&nodes), node_keys(&injected_nodes));
+            assert!(edges == injected_edges);
+        }
+    }
+
+    #[test]
+    fn test_referenced_nodes_with_skipped_blobs() {
+        use crate::bounding_box::BoundingBox;
+
+        // Each town gets a node block of its own, the second one lies
+        // outside of the filter geometry.
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            node(3, 51.2060, 3.2270),
+            way(10, &[("highway", "residential")], &[1, 2]),
+            way(11, &[("highway", "residential")], &[3]),
+            node(4, 50.8500, 4.3500),
+            node(5, 50.8505, 4.3510),
+            way(12, &[("highway", "residential")], &[4, 5]),
+        ];
+        let path = write_pbf_fixture("osmtools_referenced_skipped.osm.pbf", objects);
+        let loader = car_loader_builder(path)
+            .filter_geometry(BoundingBox::new(3.2, 51.2, 3.3, 51.21))
+            .build()
+            .unwrap();
+
+        let (nodes, edges) = loader.load_graph();
+        let (injected_nodes, injected_edges) =
+            loader.load_graph_with_referenced_nodes(&loader.collect_referenced_nodes());
+        let ids = |nodes: &[Node]| nodes.iter().map(|n| n.osm_id).collect::<Vec<_>>();
+        assert_eq!(ids(&nodes), vec![1, 2]);
+        assert_eq!(ids(&nodes), ids(&injected_nodes));
+        assert!(edges == injected_edges);
+    }
+
+    #[test]
+    fn test_dangling_edges() {
+        use osmpbfreader::OsmObj;
+
+        // Node 4 is referenced by the way but missing from the file.
+        let mut objects: Vec<OsmObj> = (1..=3)
+            .map(|id| node(id, 51.2 + id as f64 * 1e-3, 3.2))
+            .collect();
+        objects.push(way(
+            1,
+            &[("highway", "residential"), ("oneway", "yes")],
+            &[1, 2, 3, 4],
+        ));
+        let path = write_pbf_fixture("osmtools_dangling_edges.osm.pbf", objects);
+        let loader = |filter_geometry: Option<Polygon>| {
+            let mut builder = OsmLoaderBuilder::<CarEdgeFilter>::default();
+            builder
+                .edge_filter(CarEdgeFilter::default())
+                .target_crs(4839u16)
+                .pbf_path(path.clone());
+            if let Some(polygon) = filter_geometry {
+                builder.filter_geometry(polygon);
+            }
+            builder.build().unwrap()
+        };
+        let pairs = |edges: &[Edge]| -> Vec<(OsmNodeId, OsmNodeId)> {
+            edges.iter().map(|e| (e.source_osm, e.dest_osm)).collect()
+        };
+
+        let (nodes, edges) = loader(None).load_graph();
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(pairs(&edges), vec![(1, 2), (2, 3)]);
+
+        // Clips node 1, the first edge loses its source.
+        let clipped = crate::bounding_box::BoundingBox::new(3.1, 51.2015, 3.3, 51.3).to_polygon();
+        let (nodes, edges) = loader(Some(clipped)).load_graph();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(pairs(&edges), vec![(2, 3)]);
+    }
+
+    #[test]
+    fn integration_test_source_timestamp() {
+        let timestamp = loader()
+            .source_timestamp()
+            .expect("Bruegge has a timestamp");
+        assert!(timestamp.timestamp() > 1_500_000_000);
+    }
+
+    #[test]
+    fn test_output_order_of_parallel_edges() {
+        // After contraction, each way is a parallel edge from 1 to 4.
+        let oneway = |id: i64, nodes: &[i64]| {
+            way(id, &[("highway", "residential"), ("oneway", "yes")], nodes)
+        };
+        let objects = vec![
+            node(1, 51.200, 3.200),
+            node(2, 51.201, 3.201),
+            node(3, 51.199, 3.201),
+            node(4, 51.200, 3.202),
+            node(5, 51.205, 3.201),
+            node(6, 51.199, 3.2012),
+            oneway(12, &[1, 5, 4]),
+            oneway(11, &[1, 3, 4]),
+            oneway(10, &[1, 2, 4]),
+            oneway(13, &[1, 6, 4]),
+        ];
+        let path = write_pbf_fixture("osmtools_parallel_order.osm.pbf", objects);
+        let (_, edges) = car_loader_builder(path)
+            .contract(true)
+            .build()
+            .unwrap()
+            .load_graph();
+        let order: Vec<(OsmNodeId, OsmNodeId, OsmWayId)> = edges
+            .iter()
+            .map(|e| (e.source_osm, e.dest_osm, e.way_id))
+            .collect();
+        assert_eq!(order, vec![(1, 4, 10), (1, 4, 11), (1, 4, 12), (1, 4, 13)]);
+    }
+
+    #[test]
+    fn integration_test_node_degree_columns() {
+        let (nodes, edges) = OsmLoaderBuilder::<CarEdgeFilter>::default()
+            .edge_filter(CarEdgeFilter::default())
+            .target_crs(4839u16)
+            .filter_geometry(crate::bounding_box::BoundingBox::new(
+                3.22183, 51.20391, 3.23663, 51.20887,
+            ))
+            .pbf_path("data/bruegge.osm.pbf")
+            .node_degree(true)
+            .build()
+            .unwrap()
+            .load_graph_df()
+            .unwrap();
+        assert!(edges.height() > 0);
+        for column in ["in_degree", "out_degree"] {
+            let degrees = nodes.column(column).unwrap().u32().unwrap();
+            let total: u32 = degrees.into_iter().flatten().sum();
+            assert_eq!(total as usize, edges.height());
+        }
+    }
+
+    #[test]
+    fn integration_test_graph_extent() {
+        let bbox = crate::bounding_box::BoundingBox::new(3.22183, 51.20391, 3.23663, 51.20887);
+        let (nodes, _, stats) = OsmLoaderBuilder::<CarEdgeFilter>::default()
+            .edge_filter(CarEdgeFilter::default())
+            .target_crs(4839u16)
+            .filter_geometry(bbox)
+            .pbf_path("data/bruegge.osm.pbf")
+            .build()
+            .unwrap()
+            .try_load_graph_with_stats()
+            .unwrap();
+        let extent = stats.extent.expect("Bruegge has nodes in the box");
+        assert_eq!(Some(extent), graph_extent(&nodes));
+        assert!(extent.min_lon >= bbox.min_lon && extent.max_lon <= bbox.max_lon);
+        assert!(extent.min_lat >= bbox.min_lat && extent.max_lat <= bbox.max_lat);
+        // The old town is densely mapped, so the nodes span most of the box.
+        assert!(extent.max_lon - extent.min_lon > 0.5 * (bbox.max_lon - bbox.min_lon));
+        assert!(extent.max_lat - extent.min_lat > 0.5 * (bbox.max_lat - bbox.min_lat));
+    }
+
+    #[test]
+    fn test_graph_extent() {
+        assert_eq!(graph_extent(&[]), None);
+        let nodes = [
+            Node::new(1, 51.2, 3.3),
+            Node::new(2, 51.1, 3.4),
+            Node::new(3, 51.3, 3.2),
+        ];
+        assert_eq!(
+            graph_extent(&nodes),
+            Some(crate::bounding_box::BoundingBox::new(3.2, 51.1, 3.4, 51.3))
+        );
+    }
+
+    #[test]
+    fn integration_test_single_thread() {
+        let load = |num_threads: Option<usize>| {
+            let mut builder = OsmLoaderBuilder::<CarEdgeFilter>::default();
+            builder
+                .edge_filter(CarEdgeFilter::default())
+                .target_crs(4839u16)
+                .filter_geometry(crate::bounding_box::BoundingBox::new(
+                    3.22183, 51.20391, 3.23663, 51.20887,
+                ))
+                .pbf_path("data/bruegge.osm.pbf");
+            if let Some(num_threads) = num_threads {
+                builder.num_threads(num_threads);
+            }
+            builder.build().unwrap().load_graph()
+        };
+        let (nodes, edges) = load(Some(1));
+        let (all_cores_nodes, all_cores_edges) = load(None);
+        assert!(!edges.is_empty());
+        assert_eq!(nodes.len(), all_cores_nodes.len());
+        assert!(edges == all_cores_edges);
+    }
+
+    #[test]
+    fn integration_test_load_graph_from_reader() {
+        let loader = OsmLoaderBuilder::<CarEdgeFilter>::default()
+            .edge_filter(CarEdgeFilter::default())
+            .target_crs(4839u16)
+            .filter_geometry(crate::bounding_box::BoundingBox::new(
+                3.22183, 51.20391, 3.23663, 51.20887,
+            ))
+            .pbf_path("data/bruegge.osm.pbf")
+            .build()
+            .unwrap();
+        let (nodes, edges) = loader.load_graph();
+        let buffer = std::fs::read("data/bruegge.osm.pbf").unwrap();
+        let (cursor_nodes, cursor_edges) =
+            loader.load_graph_from_reader(std::io::Cursor::new(buffer));
+        assert!(!edges.is_empty());
+        let ids = |nodes: &[Node]| nodes.iter().map(|n| n.osm_id).collect::<Vec<_>>();
+        assert_eq!(ids(&nodes), ids(&cursor_nodes));
+        assert!(edges == cursor_edges);
+    }
+
+    #[test]
+    fn integration_test_mmap() {
+        let load = |mmap: bool| {
+            car_loader_builder("data/bruegge.osm.pbf")
+                .mmap(mmap)
+                .build()
+                .unwrap()
+                .load_graph()
+        };
+        let (nodes, edges) = load(false);
+        let (mapped_nodes, mapped_edges) = load(true);
+        assert!(!edges.is_empty());
+        let ids = |nodes: &[Node]| nodes.iter().map(|n| n.osm_id).collect::<Vec<_>>();
+        assert_eq!(ids(&nodes), ids(&mapped_nodes));
+        assert!(edges == mapped_edges);
+    }
+
+    #[test]
+    fn test_mmap() {
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            node(3, 51.2060, 3.2270),
+            way(10, &[("highway", "residential")], &[1, 2, 3]),
+        ];
+        let path = write_pbf_fixture("osmtools_mmap.osm.pbf", objects);
+        let load = |mmap: bool| {
+            OsmLoaderBuilder::<CarEdgeFilter>::default()
+                .edge_filter(CarEdgeFilter::default())
+                .target_crs(4839u16)
+                .pbf_path(path.clone())
+                .mmap(mmap)
+                .build()
+                .unwrap()
+                .load_graph()
+        };
+        let (nodes, edges) = load(false);
+        let (mapped_nodes, mapped_edges) = load(true);
+        assert_eq!(edges.len(), 4);
+        let coordinates = |nodes: &[Node]| {
+            nodes
+                .iter()
+                .map(|n| (n.osm_id, n.lat, n.long))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(coordinates(&nodes), coordinates(&mapped_nodes));
+        assert!(edges == mapped_edges);
+    }
+
+    #[test]
+    fn test_merge_coincident_nodes() {
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            node(3, 51.2055, 3.2260),
+            node(4, 51.2060, 3.2270),
+            way(10, &[("highway", "residential")], &[1, 2, 3, 4]),
+        ];
+        let path = write_pbf_fixture("osmtools_coincident_nodes.osm.pbf", objects);
+        let load = |merge: bool| {
+            OsmLoaderBuilder::<CarEdgeFilter>::default()
+                .edge_filter(CarEdgeFilter::default())
+                .target_crs(4839u16)
+                .pbf_path(path.clone())
+                .merge_coincident_nodes(merge)
+                .build()
+                .unwrap()
+                .try_load_graph_with_stats()
+                .unwrap()
+        };
+
+        let (nodes, edges, stats) = load(false);
+        assert_eq!(nodes.len(), 4);
+        assert!(edges.iter().any(|e| e.length == 0.0));
+        assert_eq!(stats.merged_nodes, 0);
+
+        let (nodes, edges, stats) = load(true);
+        let ids: Vec<OsmNodeId> = nodes.iter().map(|n| n.osm_id).collect();
+        assert_eq!(ids, vec![1, 2, 4]);
+        assert_eq!(stats.merged_nodes, 1);
+        let pairs: Vec<(OsmNodeId, OsmNodeId)> =
+            edges.iter().map(|e| (e.source_osm, e.dest_osm)).collect();
+        assert_eq!(pairs, vec![(1, 2), (2, 1), (2, 4), (4, 2)]);
+    }
+
+    #[test]
+    fn test_load_graph_from_reader() {
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            node(3, 51.2060, 3.2270),
+            way(10, &[("highway", "residential")], &[1, 2, 3]),
+        ];
+        let path = write_pbf_fixture("osmtools_from_reader.osm.pbf", objects);
+        let loader = car_loader_builder("unused.osm.pbf").build().unwrap();
+        let cursor = std::io::Cursor::new(std::fs::read(path).unwrap());
+        let (nodes, edges) = loader.load_graph_from_reader(cursor);
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(edges.len(), 4);
+    }
+
+    /// Subscriber that records the names of entered spans.
+    #[derive(Default)]
+    struct SpanRecorder {
+        names: std::sync::Mutex<Vec<&'static str>>,
+        entered: std::sync::Mutex<Vec<&'static str>>,
+    }
+
+    impl tracing::Subscriber for SpanRecorder {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut names = self.names.lock().unwrap();
+            names.push(span.metadata().name());
+            tracing::span::Id::from_u64(names.len() as u64)
+        }
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+        fn event(&self, _: &tracing::Event<'_>) {}
+        fn enter(&self, span: &tracing::span::Id) {
+            let name = self.names.lock().unwrap()[span.into_u64() as usize - 1];
+            self.entered.lock().unwrap().push(name);
+        }
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[test]
+    fn test_phase_spans() {
+        use std::sync::Arc;
+
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            way(10, &[("highway", "residential")], &[1, 2]),
+        ];
+        let path = write_pbf_fixture("osmtools_phase_spans.osm.pbf", objects);
+        let loader = Loader::car(path).unwrap();
+        let recorder = Arc::new(SpanRecorder::default());
+        let (_, edges) =
+            tracing::subscriber::with_default(recorder.clone(), || loader.load_graph());
+        assert_eq!(edges.len(), 2);
+        assert_eq!(
+            *recorder.entered.lock().unwrap(),
+            vec!["ways", "nodes", "metrics", "dedup"]
+        );
+    }
+
+    #[test]
+    fn test_coordinate_decimals() {
+        let objects = vec![
+            node(1, 51.2050123, 3.2250987),
+            node(2, 51.2060456, 3.2270654),
+            way(10, &[("highway", "residential")], &[1, 2]),
+        ];
+        let path = write_pbf_fixture("osmtools_coordinate_decimals.osm.pbf", objects);
+        let load = |decimals: Option<u8>| {
+            OsmLoaderBuilder::<CarEdgeFilter>::default()
+                .edge_filter(CarEdgeFilter::default())
+                .target_crs(4839u16)
+                .pbf_path(path.clone())
+                .coordinate_decimals(decimals)
+                .build()
+                .unwrap()
+                .load_graph()
+        };
+        let (mut nodes, edges) = load(Some(6));
+        nodes.sort_by_key(|n| n.osm_id);
+        assert_eq!((nodes[0].lat, nodes[0].long), (51.205012, 3.225099));
+        assert_eq!((nodes[1].lat, nodes[1].long), (51.206046, 3.227065));
+
+        let (mut full_nodes, full_edges) = load(None);
+        full_nodes.sort_by_key(|n| n.osm_id);
+        assert_eq!(full_nodes[0].lat, 51.2050123);
+        assert_eq!(edges[0].length, full_edges[0].length);
+    }
+
+    #[test]
+    fn test_empty_bounding_box_keeps_column_types() {
+        use crate::bounding_box::BoundingBox;
+
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2060, 3.2270),
+            way(10, &[("highway", "residential")], &[1, 2]),
+        ];
+        let path = write_pbf_fixture("osmtools_empty_bbox_types.osm.pbf", objects);
+        let loader = car_loader_builder(path)
+            .filter_geometry(BoundingBox::new(2.5, 51.6, 2.51, 51.61))
+            .contract(true)
+            .keep_contracted_geometry(true)
+            .build()
+            .unwrap();
+        let (nodes, edges) = loader.load_graph_df().unwrap();
+        assert_eq!((nodes.height(), edges.height()), (0, 0));
+        assert_eq!(
+            edges.column("via").unwrap().dtype(),
+            &coordinate_list_dtype()
+        );
+
+        let ways = loader.load_ways_with_geometry().unwrap();
+        assert_eq!(ways.height(), 0);
+        assert_eq!(
+            ways.column("geometry").unwrap().dtype(),
+            &coordinate_list_dtype()
+        );
+    }
+
+    #[test]
+    fn test_compact_ids() {
+        use osmpbfreader::OsmObj;
+
+        let objects = vec![
+            node(9_000_000_001, 51.2050, 3.2250),
+            node(9_000_000_007, 51.2055, 3.2260),
+            node(9_000_000_003, 51.2060, 3.2270),
+            OsmObj::Way(Way {
+                id: WayId(10),
+                tags: tags(&[("highway", "residential")]),
+                nodes: vec![
+                    NodeId(9_000_000_001),
+                    NodeId(9_000_000_007),
+                    NodeId(9_000_000_003),
+                ],
+            }),
+        ];
+        let path = write_pbf_fixture("osmtools_compact_ids.osm.pbf", objects);
+        let (nodes, edges) = car_loader_builder(path)
+            .compact_ids(true)
+            .build()
+            .unwrap()
+            .load_graph_df()
+            .unwrap();
+
+        let column = |df: &DataFrame, name: &str| -> Vec<u64> {
+            df.column(name)
+                .unwrap()
+                .u64()
+                .unwrap()
+                .into_no_null_iter()
+                .collect()
+        };
+        let osm_ids = column(&nodes, "osm_id");
+        assert_eq!(osm_ids, vec![9_000_000_001, 9_000_000_003, 9_000_000_007]);
+        assert_eq!(column(&nodes, "internal_id"), vec![0, 1, 2]);
+
+        assert_eq!(edges.height(), 4);
+        for (osm_column, internal_column) in [("source_osm", "source"), ("dest_osm", "dest")] {
+            let osm = column(&edges, osm_column);
+            for (osm_id, internal_id) in osm.into_iter().zip(column(&edges, internal_column)) {
+                assert!(internal_id < nodes.height() as u64);
+                assert_eq!(osm_ids[internal_id as usize], osm_id);
+            }
+        }
+    }
+
+    #[test]
+    fn integration_test_coordinate_decimals() {
+        let (nodes, _) = OsmLoaderBuilder::<CarEdgeFilter>::default()
+            .edge_filter(CarEdgeFilter::default())
+            .target_crs(4839u16)
+            .filter_geometry(crate::bounding_box::BoundingBox::new(
+                3.22183, 51.20391, 3.23663, 51.20887,
+            ))
+            .pbf_path("data/bruegge.osm.pbf")
+            .coordinate_decimals(Some(6))
+            .build()
+            .unwrap()
+            .load_graph();
+        assert!(!nodes.is_empty());
+        for node in nodes {
+            for value in [node.lat, node.long] {
+                assert_eq!(value, (value * 1e6).round() / 1e6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_graph_df_rejects_unsupported_feature() {
+        let path = crate::pbfextractor::header::tests::write_header_fixture(
+            "osmtools_loader_historical.osm.pbf",
+            &["OsmSchema-V0.6", "HistoricalInformation"],
+        );
+        let loader = car_loader_builder(path).build().unwrap();
+        assert!(matches!(
+            loader.validate_header(),
+            Err(PbfHeaderError::UnsupportedPbfFeature(_))
+        ));
+        let error = loader.load_graph_df().unwrap_err();
+        assert!(error.to_string().contains("HistoricalInformation"));
+    }
+
+    #[test]
+    fn test_vehicle_restrictions_on_edges() {
+        use crate::pbfextractor::metrics::TruckEdgeFilter;
+
+        let truck_loader = OsmLoaderBuilder::<TruckEdgeFilter>::default()
+            .edge_filter(TruckEdgeFilter::default())
+            .target_crs(4839u16)
+            .pbf_path("unused.osm.pbf")
+            .vehicle_restrictions(true)
+            .build()
+            .unwrap();
+        let (id_sender, _id_receiver) = channel();
+        let bridge = tagged_way(&[("highway", "residential"), ("maxweight", "7.5")]);
+        let edges = truck_loader.process_way(&bridge, Some(&id_sender));
+        assert!(!edges.is_empty());
+        assert!(edges
+            .iter()
+            .all(|e| e.max_weight == Some(7.5) && e.max_height.is_none() && !e.hgv_no));
+
+        let no_trucks = tagged_way(&[("highway", "residential"), ("hgv", "no")]);
+        let edges = truck_loader.process_way(&no_trucks, Some(&id_sender));
+        assert!(!edges.is_empty());
+        assert!(edges.iter().all(|e| e.hgv_no && e.max_weight.is_none()));
+
+        let edges = loader().process_way(&bridge, Some(&id_sender));
+        assert!(edges.iter().all(|e| e.max_weight.is_none()));
+    }
+
+    #[test]
+    fn test_negative_node_ids() {
+        let objects = vec![
+            node(-3, 51.2045, 3.2240),
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            way(10, &[("highway", "residential")], &[-3, 1, 2]),
+        ];
+        let path = write_pbf_fixture("osmtools_negative_ids.osm.pbf", objects);
+        let (nodes, edges) = Loader::car(path).unwrap().load_graph();
+        assert_eq!(
+            nodes.iter().map(|n| n.osm_id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(edges.len(), 2);
+        assert!(edges.iter().all(|e| e.source_osm != 0 && e.dest_osm != 0));
+    }
+
+    /// Loads a street from inside to outside of a box and one crossing it
+    /// completely with `boundary`.
+    fn load_boundary_fixture(boundary: BoundaryMode) -> (Vec<Node>, Vec<Edge>) {
+        use crate::bounding_box::BoundingBox;
+
+        let street = |id: i64, nodes: &[i64]| way(id, &[("highway", "residential")], nodes);
+        let objects = vec![
+            node(1, 51.25, 3.25),
+            node(2, 51.25, 3.35),
+            node(3, 51.35, 3.35),
+            node(4, 51.22, 3.15),
+            node(5, 51.22, 3.35),
+            street(10, &[1, 2, 3]),
+            street(11, &[4, 5]),
+        ];
+        let path = write_pbf_fixture("osmtools_boundary.osm.pbf", objects);
+        car_loader_builder(path)
+            .filter_geometry(BoundingBox::new(3.2, 51.2, 3.3, 51.3))
+            .boundary(boundary)
+            .build()
+            .unwrap()
+            .load_graph()
+    }
+
+    fn endpoints(edges: &[Edge]) -> Vec<(OsmNodeId, OsmNodeId)> {
+        edges.iter().map(|e| (e.source_osm, e.dest_osm)).collect()
+    }
+
+    #[test]
+    fn test_boundary_contains() {
+        let (nodes, edges) = load_boundary_fixture(BoundaryMode::Contains);
+        assert_eq!(nodes.iter().map(|n| n.osm_id).collect::<Vec<_>>(), vec![1]);
+        assert!(edges.is_empty());
+    }
+
+    #[test]
+    fn test_boundary_intersects() {
+        let (nodes, edges) = load_boundary_fixture(BoundaryMode::Intersects);
+        assert_eq!(
+            nodes.iter().map(|n| n.osm_id).collect::<Vec<_>>(),
+            vec![1, 2, 4, 5]
+        );
+        assert_eq!(endpoints(&edges), vec![(1, 2), (2, 1), (4, 5), (5, 4)]);
+    }
+
+    #[test]
+    fn test_boundary_clip() {
+        let (nodes, edges) = load_boundary_fixture(BoundaryMode::Clip);
+        let coordinates: Vec<(OsmNodeId, f64, f64)> = nodes
+            .iter()
+            .map(|n| {
+                (
+                    n.osm_id,
+                    (n.lat * 1e6).round() / 1e6,
+                    (n.long * 1e6).round() / 1e6,
+                )
+            })
+            .collect();
+        let (first, second, third) = (
+            SYNTHETIC_NODE_ID_BASE,
+            SYNTHETIC_NODE_ID_BASE + 1,
+            SYNTHETIC_NODE_ID_BASE + 2,
+        );
+        assert_eq!(
+            coordinates,
+            vec![
+                (1, 51.25, 3.25),
+                (first, 51.25, 3.3),
+                (second, 51.22, 3.2),
+                (third, 51.22, 3.3)
+            ]
+        );
+        assert_eq!(
+            endpoints(&edges),
+            vec![(1, first), (first, 1), (second, third), (third, second)]
+        );
+        let (_, unclipped) = load_boundary_fixture(BoundaryMode::Intersects);
+        assert!(edges[0].length > 0.0);
+        assert!(edges[0].length < unclipped[0].length / 1.9);
+        assert!(edges.iter().all(|e| e.way_id == 10 || e.way_id == 11));
+    }
+
+    #[test]
+    fn test_inline_edge_coords() {
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            node(3, 51.2060, 3.2270),
+            way(
+                10,
+                &[("highway", "residential"), ("oneway", "yes")],
+                &[1, 2, 3],
+            ),
+        ];
+        let path = write_pbf_fixture("osmtools_inline_edge_coords.osm.pbf", objects);
+        let mut builder = OsmLoaderBuilder::<CarEdgeFilter>::default();
+        builder
+            .edge_filter(CarEdgeFilter::default())
+            .target_crs(4839u16)
+            .pbf_path(path);
+        let (_, edges) = builder.build().unwrap().load_graph_df().unwrap();
+        assert!(edges.column("source_lat").is_err());
+
+        let (nodes, edges) = builder
+            .inline_edge_coords(true)
+            .build()
+            .unwrap()
+            .load_graph_df()
+            .unwrap();
+        let coordinate = |df: &DataFrame, column: &str, row: usize| {
+            df.column(column).unwrap().f64().unwrap().get(row).unwrap()
+        };
+        let node_row = |osm_id: u64| {
+            let ids = nodes.column("osm_id").unwrap().u64().unwrap();
+            ids.into_no_null_iter().position(|id| id == osm_id).unwrap()
+        };
+        let ids = |column: &str| edges.column(column).unwrap().u64().unwrap().get(1).unwrap();
+        let source = node_row(ids("source_osm"));
+        let dest = node_row(ids("dest_osm"));
+        assert_eq!(
+            coordinate(&edges, "source_lat", 1),
+            coordinate(&nodes, "lat", source)
+        );
+        assert_eq!(
+            coordinate(&edges, "source_long", 1),
+            coordinate(&nodes, "long", source)
+        );
+        assert_eq!(
+            coordinate(&edges, "dest_lat", 1),
+            coordinate(&nodes, "lat", dest)
+        );
+        assert_eq!(
+            coordinate(&edges, "dest_long", 1),
+            coordinate(&nodes, "long", dest)
+        );
+        assert_eq!(coordinate(&edges, "dest_lat", 1), 51.2060);
+    }
+
+    #[test]
+    fn test_lanes_and_width_columns() {
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            node(3, 51.2060, 3.2270),
+            way(
+                10,
+                &[("highway", "primary"), ("lanes", "2"), ("width", "3.5")],
+                &[1, 2],
+            ),
+            way(11, &[("highway", "primary"), ("lanes", "many")], &[2, 3]),
+        ];
+        let path = write_pbf_fixture("osmtools_lanes_and_width.osm.pbf", objects);
+        let (_, edges) = car_loader_builder(path)
+            .lanes_and_width(true)
+            .build()
+            .unwrap()
+            .load_graph_df()
+            .unwrap();
+        let edges = edges.sort(["way_id"], Default::default()).unwrap();
+        let lanes: Vec<Option<u8>> = edges
+            .column("lanes")
+            .unwrap()
+            .u8()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(lanes, vec![Some(2), Some(2), None, None]);
+        let widths: Vec<Option<f64>> = edges
+            .column("width_m")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(widths, vec![Some(3.5), Some(3.5), None, None]);
+    }
+
+    #[test]
+    fn test_conditional_max_speed_column() {
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            node(3, 51.2060, 3.2270),
+            way(
+                10,
+                &[
+                    ("highway", "residential"),
+                    ("maxspeed:conditional", "30 @ (22:00-06:00); none @ Su"),
+                ],
+                &[1, 2],
+            ),
+            way(11, &[("highway", "residential")], &[2, 3]),
+        ];
+        let path = write_pbf_fixture("osmtools_conditional_max_speed.osm.pbf", objects);
+        let edges = car_loader_builder(path)
+            .conditional_max_speed(Some(SpeedProfile::De))
+            .build()
+            .unwrap()
+            .load_graph_df()
+            .unwrap()
+            .1
+            .sort(["way_id"], Default::default())
+            .unwrap();
+        let conditionals: Vec<Option<&str>> = edges
+            .column("maxspeed_conditional")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .collect();
+        let expected =
+            r#"[{"condition":"22:00-06:00","speed":30.0},{"condition":"Su","speed":null}]"#;
+        assert_eq!(
+            conditionals,
+            vec![Some(expected), Some(expected), None, None]
+        );
+    }
+
+    #[test]
+    fn test_layer_columns() {
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            node(3, 51.2060, 3.2270),
+            way(
+                10,
+                &[
+                    ("highway", "residential"),
+                    ("bridge", "yes"),
+                    ("layer", "1"),
+                ],
+                &[1, 2],
+            ),
+            way(11, &[("highway", "residential")], &[2, 3]),
+        ];
+        let path = write_pbf_fixture("osmtools_layers.osm.pbf", objects);
+        let edges = car_loader_builder(path)
+            .layers(true)
+            .build()
+            .unwrap()
+            .load_graph_df()
+            .unwrap()
+            .1
+            .sort(["way_id"], Default::default())
+            .unwrap();
+        let flags = |name: &str| -> Vec<Option<bool>> {
+            edges
+                .column(name)
+                .unwrap()
+                .bool()
+                .unwrap()
+                .into_iter()
+                .collect()
+        };
+        assert_eq!(
+            flags("bridge"),
+            vec![Some(true), Some(true), Some(false), Some(false)]
+        );
+        assert_eq!(flags("tunnel"), vec![Some(false); 4]);
+        let layers: Vec<Option<i8>> = edges
+            .column("layer")
+            .unwrap()
+            .i8()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(layers, vec![Some(1), Some(1), None, None]);
+    }
+
+    #[test]
+    fn test_step_count_column() {
+        use crate::pbfextractor::metrics::WalkingEdgeFilter;
+
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            node(3, 51.2060, 3.2270),
+            way(10, &[("highway", "steps"), ("step_count", "12")], &[1, 2]),
+            way(11, &[("highway", "footway")], &[2, 3]),
+        ];
+        let path = write_pbf_fixture("osmtools_step_count.osm.pbf", objects);
+        let load = |filter: WalkingEdgeFilter| {
+            OsmLoaderBuilder::<WalkingEdgeFilter>::default()
+                .edge_filter(filter)
+                .target_crs(4839u16)
+                .pbf_path(path.clone())
+                .reverse_edges(true)
+                .step_count(true)
+                .build()
+                .unwrap()
+                .load_graph_df()
+                .unwrap()
+                .1
+                .sort(["way_id"], Default::default())
+                .unwrap()
+        };
+
+        let edges = load(WalkingEdgeFilter::default());
+        let step_counts: Vec<Option<u32>> = edges
+            .column("step_count")
+            .unwrap()
+            .u32()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(step_counts, vec![Some(12), Some(12), None, None]);
+
+        let edges = load(WalkingEdgeFilter::default().exclude_steps(true));
+        let way_ids: Vec<Option<u64>> = edges
+            .column("way_id")
+            .unwrap()
+            .u64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(way_ids, vec![Some(11), Some(11)]);
+    }
+
+    #[test]
+    fn test_short_ways_are_skipped() {
+        use osmpbfreader::OsmObj;
+
+        let mut single_node = tagged_way(&[("highway", "residential")]);
+        single_node.nodes = vec![NodeId(1)];
+        let mut empty = tagged_way(&[("highway", "residential")]);
+        empty.nodes = vec![];
+        let (id_sender, _id_receiver) = channel();
+        assert!(loader()
+            .process_way(&single_node, Some(&id_sender))
+            .is_empty());
+        assert!(loader().process_way(&empty, Some(&id_sender)).is_empty());
+
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            OsmObj::Way(Way {
+                id: WayId(10),
+                ..single_node
+            }),
+            OsmObj::Way(Way {
+                id: WayId(11),
+                ..empty
+            }),
+            way(12, &[("highway", "residential")], &[1, 2]),
+        ];
+        let path = write_pbf_fixture("osmtools_short_ways.osm.pbf", objects);
+        let (_, edges, stats) = car_loader_builder(path)
+            .build()
+            .unwrap()
+            .try_load_graph_with_stats()
+            .unwrap();
+        assert_eq!(stats.short_ways, 2);
+        assert!(edges.iter().all(|e| e.way_id == 12));
+        assert_eq!(edges.len(), 2);
+    }
+
+    #[test]
+    fn test_short_ways_are_not_referenced() {
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            node(3, 51.2060, 3.2270),
+            way(10, &[("highway", "residential")], &[3]),
+            way(11, &[("highway", "residential")], &[1, 2]),
+        ];
+        let path = write_pbf_fixture("osmtools_short_way_references.osm.pbf", objects);
+        let loader = car_loader_builder(path).build().unwrap();
+
+        let node_ids = loader.collect_referenced_nodes();
+        assert_eq!(node_ids, [NodeId(1), NodeId(2)].into_iter().collect());
+        let (nodes, _) = loader.load_graph();
+        let (injected_nodes, _) = loader.load_graph_with_referenced_nodes(&node_ids);
+        let ids = |nodes: &[Node]| nodes.iter().map(|n| n.osm_id).collect::<Vec<_>>();
+        assert_eq!(ids(&nodes), vec![1, 2]);
+        assert_eq!(ids(&injected_nodes), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_blobs_outside_filter_are_skipped() {
+        use crate::bounding_box::BoundingBox;
+        use osmpbfreader::OsmObj;
+
+        let street = |id: i64, nodes: [i64; 2]| {
+            OsmObj::Way(Way {
+                id: WayId(id),
+                tags: tags(&[("highway", "residential")]),
+                nodes: nodes.map(NodeId).to_vec(),
+            })
+        };
+        // Every change of object type starts a new block, so each town
+        // gets a node block of its own.
+        let objects = vec![
+            node(1, 51.2050, 3.2250),
+            node(2, 51.2055, 3.2260),
+            street(10, [1, 2]),
+            node(3, 50.8500, 4.3500),
+            node(4, 50.8505, 4.3510),
+            street(11, [3, 4]),
+        ];
+        let path = write_pbf_fixture("osmtools_blob_bbox.osm.pbf", objects);
+        let load = |boundary: BoundaryMode| {
+            OsmLoaderBuilder::<CarEdgeFilter>::default()
+                .edge_filter(CarEdgeFilter::default())
+                .target_crs(4839u16)
+                .pbf_path(path.clone())
+                .filter_geometry(BoundingBox::new(3.2, 51.2, 3.3, 51.21))
+                .boundary(boundary)
+                .build()
+                .unwrap()
+                .try_load_graph_with_stats()
+                .unwrap()
+        };
+
+        let (nodes, edges, stats) = load(BoundaryMode::Contains);
+        // The second node block in both passes, way blocks carry no box.
+        assert_eq!(stats.skipped_blobs, 2);
+        assert_eq!(nodes.len(), 2);
+        assert!(edges.iter().all(|e| e.way_id == 10));
+
+        let (intersecting_nodes, intersecting_edges, stats) = load(BoundaryMode::Intersects);
+        assert_eq!(stats.skipped_blobs, 0);
+        assert_eq!(intersecting_nodes.len(), nodes.len());
+        assert!(intersecting_edges == edges);
+    }
+
+    #[test]
+    fn test_surface_quality_on_edges() {
+        let surface_loader = car_loader_builder("data/bruegge.osm.pbf")
+            .surface_quality(true)
+            .build()
+            .unwrap();
+        let street = tagged_way(&[("highway", "residential"), ("surface", "sett")]);
+        let (id_sender, _id_receiver) = channel();
+        let edges = surface_loader.process_way(&street, Some(&id_sender));
+        assert!(edges
+            .iter()
+            .all(|e| e.surface_quality == Some(0.4) && e.rough_paving));
+
+        let edges = loader().process_way(&street, Some(&id_sender));
+        assert!(edges
+            .iter()
+            .all(|e| e.surface_quality.is_none() && !e.rough_paving));
+    }
+
+    #[test]
+    fn test_cycle_infrastructure_on_edges() {
+        let cycling_loader = car_loader_builder("data/bruegge.osm.pbf")
+            .cycle_infrastructure(true)
+            .build()
+            .unwrap();
+        let street = tagged_way(&[("highway", "secondary"), ("cycleway:right", "lane")]);
+        let (id_sender, _id_receiver) = channel();
+        let edges = cycling_loader.process_way(&street, Some(&id_sender));
+        assert!(edges
+            .iter()
+            .all(|e| e.infra_class == Some(CycleInfrastructure::Lane)));
+
+        let edges = loader().process_way(&street, Some(&id_sender));
+        assert!(edges.iter().all(|e| e.infra_class.is_none()));
+
+        let dismount = tagged_way(&[("highway", "residential"), ("bicycle", "dismount")]);
+        let edges = cycling_loader.process_way(&dismount, Some(&id_sender));
+        assert!(!edges.is_empty());
+        assert!(edges.iter().all(|e| e.dismount));
+        let edges = cycling_loader.process_way(&street, Some(&id_sender));
+        assert!(edges.iter().all(|e| !e.dismount));
+    }
+
+    fn street_edge(source: OsmNodeId, dest: OsmNodeId, length: f64) -> Edge {
+        Edge {
+            length,
+            way_id: 1,
+            ..Edge::new(source, dest)
+        }
+    }
+
+    #[test]
+    fn test_intersections_only() {
+        use osmpbfreader::OsmObj;
+
+        // Way 1 runs west to east through 1..=5, way 2 ends on it at node 3.
+        let mut objects: Vec<OsmObj> = (1..=5)
+            .map(|id| node(id, 51.2, 3.2 + id as f64 * 1e-3))
+            .collect();
+        objects.extend((6..=7).map(|id| node(id, 51.2 + (8 - id) as f64 * 1e-3, 3.203)));
+        objects.push(OsmObj::Way(Way {
+            id: WayId(1),
+            tags: tags(&[("highway", "residential")]),
+            nodes: (1..=5).map(NodeId).collect(),
+        }));
+        objects.push(way(2, &[("highway", "residential")], &[6, 7, 3]));
+        let path = write_pbf_fixture("osmtools_intersections_only.osm.pbf", objects);
+        let load = |intersections_only: bool| {
+            OsmLoaderBuilder::<CarEdgeFilter>::default()
+                .edge_filter(CarEdgeFilter::default())
+                .target_crs(4839u16)
+                .pbf_path(path.clone())
+                .intersections_only(intersections_only)
+                .build()
+                .unwrap()
+                .load_graph()
+        };
+
+        let (nodes, edges) = load(false);
+        assert_eq!(nodes.len(), 7);
+        assert_eq!(edges.len(), 12);
+
+        let (nodes, edges) = load(true);
+        let ids: Vec<OsmNodeId> = nodes.iter().map(|n| n.osm_id).collect();
+        assert_eq!(ids, vec![1, 3, 5, 6]);
+        let pairs: Vec<(OsmNodeId, OsmNodeId)> =
+            edges.iter().map(|e| (e.source_osm, e.dest_osm)).collect();
+        assert_eq!(pairs, vec![(1, 3), (3, 1), (3, 5), (3, 6), (5, 3), (6, 3)]);
+    }
+
+    #[test]
+    fn test_contract_straight_street() {
+        // 1 - 2 - 3 - 4 along a straight two-way street and an isolated 5, which
+        // contraction leaves alone.
+        let nodes: Vec<Node> = (1..=5)
+            .map(|id| Node::new(id, 51.2, 3.2 + id as f64 * 0.001))
+            .collect();
+        let mut edges = Vec::new();
+        for (a, b, length) in [(1, 2, 10.0), (2, 3, 20.0), (3, 4, 30.0)] {
+            edges.push(street_edge(a, b, length));
+            edges.push(street_edge(b, a, length));
+        }
+        let (nodes, edges) = contract_degree_two(nodes, edges, &HashSet::new(), true);
+        let mut ids: Vec<OsmNodeId> = nodes.iter().map(|n| n.osm_id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 4, 5]);
+        let mut contracted: Vec<(OsmNodeId, OsmNodeId, f64, usize)> = edges
+            .iter()
+            .map(|e| (e.source_osm, e.dest_osm, e.length, e.via.len()))
+            .collect();
+        contracted.sort_by_key(|e| (e.0, e.1));
+        assert_eq!(contracted, vec![(1, 4, 60.0, 2), (4, 1, 60.0, 2)]);
+        let forward = edges.iter().find(|e| e.source_osm == 1).unwrap();
+        assert_eq!(forward.via[0], (51.2, 3.202));
+    }
+
+    #[test]
+    fn test_contract_keeps_protected_and_junction_nodes() {
+        let nodes: Vec<Node> = (1..=5).map(|id| Node::new(id, 51.2, 3.2)).collect();
+        // Oneway 1 -> 2 -> 3 -> 4 with a branch 3 -> 5 and traffic signals at 2.
+        let edges = vec![
+            street_edge(1, 2, 1.0),
+            street_edge(2, 3, 2.0),
+            street_edge(3, 4, 3.0),
+            street_edge(3, 5, 4.0),
+        ];
+        let protected: HashSet<OsmNodeId> = [2].into_iter().collect();
+        let (nodes, edges) = contract_degree_two(nodes, edges, &protected, false);
+        assert_eq!(nodes.len(), 5);
+        assert_eq!(edges.len(), 4);
+
+        // A closed ring keeps one of its nodes.
+        let nodes: Vec<Node> = (1..=3).map(|id| Node::new(id, 51.2, 3.2)).collect();
+        let ring = vec![
+            street_edge(1, 2, 1.0),
+            street_edge(2, 3, 1.0),
+            street_edge(3, 1, 1.0),
+        ];
+        let (nodes, edges) = contract_degree_two(nodes, ring, &HashSet::new(), false);
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].length, 3.0);
+        assert_eq!(edges[0].source_osm, edges[0].dest_osm);
+    }
+
+    #[test]
+    fn test_coords() {
+        let node = |lat, lon| osmpbfreader::Node {
+            id: NodeId(1),
+            tags: Tags::new(),
+            decimicro_lat: lat,
+            decimicro_lon: lon,
+        };
+        assert_eq!(
+            coords(&node(512_093_457, 32_247_001)),
+            (51.2093457, 3.2247001)
+        );
+        assert_eq!(
+            coords(&node(-899_999_999, 1_800_000_000)),
+            (-89.9999999, 180.0)
+        );
+        // Round trips through the decimicro encoding of the osc reader.
+        let (lat, long) = coords(&node(512_093_457, -1));
+        assert_eq!((lat * DECIMICRO).round() as i32, 512_093_457);
+        assert_eq!((long * DECIMICRO).round() as i32, -1);
+    }
+
+    #[test]
+    fn test_node_into_point() {
+        let node = Node::new(1, 51.2, 3.2);
+        let point = Point::from(&node);
+        assert_eq!((point.x(), point.y()), (3.2, 51.2));
+        assert_eq!(point.0, node.coord());
+        let back = Node::new(node.osm_id, point.y(), point.x());
+        assert_eq!(back.content_hash(), node.content_hash());
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let node = Node::new(1, 51.2, 3.2);
+        assert_eq!(node.content_hash(), Node::new(1, 51.2, 3.2).content_hash());
+        assert_ne!(
+            node.content_hash(),
+            Node::new(1, 51.2, 3.2000001).content_hash()
+        );
+        assert_ne!(node.content_hash(), Node::new(2, 51.2, 3.2).content_hash());
+
+        let mut edge = Edge::new(1, 2);
+        edge.length = 10.0;
+        let mut same = Edge::new(1, 2);
+        same.length = 10.0;
+        let mut moved = Edge::new(1, 2);
+        moved.length = 10.5;
+        assert_eq!(edge.content_hash(), same.content_hash());
+        assert_ne!(edge.content_hash(), moved.content_hash());
+        assert_ne!(edge.content_hash(), Edge::new(2, 1).content_hash());
+    }
+
+    #[test]
+    fn test_node_and_edge_builders() {
+        let node = NodeBuilder::default()
+            .osm_id(1)
+            .lat(51.2)
+            .long(3.2)
+            .internal_id(0)
+            .build()
+            .unwrap();
+        assert_eq!(
+            (node.osm_id, node.in_degree, node.internal_id),
+            (1, 0, Some(0))
+        );
+        let other = NodeBuilder::default()
+            .osm_id(2)
+            .lat(51.3)
+            .long(3.3)
+            .in_degree(1)
+            .build()
+            .unwrap();
+        assert_eq!((other.in_degree, other.internal_id), (1, None));
+
+        let edge = EdgeBuilder::default()
+            .source_osm(1)
+            .dest_osm(2)
+            .length(10.0)
+            .layer(-1)
+            .tunnel(true)
+            .coordinates(&node, &other)
+            .internal_ids(0, 1)
+            .build()
+            .unwrap();
+        assert_eq!((edge.source_osm, edge.dest_osm, edge.length), (1, 2, 10.0));
+        assert_eq!(
+            (edge.layer, edge.tunnel, edge.bridge),
+            (Some(-1), true, false)
+        );
+        assert_eq!((edge.source_lat, edge.dest_long), (Some(51.2), Some(3.3)));
+        assert_eq!((edge.source, edge.dest), (Some(0), Some(1)));
+        assert_eq!((edge.way_id, edge.ferry_time), (0, None));
+        let defaults = EdgeBuilder::default().source_osm(1).dest_osm(2).build();
+        assert!(defaults.unwrap() == Edge::new(1, 2));
+
+        let missing = EdgeBuilder::default().source_osm(1).build();
+        assert_eq!(
+            missing.err().unwrap().to_string(),
+            "Missing required field dest_osm"
+        );
+        assert!(NodeBuilder::default().osm_id(1).lat(51.2).build().is_err());
+    }
+
+    #[test]
+    fn test_forward_one_way() {
+        let loader = loader();
+        let forward = tagged_way(&[("highway", "residential"), ("oneway", "yes")]);
+        assert_eq!(edge_pairs(&loader, &forward), vec![(1, 2), (2, 3)]);
+        let both = tagged_way(&[("highway", "residential")]);
+        assert_eq!(
+            edge_pairs(&loader, &both),
+            vec![(1, 2), (2, 1), (2, 3), (3, 2)]
+        );
+    }
+}
@@ -0,0 +1,515 @@
+//! Packed, static R-tree over extracted POIs, serialized next to the POI
+//! parquet so downstream tools do not have to rebuild a spatial index for
+//! every "POIs near X" query.
+//!
+//! The tree follows the flatbush layout: items are sorted along a Hilbert
+//! curve and packed bottom-up into nodes of `node_size` children, so the
+//! whole tree is a handful of flat arrays that can be read (or mmapped)
+//! without any pointer chasing.
+//!
+//! # File format
+//!
+//! All integers and floats are little endian.
+//!
+//! | field          | type                      | description                                      |
+//! |----------------|---------------------------|--------------------------------------------------|
+//! | magic          | `[u8; 8]`                 | `b"POIRTREE"`                                    |
+//! | version        | `u32`                     | currently `1`                                    |
+//! | node_size      | `u32`                     | maximum number of children per node              |
+//! | num_items      | `u64`                     | number of POIs in the tree                       |
+//! | num_levels     | `u64`                     | number of levels, leaves included                |
+//! | level_bounds   | `num_levels x u64`        | exclusive end of each level in `boxes`, leaves first |
+//! | boxes          | `level_bounds[last] x 4 x f64` | `(min_long, min_lat, max_long, max_lat)`    |
+//! | indices        | `level_bounds[last] x u64` | leaves: row of the POI in the slice passed to [`write_poi_rtree`]; nodes: offset of the first child box |
+//!
+//! The root is the last box. Leaf rows match the row order of the POI
+//! parquet written from the same slice.
+use super::node_pbf::Poi;
+use geo::{Distance, Haversine, Point};
+use std::f64::consts::FRAC_PI_2;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"POIRTREE";
+const VERSION: u32 = 1;
+const NODE_SIZE: usize = 16;
+const HILBERT_MAX: f64 = ((1 << 16) - 1) as f64;
+/// Slack in degrees added to the radius search box against rounding.
+const BOX_EPSILON: f64 = 1e-9;
+
+type BBox = [f64; 4];
+
+pub struct PoiRtree {
+    node_size: usize,
+    num_items: usize,
+    level_bounds: Vec<usize>,
+    boxes: Vec<BBox>,
+    indices: Vec<usize>,
+}
+
+/// Builds a packed R-tree over `pois` and writes it to `path`.
+pub fn write_poi_rtree(pois: &[Poi], path: &Path) -> io::Result<()> {
+    let tree = PoiRtree::new(pois);
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(tree.node_size as u32).to_le_bytes())?;
+    writer.write_all(&(tree.num_items as u64).to_le_bytes())?;
+    writer.write_all(&(tree.level_bounds.len() as u64).to_le_bytes())?;
+    for bound in &tree.level_bounds {
+        writer.write_all(&(*bound as u64).to_le_bytes())?;
+    }
+    for bbox in &tree.boxes {
+        for value in bbox {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+    }
+    for index in &tree.indices {
+        writer.write_all(&(*index as u64).to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+impl PoiRtree {
+    fn new(pois: &[Poi]) -> PoiRtree {
+        let num_items = pois.len();
+        let level_bounds = level_bounds(num_items, NODE_SIZE);
+        let num_boxes = *level_bounds.last().unwrap();
+
+        let extent = pois.iter().fold(
+            [
+                f64::INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::NEG_INFINITY,
+            ],
+            |acc, poi| union(&acc, &point_box(poi)),
+        );
+        let mut order: Vec<usize> = (0..num_items).collect();
+        order.sort_by_cached_key(|&i| hilbert_value(&pois[i], &extent));
+
+        let mut boxes: Vec<BBox> = Vec::with_capacity(num_boxes);
+        let mut indices: Vec<usize> = Vec::with_capacity(num_boxes);
+        for i in order {
+            boxes.push(point_box(&pois[i]));
+            indices.push(i);
+        }
+        let mut level_start = 0;
+        for &level_end in &level_bounds[..level_bounds.len() - 1] {
+            for child_start in (level_start..level_end).step_by(NODE_SIZE) {
+                let child_end = (child_start + NODE_SIZE).min(level_end);
+                let bbox = boxes[child_start..child_end]
+                    .iter()
+                    .fold(boxes[child_start], |acc, b| union(&acc, b));
+                boxes.push(bbox);
+                indices.push(child_start);
+            }
+            level_start = level_end;
+        }
+
+        PoiRtree {
+            node_size: NODE_SIZE,
+            num_items,
+            level_bounds,
+            boxes,
+            indices,
+        }
+    }
+
+    /// Loads a tree previously written by [`write_poi_rtree`]. Fails with
+    /// [`ErrorKind::InvalidData`] if the levels or indices do not describe a
+    /// tree of the stored node size and item count, e.g. if a node's children
+    /// do not start in the level below it, or if the file is longer than that
+    /// tree.
+    pub fn open(path: &Path) -> io::Result<PoiRtree> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "Not a POI R-tree file"));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unsupported POI R-tree version {version}"),
+            ));
+        }
+        let node_size = read_u32(&mut reader)? as usize;
+        if node_size < 2 {
+            return Err(invalid_tree(format!("node size {node_size}")));
+        }
+        let num_items = read_u64(&mut reader)? as usize;
+        let level_bounds = level_bounds(num_items, node_size);
+        let num_levels = read_u64(&mut reader)? as usize;
+        if num_levels != level_bounds.len() {
+            return Err(invalid_tree(format!("{num_levels} levels")));
+        }
+        for &expected in &level_bounds {
+            let bound = read_u64(&mut reader)? as usize;
+            if bound != expected {
+                return Err(invalid_tree(format!("level bound {bound}")));
+            }
+        }
+        let num_boxes = *level_bounds.last().unwrap();
+        let boxes = (0..num_boxes)
+            .map(|_| {
+                Ok([
+                    read_f64(&mut reader)?,
+                    read_f64(&mut reader)?,
+                    read_f64(&mut reader)?,
+                    read_f64(&mut reader)?,
+                ])
+            })
+            .collect::<io::Result<Vec<BBox>>>()?;
+        let indices = (0..num_boxes)
+            .map(|_| read_u64(&mut reader).map(|i| i as usize))
+            .collect::<io::Result<Vec<usize>>>()?;
+        if let Some(index) = indices[..num_items].iter().find(|&&i| i >= num_items) {
+            return Err(invalid_tree(format!("item row {index}")));
+        }
+        // Children lie in the level right below their node, so searches
+        // always descend and terminate.
+        let mut child_level_start = 0;
+        for level in level_bounds.windows(2) {
+            let (child_level_end, level_end) = (level[0], level[1]);
+            if let Some(offset) = indices[child_level_end..level_end]
+                .iter()
+                .find(|&&i| !(child_level_start..child_level_end).contains(&i))
+            {
+                return Err(invalid_tree(format!("child offset {offset}")));
+            }
+            child_level_start = child_level_end;
+        }
+        if reader.read(&mut [0u8])? != 0 {
+            return Err(invalid_tree("trailing data".into()));
+        }
+        Ok(PoiRtree {
+            node_size,
+            num_items,
+            level_bounds,
+            boxes,
+            indices,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.num_items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_items == 0
+    }
+
+    /// Returns the POI rows whose location lies inside the given box.
+    pub fn search(&self, min_long: f64, min_lat: f64, max_long: f64, max_lat: f64) -> Vec<usize> {
+        self.search_leaves(&[min_long, min_lat, max_long, max_lat])
+            .into_iter()
+            .map(|leaf| self.indices[leaf])
+            .collect()
+    }
+
+    /// Returns the POI rows within `radius` meters (great-circle distance) of
+    /// the given location.
+    pub fn within_radius(&self, long: f64, lat: f64, radius: f64) -> Vec<usize> {
+        let center = Point::new(long, lat);
+        self.search_leaves(&radius_box(long, lat, radius))
+            .into_iter()
+            .filter(|&leaf| {
+                let bbox = &self.boxes[leaf];
+                Haversine.distance(center, Point::new(bbox[0], bbox[1])) <= radius
+            })
+            .map(|leaf| self.indices[leaf])
+            .collect()
+    }
+
+    fn search_leaves(&self, query: &BBox) -> Vec<usize> {
+        let mut leaves = Vec::new();
+        let Some(root) = self.boxes.len().checked_sub(1) else {
+            return leaves;
+        };
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            if !intersects(&self.boxes[node], query) {
+                continue;
+            }
+            if node < self.num_items {
+                leaves.push(node);
+                continue;
+            }
+            let child_start = self.indices[node];
+            let child_level_end = *self
+                .level_bounds
+                .iter()
+                .find(|&&bound| bound > child_start)
+                .expect("Child offset outside of the tree");
+            let child_end = (child_start + self.node_size).min(child_level_end);
+            stack.extend(child_start..child_end);
+        }
+        leaves
+    }
+}
+
+/// Box around all locations within `radius` meters of the given one, on the
+/// sphere [`Haversine`] measures on. The circle reaches furthest east and
+/// west poleward of `lat`, so its longitude half-width
+/// `asin(sin(d) / cos(lat))` is a bit wider than the `d / cos(lat)` of a
+/// flat circle. Circles around a pole cover all longitudes.
+fn radius_box(long: f64, lat: f64, radius: f64) -> BBox {
+    let angle = radius / Haversine.radius();
+    let d_lat = angle.to_degrees() + BOX_EPSILON;
+    let ratio = angle.sin() / lat.to_radians().cos();
+    let d_long = if angle >= FRAC_PI_2 || ratio >= 1.0 {
+        180.0
+    } else {
+        ratio.asin().to_degrees() + BOX_EPSILON
+    };
+    [long - d_long, lat - d_lat, long + d_long, lat + d_lat]
+}
+
+/// Exclusive end of each level in the box array, leaves first, for a tree of
+/// `num_items` leaves and up to `node_size` children per node.
+fn level_bounds(num_items: usize, node_size: usize) -> Vec<usize> {
+    let mut level_bounds = vec![num_items];
+    let mut level_size = num_items;
+    while level_size > 1 {
+        level_size = level_size.div_ceil(node_size);
+        level_bounds.push(level_bounds.last().unwrap() + level_size);
+    }
+    level_bounds
+}
+
+fn invalid_tree(detail: String) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Inconsistent POI R-tree file: {detail}"),
+    )
+}
+
+fn point_box(poi: &Poi) -> BBox {
+    [poi.long, poi.lat, poi.long, poi.lat]
+}
+
+fn union(a: &BBox, b: &BBox) -> BBox {
+    [
+        a[0].min(b[0]),
+        a[1].min(b[1]),
+        a[2].max(b[2]),
+        a[3].max(b[3]),
+    ]
+}
+
+fn intersects(a: &BBox, b: &BBox) -> bool {
+    a[0] <= b[2] && a[1] <= b[3] && a[2] >= b[0] && a[3] >= b[1]
+}
+
+fn hilbert_value(poi: &Poi, extent: &BBox) -> u64 {
+    let scale = |value: f64, min: f64, max: f64| {
+        if max > min {
+            (HILBERT_MAX * (value - min) / (max - min)) as u32
+        } else {
+            0
+        }
+    };
+    hilbert(
+        scale(poi.long, extent[0], extent[2]),
+        scale(poi.lat, extent[1], extent[3]),
+    )
+}
+
+/// Position of `(x, y)` along a Hilbert curve filling a 2^16 x 2^16 grid.
+fn hilbert(mut x: u32, mut y: u32) -> u64 {
+    let n: u32 = 1 << 16;
+    let mut d: u64 = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = u32::from(x & s > 0);
+        let ry = u32::from(y & s > 0);
+        d += u64::from(s) * u64::from(s) * u64::from((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pbfextractor::node_pbf::PoiLoaderBuilder;
+    use geo::{Destination, LineString, Polygon};
+
+    fn poi(osm_id: u64, lat: f64, long: f64) -> Poi {
+        Poi {
+            osm_id,
+            lat,
+            long,
+            nearest_osm_node: None,
+            dist_to_nearest: None,
+            dist_to_nearest_haversine: None,
+            poi_type: "Shops".into(),
+            name: None,
+            raw_tag: None,
+            matched_key: None,
+            matched_value: None,
+            wheelchair: None,
+            opening_hours: None,
+            admin_area: None,
+            labeled_nearest: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_rtree_roundtrip() {
+        let pois: Vec<Poi> = (0..100)
+            .map(|i| {
+                poi(
+                    i,
+                    51.2 + (i / 10) as f64 * 0.001,
+                    3.2 + (i % 10) as f64 * 0.001,
+                )
+            })
+            .collect();
+        let path = Path::new("test/rtree_roundtrip.poirtree");
+        write_poi_rtree(&pois, path).unwrap();
+        let tree = PoiRtree::open(path).unwrap();
+        assert_eq!(tree.len(), 100);
+
+        let mut rows = tree.search(3.2015, 51.2015, 3.2035, 51.2035);
+        rows.sort();
+        assert_eq!(rows, vec![22, 23, 32, 33]);
+
+        // Neighbouring grid points are ~70m (east) and ~111m (north) apart.
+        let mut rows = tree.within_radius(3.205, 51.205, 80.0);
+        rows.sort();
+        assert_eq!(rows, vec![54, 55, 56]);
+    }
+
+    #[test]
+    fn test_within_radius_high_latitude() {
+        // At 60°N a degree of longitude is half as long as at the equator.
+        let center = Point::new(10.75, 60.0);
+        let radius = 5_000.0;
+        let pois: Vec<Poi> = (0..24)
+            .flat_map(|i| [0.9999 * radius, 1.0001 * radius].map(|d| (i, d)))
+            .map(|(i, distance)| {
+                let p = Haversine.destination(center, i as f64 * 15.0, distance);
+                poi(0, p.y(), p.x())
+            })
+            .collect();
+        let path = Path::new("test/rtree_high_latitude.poirtree");
+        write_poi_rtree(&pois, path).unwrap();
+        let tree = PoiRtree::open(path).unwrap();
+
+        let mut rows = tree.within_radius(center.x(), center.y(), radius);
+        rows.sort();
+        assert_eq!(rows, (0..48).step_by(2).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_open_rejects_inconsistent_files() {
+        let pois: Vec<Poi> = (0..40)
+            .map(|i| poi(i, 51.2, 3.2 + i as f64 * 0.001))
+            .collect();
+        let path = Path::new("test/rtree_inconsistent.poirtree");
+        write_poi_rtree(&pois, path).unwrap();
+        let valid = std::fs::read(path).unwrap();
+        let assert_invalid = |bytes: &[u8]| {
+            std::fs::write(path, bytes).unwrap();
+            let error = PoiRtree::open(path)
+                .err()
+                .expect("Opened an inconsistent tree");
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+        };
+
+        // node_size
+        let mut bytes = valid.clone();
+        bytes[12..16].copy_from_slice(&0u32.to_le_bytes());
+        assert_invalid(&bytes);
+        // num_items
+        let mut bytes = valid.clone();
+        bytes[16..24].copy_from_slice(&41u64.to_le_bytes());
+        assert_invalid(&bytes);
+        // Leaf row of the first item, after the header, 3 level bounds and
+        // 44 boxes.
+        let mut bytes = valid.clone();
+        let first_index = 32 + 3 * 8 + 44 * 32;
+        bytes[first_index..first_index + 8].copy_from_slice(&40u64.to_le_bytes());
+        assert_invalid(&bytes);
+        // Child offsets of the root pointing at itself and of the first
+        // internal node pointing into its own level.
+        for (node, offset) in [(43, 43u64), (40, 41)] {
+            let mut bytes = valid.clone();
+            let index = first_index + node * 8;
+            bytes[index..index + 8].copy_from_slice(&offset.to_le_bytes());
+            assert_invalid(&bytes);
+        }
+        let mut bytes = valid.clone();
+        bytes.push(0);
+        assert_invalid(&bytes);
+    }
+
+    #[test]
+    fn integration_test_poi_rtree() {
+        let bounding_box = Polygon::new(
+            LineString::from(vec![
+                (3.22183, 51.20391),
+                (3.23663, 51.20391),
+                (3.23663, 51.20887),
+                (3.22183, 51.20887),
+                (3.22183, 51.20391),
+            ]),
+            vec![],
+        );
+        let pois = PoiLoaderBuilder::default()
+            .target_crs(4839u16)
+            .filter_geometry(bounding_box)
+            .pbf_path("data/bruegge.osm.pbf")
+            .nodes_to_match_parquet("test/bruegge_poitest_walking_nodes.parquet")
+            .build()
+            .unwrap()
+            .load_graph();
+        let path = Path::new("test/bruegge_pois.poirtree");
+        write_poi_rtree(&pois, path).unwrap();
+        let tree = PoiRtree::open(path).unwrap();
+        assert_eq!(tree.len(), pois.len());
+
+        let (long, lat, radius) = (3.2247, 51.2093, 200.0);
+        let mut rows = tree.within_radius(long, lat, radius);
+        rows.sort();
+        let expected: Vec<usize> = pois
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| {
+                Haversine.distance(Point::new(long, lat), Point::new(p.long, p.lat)) <= radius
+            })
+            .map(|(i, _)| i)
+            .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(rows, expected);
+    }
+}
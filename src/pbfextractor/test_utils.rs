@@ -0,0 +1,52 @@
+//! Builders for small synthetic pbf fixtures shared by the unit tests.
+use super::metrics::CarEdgeFilter;
+use super::osc::PbfWriter;
+use super::pbf::{OsmLoaderBuilder, DECIMICRO};
+use osmpbfreader::{Node, NodeId, OsmObj, Tags, Way, WayId};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+pub(crate) fn tags(pairs: &[(&str, &str)]) -> Tags {
+    pairs
+        .iter()
+        .map(|(k, v)| ((*k).into(), (*v).into()))
+        .collect()
+}
+
+pub(crate) fn node(id: i64, lat: f64, lon: f64) -> OsmObj {
+    OsmObj::Node(Node {
+        id: NodeId(id),
+        tags: Tags::new(),
+        decimicro_lat: (lat * DECIMICRO).round() as i32,
+        decimicro_lon: (lon * DECIMICRO).round() as i32,
+    })
+}
+
+pub(crate) fn way(id: i64, way_tags: &[(&str, &str)], nodes: &[i64]) -> OsmObj {
+    OsmObj::Way(Way {
+        id: WayId(id),
+        tags: tags(way_tags),
+        nodes: nodes.iter().map(|&n| NodeId(n)).collect(),
+    })
+}
+
+/// Writes `objects` in the given order to `name` in the temporary directory.
+pub(crate) fn write_pbf_fixture(name: &str, objects: Vec<OsmObj>) -> PathBuf {
+    let path = std::env::temp_dir().join(name);
+    let mut writer = PbfWriter::new(File::create(&path).unwrap()).unwrap();
+    for obj in objects {
+        writer.push(obj).unwrap();
+    }
+    writer.finish().unwrap();
+    path
+}
+
+/// Builder of a car network loader for the fixture at `path`.
+pub(crate) fn car_loader_builder<P: AsRef<Path>>(path: P) -> OsmLoaderBuilder<CarEdgeFilter> {
+    let mut builder = OsmLoaderBuilder::default();
+    builder
+        .edge_filter(CarEdgeFilter::default())
+        .target_crs(4839u16)
+        .pbf_path(path.as_ref());
+    builder
+}
@@ -0,0 +1,259 @@
+use geo::kernels::{Kernel, Orientation};
+use geo::{BoundingRect, Contains, Coord, GeoNum, Intersects, Line, Point, Polygon, Rect};
+use rstar::{RTree, AABB};
+
+/// Filter geometries whose bounding box covers more square degrees than this
+/// are split into tiles when automatic tiling is enabled.
+const AUTO_TILE_MIN_AREA: f64 = 0.01;
+const TILES_PER_AXIS: usize = 32;
+const TILE_MARGIN: f64 = 1e-6;
+
+#[derive(Clone, Copy, PartialEq)]
+enum TileCover {
+    Inside,
+    Outside,
+    Partial,
+}
+
+/// Point-in-polygon filter for the filter geometry of the loaders.
+///
+/// Points are tested against a [`PreparedPolygon`]. With automatic tiling,
+/// large filter geometries are also split into a grid of tiles, and tiles not
+/// touched by the polygon boundary are classified once up front, so only
+/// points in tiles crossed by the boundary need the indexed containment
+/// check. This is purely a speed-up of the filter; nodes are not loaded or
+/// processed per tile. The answer is always the same as `Polygon::contains`,
+/// which keeps edges crossing tile seams intact.
+pub(crate) struct GeometryFilter {
+    prepared: PreparedPolygon,
+    grid: Option<TileGrid>,
+}
+
+struct TileGrid {
+    bounds: Rect,
+    tile_width: f64,
+    tile_height: f64,
+    tiles: Vec<TileCover>,
+}
+
+impl GeometryFilter {
+    pub(crate) fn new(polygon: &Polygon, auto_tile: bool) -> GeometryFilter {
+        let grid = polygon
+            .bounding_rect()
+            .filter(|bounds| auto_tile && bounds.width() * bounds.height() > AUTO_TILE_MIN_AREA)
+            .map(|bounds| TileGrid::new(polygon, bounds));
+        GeometryFilter {
+            prepared: PreparedPolygon::new(polygon),
+            grid,
+        }
+    }
+
+    pub(crate) fn contains(&self, point: &Point) -> bool {
+        match self.grid.as_ref().map(|grid| grid.cover(point)) {
+            Some(TileCover::Inside) => true,
+            Some(TileCover::Outside) => false,
+            Some(TileCover::Partial) | None => self.prepared.contains(point),
+        }
+    }
+}
+
+impl TileGrid {
+    fn new(polygon: &Polygon, bounds: Rect) -> TileGrid {
+        let tile_width = bounds.width() / TILES_PER_AXIS as f64;
+        let tile_height = bounds.height() / TILES_PER_AXIS as f64;
+        let mut tiles = Vec::with_capacity(TILES_PER_AXIS * TILES_PER_AXIS);
+        for row in 0..TILES_PER_AXIS {
+            for col in 0..TILES_PER_AXIS {
+                // Slightly enlarged so rounding in `cover` can't place a point
+                // just outside the tile it was classified for.
+                let min = Coord {
+                    x: bounds.min().x + col as f64 * tile_width - tile_width * TILE_MARGIN,
+                    y: bounds.min().y + row as f64 * tile_height - tile_height * TILE_MARGIN,
+                };
+                let max = Coord {
+                    x: min.x + tile_width * (1.0 + 2.0 * TILE_MARGIN),
+                    y: min.y + tile_height * (1.0 + 2.0 * TILE_MARGIN),
+                };
+                let tile = Rect::new(min, max);
+                let touches_boundary = polygon.exterior().intersects(&tile)
+                    || polygon
+                        .interiors()
+                        .iter()
+                        .any(|ring| ring.intersects(&tile));
+                tiles.push(if touches_boundary {
+                    TileCover::Partial
+                } else if polygon.contains(&tile.center()) {
+                    TileCover::Inside
+                } else {
+                    TileCover::Outside
+                });
+            }
+        }
+        TileGrid {
+            bounds,
+            tile_width,
+            tile_height,
+            tiles,
+        }
+    }
+
+    fn cover(&self, point: &Point) -> TileCover {
+        if !self.bounds.intersects(point) {
+            return TileCover::Outside;
+        }
+        let col = ((point.x() - self.bounds.min().x) / self.tile_width) as usize;
+        let row = ((point.y() - self.bounds.min().y) / self.tile_height) as usize;
+        self.tiles[row.min(TILES_PER_AXIS - 1) * TILES_PER_AXIS + col.min(TILES_PER_AXIS - 1)]
+    }
+}
+
+/// Polygon with its boundary segments indexed in an R-tree, for many
+/// point-in-polygon tests against a detailed boundary.
+///
+/// `contains` casts a ray from the point towards positive x and only looks at
+/// the segments whose envelope the ray crosses, so a query costs roughly
+/// `O(log n)` instead of `O(n)` in the number of vertices. Like
+/// `Polygon::contains`, points on the boundary (including hole boundaries)
+/// are not contained.
+struct PreparedPolygon {
+    segments: RTree<Line>,
+}
+
+impl PreparedPolygon {
+    fn new(polygon: &Polygon) -> PreparedPolygon {
+        let segments = polygon
+            .exterior()
+            .lines()
+            .chain(polygon.interiors().iter().flat_map(|ring| ring.lines()))
+            .collect();
+        PreparedPolygon {
+            segments: RTree::bulk_load(segments),
+        }
+    }
+
+    fn contains(&self, point: &Point) -> bool {
+        let p = point.0;
+        let on_boundary = self
+            .segments
+            .locate_in_envelope_intersecting(&AABB::from_point(*point))
+            .any(|segment| orient(segment, p) == Orientation::Collinear);
+        if on_boundary {
+            return false;
+        }
+        let ray = AABB::from_corners(*point, Point::new(f64::MAX, p.y));
+        let crossings = self
+            .segments
+            .locate_in_envelope_intersecting(&ray)
+            .filter(|segment| {
+                let (start, end) = (segment.start, segment.end);
+                // Half-open in y, so a ray through a vertex counts it once.
+                if (start.y > p.y) == (end.y > p.y) {
+                    return false;
+                }
+                // The segment crosses the ray to the right of `p` iff `p` lies
+                // to the left of an upward or to the right of a downward segment.
+                let expected = if start.y < end.y {
+                    Orientation::CounterClockwise
+                } else {
+                    Orientation::Clockwise
+                };
+                orient(segment, p) == expected
+            })
+            .count();
+        crossings % 2 == 1
+    }
+}
+
+fn orient(segment: &Line, p: Coord) -> Orientation {
+    <f64 as GeoNum>::Ker::orient2d(segment.start, segment.end, p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::LineString;
+
+    #[test]
+    fn test_tiled_filter_matches_polygon() {
+        // Concave polygon with a hole, large enough to be tiled.
+        let polygon = Polygon::new(
+            LineString::from(vec![
+                (0.0, 0.0),
+                (1.0, 0.0),
+                (1.0, 1.0),
+                (0.5, 0.3),
+                (0.0, 1.0),
+                (0.0, 0.0),
+            ]),
+            vec![LineString::from(vec![
+                (0.1, 0.1),
+                (0.2, 0.1),
+                (0.2, 0.2),
+                (0.1, 0.2),
+                (0.1, 0.1),
+            ])],
+        );
+        let tiled = GeometryFilter::new(&polygon, true);
+        assert!(tiled.grid.is_some());
+        assert!(tiled
+            .grid
+            .as_ref()
+            .unwrap()
+            .tiles
+            .contains(&TileCover::Inside));
+        for i in 0..=128 {
+            for j in 0..=128 {
+                // Includes points on tile seams and on the polygon boundary.
+                let point = Point::new(-0.1 + i as f64 / 100.0, -0.1 + j as f64 / 100.0);
+                assert_eq!(tiled.contains(&point), polygon.contains(&point));
+            }
+        }
+    }
+
+    #[test]
+    fn test_small_geometry_is_not_tiled() {
+        let polygon = Polygon::new(
+            LineString::from(vec![(0.0, 0.0), (0.01, 0.0), (0.01, 0.01), (0.0, 0.0)]),
+            vec![],
+        );
+        assert!(GeometryFilter::new(&polygon, true).grid.is_none());
+        assert!(GeometryFilter::new(&polygon, false).grid.is_none());
+    }
+
+    #[test]
+    fn test_prepared_polygon_matches_polygon() {
+        // Concave polygon with a hole and a vertex exactly on some rays.
+        let polygon = Polygon::new(
+            LineString::from(vec![
+                (0.0, 0.0),
+                (1.0, 0.0),
+                (1.0, 1.0),
+                (0.5, 0.3),
+                (0.0, 1.0),
+                (0.0, 0.5),
+                (0.2, 0.5),
+                (0.0, 0.4),
+                (0.0, 0.0),
+            ]),
+            vec![LineString::from(vec![
+                (0.6, 0.1),
+                (0.8, 0.1),
+                (0.8, 0.2),
+                (0.6, 0.2),
+                (0.6, 0.1),
+            ])],
+        );
+        let prepared = PreparedPolygon::new(&polygon);
+        for i in 0..=120 {
+            for j in 0..=120 {
+                // Includes points on vertices, edges and hole boundaries.
+                let point = Point::new(-0.1 + i as f64 / 100.0, -0.1 + j as f64 / 100.0);
+                assert_eq!(
+                    prepared.contains(&point),
+                    polygon.contains(&point),
+                    "{point:?}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use polars::prelude::{DataFrame, PolarsError};
+use rusqlite::{params, Connection};
+use std::error::Error;
+use std::fmt::Display;
+use std::path::Path;
+
+const SCHEMA: &str = "
+    CREATE TABLE nodes (
+        osm_id INTEGER PRIMARY KEY,
+        lat REAL NOT NULL,
+        long REAL NOT NULL
+    );
+    CREATE TABLE edges (
+        source_osm INTEGER NOT NULL,
+        dest_osm INTEGER NOT NULL,
+        length REAL NOT NULL
+    );
+    CREATE INDEX edges_source_osm ON edges (source_osm);
+    CREATE INDEX edges_dest_osm ON edges (dest_osm);
+";
+
+#[derive(Debug)]
+pub enum SqliteError {
+    Sqlite(rusqlite::Error),
+    Polars(PolarsError),
+    Io(std::io::Error),
+}
+
+impl Error for SqliteError {}
+impl Display for SqliteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SqliteError::Sqlite(error) => write!(f, "Could not write SQLite graph: {error}"),
+            SqliteError::Polars(error) => write!(f, "Invalid graph DataFrame: {error}"),
+            SqliteError::Io(error) => write!(f, "Could not write SQLite graph: {error}"),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for SqliteError {
+    fn from(error: rusqlite::Error) -> Self {
+        SqliteError::Sqlite(error)
+    }
+}
+
+impl From<std::io::Error> for SqliteError {
+    fn from(error: std::io::Error) -> Self {
+        SqliteError::Io(error)
+    }
+}
+
+impl From<PolarsError> for SqliteError {
+    fn from(error: PolarsError) -> Self {
+        SqliteError::Polars(error)
+    }
+}
+
+/// Writes the node and edge DataFrames of `load_graph_df` as a routable graph
+/// into a new SQLite database at `path`, replacing an existing file.
+///
+/// Nodes are keyed by `osm_id` and edges are indexed by both endpoints, so
+/// fetching the outgoing or incoming edges of a node is a single index lookup.
+/// Only `osm_id`, `lat` and `long` of the nodes and `source_osm`, `dest_osm`
+/// and `length` of the edges are written.
+pub fn write_graph_sqlite(
+    df_nodes: &DataFrame,
+    df_edges: &DataFrame,
+    path: &Path,
+) -> Result<(), SqliteError> {
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let ids = df_nodes.column("osm_id")?.u64()?;
+    let lats = df_nodes.column("lat")?.f64()?;
+    let longs = df_nodes.column("long")?.f64()?;
+    let sources = df_edges.column("source_osm")?.u64()?;
+    let dests = df_edges.column("dest_osm")?.u64()?;
+    let lengths = df_edges.column("length")?.f64()?;
+
+    let mut connection = Connection::open(path)?;
+    connection.execute_batch(SCHEMA)?;
+    let transaction = connection.transaction()?;
+    {
+        let mut insert_node =
+            transaction.prepare("INSERT INTO nodes (osm_id, lat, long) VALUES (?1, ?2, ?3)")?;
+        for ((id, lat), long) in ids.into_iter().zip(lats).zip(longs) {
+            insert_node.execute(params![id.map(|id| id as i64), lat, long])?;
+        }
+        let mut insert_edge = transaction
+            .prepare("INSERT INTO edges (source_osm, dest_osm, length) VALUES (?1, ?2, ?3)")?;
+        for ((source, dest), length) in sources.into_iter().zip(dests).zip(lengths) {
+            insert_edge.execute(params![
+                source.map(|id| id as i64),
+                dest.map(|id| id as i64),
+                length
+            ])?;
+        }
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::df;
+
+    #[test]
+    fn test_write_graph_sqlite() -> Result<(), SqliteError> {
+        let nodes = df!(
+            "osm_id" => [1u64, 2, 3],
+            "lat" => [51.2, 51.3, 51.2],
+            "long" => [3.2, 3.2, 3.3],
+        )?;
+        let edges = df!(
+            "source_osm" => [1u64, 1, 2],
+            "dest_osm" => [2u64, 3, 1],
+            "length" => [10.0, 20.0, 10.0],
+        )?;
+        let path = std::env::temp_dir().join("osmtools_write_graph.sqlite");
+        write_graph_sqlite(&nodes, &edges, &path)?;
+        // Exporting again replaces the earlier export.
+        write_graph_sqlite(&nodes, &edges, &path)?;
+
+        let connection = Connection::open(&path)?;
+        let mut statement = connection.prepare(
+            "SELECT dest_osm, length FROM edges WHERE source_osm = ?1 ORDER BY dest_osm",
+        )?;
+        let outgoing = statement
+            .query_map([1], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        assert_eq!(outgoing, vec![(2, 10.0), (3, 20.0)]);
+
+        let lat: f64 =
+            connection.query_row("SELECT lat FROM nodes WHERE osm_id = ?1", [2], |row| {
+                row.get(0)
+            })?;
+        assert_eq!(lat, 51.3);
+        let edge_count: usize =
+            connection.query_row("SELECT COUNT(*) FROM edges", [], |row| row.get(0))?;
+        assert_eq!(edge_count, 3);
+        Ok(())
+    }
+}
@@ -6,7 +6,9 @@ use log::info;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
-fn download_source(url: &String, filename: &String, target_dir: &String) -> Result<PathBuf> {
+/// Path of `filename` in `target_dir`, creating the directory and removing a
+/// previous download.
+fn prepare_target(filename: &str, target_dir: &str) -> PathBuf {
     let path = Path::new(target_dir);
     if !path.exists() {
         info!("Creating directories for path {target_dir}");
@@ -24,6 +26,12 @@ fn download_source(url: &String, filename: &String, target_dir: &String) -> Resu
             Err(error) => panic!("Problem removing the existing pbf file: {error:?}"),
         }
     }
+    filepath_buf
+}
+
+fn download_source(url: &String, filename: &str, target_dir: &str) -> Result<PathBuf> {
+    let filepath_buf = prepare_target(filename, target_dir);
+    let filepath = filepath_buf.as_path();
     info!("Downloading file");
     let response = reqwest::blocking::get(url)?;
     let mut file = File::create(filepath)?;
@@ -33,7 +41,62 @@ fn download_source(url: &String, filename: &String, target_dir: &String) -> Resu
     Ok(filepath_buf)
 }
 
-pub fn download(source_name: &String, target_dir: &String) -> Result<PathBuf> {
+pub fn download(source_name: &String, target_dir: &str) -> Result<PathBuf> {
     let (filename, url) = get_bbbike_source(source_name).expect("Not available at source BBBike");
     download_source(&url, &filename, target_dir)
 }
+
+/// Downloads `url` to `filename` in `target_dir` without blocking the async
+/// runtime, streaming the response body to the file.
+#[cfg(feature = "async")]
+pub async fn download_async(url: &str, filename: &str, target_dir: &str) -> Result<PathBuf> {
+    use futures_util::StreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    let filepath = prepare_target(filename, target_dir);
+    info!("Downloading file");
+    let response = reqwest::Client::new()
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?;
+    let mut file = tokio::fs::File::create(&filepath).await?;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        file.write_all(&chunk?).await?;
+    }
+    file.flush().await?;
+    Ok(filepath)
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_download_async() {
+        let server = MockServer::start().await;
+        let body: Vec<u8> = (0..=255).cycle().take(100_000).collect();
+        Mock::given(method("GET"))
+            .and(path("/Bruegge/Bruegge.osm.pbf"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(body.clone()))
+            .mount(&server)
+            .await;
+        let target_dir = std::env::temp_dir().join("osmtools_download_async");
+        let target_dir = target_dir.to_str().unwrap();
+
+        let url = format!("{}/Bruegge/Bruegge.osm.pbf", server.uri());
+        let filepath = download_async(&url, "bruegge.osm.pbf", target_dir)
+            .await
+            .unwrap();
+        assert_eq!(filepath, Path::new(target_dir).join("bruegge.osm.pbf"));
+        assert_eq!(std::fs::read(&filepath).unwrap(), body);
+
+        let missing = format!("{}/Gent/Gent.osm.pbf", server.uri());
+        assert!(download_async(&missing, "gent.osm.pbf", target_dir)
+            .await
+            .is_err());
+    }
+}
@@ -1,10 +1,19 @@
+use std::collections::HashMap;
 use std::error;
 
-use geo::Point;
+use crate::pbfextractor::pbf::{Edge, Node, OsmNodeId};
+use geo::{Distance, Haversine, Line, Point};
 use kiddo::{ImmutableKdTree, SquaredEuclidean};
 use polars::prelude::*;
 use proj4rs;
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
 
+/// Adds the id of and distance to the nearest node of `nodes_to_match` to each
+/// row of `geo_df`.
+///
+/// Distances are Euclidean in `target_crs`. For a geographic target CRS such
+/// as 4326 they are great-circle distances in meters instead of radians.
 pub fn add_nearest_node_to_geo_df<'a>(
     geo_df: DataFrame,
     nodes_to_match: &DataFrame,
@@ -12,36 +21,17 @@ pub fn add_nearest_node_to_geo_df<'a>(
 ) -> Result<DataFrame, Box<dyn error::Error>> {
     let proj_from = proj4rs::Proj::from_epsg_code(4326_u16)?;
     let proj_to = proj4rs::Proj::from_epsg_code(target_crs)?;
-    let mut nodes: Vec<Point> = nodes_to_match
-        .column("lat")?
-        .f64()?
-        .into_iter()
-        .zip(nodes_to_match.column("long")?.f64()?.into_iter())
-        .map(|(lat, long)| Point::new(long.unwrap(), lat.unwrap()).to_radians())
-        .collect();
-    nodes
-        .iter_mut()
-        .for_each(|p| proj4rs::transform::transform(&proj_from, &proj_to, p).unwrap());
-    let nodes_arr: Vec<[f64; 2]> = nodes.iter().map(|p| [p.x(), p.y()]).collect();
-    let kdtree = ImmutableKdTree::new_from_slice(&nodes_arr);
-    let (id, dist): (Vec<u64>, Vec<f64>) = geo_df
-        .column("lat")?
-        .f64()?
+    let nodes: Vec<Point> = points(nodes_to_match)?;
+    let targets: Vec<Point> = points(&geo_df)?;
+    let nearest: Vec<(usize, f64)> = if proj_to.is_latlong() {
+        nearest_great_circle(&nodes, &targets)
+    } else {
+        nearest_projected(&nodes, &targets, &proj_from, &proj_to)?
+    };
+    let osm_ids = nodes_to_match.column("osm_id")?.u64()?;
+    let (id, dist): (Vec<u64>, Vec<f64>) = nearest
         .into_iter()
-        .zip(geo_df.column("long")?.f64()?.into_iter())
-        .map(|(lat, long)| {
-            let mut point = Point::new(long.unwrap(), lat.unwrap()).to_radians();
-            proj4rs::transform::transform(&proj_from, &proj_to, &mut point).unwrap();
-            let nearest_node = kdtree.nearest_one::<SquaredEuclidean>(&[point.x(), point.y()]);
-            let matched_nearest_node = nodes_to_match
-                .column("osm_id")
-                .unwrap()
-                .u64()
-                .unwrap()
-                .get(nearest_node.item as usize)
-                .unwrap();
-            (matched_nearest_node, nearest_node.distance.sqrt())
-        })
+        .map(|(item, distance)| (osm_ids.get(item).unwrap(), distance))
         .unzip();
     let series_nearest_node = Series::new("nearest_node_osm_id".into(), id);
     let series_nearest_distance = Series::new("nearest_node_distance".into(), dist);
@@ -52,6 +42,140 @@ pub fn add_nearest_node_to_geo_df<'a>(
         .map_err(|e| e.into());
 }
 
+fn points(df: &DataFrame) -> PolarsResult<Vec<Point>> {
+    Ok(df
+        .column("lat")?
+        .f64()?
+        .into_iter()
+        .zip(df.column("long")?.f64()?)
+        .map(|(lat, long)| Point::new(long.unwrap(), lat.unwrap()))
+        .collect())
+}
+
+fn nearest_projected(
+    nodes: &[Point],
+    targets: &[Point],
+    proj_from: &proj4rs::Proj,
+    proj_to: &proj4rs::Proj,
+) -> Result<Vec<(usize, f64)>, Box<dyn error::Error>> {
+    let project = |p: &Point| -> Result<[f64; 2], Box<dyn error::Error>> {
+        let mut point = p.to_radians();
+        proj4rs::transform::transform(proj_from, proj_to, &mut point)?;
+        Ok([point.x(), point.y()])
+    };
+    let nodes_arr = nodes.iter().map(project).collect::<Result<Vec<_>, _>>()?;
+    let kdtree = ImmutableKdTree::new_from_slice(&nodes_arr);
+    targets
+        .iter()
+        .map(|p| {
+            let nearest_node = kdtree.nearest_one::<SquaredEuclidean>(&project(p)?);
+            Ok((nearest_node.item as usize, nearest_node.distance.sqrt()))
+        })
+        .collect()
+}
+
+/// Nearest neighbours by great-circle distance. The search runs on points of
+/// the unit sphere, where the chord length grows monotonically with the
+/// great-circle distance, so the kd-tree finds the true nearest node.
+fn nearest_great_circle(nodes: &[Point], targets: &[Point]) -> Vec<(usize, f64)> {
+    let nodes_arr: Vec<[f64; 3]> = nodes.iter().map(unit_sphere).collect();
+    let kdtree = ImmutableKdTree::new_from_slice(&nodes_arr);
+    targets
+        .iter()
+        .map(|p| {
+            let item = kdtree.nearest_one::<SquaredEuclidean>(&unit_sphere(p)).item as usize;
+            (item, Haversine.distance(nodes[item], *p))
+        })
+        .collect()
+}
+
+fn unit_sphere(p: &Point) -> [f64; 3] {
+    let (lat, long) = (p.y().to_radians(), p.x().to_radians());
+    [lat.cos() * long.cos(), lat.cos() * long.sin(), lat.sin()]
+}
+
+/// Position of a point snapped onto the nearest edge of a graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdgeSnap {
+    pub source_osm: OsmNodeId,
+    pub dest_osm: OsmNodeId,
+    /// Position of the snapped point along the edge, from 0 at `source_osm`
+    /// to 1 at `dest_osm`.
+    pub fraction: f64,
+    /// Distance between the point and its perpendicular projection onto the
+    /// edge.
+    pub distance: f64,
+}
+
+/// Snaps each of `points` (longitude, latitude) onto the nearest segment of
+/// `edges`, instead of onto the nearest node.
+///
+/// Coordinates are projected to `target_crs` first and distances are Euclidean
+/// in that CRS, so it has to be a projected CRS. Edges whose endpoints are
+/// missing from `nodes` are ignored; `None` is returned for every point if no
+/// edge is left.
+pub fn snap_to_edge(
+    points: &[Point],
+    nodes: &[Node],
+    edges: &[Edge],
+    target_crs: u16,
+) -> Result<Vec<Option<EdgeSnap>>, Box<dyn error::Error>> {
+    let proj_from = proj4rs::Proj::from_epsg_code(4326_u16)?;
+    let proj_to = proj4rs::Proj::from_epsg_code(target_crs)?;
+    if proj_to.is_latlong() {
+        return Err(format!("snapping to edges requires a projected CRS, got {target_crs}").into());
+    }
+    let project = |p: Point| -> Result<Point, Box<dyn error::Error>> {
+        let mut point = p.to_radians();
+        proj4rs::transform::transform(&proj_from, &proj_to, &mut point)?;
+        Ok(point)
+    };
+    let mut projected_nodes = HashMap::with_capacity(nodes.len());
+    for node in nodes {
+        projected_nodes.insert(node.osm_id, project(Point::new(node.long, node.lat))?);
+    }
+    let segments: Vec<GeomWithData<Line, usize>> = edges
+        .iter()
+        .enumerate()
+        .filter_map(|(i, edge)| {
+            let source = projected_nodes.get(&edge.source_osm)?;
+            let dest = projected_nodes.get(&edge.dest_osm)?;
+            Some(GeomWithData::new(Line::new(*source, *dest), i))
+        })
+        .collect();
+    let rtree = RTree::bulk_load(segments);
+    points
+        .iter()
+        .map(|p| {
+            let point = project(*p)?;
+            Ok(rtree
+                .nearest_neighbor(&[point.x(), point.y()].into())
+                .map(|segment| {
+                    let edge = &edges[segment.data];
+                    let (fraction, snapped) = project_onto_line(segment.geom(), point);
+                    EdgeSnap {
+                        source_osm: edge.source_osm,
+                        dest_osm: edge.dest_osm,
+                        fraction,
+                        distance: geo::Euclidean.distance(point, snapped),
+                    }
+                }))
+        })
+        .collect()
+}
+
+/// Perpendicular projection of `point` onto `line`, clamped to its endpoints.
+fn project_onto_line(line: &Line, point: Point) -> (f64, Point) {
+    let delta = line.delta();
+    let length_squared = delta.x * delta.x + delta.y * delta.y;
+    if length_squared == 0.0 {
+        return (0.0, line.start_point());
+    }
+    let offset = point - line.start_point();
+    let fraction = ((offset.x() * delta.x + offset.y() * delta.y) / length_squared).clamp(0.0, 1.0);
+    (fraction, line.start_point() + Point::from(delta) * fraction)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -90,8 +214,13 @@ mod tests {
                 );
                 assert_eq!(
                     df.column("nearest_node_distance")?.get(1).unwrap(),
-                    polars::prelude::AnyValue::Float64(Point::new(0.0, 1.0).to_radians().y())
+                    polars::prelude::AnyValue::Float64(
+                        Haversine.distance(Point::new(0.0, 0.0), Point::new(1.0, 0.0))
+                    )
                 );
+                // One degree of longitude at the equator is ~111.2 km.
+                let dist = df.column("nearest_node_distance")?.f64()?.get(1).unwrap();
+                assert!((dist - 111_195.0).abs() < 1.0);
             }
             Err(err) => panic!("No Error allowed in this test: {err:?}"),
         };
@@ -152,4 +281,67 @@ mod tests {
         };
         Ok(())
     }
+
+    #[test]
+    fn test_great_circle_matches_projected_distance() -> Result<(), Box<dyn error::Error>> {
+        let add_df = df![
+            "lat" => [ 50.9488246, 50.9498878, 50.9482893],
+            "long" => [6.9117076, 6.9169238, 6.9202445],
+            "osm_id" => [1u64, 2u64, 3u64]
+        ]?;
+        let target_df = df![
+            "lat" => [50.9500121, 50.9481067],
+            "long" => [6.9217811, 6.9141058],
+            "osm_id" => [0u64, 10u64]
+        ]?;
+        let geographic = add_nearest_node_to_geo_df(target_df.clone(), &add_df, 4326)?;
+        let projected = add_nearest_node_to_geo_df(target_df, &add_df, 4839)?;
+        assert!(geographic
+            .column("nearest_node_osm_id")?
+            .equals(projected.column("nearest_node_osm_id")?));
+        let geographic_dist = geographic.column("nearest_node_distance")?.f64()?;
+        let projected_dist = projected.column("nearest_node_distance")?.f64()?;
+        for (a, b) in geographic_dist.into_iter().zip(projected_dist) {
+            assert!((a.unwrap() - b.unwrap()).abs() < 1.0);
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_snap_to_edge_between_collinear_nodes() -> Result<(), Box<dyn error::Error>> {
+        let nodes = vec![
+            Node::new(1, 50.9480, 6.9100),
+            Node::new(2, 50.9480, 6.9200),
+            Node::new(3, 50.9550, 6.9100),
+        ];
+        let edges = vec![Edge::new(1, 2), Edge::new(2, 1), Edge::new(1, 3)];
+        // Slightly north of the 1-2 edge, a third of the way from node 1: node
+        // 1 is the nearest node, but the edge is much closer than it.
+        let poi = Point::new(6.91333, 50.94805);
+        let snaps = snap_to_edge(&[poi], &nodes, &edges, 4839)?;
+        let snap = snaps[0].as_ref().unwrap();
+        assert_eq!(
+            (
+                snap.source_osm.min(snap.dest_osm),
+                snap.source_osm.max(snap.dest_osm)
+            ),
+            (1, 2)
+        );
+        let from_node_1 = if snap.source_osm == 1 {
+            snap.fraction
+        } else {
+            1.0 - snap.fraction
+        };
+        assert!((from_node_1 - 1.0 / 3.0).abs() < 0.01);
+        assert!(snap.distance < 10.0);
+        let to_node = Haversine.distance(poi, Point::new(6.9100, 50.9480));
+        assert!(to_node > 200.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snap_to_edge_requires_projected_crs() {
+        let nodes = vec![Node::new(1, 0.0, 0.0), Node::new(2, 0.0, 1.0)];
+        let edges = vec![Edge::new(1, 2)];
+        assert!(snap_to_edge(&[Point::new(0.5, 0.0)], &nodes, &edges, 4326).is_err());
+    }
 }
@@ -1,3 +1,4 @@
+use crate::bounding_box::BoundingBox;
 use core::fmt;
 use std::error::Error;
 
@@ -8,7 +9,7 @@ pub struct SourceNotFoundError {
 
 impl Error for SourceNotFoundError {}
 impl SourceNotFoundError {
-    fn new(source_name: String) -> Self {
+    pub(crate) fn new(source_name: String) -> Self {
         Self { source_name }
     }
 }
@@ -23,244 +24,289 @@ impl fmt::Display for SourceNotFoundError {
     }
 }
 
-const CITIES: [&str; 235] = [
-    "Aachen",
-    "Aarhus",
-    "Adelaide",
-    "Albuquerque",
-    "Alexandria",
-    "Amsterdam",
-    "Antwerpen",
-    "Arnhem",
-    "Auckland",
-    "Augsburg",
-    "Austin",
-    "Baghdad",
-    "Baku",
-    "Balaton",
-    "Bamberg",
-    "Bangkok",
-    "Barcelona",
-    "Basel",
-    "Beijing",
-    "Beirut",
-    "Berkeley",
-    "Berlin",
-    "Bern",
-    "Bielefeld",
-    "Birmingham",
-    "Bochum",
-    "Bogota",
-    "Bombay",
-    "Bonn",
-    "Bordeaux",
-    "Boulder",
-    "BrandenburgHavel",
-    "Braunschweig",
-    "Bremen",
-    "Bremerhaven",
-    "Brisbane",
-    "Bristol",
-    "Brno",
-    "Bruegge",
-    "Bruessel",
-    "Budapest",
-    "BuenosAires",
-    "Cairo",
-    "Calgary",
-    "Cambridge",
-    "CambridgeMa",
-    "Canberra",
-    "CapeTown",
-    "Chemnitz",
-    "Chicago",
-    "ClermontFerrand",
-    "Colmar",
-    "Copenhagen",
-    "Cork",
-    "Corsica",
-    "Corvallis",
-    "Cottbus",
-    "Cracow",
-    "CraterLake",
-    "Curitiba",
-    "Cusco",
-    "Dallas",
-    "Darmstadt",
-    "Davis",
-    "DenHaag",
-    "Denver",
-    "Dessau",
-    "Dortmund",
-    "Dresden",
-    "Dublin",
-    "Duesseldorf",
-    "Duisburg",
-    "Edinburgh",
-    "Eindhoven",
-    "Emden",
-    "Erfurt",
-    "Erlangen",
-    "Eugene",
-    "Flensburg",
-    "FortCollins",
-    "Frankfurt",
-    "FrankfurtOder",
-    "Freiburg",
-    "Gdansk",
-    "Genf",
-    "Gent",
-    "Gera",
-    "Glasgow",
-    "Gliwice",
-    "Goerlitz",
-    "Goeteborg",
-    "Goettingen",
-    "Graz",
-    "Groningen",
-    "Halifax",
-    "Halle",
-    "Hamburg",
-    "Hamm",
-    "Hannover",
-    "Heilbronn",
-    "Helsinki",
-    "Hertogenbosch",
-    "Huntsville",
-    "Innsbruck",
-    "Istanbul",
-    "Jena",
-    "Jerusalem",
-    "Johannesburg",
-    "Kaiserslautern",
-    "Karlsruhe",
-    "Kassel",
-    "Katowice",
-    "Kaunas",
-    "Kiel",
-    "Kiew",
-    "Koblenz",
-    "Koeln",
-    "Konstanz",
-    "LaPaz",
-    "LaPlata",
-    "LakeGarda",
-    "Lausanne",
-    "Leeds",
-    "Leipzig",
-    "Lima",
-    "Linz",
-    "Lisbon",
-    "Liverpool",
-    "Ljubljana",
-    "Lodz",
-    "London",
-    "Luebeck",
-    "Luxemburg",
-    "Lyon",
-    "Maastricht",
-    "Madison",
-    "Madrid",
-    "Magdeburg",
-    "Mainz",
-    "Malmoe",
-    "Manchester",
-    "Mannheim",
-    "Marseille",
-    "Melbourne",
-    "Memphis",
-    "MexicoCity",
-    "Miami",
-    "Moenchengladbach",
-    "Montevideo",
-    "Montpellier",
-    "Montreal",
-    "Moscow",
-    "Muenchen",
-    "Muenster",
-    "NewDelhi",
-    "NewOrleans",
-    "NewYorkCity",
-    "Nuernberg",
-    "Oldenburg",
-    "Oranienburg",
-    "Orlando",
-    "Oslo",
-    "Osnabrueck",
-    "Ostrava",
-    "Ottawa",
-    "Paderborn",
-    "Palma",
-    "PaloAlto",
-    "Paris",
-    "Perth",
-    "Philadelphia",
-    "PhnomPenh",
-    "Portland",
-    "PortlandME",
-    "Porto",
-    "PortoAlegre",
-    "Potsdam",
-    "Poznan",
-    "Prag",
-    "Providence",
-    "Regensburg",
-    "Riga",
-    "RiodeJaneiro",
-    "Rostock",
-    "Rotterdam",
-    "Ruegen",
-    "Saarbruecken",
-    "Sacramento",
-    "Saigon",
-    "Salzburg",
-    "SanFrancisco",
-    "SanJose",
-    "SanktPetersburg",
-    "SantaBarbara",
-    "SantaCruz",
-    "Santiago",
-    "Sarajewo",
-    "Schwerin",
-    "Seattle",
-    "Seoul",
-    "Sheffield",
-    "Singapore",
-    "Sofia",
-    "Stockholm",
-    "Stockton",
-    "Strassburg",
-    "Stuttgart",
-    "Sucre",
-    "Sydney",
-    "Szczecin",
-    "Tallinn",
-    "Tehran",
-    "Tilburg",
-    "Tokyo",
-    "Toronto",
-    "Toulouse",
-    "Trondheim",
-    "Tucson",
-    "Turin",
-    "UlanBator",
-    "Ulm",
-    "Usedom",
-    "Utrecht",
-    "Vancouver",
-    "Victoria",
-    "WarenMueritz",
-    "Warsaw",
-    "WashingtonDC",
-    "Waterloo",
-    "Wien",
-    "Wroclaw",
-    "Wuerzburg",
-    "Wuppertal",
-    "Zagreb",
-    "Zuerich",
+/// A city BBBike offers an extract of.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CityEntry {
+    /// BBBike name of the extract, e.g. `Koeln`.
+    pub name: &'static str,
+    /// ISO 3166-1 alpha-2 code of the country, e.g. `DE`.
+    pub country: &'static str,
+    /// Rough extent of the extract, if known: about 40 km across around the
+    /// city center, more for regional extracts such as `Corsica`.
+    pub bbox: Option<BoundingBox>,
+}
+
+/// `bbox` as `[min_lon, min_lat, max_lon, max_lat]`.
+const fn city(name: &'static str, country: &'static str, bbox: [f64; 4]) -> CityEntry {
+    CityEntry {
+        name,
+        country,
+        bbox: Some(BoundingBox {
+            min_lon: bbox[0],
+            min_lat: bbox[1],
+            max_lon: bbox[2],
+            max_lat: bbox[3],
+        }),
+    }
+}
+
+/// Every city BBBike offers an extract of.
+pub static CITY_CATALOG: [CityEntry; 235] = [
+    city("Aachen", "DE", [5.80, 50.60, 6.37, 50.96]),
+    city("Aarhus", "DK", [9.89, 55.98, 10.53, 56.34]),
+    city("Adelaide", "AU", [138.38, -35.11, 138.82, -34.75]),
+    city("Albuquerque", "US", [-106.87, 34.90, -106.43, 35.26]),
+    city("Alexandria", "EG", [29.71, 31.02, 30.13, 31.38]),
+    city("Amsterdam", "NL", [4.60, 52.19, 5.19, 52.55]),
+    city("Antwerpen", "BE", [4.12, 51.04, 4.69, 51.40]),
+    city("Arnhem", "NL", [5.61, 51.81, 6.19, 52.16]),
+    city("Auckland", "NZ", [174.54, -37.03, 174.99, -36.67]),
+    city("Augsburg", "DE", [10.63, 48.19, 11.17, 48.55]),
+    city("Austin", "US", [-97.95, 30.09, -97.53, 30.45]),
+    city("Baghdad", "IQ", [44.15, 33.14, 44.58, 33.49]),
+    city("Baku", "AZ", [49.63, 40.23, 50.10, 40.59]),
+    city("Balaton", "HU", [17.14, 46.43, 18.32, 47.23]),
+    city("Bamberg", "DE", [10.62, 49.72, 11.18, 50.08]),
+    city("Bangkok", "TH", [100.32, 13.58, 100.69, 13.94]),
+    city("Barcelona", "ES", [1.93, 41.21, 2.41, 41.56]),
+    city("Basel", "CH", [7.32, 47.38, 7.86, 47.74]),
+    city("Beijing", "CN", [116.17, 39.72, 116.64, 40.08]),
+    city("Beirut", "LB", [35.29, 33.71, 35.72, 34.07]),
+    city("Berkeley", "US", [-122.50, 37.69, -122.05, 38.05]),
+    city("Berlin", "DE", [13.11, 52.34, 13.70, 52.70]),
+    city("Bern", "CH", [7.18, 46.77, 7.71, 47.13]),
+    city("Bielefeld", "DE", [8.24, 51.85, 8.82, 52.21]),
+    city("Birmingham", "GB", [-2.19, 52.31, -1.59, 52.67]),
+    city("Bochum", "DE", [6.93, 51.30, 7.50, 51.66]),
+    city("Bogota", "CO", [-74.25, 4.53, -73.89, 4.89]),
+    city("Bombay", "IN", [72.69, 18.90, 73.07, 19.26]),
+    city("Bonn", "DE", [6.81, 50.56, 7.38, 50.92]),
+    city("Bordeaux", "FR", [-0.83, 44.66, -0.33, 45.02]),
+    city("Boulder", "US", [-105.51, 39.84, -105.04, 40.19]),
+    city("BrandenburgHavel", "DE", [12.27, 52.23, 12.86, 52.59]),
+    city("Braunschweig", "DE", [10.23, 52.09, 10.81, 52.45]),
+    city("Bremen", "DE", [8.50, 52.90, 9.10, 53.26]),
+    city("Bremerhaven", "DE", [8.28, 53.36, 8.88, 53.72]),
+    city("Brisbane", "AU", [152.82, -27.65, 153.23, -27.29]),
+    city("Bristol", "GB", [-2.88, 51.28, -2.30, 51.63]),
+    city("Brno", "CZ", [16.33, 49.02, 16.88, 49.37]),
+    city("Bruegge", "BE", [2.94, 51.03, 3.51, 51.39]),
+    city("Bruessel", "BE", [4.07, 50.67, 4.64, 51.03]),
+    city("Budapest", "HU", [18.77, 47.32, 19.31, 47.68]),
+    city("BuenosAires", "AR", [-58.60, -34.78, -58.16, -34.42]),
+    city("Cairo", "EG", [31.03, 29.86, 31.44, 30.22]),
+    city("Calgary", "CA", [-114.34, 50.87, -113.77, 51.22]),
+    city("Cambridge", "GB", [-0.17, 52.03, 0.42, 52.38]),
+    city("CambridgeMa", "US", [-71.35, 42.19, -70.86, 42.55]),
+    city("Canberra", "AU", [148.91, -35.46, 149.35, -35.10]),
+    city("CapeTown", "ZA", [18.21, -34.10, 18.64, -33.75]),
+    city("Chemnitz", "DE", [12.64, 50.65, 13.21, 51.01]),
+    city("Chicago", "US", [-87.87, 41.70, -87.39, 42.06]),
+    city("ClermontFerrand", "FR", [2.83, 45.60, 3.34, 45.96]),
+    city("Colmar", "FR", [7.09, 47.90, 7.63, 48.26]),
+    city("Copenhagen", "DK", [12.25, 55.50, 12.89, 55.86]),
+    city("Cork", "IE", [-8.77, 51.72, -8.18, 52.08]),
+    city("Corsica", "FR", [7.89, 41.25, 10.31, 43.05]),
+    city("Corvallis", "US", [-123.51, 44.39, -123.01, 44.74]),
+    city("Cottbus", "DE", [14.04, 51.58, 14.62, 51.94]),
+    city("Cracow", "PL", [19.67, 49.89, 20.22, 50.24]),
+    city("CraterLake", "US", [-122.48, 42.67, -121.74, 43.21]),
+    city("Curitiba", "BR", [-49.47, -25.61, -49.07, -25.25]),
+    city("Cusco", "PE", [-72.15, -13.71, -71.78, -13.35]),
+    city("Dallas", "US", [-97.01, 32.60, -96.58, 32.96]),
+    city("Darmstadt", "DE", [8.37, 49.69, 8.93, 50.05]),
+    city("Davis", "US", [-121.97, 38.37, -121.51, 38.72]),
+    city("DenHaag", "NL", [4.01, 51.89, 4.59, 52.25]),
+    city("Denver", "US", [-105.22, 39.56, -104.76, 39.92]),
+    city("Dessau", "DE", [11.96, 51.66, 12.54, 52.01]),
+    city("Dortmund", "DE", [7.18, 51.33, 7.76, 51.69]),
+    city("Dresden", "DE", [13.45, 50.87, 14.02, 51.23]),
+    city("Dublin", "IE", [-6.56, 53.17, -5.96, 53.53]),
+    city("Duesseldorf", "DE", [6.49, 51.05, 7.06, 51.41]),
+    city("Duisburg", "DE", [6.47, 51.26, 7.05, 51.61]),
+    city("Edinburgh", "GB", [-3.51, 55.77, -2.87, 56.13]),
+    city("Eindhoven", "NL", [5.18, 51.26, 5.76, 51.62]),
+    city("Emden", "DE", [6.90, 53.19, 7.51, 53.55]),
+    city("Erfurt", "DE", [10.74, 50.80, 11.31, 51.16]),
+    city("Erlangen", "DE", [10.73, 49.41, 11.28, 49.77]),
+    city("Eugene", "US", [-123.34, 43.87, -122.84, 44.23]),
+    city("Flensburg", "DE", [9.13, 54.61, 9.76, 54.97]),
+    city("FortCollins", "US", [-105.32, 40.41, -104.85, 40.76]),
+    city("Frankfurt", "DE", [8.40, 49.93, 8.96, 50.29]),
+    city("FrankfurtOder", "DE", [14.26, 52.16, 14.85, 52.52]),
+    city("Freiburg", "DE", [7.57, 47.82, 8.11, 48.18]),
+    city("Gdansk", "PL", [18.34, 54.17, 18.95, 54.53]),
+    city("Genf", "CH", [5.88, 46.02, 6.40, 46.38]),
+    city("Gent", "BE", [3.43, 50.87, 4.00, 51.23]),
+    city("Gera", "DE", [11.80, 50.70, 12.37, 51.06]),
+    city("Glasgow", "GB", [-4.57, 55.68, -3.93, 56.04]),
+    city("Gliwice", "PL", [18.38, 50.11, 18.95, 50.47]),
+    city("Goerlitz", "DE", [14.70, 50.97, 15.27, 51.33]),
+    city("Goeteborg", "SE", [11.64, 57.53, 12.31, 57.89]),
+    city("Goettingen", "DE", [9.63, 51.36, 10.20, 51.72]),
+    city("Graz", "AT", [15.18, 46.89, 15.70, 47.25]),
+    city("Groningen", "NL", [6.27, 53.04, 6.87, 53.40]),
+    city("Halifax", "CA", [-63.83, 44.47, -63.32, 44.83]),
+    city("Halle", "DE", [11.68, 51.30, 12.26, 51.66]),
+    city("Hamburg", "DE", [9.69, 53.37, 10.30, 53.73]),
+    city("Hamm", "DE", [7.53, 51.50, 8.11, 51.86]),
+    city("Hannover", "DE", [9.44, 52.20, 10.03, 52.56]),
+    city("Heilbronn", "DE", [8.94, 48.96, 9.49, 49.32]),
+    city("Helsinki", "FI", [24.58, 59.99, 25.30, 60.35]),
+    city("Hertogenbosch", "NL", [5.01, 51.51, 5.59, 51.87]),
+    city("Huntsville", "US", [-86.80, 34.55, -86.37, 34.91]),
+    city("Innsbruck", "AT", [11.14, 47.09, 11.67, 47.45]),
+    city("Istanbul", "TR", [28.74, 40.83, 29.22, 41.19]),
+    city("Jena", "DE", [11.30, 50.75, 11.87, 51.11]),
+    city("Jerusalem", "IL", [35.00, 31.59, 35.43, 31.95]),
+    city("Johannesburg", "ZA", [27.85, -26.38, 28.25, -26.02]),
+    city("Kaiserslautern", "DE", [7.49, 49.26, 8.05, 49.62]),
+    city("Karlsruhe", "DE", [8.13, 48.83, 8.68, 49.19]),
+    city("Kassel", "DE", [9.19, 51.13, 9.77, 51.49]),
+    city("Katowice", "PL", [18.74, 50.08, 19.31, 50.44]),
+    city("Kaunas", "LT", [23.59, 54.72, 24.22, 55.08]),
+    city("Kiel", "DE", [9.81, 54.14, 10.43, 54.50]),
+    city("Kiew", "UA", [30.24, 50.27, 30.81, 50.63]),
+    city("Koblenz", "DE", [7.31, 50.18, 7.87, 50.54]),
+    city("Koeln", "DE", [6.67, 50.76, 7.25, 51.12]),
+    city("Konstanz", "DE", [8.91, 47.48, 9.44, 47.84]),
+    city("LaPaz", "BO", [-68.34, -16.68, -67.96, -16.32]),
+    city("LaPlata", "AR", [-58.17, -35.10, -57.74, -34.74]),
+    city("LakeGarda", "IT", [10.23, 45.34, 11.13, 45.96]),
+    city("Lausanne", "CH", [6.37, 46.34, 6.89, 46.70]),
+    city("Leeds", "GB", [-1.85, 53.62, -1.24, 53.98]),
+    city("Leipzig", "DE", [12.09, 51.16, 12.66, 51.52]),
+    city("Lima", "PE", [-77.23, -12.23, -76.86, -11.87]),
+    city("Linz", "AT", [14.02, 48.13, 14.56, 48.49]),
+    city("Lisbon", "PT", [-9.37, 38.54, -8.91, 38.90]),
+    city("Liverpool", "GB", [-3.29, 53.23, -2.69, 53.59]),
+    city("Ljubljana", "SI", [14.25, 45.88, 14.76, 46.24]),
+    city("Lodz", "PL", [19.17, 51.58, 19.75, 51.94]),
+    city("London", "GB", [-0.42, 51.33, 0.16, 51.69]),
+    city("Luebeck", "DE", [10.38, 53.69, 10.99, 54.05]),
+    city("Luxemburg", "LU", [5.85, 49.43, 6.41, 49.79]),
+    city("Lyon", "FR", [4.58, 45.58, 5.09, 45.94]),
+    city("Maastricht", "NL", [5.41, 50.67, 5.98, 51.03]),
+    city("Madison", "US", [-89.65, 42.89, -89.16, 43.25]),
+    city("Madrid", "ES", [-3.94, 40.24, -3.47, 40.60]),
+    city("Magdeburg", "DE", [11.34, 51.94, 11.92, 52.30]),
+    city("Mainz", "DE", [7.97, 49.81, 8.53, 50.17]),
+    city("Malmoe", "SE", [12.69, 55.43, 13.32, 55.78]),
+    city("Manchester", "GB", [-2.54, 53.30, -1.94, 53.66]),
+    city("Mannheim", "DE", [8.19, 49.31, 8.74, 49.67]),
+    city("Marseille", "FR", [5.12, 43.12, 5.62, 43.48]),
+    city("Melbourne", "AU", [144.74, -37.99, 145.19, -37.63]),
+    city("Memphis", "US", [-90.27, 34.97, -89.83, 35.33]),
+    city("MexicoCity", "MX", [-99.32, 19.25, -98.94, 19.61]),
+    city("Miami", "US", [-80.39, 25.58, -79.99, 25.94]),
+    city("Moenchengladbach", "DE", [6.16, 51.00, 6.73, 51.36]),
+    city("Montevideo", "UY", [-56.38, -35.08, -55.95, -34.72]),
+    city("Montpellier", "FR", [3.63, 43.43, 4.13, 43.79]),
+    city("Montreal", "CA", [-73.82, 45.32, -73.31, 45.68]),
+    city("Moscow", "RU", [37.30, 55.58, 37.94, 55.94]),
+    city("Muenchen", "DE", [11.31, 47.96, 11.84, 48.32]),
+    city("Muenster", "DE", [7.33, 51.78, 7.92, 52.14]),
+    city("NewDelhi", "IN", [77.00, 28.43, 77.41, 28.79]),
+    city("NewOrleans", "US", [-90.28, 29.77, -89.86, 30.13]),
+    city("NewYorkCity", "US", [-74.24, 40.53, -73.77, 40.89]),
+    city("Nuernberg", "DE", [10.80, 49.27, 11.35, 49.63]),
+    city("Oldenburg", "DE", [7.91, 52.96, 8.51, 53.32]),
+    city("Oranienburg", "DE", [12.94, 52.57, 13.53, 52.93]),
+    city("Orlando", "US", [-81.58, 28.36, -81.17, 28.72]),
+    city("Oslo", "NO", [10.39, 59.73, 11.11, 60.09]),
+    city("Osnabrueck", "DE", [7.75, 52.10, 8.34, 52.46]),
+    city("Ostrava", "CZ", [17.98, 49.64, 18.54, 50.00]),
+    city("Ottawa", "CA", [-75.95, 45.24, -75.44, 45.60]),
+    city("Paderborn", "DE", [8.46, 51.54, 9.05, 51.90]),
+    city("Palma", "ES", [2.42, 39.39, 2.88, 39.75]),
+    city("PaloAlto", "US", [-122.37, 37.26, -121.92, 37.62]),
+    city("Paris", "FR", [2.08, 48.68, 2.63, 49.04]),
+    city("Perth", "AU", [115.65, -32.13, 116.07, -31.77]),
+    city("Philadelphia", "US", [-75.40, 39.77, -74.93, 40.13]),
+    city("PhnomPenh", "KH", [104.74, 11.38, 105.11, 11.74]),
+    city("Portland", "US", [-122.94, 45.34, -122.42, 45.69]),
+    city("PortlandME", "US", [-70.51, 43.48, -70.01, 43.84]),
+    city("Porto", "PT", [-8.87, 40.98, -8.39, 41.34]),
+    city("PortoAlegre", "BR", [-51.43, -30.21, -51.01, -29.86]),
+    city("Potsdam", "DE", [12.77, 52.21, 13.36, 52.57]),
+    city("Poznan", "PL", [16.63, 52.23, 17.22, 52.59]),
+    city("Prag", "CZ", [14.16, 49.90, 14.72, 50.26]),
+    city("Providence", "US", [-71.65, 41.64, -71.17, 42.00]),
+    city("Regensburg", "DE", [11.83, 48.83, 12.38, 49.19]),
+    city("Riga", "LV", [23.78, 56.77, 24.43, 57.13]),
+    city("RiodeJaneiro", "BR", [-43.37, -23.09, -42.98, -22.73]),
+    city("Rostock", "DE", [11.79, 53.91, 12.41, 54.27]),
+    city("Rotterdam", "NL", [4.19, 51.74, 4.77, 52.10]),
+    city("Ruegen", "DE", [12.86, 54.11, 13.94, 54.73]),
+    city("Saarbruecken", "DE", [6.72, 49.06, 7.27, 49.42]),
+    city("Sacramento", "US", [-121.72, 38.40, -121.26, 38.76]),
+    city("Saigon", "VN", [106.45, 10.64, 106.81, 11.00]),
+    city("Salzburg", "AT", [12.79, 47.63, 13.32, 47.99]),
+    city("SanFrancisco", "US", [-122.65, 37.60, -122.19, 37.95]),
+    city("SanJose", "US", [-122.11, 37.16, -121.66, 37.52]),
+    city("SanktPetersburg", "RU", [30.00, 59.75, 30.72, 60.11]),
+    city("SantaBarbara", "US", [-119.92, 34.24, -119.48, 34.60]),
+    city("SantaCruz", "US", [-122.26, 36.79, -121.81, 37.15]),
+    city("Santiago", "CL", [-70.88, -33.63, -70.45, -33.27]),
+    city("Sarajewo", "BA", [18.16, 43.68, 18.66, 44.04]),
+    city("Schwerin", "DE", [11.10, 53.46, 11.70, 53.82]),
+    city("Seattle", "US", [-122.60, 47.43, -122.07, 47.79]),
+    city("Seoul", "KR", [126.75, 37.39, 127.20, 37.75]),
+    city("Sheffield", "GB", [-1.77, 53.20, -1.17, 53.56]),
+    city("Singapore", "SG", [103.64, 1.17, 104.00, 1.53]),
+    city("Sofia", "BG", [23.08, 42.52, 23.57, 42.88]),
+    city("Stockholm", "SE", [17.72, 59.15, 18.42, 59.51]),
+    city("Stockton", "US", [-121.52, 37.78, -121.06, 38.14]),
+    city("Strassburg", "FR", [7.48, 48.39, 8.02, 48.75]),
+    city("Stuttgart", "DE", [8.91, 48.60, 9.46, 48.96]),
+    city("Sucre", "BO", [-65.45, -19.23, -65.07, -18.87]),
+    city("Sydney", "AU", [150.99, -34.05, 151.43, -33.69]),
+    city("Szczecin", "PL", [14.25, 53.25, 14.85, 53.61]),
+    city("Tallinn", "EE", [24.40, 59.26, 25.11, 59.62]),
+    city("Tehran", "IR", [51.17, 35.51, 51.61, 35.87]),
+    city("Tilburg", "NL", [4.80, 51.38, 5.38, 51.74]),
+    city("Tokyo", "JP", [139.43, 35.50, 139.87, 35.86]),
+    city("Toronto", "CA", [-79.63, 43.47, -79.13, 43.83]),
+    city("Toulouse", "FR", [1.20, 43.43, 1.69, 43.78]),
+    city("Trondheim", "NO", [9.99, 63.25, 10.80, 63.61]),
+    city("Tucson", "US", [-111.19, 32.04, -110.76, 32.40]),
+    city("Turin", "IT", [7.43, 44.89, 7.94, 45.25]),
+    city("UlanBator", "MN", [106.64, 47.71, 107.17, 48.07]),
+    city("Ulm", "DE", [9.72, 48.22, 10.26, 48.58]),
+    city("Usedom", "DE", [13.54, 53.68, 14.46, 54.22]),
+    city("Utrecht", "NL", [4.83, 51.91, 5.41, 52.27]),
+    city("Vancouver", "CA", [-123.40, 49.10, -122.85, 49.46]),
+    city("Victoria", "CA", [-123.64, 48.25, -123.10, 48.61]),
+    city("WarenMueritz", "DE", [12.38, 53.34, 12.98, 53.70]),
+    city("Warsaw", "PL", [20.72, 52.05, 21.31, 52.41]),
+    city("WashingtonDC", "US", [-77.27, 38.73, -76.81, 39.09]),
+    city("Waterloo", "CA", [-80.77, 43.29, -80.28, 43.64]),
+    city("Wien", "AT", [16.10, 48.03, 16.64, 48.39]),
+    city("Wroclaw", "PL", [16.75, 50.93, 17.33, 51.29]),
+    city("Wuerzburg", "DE", [9.67, 49.61, 10.23, 49.97]),
+    city("Wuppertal", "DE", [6.86, 51.08, 7.44, 51.44]),
+    city("Zagreb", "HR", [15.72, 45.64, 16.24, 45.99]),
+    city("Zuerich", "CH", [8.28, 47.20, 8.81, 47.56]),
 ];
 
+/// The cities of the country with the ISO 3166-1 alpha-2 `code`, ignoring
+/// case, in alphabetical order.
+pub fn list_cities_in_country(code: &str) -> Vec<&'static CityEntry> {
+    CITY_CATALOG
+        .iter()
+        .filter(|city| city.country.eq_ignore_ascii_case(code))
+        .collect()
+}
+
+/// Default filter box for `city`, ignoring case. `None` for cities outside
+/// of the catalog.
+pub fn default_bbox(city: &str) -> Option<BoundingBox> {
+    CITY_CATALOG
+        .iter()
+        .find(|entry| entry.name.eq_ignore_ascii_case(city))
+        .and_then(|entry| entry.bbox)
+}
+
 #[allow(dead_code)]
 pub fn get_bbbike_source(city_name: &String) -> Result<(String, String), SourceNotFoundError> {
     let base_url = "https://download.bbbike.org/osm/bbbike";
@@ -268,7 +314,7 @@ pub fn get_bbbike_source(city_name: &String) -> Result<(String, String), SourceN
     let mut filename = String::new();
     let mut url = String::new();
     let mut found = false;
-    for city in CITIES {
+    for CityEntry { name: city, .. } in &CITY_CATALOG {
         let city_lower = city.to_lowercase();
         if city_lower == city_name.to_lowercase() {
             found = true;
@@ -330,4 +376,31 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_list_cities_in_country() {
+        let names: Vec<&str> = list_cities_in_country("de")
+            .iter()
+            .map(|city| city.name)
+            .collect();
+        assert!(names.contains(&"Koeln"));
+        assert!(names.contains(&"Berlin"));
+        assert!(!names.contains(&"Zuerich"));
+        assert!(list_cities_in_country("DE")
+            .iter()
+            .all(|city| city.country == "DE"));
+        assert!(list_cities_in_country("XX").is_empty());
+    }
+
+    #[test]
+    fn test_default_bbox() {
+        use geo::{Contains, Point, Polygon};
+
+        let koeln: Polygon = default_bbox("koeln").unwrap().into();
+        // Cologne Cathedral.
+        assert!(koeln.contains(&Point::new(6.9583, 50.9413)));
+        let berlin: Polygon = default_bbox("Berlin").unwrap().into();
+        assert!(!berlin.contains(&Point::new(6.9583, 50.9413)));
+        assert_eq!(default_bbox("Hogwarts"), None);
+    }
 }